@@ -0,0 +1,1546 @@
+//! The C ABI of a `--bridge` build: `exports.hpp` and `exports.cpp`,
+//! generated from the public classes of the project.
+//!
+//! Every class becomes an opaque handle created with `<class>_new` (or
+//! `<class>_new_with` for its parameterized constructor) and released with
+//! `<class>_free`. Each public method gets an `extern "C"` wrapper that
+//! returns a `ucpp_status` and writes its result through a trailing `out`
+//! pointer. Exceptions never cross the boundary: they become
+//! `UCPP_EXCEPTION`, with the message kept for `<project>_last_error`.
+
+use crate::{Class, Param, Visibility};
+
+pub const HEADER_NAME: &str = "exports.hpp";
+pub const SOURCE_NAME: &str = "exports.cpp";
+
+/// How a value crosses the boundary.
+#[derive(Debug, Clone, PartialEq)]
+enum CType {
+    Int,
+    Float,
+    Bool,
+    /// Borrowed `const char*` in arguments; a `malloc`ed copy owned by the
+    /// caller in results.
+    String,
+    /// A project class, passed by handle.
+    Handle(String),
+    /// A handle to a class the wrapper itself allocated with `new`.
+    Created(String),
+}
+
+impl CType {
+    fn of(ty: &str, classes: &[Class]) -> Option<CType> {
+        match ty.trim() {
+            "Int" | "int" => Some(CType::Int),
+            "Float" | "float" => Some(CType::Float),
+            "Bool" | "bool" => Some(CType::Bool),
+            "String" => Some(CType::String),
+            t => classes
+                .iter()
+                .find(|c| c.name == t)
+                .map(|c| CType::Handle(c.name.clone())),
+        }
+    }
+
+    fn param(&self, name: &str) -> String {
+        match self {
+            CType::Int => format!("int {}", name),
+            CType::Float => format!("float {}", name),
+            CType::Bool => format!("bool {}", name),
+            CType::String => format!("const char* {}", name),
+            CType::Handle(c) | CType::Created(c) => {
+                format!("const {}* {}", handle(c), name)
+            }
+        }
+    }
+
+    fn out(&self) -> String {
+        match self {
+            CType::Int => "int* out".to_string(),
+            CType::Float => "float* out".to_string(),
+            CType::Bool => "bool* out".to_string(),
+            CType::String => "char** out".to_string(),
+            CType::Handle(c) | CType::Created(c) => format!("{}** out", handle(c)),
+        }
+    }
+
+    fn is_pointer(&self) -> bool {
+        matches!(self, CType::String | CType::Handle(_) | CType::Created(_))
+    }
+
+    /// The C++ argument built from the C parameter `name`.
+    fn unwrap(&self, name: &str, classes: &[Class]) -> String {
+        match self {
+            CType::String => format!("std::string({})", name),
+            CType::Handle(c) | CType::Created(c) => format!(
+                "*reinterpret_cast<const {}*>({})",
+                qualified(classes, c),
+                name
+            ),
+            _ => name.to_string(),
+        }
+    }
+
+    /// Stores the C++ value `result` into `*out`.
+    fn store(&self, classes: &[Class]) -> String {
+        match self {
+            CType::String => "*out = ucpp_strdup(result);".to_string(),
+            CType::Handle(c) => format!(
+                "*out = reinterpret_cast<{}*>(new {}(std::move(result)));",
+                handle(c),
+                qualified(classes, c)
+            ),
+            CType::Created(c) => format!("*out = reinterpret_cast<{}*>(result);", handle(c)),
+            _ => "*out = result;".to_string(),
+        }
+    }
+}
+
+fn handle(class: &str) -> String {
+    format!("{}Handle", class)
+}
+
+fn prefix(class: &str) -> String {
+    class.to_lowercase()
+}
+
+fn qualified(classes: &[Class], name: &str) -> String {
+    match classes.iter().find(|c| c.name == name) {
+        Some(Class {
+            namespace: Some(ns),
+            ..
+        }) => format!("{}::{}", ns, name),
+        _ => name.to_string(),
+    }
+}
+
+/// C identifiers for the project's own functions.
+fn project_prefix(project: &str) -> String {
+    crate::codegen::identifier(project)
+}
+
+/// C parameter names may not shadow `self` or `out`.
+fn c_name(p: &Param) -> String {
+    match p.name.as_str() {
+        "self" | "out" => format!("{}_", p.name),
+        n => n.to_string(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Constructor,
+    Method,
+    Static,
+}
+
+/// One exported function of a class.
+struct Function {
+    /// The C name, e.g. `counter_add`.
+    name: String,
+    /// The wrapped member: the method name, or `new`/`new_with`.
+    member: String,
+    kind: Kind,
+    /// C parameter names with their types, without `self` and `out`.
+    args: Vec<(String, CType)>,
+    ret: Option<CType>,
+    doc: String,
+}
+
+/// Either the exported function or why it was left out.
+type Export = Result<Function, String>;
+
+fn marshal(params: &[Param], classes: &[Class]) -> Result<Vec<(String, CType)>, String> {
+    params
+        .iter()
+        .map(|p| match CType::of(&p.ty, classes) {
+            Some(t) => Ok((c_name(p), t)),
+            None => Err(format!("parameter '{}' has type {}", p.name, p.ty)),
+        })
+        .collect()
+}
+
+fn constructor(c: &Class, member: &str, params: &[Param], classes: &[Class]) -> Export {
+    let name = format!("{}_{}", prefix(&c.name), member);
+    let args = marshal(params, classes).map_err(|e| format!("{}: {}", name, e))?;
+    Ok(Function {
+        doc: format!(
+            "Creates a {}; release it with {}_free.",
+            c.name,
+            prefix(&c.name)
+        ),
+        name,
+        member: member.to_string(),
+        kind: Kind::Constructor,
+        args,
+        ret: Some(CType::Created(c.name.clone())),
+    })
+}
+
+fn exports(c: &Class, classes: &[Class]) -> Vec<Export> {
+    let mut out = vec![constructor(c, "new", &[], classes)];
+    let with: Option<Vec<Param>> = match &c.ctor_params {
+        Some(ps) if !ps.is_empty() => Some(ps.clone()),
+        None if !c.fields.is_empty() => Some(
+            c.fields
+                .iter()
+                .map(|f| Param {
+                    name: f.name.clone(),
+                    ty: f.ty.clone(),
+                })
+                .collect(),
+        ),
+        _ => None,
+    };
+    if let Some(ps) = with {
+        out.push(constructor(c, "new_with", &ps, classes));
+    }
+    for m in c.methods.iter().filter(|m| m.vis == Visibility::Public) {
+        let name = format!("{}_{}", prefix(&c.name), m.name);
+        let ret = match m.return_type.trim() {
+            "Void" | "void" => None,
+            t => match CType::of(t, classes) {
+                Some(r) => Some(r),
+                None => {
+                    out.push(Err(format!("{}: returns {}", name, t)));
+                    continue;
+                }
+            },
+        };
+        let args = match marshal(&m.params, classes) {
+            Ok(a) => a,
+            Err(e) => {
+                out.push(Err(format!("{}: {}", name, e)));
+                continue;
+            }
+        };
+        let doc = m
+            .doc
+            .as_deref()
+            .and_then(|d| d.lines().next())
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("Calls {}::{}.", c.name, m.name));
+        out.push(Ok(Function {
+            name,
+            member: m.name.clone(),
+            kind: if m.is_static {
+                Kind::Static
+            } else {
+                Kind::Method
+            },
+            args,
+            ret,
+            doc,
+        }));
+    }
+    out
+}
+
+fn c_decl(f: &Function, class: &str) -> String {
+    let mut params: Vec<String> = Vec::new();
+    if f.kind == Kind::Method {
+        params.push(format!("{}* self", handle(class)));
+    }
+    params.extend(f.args.iter().map(|(n, t)| t.param(n)));
+    if let Some(r) = &f.ret {
+        params.push(r.out());
+    }
+    if params.is_empty() {
+        params.push("void".to_string());
+    }
+    format!("ucpp_status {}({})", f.name, params.join(", "))
+}
+
+/// The wrapper body: null checks, the call and the exception barrier.
+fn c_body(f: &Function, class: &str, classes: &[Class]) -> String {
+    let mut required: Vec<&str> = Vec::new();
+    if f.kind == Kind::Method {
+        required.push("self");
+    }
+    required.extend(
+        f.args
+            .iter()
+            .filter(|(_, t)| t.is_pointer())
+            .map(|(n, _)| n.as_str()),
+    );
+    if f.ret.is_some() {
+        required.push("out");
+    }
+    let unwrapped: Vec<String> = f.args.iter().map(|(n, t)| t.unwrap(n, classes)).collect();
+    let q = qualified(classes, class);
+    let call = match f.kind {
+        Kind::Constructor => format!("new {}({})", q, unwrapped.join(", ")),
+        Kind::Static => format!("{}::{}({})", q, f.member, unwrapped.join(", ")),
+        Kind::Method => format!(
+            "reinterpret_cast<{}*>(self)->{}({})",
+            q,
+            f.member,
+            unwrapped.join(", ")
+        ),
+    };
+    let mut body = String::new();
+    if !required.is_empty() {
+        let cond: Vec<String> = required.iter().map(|r| format!("!{}", r)).collect();
+        body.push_str(&format!("  if ({}) {{\n", cond.join(" || ")));
+        body.push_str(&format!(
+            "    return ucpp_fail(UCPP_NULL_ARGUMENT, \"{}: null argument\");\n",
+            f.name
+        ));
+        body.push_str("  }\n");
+    }
+    body.push_str("  try {\n");
+    match &f.ret {
+        Some(r) => {
+            body.push_str(&format!("    auto result = {};\n", call));
+            body.push_str(&format!("    {}\n", r.store(classes)));
+        }
+        None => body.push_str(&format!("    {};\n", call)),
+    }
+    body.push_str("    return UCPP_OK;\n");
+    body.push_str("  } catch (const std::exception& e) {\n");
+    body.push_str("    return ucpp_fail(UCPP_EXCEPTION, e.what());\n");
+    body.push_str("  } catch (...) {\n");
+    body.push_str("    return ucpp_fail(UCPP_EXCEPTION, \"unknown C++ exception\");\n");
+    body.push_str("  }\n");
+    body
+}
+
+/// `exports.hpp`: a header usable from C and C++.
+pub fn header(project: &str, classes: &[Class]) -> String {
+    let pp = project_prefix(project);
+    let mut h = String::new();
+    h.push_str("#pragma once\n");
+    h.push_str("// C interface of the bridge library, generated from the public classes.\n");
+    h.push_str("//\n");
+    h.push_str("// Every function returns a ucpp_status. String arguments are borrowed for\n");
+    h.push_str("// the duration of the call; strings returned through `out` belong to the\n");
+    h.push_str(&format!(
+        "// caller and are released with {}_string_free. Handles are released with\n",
+        pp
+    ));
+    h.push_str("// the matching <class>_free.\n");
+    h.push_str(crate::codegen::API_MACRO);
+    h.push_str("\n#ifdef __cplusplus\n");
+    h.push_str("extern \"C\" {\n");
+    h.push_str("#else\n");
+    h.push_str("#include <stdbool.h>\n");
+    h.push_str("#endif\n\n");
+    h.push_str("#ifndef UCPP_STATUS_DEFINED\n");
+    h.push_str("#define UCPP_STATUS_DEFINED\n");
+    h.push_str("typedef int ucpp_status;\n");
+    h.push_str("#define UCPP_OK 0\n");
+    h.push_str("#define UCPP_NULL_ARGUMENT 1\n");
+    h.push_str("#define UCPP_EXCEPTION 2\n");
+    h.push_str("#endif\n\n");
+    h.push_str("/// Message of the last failure on this thread.\n");
+    h.push_str(&format!("UCPP_API const char* {}_last_error(void);\n", pp));
+    h.push_str("/// Releases a string returned by this library.\n");
+    h.push_str(&format!("UCPP_API void {}_string_free(char* s);\n", pp));
+    for c in classes {
+        h.push_str(&format!(
+            "\ntypedef struct {} {};\n",
+            handle(&c.name),
+            handle(&c.name)
+        ));
+        for e in exports(c, classes) {
+            match e {
+                Ok(f) => {
+                    h.push_str(&format!("/// {}\n", f.doc));
+                    h.push_str(&format!("UCPP_API {};\n", c_decl(&f, &c.name)));
+                }
+                Err(why) => h.push_str(&format!("// not exported: {}\n", why)),
+            }
+        }
+        h.push_str(&format!(
+            "UCPP_API void {}_free({}* self);\n",
+            prefix(&c.name),
+            handle(&c.name)
+        ));
+    }
+    h.push_str("\n#ifdef __cplusplus\n");
+    h.push_str("}\n");
+    h.push_str("#endif\n");
+    h
+}
+
+/// `exports.cpp`: the wrappers behind [`header`], for classes declared in
+/// one header each.
+pub fn source(project: &str, classes: &[Class]) -> String {
+    let headers: Vec<String> = classes
+        .iter()
+        .map(|c| format!("{}.hpp", c.name.to_lowercase()))
+        .collect();
+    source_with(project, classes, &headers)
+}
+
+/// Like [`source`], for classes declared in `headers`.
+pub fn source_with(project: &str, classes: &[Class], headers: &[String]) -> String {
+    let pp = project_prefix(project);
+    let mut s = String::new();
+    s.push_str(&format!("#include \"{}\"\n", HEADER_NAME));
+    for h in headers {
+        s.push_str(&format!("#include \"{}\"\n", h));
+    }
+    for inc in [
+        "cstdlib",
+        "cstring",
+        "exception",
+        "new",
+        "string",
+        "utility",
+    ] {
+        s.push_str(&format!("#include <{}>\n", inc));
+    }
+    s.push_str("\nnamespace {\n");
+    s.push_str("thread_local std::string ucpp_last_error;\n\n");
+    s.push_str("ucpp_status ucpp_fail(ucpp_status status, const char* message) {\n");
+    s.push_str("  ucpp_last_error = message;\n");
+    s.push_str("  return status;\n");
+    s.push_str("}\n\n");
+    // Unused when no method returns a String.
+    s.push_str("[[maybe_unused]] char* ucpp_strdup(const std::string& s) {\n");
+    s.push_str("  char* copy = static_cast<char*>(std::malloc(s.size() + 1));\n");
+    s.push_str("  if (!copy) throw std::bad_alloc();\n");
+    s.push_str("  std::memcpy(copy, s.c_str(), s.size() + 1);\n");
+    s.push_str("  return copy;\n");
+    s.push_str("}\n");
+    s.push_str("}  // namespace\n\n");
+    s.push_str("extern \"C\" {\n\n");
+    s.push_str(&format!("const char* {}_last_error(void) {{\n", pp));
+    s.push_str("  return ucpp_last_error.c_str();\n");
+    s.push_str("}\n\n");
+    s.push_str(&format!("void {}_string_free(char* s) {{\n", pp));
+    s.push_str("  std::free(s);\n");
+    s.push_str("}\n");
+    for c in classes {
+        for f in exports(c, classes).into_iter().flatten() {
+            s.push_str(&format!(
+                "\n{} {{\n{}}}\n",
+                c_decl(&f, &c.name),
+                c_body(&f, &c.name, classes)
+            ));
+        }
+        s.push_str(&format!(
+            "\nvoid {}_free({}* self) {{\n  delete reinterpret_cast<{}*>(self);\n}}\n",
+            prefix(&c.name),
+            handle(&c.name),
+            qualified(classes, &c.name)
+        ));
+    }
+    s.push_str("\n}  // extern \"C\"\n");
+    s
+}
+
+/// Directory of the generated Python module, inside the project output.
+pub const PYTHON_DIR: &str = "python";
+
+/// File name of the Python module for `project`.
+pub fn python_module_name(project: &str) -> String {
+    format!("{}.py", project_prefix(project))
+}
+
+const PYTHON_KEYWORDS: [&str; 35] = [
+    "False", "None", "True", "and", "as", "assert", "async", "await", "break", "class", "continue",
+    "def", "del", "elif", "else", "except", "finally", "for", "from", "global", "if", "import",
+    "in", "is", "lambda", "nonlocal", "not", "or", "pass", "raise", "return", "try", "while",
+    "with", "yield",
+];
+
+fn py_name(name: &str) -> String {
+    if PYTHON_KEYWORDS.contains(&name) {
+        format!("{}_", name)
+    } else {
+        name.to_string()
+    }
+}
+
+impl CType {
+    /// The `ctypes` type of an argument.
+    fn ctypes_arg(&self) -> String {
+        match self {
+            CType::Int => "ctypes.c_int".to_string(),
+            CType::Float => "ctypes.c_float".to_string(),
+            CType::Bool => "ctypes.c_bool".to_string(),
+            CType::String => "ctypes.c_char_p".to_string(),
+            CType::Handle(c) | CType::Created(c) => format!("ctypes.POINTER(_{})", handle(c)),
+        }
+    }
+
+    /// The `ctypes` object receiving a result. Strings stay raw pointers so
+    /// they can be released after decoding.
+    fn ctypes_out(&self) -> String {
+        match self {
+            CType::String => "ctypes.c_void_p".to_string(),
+            t => t.ctypes_arg(),
+        }
+    }
+
+    fn py_hint(&self) -> String {
+        match self {
+            CType::Int => "int".to_string(),
+            CType::Float => "float".to_string(),
+            CType::Bool => "bool".to_string(),
+            CType::String => "str".to_string(),
+            CType::Handle(c) | CType::Created(c) => format!("\"{}\"", c),
+        }
+    }
+
+    fn py_arg(&self, name: &str) -> String {
+        match self {
+            CType::String => format!("{}.encode(\"utf-8\")", name),
+            CType::Handle(_) | CType::Created(_) => format!("{}._handle", name),
+            _ => name.to_string(),
+        }
+    }
+
+    fn py_result(&self) -> String {
+        match self {
+            CType::String => "_take_string(out)".to_string(),
+            CType::Handle(c) | CType::Created(c) => format!("{}._adopt(out)", c),
+            _ => "out.value".to_string(),
+        }
+    }
+}
+
+/// `argtypes`/`restype` of one C function.
+fn py_signature(s: &mut String, f: &Function, class: &str) {
+    let mut types: Vec<String> = Vec::new();
+    if f.kind == Kind::Method {
+        types.push(format!("ctypes.POINTER(_{})", handle(class)));
+    }
+    types.extend(f.args.iter().map(|(_, t)| t.ctypes_arg()));
+    if let Some(r) = &f.ret {
+        types.push(format!("ctypes.POINTER({})", r.ctypes_out()));
+    }
+    s.push_str(&format!(
+        "_lib.{}.argtypes = [{}]\n",
+        f.name,
+        types.join(", ")
+    ));
+    s.push_str(&format!("_lib.{}.restype = ctypes.c_int\n", f.name));
+}
+
+/// Calls `f` with the Python values named after its C parameters and, for
+/// functions with a result, leaves it in `out`.
+fn py_call(s: &mut String, f: &Function, indent: &str) {
+    let mut args: Vec<String> = Vec::new();
+    if f.kind == Kind::Method {
+        args.push("self._handle".to_string());
+    }
+    args.extend(f.args.iter().map(|(n, t)| t.py_arg(&py_name(n))));
+    if let Some(r) = &f.ret {
+        s.push_str(&format!("{}out = {}()\n", indent, r.ctypes_out()));
+        args.push("ctypes.byref(out)".to_string());
+    }
+    s.push_str(&format!(
+        "{}_check(_lib.{}({}))\n",
+        indent,
+        f.name,
+        args.join(", ")
+    ));
+}
+
+fn py_params(f: &Function) -> Vec<String> {
+    f.args
+        .iter()
+        .map(|(n, t)| format!("{}: {}", py_name(n), t.py_hint()))
+        .collect()
+}
+
+fn py_class(s: &mut String, c: &Class, functions: &[Function]) {
+    s.push_str(&format!("\n\nclass {}:\n", c.name));
+    let doc = c
+        .doc
+        .as_deref()
+        .and_then(|d| d.lines().next())
+        .map(str::to_string)
+        .unwrap_or_else(|| format!("Wraps a {} handle.", c.name));
+    s.push_str(&format!("    \"\"\"{}\"\"\"\n\n", doc.replace('"', "'")));
+    s.push_str("    _handle: Any = None\n");
+
+    let ctors: Vec<&Function> = functions
+        .iter()
+        .filter(|f| f.kind == Kind::Constructor)
+        .collect();
+    if let [new] = ctors.as_slice() {
+        s.push_str("\n    def __init__(self) -> None:\n");
+        py_call(s, new, "        ");
+        s.push_str("        self._handle = out\n");
+    } else {
+        for f in &ctors {
+            let mut params = vec!["self".to_string()];
+            params.extend(py_params(f));
+            s.push_str("\n    @overload\n");
+            s.push_str(&format!(
+                "    def __init__({}) -> None: ...\n",
+                params.join(", ")
+            ));
+        }
+        s.push_str("\n    def __init__(self, *args: Any) -> None:\n");
+        for (i, f) in ctors.iter().enumerate() {
+            let keyword = if i == 0 { "if" } else { "elif" };
+            s.push_str(&format!(
+                "        {} len(args) == {}:\n",
+                keyword,
+                f.args.len()
+            ));
+            if !f.args.is_empty() {
+                let names: Vec<String> = f.args.iter().map(|(n, _)| py_name(n)).collect();
+                let target = match names.as_slice() {
+                    [one] => format!("({},)", one),
+                    _ => names.join(", "),
+                };
+                s.push_str(&format!("            {} = args\n", target));
+            }
+            py_call(s, f, "            ");
+        }
+        s.push_str("        else:\n");
+        s.push_str(&format!(
+            "            raise TypeError(\"{}() takes {} arguments\")\n",
+            c.name,
+            ctors
+                .iter()
+                .map(|f| f.args.len().to_string())
+                .collect::<Vec<_>>()
+                .join(" or ")
+        ));
+        s.push_str("        self._handle = out\n");
+    }
+
+    s.push_str("\n    @classmethod\n");
+    s.push_str(&format!(
+        "    def _adopt(cls, handle: Any) -> \"{}\":\n",
+        c.name
+    ));
+    s.push_str("        obj = cls.__new__(cls)\n");
+    s.push_str("        obj._handle = handle\n");
+    s.push_str("        return obj\n");
+
+    s.push_str("\n    def __del__(self) -> None:\n");
+    s.push_str("        if self._handle:\n");
+    s.push_str(&format!(
+        "            _lib.{}_free(self._handle)\n",
+        prefix(&c.name)
+    ));
+    s.push_str("            self._handle = None\n");
+
+    for f in functions.iter().filter(|f| f.kind != Kind::Constructor) {
+        let mut params: Vec<String> = Vec::new();
+        if f.kind == Kind::Method {
+            params.push("self".to_string());
+        } else {
+            s.push_str("\n    @staticmethod");
+        }
+        params.extend(py_params(f));
+        let ret = f.ret.as_ref().map_or("None".to_string(), |r| r.py_hint());
+        s.push_str(&format!(
+            "\n    def {}({}) -> {}:\n",
+            py_name(&f.member),
+            params.join(", "),
+            ret
+        ));
+        s.push_str(&format!(
+            "        \"\"\"{}\"\"\"\n",
+            f.doc.replace('"', "'")
+        ));
+        py_call(s, f, "        ");
+        if let Some(r) = &f.ret {
+            s.push_str(&format!("        return {}\n", r.py_result()));
+        }
+    }
+}
+
+/// A Python module loading the bridge library with `ctypes` and wrapping
+/// each class's handle in a Python class of the same name.
+pub fn python(project: &str, classes: &[Class]) -> String {
+    let pp = project_prefix(project);
+    let all: Vec<(&Class, Vec<Function>)> = classes
+        .iter()
+        .map(|c| (c, exports(c, classes).into_iter().flatten().collect()))
+        .collect();
+    let mut s = String::new();
+    s.push_str(&format!(
+        "\"\"\"Python bindings for the {} bridge library, generated by ultracpp.\n\n",
+        project
+    ));
+    s.push_str("Strings cross the boundary as UTF-8. A failed call raises BridgeError.\n");
+    s.push_str("\"\"\"\n\n");
+    s.push_str("import ctypes\nimport os\nimport sys\nfrom typing import Any, overload\n\n");
+    let mut names = vec!["\"BridgeError\"".to_string()];
+    names.extend(classes.iter().map(|c| format!("\"{}\"", c.name)));
+    s.push_str(&format!("__all__ = [{}]\n\n\n", names.join(", ")));
+
+    s.push_str("def _library_path() -> str:\n");
+    s.push_str(&format!(
+        "    override = os.environ.get(\"{}_LIBRARY\")\n",
+        pp.to_uppercase()
+    ));
+    s.push_str("    if override:\n");
+    s.push_str("        return override\n");
+    s.push_str(&format!(
+        "    name = \"{0}.dll\" if sys.platform.startswith(\"win\") else \"lib{0}.so\"\n",
+        project
+    ));
+    s.push_str("    here = os.path.dirname(os.path.abspath(__file__))\n");
+    s.push_str("    return os.path.join(here, \"..\", \"build\", \"bin\", name)\n\n\n");
+    s.push_str("_lib = ctypes.CDLL(_library_path())\n\n\n");
+
+    s.push_str("class BridgeError(Exception):\n");
+    s.push_str("    \"\"\"A call into the library failed; `status` is its ucpp_status.\"\"\"\n\n");
+    s.push_str("    def __init__(self, status: int, message: str) -> None:\n");
+    s.push_str("        super().__init__(message)\n");
+    s.push_str("        self.status = status\n\n\n");
+
+    s.push_str(&format!("_lib.{}_last_error.argtypes = []\n", pp));
+    s.push_str(&format!(
+        "_lib.{}_last_error.restype = ctypes.c_char_p\n",
+        pp
+    ));
+    s.push_str(&format!(
+        "_lib.{}_string_free.argtypes = [ctypes.c_void_p]\n",
+        pp
+    ));
+    s.push_str(&format!("_lib.{}_string_free.restype = None\n\n\n", pp));
+    s.push_str("def _check(status: int) -> None:\n");
+    s.push_str("    if status != 0:\n");
+    s.push_str(&format!(
+        "        raise BridgeError(status, _lib.{}_last_error().decode(\"utf-8\", \"replace\"))\n\n\n",
+        pp
+    ));
+    s.push_str("def _take_string(out: ctypes.c_void_p) -> str:\n");
+    s.push_str("    try:\n");
+    s.push_str("        return ctypes.string_at(out.value).decode(\"utf-8\")\n");
+    s.push_str("    finally:\n");
+    s.push_str(&format!("        _lib.{}_string_free(out)\n", pp));
+
+    for (c, _) in &all {
+        s.push_str(&format!(
+            "\n\nclass _{}(ctypes.Structure):\n    pass\n",
+            handle(&c.name)
+        ));
+    }
+    s.push('\n');
+    for (c, functions) in &all {
+        s.push('\n');
+        for f in functions {
+            py_signature(&mut s, f, &c.name);
+        }
+        s.push_str(&format!(
+            "_lib.{}_free.argtypes = [ctypes.POINTER(_{})]\n",
+            prefix(&c.name),
+            handle(&c.name)
+        ));
+        s.push_str(&format!("_lib.{}_free.restype = None\n", prefix(&c.name)));
+    }
+    for (c, functions) in &all {
+        py_class(&mut s, c, functions);
+    }
+    s
+}
+
+/// Directory of the generated Rust crate, inside the project output.
+pub const RUST_DIR: &str = "rust";
+
+const RUST_KEYWORDS: [&str; 38] = [
+    "as", "async", "await", "box", "break", "const", "continue", "crate", "dyn", "else", "enum",
+    "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+    "mut", "pub", "ref", "return", "static", "struct", "trait", "true", "type", "unsafe", "use",
+    "where", "while", "yield", "try",
+];
+
+fn rs_name(name: &str) -> String {
+    if RUST_KEYWORDS.contains(&name) {
+        format!("r#{}", name)
+    } else {
+        name.to_string()
+    }
+}
+
+impl CType {
+    /// The type of an argument in the `extern "C"` block.
+    fn rs_sys(&self) -> String {
+        match self {
+            CType::Int => "c_int".to_string(),
+            CType::Float => "f32".to_string(),
+            CType::Bool => "bool".to_string(),
+            CType::String => "*const c_char".to_string(),
+            CType::Handle(c) | CType::Created(c) => format!("*const {}", handle(c)),
+        }
+    }
+
+    fn rs_sys_out(&self) -> String {
+        match self {
+            CType::String => "*mut *mut c_char".to_string(),
+            CType::Handle(c) | CType::Created(c) => format!("*mut *mut {}", handle(c)),
+            t => format!("*mut {}", t.rs_sys()),
+        }
+    }
+
+    /// The type a safe wrapper takes.
+    fn rs_param(&self) -> String {
+        match self {
+            CType::Int => "i32".to_string(),
+            CType::Float => "f32".to_string(),
+            CType::Bool => "bool".to_string(),
+            CType::String => "&str".to_string(),
+            CType::Handle(c) | CType::Created(c) => format!("&{}", c),
+        }
+    }
+
+    /// The type a safe wrapper returns.
+    fn rs_return(&self) -> String {
+        match self {
+            CType::String => "String".to_string(),
+            CType::Handle(c) | CType::Created(c) => c.clone(),
+            t => t.rs_param(),
+        }
+    }
+
+    fn rs_out_init(&self) -> &'static str {
+        match self {
+            CType::Int => "0",
+            CType::Float => "0.0",
+            CType::Bool => "false",
+            _ => "ptr::null_mut()",
+        }
+    }
+
+    fn rs_result(&self) -> String {
+        match self {
+            CType::String => "take_string(out)".to_string(),
+            CType::Handle(c) | CType::Created(c) => format!("{} {{ raw: out }}", c),
+            _ => "out".to_string(),
+        }
+    }
+}
+
+fn rs_sys_decl(f: &Function, class: &str) -> String {
+    let mut params: Vec<String> = Vec::new();
+    if f.kind == Kind::Method {
+        params.push(format!("this: *mut {}", handle(class)));
+    }
+    params.extend(
+        f.args
+            .iter()
+            .map(|(n, t)| format!("{}: {}", rs_name(n), t.rs_sys())),
+    );
+    if let Some(r) = &f.ret {
+        params.push(format!("out: {}", r.rs_sys_out()));
+    }
+    format!("pub fn {}({}) -> c_int;", f.name, params.join(", "))
+}
+
+fn rs_method(s: &mut String, f: &Function) {
+    let mut params: Vec<String> = Vec::new();
+    if f.kind == Kind::Method {
+        params.push("&mut self".to_string());
+    }
+    params.extend(
+        f.args
+            .iter()
+            .map(|(n, t)| format!("{}: {}", rs_name(n), t.rs_param())),
+    );
+    let ret = match (&f.kind, &f.ret) {
+        (Kind::Constructor, _) => "Self".to_string(),
+        (_, Some(r)) => r.rs_return(),
+        (_, None) => "()".to_string(),
+    };
+    // Dropping the wrapper frees the handle, so constructors drop the hint.
+    let doc = match f.kind {
+        Kind::Constructor => format!("{}.", f.doc.split(';').next().unwrap_or_default()),
+        _ => f.doc.clone(),
+    };
+    s.push_str(&format!("\n    /// {}\n", doc));
+    s.push_str(&format!(
+        "    pub fn {}({}) -> Result<{}> {{\n",
+        rs_name(&f.member),
+        params.join(", "),
+        ret
+    ));
+    let mut args: Vec<String> = Vec::new();
+    if f.kind == Kind::Method {
+        args.push("self.raw".to_string());
+    }
+    for (n, t) in &f.args {
+        let n = rs_name(n);
+        match t {
+            CType::String => {
+                s.push_str(&format!("        let {0} = c_string({0})?;\n", n));
+                args.push(format!("{}.as_ptr()", n));
+            }
+            CType::Handle(_) | CType::Created(_) => args.push(format!("{}.raw", n)),
+            _ => args.push(n),
+        }
+    }
+    if let Some(r) = &f.ret {
+        s.push_str(&format!("        let mut out = {};\n", r.rs_out_init()));
+        args.push("&mut out".to_string());
+    }
+    s.push_str(&format!(
+        "        check(unsafe {{ sys::{}({}) }})?;\n",
+        f.name,
+        args.join(", ")
+    ));
+    s.push_str(&format!(
+        "        Ok({})\n",
+        f.ret.as_ref().map_or("()".to_string(), |r| r.rs_result())
+    ));
+    s.push_str("    }\n");
+}
+
+/// Package name of the generated Rust crate.
+pub fn rust_crate_name(project: &str) -> String {
+    project_prefix(project).replace('_', "-")
+}
+
+/// `Cargo.toml` of the generated Rust crate.
+pub fn rust_manifest(project: &str) -> String {
+    format!(
+        "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nedition = \"2021\"\nlinks = \"{}\"\nbuild = \"build.rs\"\n\n[lib]\npath = \"src/lib.rs\"\n",
+        rust_crate_name(project),
+        project
+    )
+}
+
+/// `build.rs` of the generated Rust crate: links the bridge library from
+/// `build/bin`, or from `<PROJECT>_LIB_DIR` when set.
+pub fn rust_build_script(project: &str) -> String {
+    let var = format!("{}_LIB_DIR", project_prefix(project).to_uppercase());
+    let mut s = String::new();
+    s.push_str("use std::env;\nuse std::path::PathBuf;\n\n");
+    s.push_str("fn main() {\n");
+    s.push_str(&format!("    let dir = match env::var(\"{}\") {{\n", var));
+    s.push_str("        Ok(dir) => PathBuf::from(dir),\n");
+    s.push_str("        Err(_) => PathBuf::from(env::var(\"CARGO_MANIFEST_DIR\").unwrap())\n");
+    s.push_str("            .join(\"..\")\n");
+    s.push_str("            .join(\"build\")\n");
+    s.push_str("            .join(\"bin\"),\n");
+    s.push_str("    };\n");
+    s.push_str("    println!(\"cargo:rustc-link-search=native={}\", dir.display());\n");
+    s.push_str(&format!(
+        "    println!(\"cargo:rerun-if-env-changed={}\");\n",
+        var
+    ));
+    s.push_str("}\n");
+    s
+}
+
+/// `src/lib.rs` of the generated Rust crate: the raw declarations in `sys`
+/// and a safe struct per class that frees its handle on drop.
+pub fn rust(project: &str, classes: &[Class]) -> String {
+    let pp = project_prefix(project);
+    let all: Vec<(&Class, Vec<Function>)> = classes
+        .iter()
+        .map(|c| (c, exports(c, classes).into_iter().flatten().collect()))
+        .collect();
+    let mut s = String::new();
+    s.push_str(&format!(
+        "//! Rust bindings for the {} bridge library, generated by ultracpp.\n",
+        project
+    ));
+    s.push_str("//!\n");
+    s.push_str("//! Every call returns a [`Result`]; C++ exceptions arrive as an [`Error`]\n");
+    s.push_str("//! with status [`EXCEPTION`]. Each wrapper frees its handle when dropped.\n\n");
+    s.push_str("use std::ffi::{CStr, CString};\n");
+    s.push_str("use std::fmt;\n");
+    s.push_str("use std::os::raw::{c_char, c_int};\n");
+    s.push_str("use std::ptr;\n\n");
+    s.push_str("/// `ucpp_status` of a call given a null pointer.\n");
+    s.push_str("pub const NULL_ARGUMENT: i32 = 1;\n");
+    s.push_str("/// `ucpp_status` of a call that threw.\n");
+    s.push_str("pub const EXCEPTION: i32 = 2;\n\n");
+
+    s.push_str("/// Raw declarations of the C interface in `exports.hpp`.\n");
+    s.push_str("pub mod sys {\n");
+    s.push_str("    use std::os::raw::{c_char, c_int};\n");
+    for (c, _) in &all {
+        s.push_str(&format!(
+            "\n    #[repr(C)]\n    pub struct {} {{\n        _private: [u8; 0],\n    }}\n",
+            handle(&c.name)
+        ));
+    }
+    s.push_str(&format!("\n    #[link(name = \"{}\")]\n", project));
+    s.push_str("    extern \"C\" {\n");
+    s.push_str(&format!(
+        "        pub fn {}_last_error() -> *const c_char;\n",
+        pp
+    ));
+    s.push_str(&format!(
+        "        pub fn {}_string_free(s: *mut c_char);\n",
+        pp
+    ));
+    for (c, functions) in &all {
+        for f in functions {
+            s.push_str(&format!("        {}\n", rs_sys_decl(f, &c.name)));
+        }
+        s.push_str(&format!(
+            "        pub fn {}_free(this: *mut {});\n",
+            prefix(&c.name),
+            handle(&c.name)
+        ));
+    }
+    s.push_str("    }\n}\n\n");
+
+    s.push_str("/// A failed call into the library.\n");
+    s.push_str("#[derive(Debug, Clone, PartialEq, Eq)]\n");
+    s.push_str("pub struct Error {\n");
+    s.push_str("    /// The `ucpp_status` of the call.\n");
+    s.push_str("    pub status: i32,\n");
+    s.push_str("    pub message: String,\n");
+    s.push_str("}\n\n");
+    s.push_str("impl fmt::Display for Error {\n");
+    s.push_str("    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {\n");
+    s.push_str("        f.write_str(&self.message)\n");
+    s.push_str("    }\n");
+    s.push_str("}\n\n");
+    s.push_str("impl std::error::Error for Error {}\n\n");
+    s.push_str("pub type Result<T> = std::result::Result<T, Error>;\n\n");
+    s.push_str("fn check(status: c_int) -> Result<()> {\n");
+    s.push_str("    if status == 0 {\n");
+    s.push_str("        return Ok(());\n");
+    s.push_str("    }\n");
+    s.push_str(&format!(
+        "    let message = unsafe {{ CStr::from_ptr(sys::{}_last_error()) }};\n",
+        pp
+    ));
+    s.push_str("    Err(Error {\n");
+    s.push_str("        status,\n");
+    s.push_str("        message: message.to_string_lossy().into_owned(),\n");
+    s.push_str("    })\n");
+    s.push_str("}\n\n");
+    s.push_str("fn c_string(s: &str) -> Result<CString> {\n");
+    s.push_str("    CString::new(s).map_err(|_| Error {\n");
+    s.push_str("        status: NULL_ARGUMENT,\n");
+    s.push_str("        message: \"string contains a NUL byte\".to_string(),\n");
+    s.push_str("    })\n");
+    s.push_str("}\n\n");
+    s.push_str("/// Copies a string returned by the library and releases it.\n");
+    s.push_str("fn take_string(s: *mut c_char) -> String {\n");
+    s.push_str("    let copy = unsafe { CStr::from_ptr(s) }.to_string_lossy().into_owned();\n");
+    s.push_str(&format!("    unsafe {{ sys::{}_string_free(s) }};\n", pp));
+    s.push_str("    copy\n");
+    s.push_str("}\n");
+
+    for (c, functions) in &all {
+        let doc = c
+            .doc
+            .as_deref()
+            .and_then(|d| d.lines().next())
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("Owns a {} created by the library.", c.name));
+        s.push_str(&format!("\n/// {}\n", doc));
+        s.push_str(&format!("pub struct {} {{\n", c.name));
+        s.push_str(&format!("    raw: *mut sys::{},\n", handle(&c.name)));
+        s.push_str("}\n\n");
+        s.push_str(&format!("impl {} {{", c.name));
+        for f in functions {
+            rs_method(&mut s, f);
+        }
+        s.push_str("\n    /// The handle, for calling [`sys`] directly.\n");
+        s.push_str(&format!(
+            "    pub fn as_ptr(&self) -> *mut sys::{} {{\n",
+            handle(&c.name)
+        ));
+        s.push_str("        self.raw\n");
+        s.push_str("    }\n");
+        s.push_str("}\n\n");
+        s.push_str(&format!("impl Drop for {} {{\n", c.name));
+        s.push_str("    fn drop(&mut self) {\n");
+        s.push_str(&format!(
+            "        unsafe {{ sys::{}_free(self.raw) }}\n",
+            prefix(&c.name)
+        ));
+        s.push_str("    }\n");
+        s.push_str("}\n");
+    }
+    s
+}
+
+/// Directory of the generated Node.js addon, inside the project output.
+pub const NODE_DIR: &str = "node";
+
+impl CType {
+    fn ts_type(&self) -> String {
+        match self {
+            CType::Int | CType::Float => "number".to_string(),
+            CType::Bool => "boolean".to_string(),
+            CType::String => "string".to_string(),
+            CType::Handle(c) | CType::Created(c) => c.clone(),
+        }
+    }
+
+    /// Declares `name` and fills it from the JS value `value`; returns from
+    /// the callback with a pending `TypeError` when the value does not fit.
+    fn napi_unwrap(&self, name: &str, value: &str) -> String {
+        let (decl, call) = match self {
+            CType::Int => (
+                format!("int {}", name),
+                format!("to_int(env, {}, &{})", value, name),
+            ),
+            CType::Float => (
+                format!("float {}", name),
+                format!("to_float(env, {}, &{})", value, name),
+            ),
+            CType::Bool => (
+                format!("bool {}", name),
+                format!("to_bool(env, {}, &{})", value, name),
+            ),
+            CType::String => (
+                format!("std::string {}", name),
+                format!("to_string(env, {}, &{})", value, name),
+            ),
+            CType::Handle(c) | CType::Created(c) => (
+                format!("{}* {} = nullptr", handle(c), name),
+                format!(
+                    "to_handle(env, {}, {}_class, reinterpret_cast<void**>(&{}))",
+                    value,
+                    prefix(c),
+                    name
+                ),
+            ),
+        };
+        format!("  {};\n  if (!{}) return nullptr;\n", decl, call)
+    }
+
+    fn napi_arg(&self, name: &str) -> String {
+        match self {
+            CType::String => format!("{}.c_str()", name),
+            _ => name.to_string(),
+        }
+    }
+
+    fn napi_out(&self) -> String {
+        match self {
+            CType::Int => "int out = 0".to_string(),
+            CType::Float => "float out = 0".to_string(),
+            CType::Bool => "bool out = false".to_string(),
+            CType::String => "char* out = nullptr".to_string(),
+            CType::Handle(c) | CType::Created(c) => format!("{}* out = nullptr", handle(c)),
+        }
+    }
+
+    fn napi_result(&self) -> String {
+        match self {
+            CType::Int => "from_int(env, out)".to_string(),
+            CType::Float => "from_double(env, out)".to_string(),
+            CType::Bool => "from_bool(env, out)".to_string(),
+            CType::String => "from_string(env, out)".to_string(),
+            CType::Handle(c) | CType::Created(c) => format!("adopt(env, {}_class, out)", prefix(c)),
+        }
+    }
+}
+
+/// The call of `f` with its arguments already unwrapped, failing with a JS
+/// error on a non-OK status.
+fn napi_call(s: &mut String, f: &Function) {
+    let mut args: Vec<String> = Vec::new();
+    if f.kind == Kind::Method {
+        args.push("self".to_string());
+    }
+    args.extend(f.args.iter().map(|(n, t)| t.napi_arg(n)));
+    if let Some(r) = &f.ret {
+        s.push_str(&format!("  {};\n", r.napi_out()));
+        args.push("&out".to_string());
+    }
+    s.push_str(&format!(
+        "  ucpp_status status = {}({});\n",
+        f.name,
+        args.join(", ")
+    ));
+    s.push_str("  if (status != UCPP_OK) return fail(env, status);\n");
+}
+
+fn napi_class(s: &mut String, c: &Class, functions: &[Function]) {
+    let p = prefix(&c.name);
+    let ctors: Vec<&Function> = functions
+        .iter()
+        .filter(|f| f.kind == Kind::Constructor)
+        .collect();
+    let capacity = ctors.iter().map(|f| f.args.len()).max().unwrap_or(0).max(1);
+    s.push_str(&format!(
+        "\nnapi_value {}_construct(napi_env env, napi_callback_info info) {{\n",
+        p
+    ));
+    s.push_str(&format!("  size_t argc = {};\n", capacity));
+    s.push_str(&format!("  napi_value argv[{}];\n", capacity));
+    s.push_str("  napi_value this_arg;\n");
+    s.push_str("  napi_get_cb_info(env, info, &argc, argv, &this_arg, nullptr);\n");
+    s.push_str(&format!("  {}* handle = nullptr;\n", handle(&c.name)));
+    s.push_str("  if (is_external(env, argc, argv)) {\n");
+    s.push_str("    napi_get_value_external(env, argv[0], reinterpret_cast<void**>(&handle));\n");
+    for f in &ctors {
+        s.push_str(&format!("  }} else if (argc == {}) {{\n", f.args.len()));
+        let mut body = String::new();
+        for (i, (n, t)) in f.args.iter().enumerate() {
+            body.push_str(&t.napi_unwrap(n, &format!("argv[{}]", i)));
+        }
+        let mut args: Vec<String> = f.args.iter().map(|(n, t)| t.napi_arg(n)).collect();
+        args.push("&handle".to_string());
+        body.push_str(&format!(
+            "  ucpp_status status = {}({});\n",
+            f.name,
+            args.join(", ")
+        ));
+        body.push_str("  if (status != UCPP_OK) return fail(env, status);\n");
+        for line in body.lines() {
+            s.push_str(&format!("  {}\n", line));
+        }
+    }
+    s.push_str("  } else {\n");
+    s.push_str(&format!(
+        "    napi_throw_type_error(env, nullptr, \"{}() takes {} arguments\");\n",
+        c.name,
+        ctors
+            .iter()
+            .map(|f| f.args.len().to_string())
+            .collect::<Vec<_>>()
+            .join(" or ")
+    ));
+    s.push_str("    return nullptr;\n");
+    s.push_str("  }\n");
+    s.push_str("  napi_wrap(\n");
+    s.push_str("      env, this_arg, handle,\n");
+    s.push_str("      [](napi_env, void* data, void*) {\n");
+    s.push_str(&format!(
+        "        {}_free(static_cast<{}*>(data));\n",
+        p,
+        handle(&c.name)
+    ));
+    s.push_str("      },\n");
+    s.push_str("      nullptr, nullptr);\n");
+    s.push_str("  return this_arg;\n");
+    s.push_str("}\n");
+
+    for f in functions.iter().filter(|f| f.kind != Kind::Constructor) {
+        let capacity = f.args.len().max(1);
+        s.push_str(&format!(
+            "\nnapi_value {}_js(napi_env env, napi_callback_info info) {{\n",
+            f.name
+        ));
+        s.push_str(&format!("  size_t argc = {};\n", capacity));
+        s.push_str(&format!("  napi_value argv[{}];\n", capacity));
+        s.push_str("  napi_value this_arg;\n");
+        s.push_str("  napi_get_cb_info(env, info, &argc, argv, &this_arg, nullptr);\n");
+        if !f.args.is_empty() {
+            s.push_str(&format!(
+                "  if (argc < {}) return arity_error(env, \"{}\", {});\n",
+                f.args.len(),
+                f.member,
+                f.args.len()
+            ));
+        }
+        if f.kind == Kind::Method {
+            s.push_str(&format!("  {}* self = nullptr;\n", handle(&c.name)));
+            s.push_str(&format!(
+                "  if (!to_handle(env, this_arg, {}_class, reinterpret_cast<void**>(&self))) return nullptr;\n",
+                p
+            ));
+        }
+        for (i, (n, t)) in f.args.iter().enumerate() {
+            s.push_str(&t.napi_unwrap(n, &format!("argv[{}]", i)));
+        }
+        napi_call(s, f);
+        match &f.ret {
+            Some(r) => s.push_str(&format!("  return {};\n", r.napi_result())),
+            None => s.push_str("  return undefined(env);\n"),
+        }
+        s.push_str("}\n");
+    }
+}
+
+/// `binding.cc`: an N-API addon exposing each class as a JS class on top
+/// of the C interface.
+pub fn node_binding(project: &str, classes: &[Class]) -> String {
+    let pp = project_prefix(project);
+    let all: Vec<(&Class, Vec<Function>)> = classes
+        .iter()
+        .map(|c| (c, exports(c, classes).into_iter().flatten().collect()))
+        .collect();
+    let mut s = String::new();
+    s.push_str(&format!(
+        "// N-API addon for the {} bridge library, generated by ultracpp.\n",
+        project
+    ));
+    s.push_str("#include <node_api.h>\n\n");
+    s.push_str("#include <string>\n\n");
+    s.push_str(&format!("#include \"{}\"\n\n", HEADER_NAME));
+    s.push_str("namespace {\n\n");
+    for (c, _) in &all {
+        s.push_str(&format!("napi_ref {}_class = nullptr;\n", prefix(&c.name)));
+    }
+    s.push_str(
+        r#"
+// Throws the library's last error; `code` is the status name.
+napi_value fail(napi_env env, ucpp_status status) {
+  const char* code = status == UCPP_NULL_ARGUMENT ? "UCPP_NULL_ARGUMENT" : "UCPP_EXCEPTION";
+"#,
+    );
+    s.push_str(&format!(
+        "  napi_throw_error(env, code, {}_last_error());\n",
+        pp
+    ));
+    s.push_str(
+        r#"  return nullptr;
+}
+
+[[maybe_unused]] napi_value arity_error(napi_env env, const char* name, int expected) {
+  std::string message = std::string(name) + "() takes " + std::to_string(expected) + " arguments";
+  napi_throw_type_error(env, nullptr, message.c_str());
+  return nullptr;
+}
+
+[[maybe_unused]] napi_value undefined(napi_env env) {
+  napi_value v;
+  napi_get_undefined(env, &v);
+  return v;
+}
+
+[[maybe_unused]] bool to_int(napi_env env, napi_value v, int* out) {
+  int32_t n;
+  if (napi_get_value_int32(env, v, &n) != napi_ok) {
+    napi_throw_type_error(env, nullptr, "expected a number");
+    return false;
+  }
+  *out = n;
+  return true;
+}
+
+[[maybe_unused]] bool to_float(napi_env env, napi_value v, float* out) {
+  double d;
+  if (napi_get_value_double(env, v, &d) != napi_ok) {
+    napi_throw_type_error(env, nullptr, "expected a number");
+    return false;
+  }
+  *out = static_cast<float>(d);
+  return true;
+}
+
+[[maybe_unused]] bool to_bool(napi_env env, napi_value v, bool* out) {
+  if (napi_get_value_bool(env, v, out) != napi_ok) {
+    napi_throw_type_error(env, nullptr, "expected a boolean");
+    return false;
+  }
+  return true;
+}
+
+[[maybe_unused]] bool to_string(napi_env env, napi_value v, std::string* out) {
+  size_t len;
+  if (napi_get_value_string_utf8(env, v, nullptr, 0, &len) != napi_ok) {
+    napi_throw_type_error(env, nullptr, "expected a string");
+    return false;
+  }
+  out->resize(len + 1);
+  napi_get_value_string_utf8(env, v, &(*out)[0], len + 1, &len);
+  out->resize(len);
+  return true;
+}
+
+// Unwraps an instance of the class behind `ctor`.
+[[maybe_unused]] bool to_handle(napi_env env, napi_value v, napi_ref ctor, void** out) {
+  napi_value cls;
+  bool is_instance = false;
+  napi_get_reference_value(env, ctor, &cls);
+  if (napi_instanceof(env, v, cls, &is_instance) != napi_ok || !is_instance ||
+      napi_unwrap(env, v, out) != napi_ok) {
+    napi_throw_type_error(env, nullptr, "expected a wrapped object");
+    return false;
+  }
+  return true;
+}
+
+[[maybe_unused]] napi_value from_int(napi_env env, int n) {
+  napi_value v;
+  napi_create_int32(env, n, &v);
+  return v;
+}
+
+[[maybe_unused]] napi_value from_double(napi_env env, double d) {
+  napi_value v;
+  napi_create_double(env, d, &v);
+  return v;
+}
+
+[[maybe_unused]] napi_value from_bool(napi_env env, bool b) {
+  napi_value v;
+  napi_get_boolean(env, b, &v);
+  return v;
+}
+
+"#,
+    );
+    s.push_str("// Takes ownership of a string returned by the library.\n");
+    s.push_str("[[maybe_unused]] napi_value from_string(napi_env env, char* str) {\n");
+    s.push_str("  napi_value v;\n");
+    s.push_str("  napi_create_string_utf8(env, str, NAPI_AUTO_LENGTH, &v);\n");
+    s.push_str(&format!("  {}_string_free(str);\n", pp));
+    s.push_str("  return v;\n");
+    s.push_str("}\n");
+    s.push_str(
+        r#"
+// Constructors given a single external adopt it instead of creating a handle.
+bool is_external(napi_env env, size_t argc, napi_value* argv) {
+  napi_valuetype type;
+  return argc == 1 && napi_typeof(env, argv[0], &type) == napi_ok && type == napi_external;
+}
+
+// Wraps a handle returned by the library in a new instance of its class.
+[[maybe_unused]] napi_value adopt(napi_env env, napi_ref ctor, void* handle) {
+  napi_value cls, external, instance;
+  napi_get_reference_value(env, ctor, &cls);
+  napi_create_external(env, handle, nullptr, nullptr, &external);
+  napi_new_instance(env, cls, 1, &external, &instance);
+  return instance;
+}
+"#,
+    );
+    for (c, functions) in &all {
+        napi_class(&mut s, c, functions);
+    }
+    s.push_str("\nnapi_value init(napi_env env, napi_value exports) {\n");
+    for (c, functions) in &all {
+        let p = prefix(&c.name);
+        let members: Vec<&Function> = functions
+            .iter()
+            .filter(|f| f.kind != Kind::Constructor)
+            .collect();
+        s.push_str("  {\n");
+        if !members.is_empty() {
+            s.push_str("    napi_property_descriptor props[] = {\n");
+            for f in &members {
+                let attr = if f.kind == Kind::Static {
+                    "napi_static"
+                } else {
+                    "napi_default"
+                };
+                s.push_str(&format!(
+                    "        {{\"{}\", nullptr, {}_js, nullptr, nullptr, nullptr, {}, nullptr}},\n",
+                    f.member, f.name, attr
+                ));
+            }
+            s.push_str("    };\n");
+        }
+        let (count, props) = if members.is_empty() {
+            ("0".to_string(), "nullptr")
+        } else {
+            ("sizeof(props) / sizeof(props[0])".to_string(), "props")
+        };
+        s.push_str("    napi_value cls;\n");
+        s.push_str(&format!(
+            "    napi_define_class(env, \"{}\", NAPI_AUTO_LENGTH, {}_construct, nullptr, {}, {}, &cls);\n",
+            c.name, p, count, props
+        ));
+        s.push_str(&format!(
+            "    napi_create_reference(env, cls, 1, &{}_class);\n",
+            p
+        ));
+        s.push_str(&format!(
+            "    napi_set_named_property(env, exports, \"{}\", cls);\n",
+            c.name
+        ));
+        s.push_str("  }\n");
+    }
+    s.push_str("  return exports;\n");
+    s.push_str("}\n\n");
+    s.push_str("}  // namespace\n\n");
+    s.push_str("NAPI_MODULE(NODE_GYP_MODULE_NAME, init)\n");
+    s
+}
+
+/// `binding.gyp`: builds the addon against the bridge library in `build/bin`.
+pub fn node_gyp(project: &str) -> String {
+    let pp = project_prefix(project);
+    format!(
+        r#"{{
+  "targets": [
+    {{
+      "target_name": "{pp}",
+      "sources": ["binding.cc"],
+      "include_dirs": ["../include"],
+      "conditions": [
+        ["OS=='win'", {{
+          "libraries": ["<(module_root_dir)/../build/bin/{project}.lib"]
+        }}, {{
+          "libraries": [
+            "-L<(module_root_dir)/../build/bin",
+            "-l{project}",
+            "-Wl,-rpath,<(module_root_dir)/../build/bin"
+          ]
+        }}]
+      ]
+    }}
+  ]
+}}
+"#
+    )
+}
+
+/// `package.json` of the addon, pointing at the loader and the typings.
+pub fn node_package(project: &str) -> String {
+    format!(
+        r#"{{
+  "name": "{}",
+  "version": "0.1.0",
+  "main": "index.js",
+  "types": "index.d.ts",
+  "gypfile": true,
+  "scripts": {{
+    "install": "node-gyp rebuild"
+  }}
+}}
+"#,
+        rust_crate_name(project)
+    )
+}
+
+/// `index.js`: loads the compiled addon.
+pub fn node_index(project: &str) -> String {
+    format!(
+        "module.exports = require('./build/Release/{}.node');\n",
+        project_prefix(project)
+    )
+}
+
+/// `index.d.ts`: TypeScript declarations of the classes in the addon.
+pub fn node_typings(project: &str, classes: &[Class]) -> String {
+    let mut s = format!(
+        "// Type declarations for the {} addon, generated by ultracpp.\n",
+        project
+    );
+    s.push_str("// Failed calls throw an Error whose `code` is UCPP_EXCEPTION or\n");
+    s.push_str("// UCPP_NULL_ARGUMENT.\n");
+    for c in classes {
+        let functions: Vec<Function> = exports(c, classes).into_iter().flatten().collect();
+        s.push('\n');
+        if let Some(doc) = c.doc.as_deref().and_then(|d| d.lines().next()) {
+            s.push_str(&format!("/** {} */\n", doc));
+        }
+        s.push_str(&format!("export class {} {{\n", c.name));
+        for f in &functions {
+            let params: Vec<String> = f
+                .args
+                .iter()
+                .map(|(n, t)| format!("{}: {}", n, t.ts_type()))
+                .collect();
+            if f.kind == Kind::Constructor {
+                s.push_str(&format!("  constructor({});\n", params.join(", ")));
+                continue;
+            }
+            s.push_str(&format!("  /** {} */\n", f.doc));
+            let ret = f.ret.as_ref().map_or("void".to_string(), |r| r.ts_type());
+            let static_kw = if f.kind == Kind::Static {
+                "static "
+            } else {
+                ""
+            };
+            s.push_str(&format!(
+                "  {}{}({}): {};\n",
+                static_kw,
+                f.member,
+                params.join(", "),
+                ret
+            ));
+        }
+        s.push_str("}\n");
+    }
+    s
+}
@@ -0,0 +1,1334 @@
+//! How a generated project is compiled, independent of the toolchain.
+//!
+//! A [`BuildPlan`] lists the translation units, include dirs, defines,
+//! flags and targets once. The GCC, Clang and MSVC backends turn it into
+//! command lines, which are either run here (incrementally and in parallel)
+//! or rendered as `build.sh`, `build.bat` or `build.ninja`.
+
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::process::{Command, Output};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::cli::{Compiler, Sanitizer};
+use crate::codegen::CppStd;
+use crate::json::{obj, Value};
+
+pub const COMPILE_DATABASE_NAME: &str = "compile_commands.json";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetKind {
+    Executable,
+    SharedLibrary,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Target {
+    pub kind: TargetKind,
+    /// Base name; the backend adds `.exe`, `lib….so` or `.dll`.
+    pub name: String,
+    /// The plan sources linked into this target.
+    pub sources: Vec<String>,
+}
+
+/// Paths are relative to the generated project directory.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildPlan {
+    /// Project name, used for release archives.
+    pub name: String,
+    pub sources: Vec<String>,
+    pub include_dirs: Vec<String>,
+    /// `NAME` or `NAME=VALUE`.
+    pub defines: Vec<String>,
+    /// Extra compiler flags, passed verbatim.
+    pub flags: Vec<String>,
+    pub std: CppStd,
+    pub release: bool,
+    pub sanitize: Option<Sanitizer>,
+    pub coverage: bool,
+    /// Header to precompile, e.g. `include/pch.hpp`.
+    pub pch: Option<String>,
+    pub link_libs: Vec<String>,
+    pub targets: Vec<Target>,
+}
+
+/// A step of [`BuildPlan::execute`] that could not run or failed.
+#[derive(Debug)]
+pub struct BuildError {
+    pub program: String,
+    /// `None` when the program could not be started.
+    pub output: Option<Output>,
+}
+
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.output {
+            None => write!(f, "cannot run {}", self.program),
+            Some(out) => write!(f, "{} failed with {}", self.program, out.status),
+        }
+    }
+}
+
+pub fn program(tc: Compiler) -> &'static str {
+    match tc {
+        Compiler::Gcc => "g++",
+        Compiler::Clang => "clang++",
+        Compiler::Msvc => "cl.exe",
+    }
+}
+
+fn stem(path: &str) -> &str {
+    let name = path.rsplit(['/', '\\']).next().unwrap_or(path);
+    name.rsplit_once('.').map_or(name, |(s, _)| s)
+}
+
+fn file_name(path: &str) -> &str {
+    path.rsplit(['/', '\\']).next().unwrap_or(path)
+}
+
+/// A C++20 module interface unit, named after its module.
+pub fn is_module_unit(path: &str) -> bool {
+    path.ends_with(".cppm")
+}
+
+impl BuildPlan {
+    pub fn object(&self, tc: Compiler, source: &str) -> String {
+        let ext = if tc == Compiler::Msvc { "obj" } else { "o" };
+        format!("build/obj/{}.{}", stem(source), ext)
+    }
+
+    /// Dependency file written next to the object of `source`.
+    pub fn depfile(&self, tc: Compiler, source: &str) -> String {
+        let ext = if tc == Compiler::Msvc { "json" } else { "d" };
+        format!("{}.{}", self.object(tc, source), ext)
+    }
+
+    pub fn output(&self, tc: Compiler, target: &Target) -> String {
+        let file = match (target.kind, tc) {
+            (TargetKind::Executable, Compiler::Msvc) => format!("{}.exe", target.name),
+            (TargetKind::Executable, _) => {
+                format!("{}{}", target.name, std::env::consts::EXE_SUFFIX)
+            }
+            (TargetKind::SharedLibrary, Compiler::Msvc) => format!("{}.dll", target.name),
+            (TargetKind::SharedLibrary, _) => format!("lib{}.so", target.name),
+        };
+        format!("build/bin/{}", file)
+    }
+
+    /// The precompiled header file, for GCC and Clang.
+    pub fn pch_output(&self, tc: Compiler) -> Option<String> {
+        let header = file_name(self.pch.as_deref()?);
+        match tc {
+            Compiler::Gcc => Some(format!("build/obj/pch/{}.gch", header)),
+            Compiler::Clang => Some(format!("build/obj/pch/{}.pch", header)),
+            Compiler::Msvc => None,
+        }
+    }
+
+    /// The MSVC unit that creates the precompiled header: the source named
+    /// after the header, e.g. `src/pch.cpp`.
+    fn msvc_pch_unit(&self) -> Option<&str> {
+        let header = stem(self.pch.as_deref()?);
+        self.sources
+            .iter()
+            .map(String::as_str)
+            .find(|s| stem(s) == header)
+    }
+
+    /// Module interfaces come first in `sources`, in the order they
+    /// import each other.
+    fn module_units(&self) -> impl Iterator<Item = &str> {
+        self.sources
+            .iter()
+            .map(String::as_str)
+            .filter(|s| is_module_unit(s))
+    }
+
+    fn uses_modules(&self) -> bool {
+        self.module_units().next().is_some()
+    }
+
+    /// Flags compiling `source` as a module interface, which also writes
+    /// its compiled interface where importers look for it: `gcm.cache/`
+    /// for GCC, `build/obj/` for Clang and MSVC.
+    fn unit_flags(&self, tc: Compiler, source: &str) -> Vec<String> {
+        if !is_module_unit(source) {
+            return Vec::new();
+        }
+        match tc {
+            Compiler::Gcc => vec!["-x".into(), "c++".into()],
+            Compiler::Clang => vec!["-x".into(), "c++-module".into(), "-fmodule-output".into()],
+            Compiler::Msvc => vec![
+                "/TP".into(),
+                "/interface".into(),
+                "/ifcOutput".into(),
+                "build/obj/".into(),
+            ],
+        }
+    }
+
+    fn shared(&self) -> bool {
+        self.targets
+            .iter()
+            .any(|t| t.kind == TargetKind::SharedLibrary)
+    }
+
+    /// Flags that decide what code is generated, shared by every compile
+    /// and by the precompiled header.
+    fn code_flags(&self, tc: Compiler) -> Vec<String> {
+        let mut f: Vec<String> = Vec::new();
+        match tc {
+            Compiler::Msvc => {
+                f.push("/nologo".into());
+                f.push(
+                    match self.std {
+                        CppStd::Cpp17 => "/std:c++17",
+                        CppStd::Cpp20 => "/std:c++20",
+                        CppStd::Cpp23 => "/std:c++latest",
+                    }
+                    .into(),
+                );
+                f.push("/EHsc".into());
+                f.push("/permissive-".into());
+                // Literals are UTF-8 in the sources and in the program.
+                f.push("/utf-8".into());
+                if self.release {
+                    f.push("/O2".into());
+                }
+                if self.sanitize == Some(Sanitizer::Address) {
+                    f.push("/fsanitize=address".into());
+                }
+                for d in &self.defines {
+                    f.push(format!("/D{}", d));
+                }
+                for i in &self.include_dirs {
+                    f.push(format!("/I{}", i));
+                }
+            }
+            Compiler::Gcc | Compiler::Clang => {
+                f.push(format!("-std={}", self.std.as_str()));
+                if self.release {
+                    f.push("-O2".into());
+                }
+                match self.sanitize {
+                    Some(Sanitizer::Address) => f.push("-fsanitize=address".into()),
+                    Some(Sanitizer::Undefined) => f.push("-fsanitize=undefined".into()),
+                    Some(Sanitizer::Thread) => f.push("-fsanitize=thread".into()),
+                    None => {}
+                }
+                if self.coverage {
+                    f.push("-fprofile-arcs".into());
+                    f.push("-ftest-coverage".into());
+                }
+                f.push("-fvisibility=hidden".into());
+                if self.shared() {
+                    f.push("-fPIC".into());
+                }
+                for d in &self.defines {
+                    f.push(format!("-D{}", d));
+                }
+                for i in &self.include_dirs {
+                    f.push("-I".into());
+                    f.push(i.clone());
+                }
+            }
+        }
+        f.extend(self.flags.iter().cloned());
+        f
+    }
+
+    /// Every flag of a compile except the file names.
+    pub fn compile_flags(&self, tc: Compiler) -> Vec<String> {
+        let mut f = self.code_flags(tc);
+        match tc {
+            Compiler::Msvc => {
+                f.push("/W4".into());
+                f.push("/WX".into());
+            }
+            Compiler::Gcc | Compiler::Clang => {
+                f.push("-Wall".into());
+                f.push("-Wextra".into());
+                f.push("-Werror".into());
+            }
+        }
+        if self.uses_modules() {
+            match tc {
+                Compiler::Gcc => {
+                    f.push("-fmodules-ts".into());
+                    // Plain depfiles: the ones listing modules need a mapper.
+                    f.push("-Mno-modules".into());
+                }
+                Compiler::Clang => f.push("-fprebuilt-module-path=build/obj".into()),
+                Compiler::Msvc => {
+                    f.push("/ifcSearchDir".into());
+                    f.push("build/obj".into());
+                }
+            }
+        }
+        match (tc, self.pch_output(tc)) {
+            // GCC picks `pch.hpp.gch` up while searching for `pch.hpp`.
+            (Compiler::Gcc, Some(_)) => {
+                f.insert(0, "build/obj/pch".into());
+                f.insert(0, "-I".into());
+            }
+            (Compiler::Clang, Some(pch)) => {
+                f.push("-include-pch".into());
+                f.push(pch);
+            }
+            _ => {}
+        }
+        f
+    }
+
+    /// Command precompiling the header, for GCC and Clang. Warnings are
+    /// off: a header compiled on its own always trips `#pragma once`.
+    pub fn pch_command(&self, tc: Compiler) -> Option<Vec<String>> {
+        let out = self.pch_output(tc)?;
+        let mut c = vec![program(tc).to_string()];
+        c.extend(self.code_flags(tc));
+        c.extend(["-w", "-x", "c++-header"].map(String::from));
+        c.push(self.pch.clone()?);
+        c.push("-o".into());
+        c.push(out);
+        Some(c)
+    }
+
+    pub fn compile_command(&self, tc: Compiler, source: &str) -> Vec<String> {
+        let mut c = vec![program(tc).to_string()];
+        c.extend(self.compile_flags(tc));
+        let obj = self.object(tc, source);
+        let dep = self.depfile(tc, source);
+        match tc {
+            Compiler::Msvc => {
+                if let (Some(pch), Some(unit)) = (&self.pch, self.msvc_pch_unit()) {
+                    let mode = if unit == source { "/Yc" } else { "/Yu" };
+                    c.push(format!("{}{}", mode, file_name(pch)));
+                    c.push(format!("/Fpbuild/obj/{}.pch", stem(pch)));
+                }
+                c.push("/sourceDependencies".into());
+                c.push(dep);
+                c.extend(self.unit_flags(tc, source));
+                c.push("/c".into());
+                c.push(source.to_string());
+                c.push(format!("/Fo{}", obj));
+            }
+            Compiler::Gcc | Compiler::Clang => {
+                c.extend(["-MMD", "-MF"].map(String::from));
+                c.push(dep);
+                c.extend(self.unit_flags(tc, source));
+                c.push("-c".into());
+                c.push(source.to_string());
+                c.push("-o".into());
+                c.push(obj);
+            }
+        }
+        c
+    }
+
+    pub fn link_command(&self, tc: Compiler, target: &Target) -> Vec<String> {
+        let objects = target.sources.iter().map(|s| self.object(tc, s));
+        let out = self.output(tc, target);
+        let mut c = vec![program(tc).to_string()];
+        match tc {
+            Compiler::Msvc => {
+                c.push("/nologo".into());
+                if self.sanitize == Some(Sanitizer::Address) {
+                    c.push("/fsanitize=address".into());
+                }
+                if target.kind == TargetKind::SharedLibrary {
+                    c.push("/LD".into());
+                }
+                c.extend(objects);
+                c.push(format!("/Fe{}", out));
+                c.extend(self.link_libs.iter().map(|l| format!("{}.lib", l)));
+            }
+            Compiler::Gcc | Compiler::Clang => {
+                c.extend(self.code_flags(tc));
+                if target.kind == TargetKind::SharedLibrary {
+                    c.push("-shared".into());
+                }
+                c.extend(objects);
+                c.push("-o".into());
+                c.push(out);
+                c.extend(self.link_libs.iter().map(|l| format!("-l{}", l)));
+            }
+        }
+        c
+    }
+
+    /// The `compile_commands.json` entries for `dir`, one per source with
+    /// the exact arguments [`BuildPlan::execute`] uses.
+    pub fn compile_database(&self, dir: &Path, tc: Compiler) -> Value {
+        let directory = dir.display().to_string();
+        let entries: Vec<Value> = self
+            .sources
+            .iter()
+            .map(|src| {
+                let args: Vec<Value> = self
+                    .compile_command(tc, src)
+                    .into_iter()
+                    .map(Value::from)
+                    .collect();
+                obj(vec![
+                    ("directory", directory.as_str().into()),
+                    ("file", src.as_str().into()),
+                    ("arguments", args.into()),
+                    ("output", self.object(tc, src).into()),
+                ])
+            })
+            .collect();
+        entries.into()
+    }
+
+    /// Identifies the flags objects were built with; a change rebuilds all.
+    fn stamp(&self, tc: Compiler) -> String {
+        format!("{} {}\n", program(tc), self.compile_flags(tc).join(" "))
+    }
+
+    /// Compiles and links in `dir`. A unit is only recompiled when its
+    /// object is older than the source or a header it included last time,
+    /// or the flags changed; a target is only relinked when one of its
+    /// objects was rebuilt. Compiles run on up to `jobs` threads. `env` is
+    /// added to the environment of every command (the MSVC variables).
+    pub fn execute(
+        &self,
+        dir: &Path,
+        tc: Compiler,
+        env: &[(String, String)],
+        jobs: usize,
+    ) -> Result<(), BuildError> {
+        for d in ["build/obj/pch", "build/bin"] {
+            let _ = fs::create_dir_all(dir.join(d));
+        }
+        let run = |cmd: &[String]| -> Result<(), BuildError> {
+            let out = Command::new(&cmd[0])
+                .args(&cmd[1..])
+                .current_dir(dir)
+                .envs(env.iter().map(|(k, v)| (k, v)))
+                .output()
+                .map_err(|_| BuildError {
+                    program: cmd[0].clone(),
+                    output: None,
+                })?;
+            if out.status.success() {
+                Ok(())
+            } else {
+                Err(BuildError {
+                    program: cmd[0].clone(),
+                    output: Some(out),
+                })
+            }
+        };
+        let mtime = |p: &str| fs::metadata(dir.join(p)).and_then(|m| m.modified()).ok();
+
+        let stamp_path = dir.join("build/obj/flags.txt");
+        let stamp = self.stamp(tc);
+        let mut all_stale = fs::read_to_string(&stamp_path).ok().as_deref() != Some(&stamp);
+        if all_stale {
+            let _ = fs::remove_file(&stamp_path);
+        }
+        if let (Some(cmd), Some(out), Some(header)) =
+            (self.pch_command(tc), self.pch_output(tc), &self.pch)
+        {
+            if all_stale || !newer_than(mtime(&out), &[mtime(header)]) {
+                run(&cmd)?;
+                all_stale = true;
+            }
+        }
+
+        // The MSVC unit creating the precompiled header goes first, and
+        // rebuilding it rebuilds the units using it.
+        if let Some(unit) = self.msvc_pch_unit().filter(|_| tc == Compiler::Msvc) {
+            if all_stale || !self.up_to_date(dir, tc, unit, &mtime) {
+                run(&self.compile_command(tc, unit))?;
+                all_stale = true;
+            }
+        }
+        // Module interfaces are compiled one at a time, in order; rebuilding
+        // one rebuilds everything after it, which may import it.
+        for unit in self.module_units() {
+            if all_stale || !self.up_to_date(dir, tc, unit, &mtime) {
+                run(&self.compile_command(tc, unit))?;
+                all_stale = true;
+            }
+        }
+        let rest: Vec<&str> = self
+            .sources
+            .iter()
+            .map(String::as_str)
+            .filter(|s| !is_module_unit(s))
+            .filter(|s| tc != Compiler::Msvc || self.msvc_pch_unit() != Some(*s))
+            .filter(|s| all_stale || !self.up_to_date(dir, tc, s, &mtime))
+            .collect();
+        let next = AtomicUsize::new(0);
+        let failure: Mutex<Option<BuildError>> = Mutex::new(None);
+        std::thread::scope(|scope| {
+            for _ in 0..jobs.clamp(1, rest.len().max(1)) {
+                scope.spawn(|| loop {
+                    let i = next.fetch_add(1, Ordering::SeqCst);
+                    if i >= rest.len() || failure.lock().unwrap().is_some() {
+                        break;
+                    }
+                    if let Err(e) = run(&self.compile_command(tc, rest[i])) {
+                        failure.lock().unwrap().get_or_insert(e);
+                    }
+                });
+            }
+        });
+        if let Some(e) = failure.into_inner().unwrap() {
+            return Err(e);
+        }
+
+        for t in &self.targets {
+            let out = self.output(tc, t);
+            let objects: Vec<Option<SystemTime>> = t
+                .sources
+                .iter()
+                .map(|s| mtime(&self.object(tc, s)))
+                .collect();
+            let list = dir.join(format!("build/obj/{}.link", file_name(&out)));
+            let cmd = self.link_command(tc, t);
+            let same_list = fs::read_to_string(&list).ok() == Some(cmd.join(" "));
+            if !same_list || !newer_than(mtime(&out), &objects) {
+                run(&cmd)?;
+                let _ = fs::write(&list, cmd.join(" "));
+            }
+        }
+        if fs::read_to_string(&stamp_path).ok().as_deref() != Some(&stamp) {
+            let _ = fs::write(&stamp_path, &stamp);
+        }
+        Ok(())
+    }
+
+    fn up_to_date(
+        &self,
+        dir: &Path,
+        tc: Compiler,
+        source: &str,
+        mtime: &dyn Fn(&str) -> Option<SystemTime>,
+    ) -> bool {
+        let Ok(dep) = fs::read_to_string(dir.join(self.depfile(tc, source))) else {
+            return false;
+        };
+        let mut inputs: Vec<Option<SystemTime>> = vec![mtime(source)];
+        inputs.extend(dependencies(tc, &dep).iter().map(|d| mtime(d)));
+        newer_than(mtime(&self.object(tc, source)), &inputs)
+    }
+
+    /// A bash script building the plan with GCC or Clang, recompiling only
+    /// stale objects like [`BuildPlan::execute`] (but one at a time).
+    pub fn render_sh(&self, tc: Compiler) -> String {
+        let mut s = String::new();
+        s.push_str("#!/usr/bin/env bash\n");
+        s.push_str("set -e\n");
+        s.push_str("cd \"$(dirname \"$0\")\"\n");
+        s.push_str("mkdir -p build/obj/pch build/bin\n");
+        s.push_str(&format!("STAMP={}\n", sh_quote(self.stamp(tc).trim_end())));
+        s.push_str("ALL=0\n");
+        s.push_str("if [ \"$(cat build/obj/flags.txt 2>/dev/null)\" != \"$STAMP\" ]; then\n");
+        s.push_str("  ALL=1\n");
+        s.push_str("fi\n");
+        s.push_str("# stale OBJECT DEPFILE SOURCE\n");
+        s.push_str("stale() {\n");
+        s.push_str("  [ \"$ALL\" = 1 ] || [ ! -f \"$1\" ] || [ ! -f \"$2\" ] || [ \"$3\" -nt \"$1\" ] ||\n");
+        s.push_str("    [ -n \"$(find $(sed -e 's/^[^:]*://' -e 's/\\\\$//' \"$2\") -newer \"$1\" 2>/dev/null)\" ]\n");
+        s.push_str("}\n");
+        if let (Some(cmd), Some(out), Some(header)) =
+            (self.pch_command(tc), self.pch_output(tc), &self.pch)
+        {
+            s.push_str(&format!(
+                "if [ \"$ALL\" = 1 ] || [ ! -f {} ] || [ {} -nt {} ]; then\n",
+                out, header, out
+            ));
+            s.push_str(&format!("  {}\n", sh_command(&cmd)));
+            s.push_str("  ALL=1\n");
+            s.push_str("fi\n");
+        }
+        s.push_str("RELINK=0\n");
+        for src in &self.sources {
+            s.push_str(&format!(
+                "if stale {} {} {}; then\n",
+                self.object(tc, src),
+                self.depfile(tc, src),
+                src
+            ));
+            s.push_str(&format!(
+                "  {}\n",
+                sh_command(&self.compile_command(tc, src))
+            ));
+            if is_module_unit(src) {
+                s.push_str("  ALL=1\n");
+            }
+            s.push_str("  RELINK=1\n");
+            s.push_str("fi\n");
+        }
+        for t in &self.targets {
+            s.push_str(&format!(
+                "if [ \"$RELINK\" = 1 ] || [ ! -f {} ]; then\n",
+                self.output(tc, t)
+            ));
+            s.push_str(&format!("  {}\n", sh_command(&self.link_command(tc, t))));
+            s.push_str("fi\n");
+        }
+        s.push_str("echo \"$STAMP\" > build/obj/flags.txt\n");
+        if self.release {
+            s.push_str("mkdir -p build/release\n");
+            s.push_str(&format!(
+                "tar -czf build/release/{}-linux.tar.gz include build/bin README.md\n",
+                self.name
+            ));
+        }
+        s
+    }
+
+    /// A batch file building the plan with MSVC, after loading the
+    /// developer environment from `vsdevcmd` when `cl.exe` is not on PATH.
+    pub fn render_bat(&self, vsdevcmd: Option<&Path>) -> String {
+        let tc = Compiler::Msvc;
+        let mut s = String::new();
+        s.push_str("@echo off\n");
+        s.push_str("setlocal\n");
+        s.push_str("cd /d \"%~dp0\"\n");
+        let dev = vsdevcmd.map_or("VsDevCmd.bat".to_string(), |p| p.display().to_string());
+        s.push_str(&format!(
+            "where cl.exe >nul 2>nul || call \"{}\" >nul\n",
+            dev
+        ));
+        s.push_str("if not exist build\\obj mkdir build\\obj\n");
+        s.push_str("if not exist build\\bin mkdir build\\bin\n");
+        let mut sources: Vec<&String> = self.sources.iter().collect();
+        sources.sort_by_key(|s| Some(s.as_str()) != self.msvc_pch_unit());
+        for src in sources {
+            s.push_str(&format!(
+                "{} || exit /b 1\n",
+                bat_command(&self.compile_command(tc, src))
+            ));
+        }
+        for t in &self.targets {
+            s.push_str(&format!(
+                "{} || exit /b 1\n",
+                bat_command(&self.link_command(tc, t))
+            ));
+        }
+        if self.release {
+            s.push_str("if not exist build\\release mkdir build\\release\n");
+            s.push_str(&format!("powershell -Command Compress-Archive -Force -Path include\\*,build\\bin\\*,README.md -DestinationPath \"build\\release\\{}-windows.zip\"\n", self.name));
+        }
+        s
+    }
+
+    /// A Ninja file with one edge per object, using the compiler's
+    /// dependency output, and one per target. The default targets use the
+    /// plan's flags; `asan`, `ubsan`, `tsan` and `coverage` build the same
+    /// targets with that variant under `build/<variant>/`.
+    pub fn render_ninja(&self, tc: Compiler) -> String {
+        let mut s = String::new();
+        s.push_str("# Generated by ultracpp.\n");
+        s.push_str("ninja_required_version = 1.3\n\n");
+        s.push_str(&format!("cxx = {}\n", program(tc)));
+        s.push_str(&format!(
+            "cflags = {}\n",
+            ninja_join(tc, &self.compile_flags(tc))
+        ));
+        if let Some(unit) = self.module_units().next() {
+            s.push_str(&format!(
+                "unitflags = {}\n",
+                ninja_join(tc, &self.unit_flags(tc, unit))
+            ));
+        }
+        if let Some(cmd) = self.pch_command(tc) {
+            s.push_str(&format!(
+                "pchcflags = {}\n",
+                ninja_join(tc, &cmd[1..cmd.len() - 3])
+            ));
+        }
+        match tc {
+            Compiler::Msvc => {
+                s.push_str("msvc_deps_prefix = Note: including file:\n\n");
+                s.push_str("rule cxx\n");
+                s.push_str("  command = $cxx $cflags $pchflags /showIncludes /c $in /Fo$out\n");
+                s.push_str("  deps = msvc\n");
+            }
+            Compiler::Gcc | Compiler::Clang => {
+                s.push('\n');
+                s.push_str("rule cxx\n");
+                s.push_str("  command = $cxx $cflags -MMD -MF $out.d -c $in -o $out\n");
+                s.push_str("  deps = gcc\n");
+                s.push_str("  depfile = $out.d\n");
+            }
+        }
+        s.push_str("  description = CXX $in\n\n");
+        if self.uses_modules() {
+            s.push_str("rule cxxmodule\n");
+            match tc {
+                Compiler::Msvc => {
+                    s.push_str(
+                        "  command = $cxx $cflags /showIncludes $unitflags /c $in /Fo$out\n",
+                    );
+                    s.push_str("  deps = msvc\n");
+                }
+                Compiler::Gcc | Compiler::Clang => {
+                    s.push_str(
+                        "  command = $cxx $cflags -MMD -MF $out.d $unitflags -c $in -o $out\n",
+                    );
+                    s.push_str("  deps = gcc\n");
+                    s.push_str("  depfile = $out.d\n");
+                }
+            }
+            s.push_str("  description = CXX $in\n\n");
+        }
+        s.push_str("rule link\n");
+        s.push_str("  command = $cxx $args\n");
+        s.push_str("  description = LINK $out\n\n");
+        if self.pch_output(tc).is_some() {
+            s.push_str("rule pch\n");
+            s.push_str("  command = $cxx $pchcflags $in -o $out\n");
+            s.push_str("  description = PCH $in\n\n");
+        }
+        let (edges, outputs) = self.ninja_edges(tc, false);
+        s.push_str(&edges);
+        for (name, variant) in self.variants(tc) {
+            s.push_str(&format!("\n# {}\n", name));
+            if (variant.sanitize, variant.coverage) == (self.sanitize, self.coverage) {
+                s.push_str(&format!("build {}: phony {}\n", name, outputs.join(" ")));
+                continue;
+            }
+            // The variant's paths, flags included, move under its own
+            // directory so its objects never mix with the default ones.
+            let relocate = |text: &str| {
+                text.replace("build/obj", &format!("build/{}/obj", name))
+                    .replace("build/bin", &format!("build/{}/bin", name))
+            };
+            let (edges, outputs) = variant.ninja_edges(tc, true);
+            s.push_str(&relocate(&edges));
+            s.push_str(&format!(
+                "build {}: phony {}\n",
+                name,
+                relocate(&outputs.join(" "))
+            ));
+        }
+        s.push_str(&format!("\ndefault {}\n", outputs.join(" ")));
+        s
+    }
+
+    /// The sanitizer and coverage builds offered by `build.ninja`, named
+    /// like `--sanitize`. MSVC only has AddressSanitizer.
+    fn variants(&self, tc: Compiler) -> Vec<(&'static str, BuildPlan)> {
+        let sanitizers: &[Sanitizer] = match tc {
+            Compiler::Msvc => &[Sanitizer::Address],
+            Compiler::Gcc | Compiler::Clang => {
+                &[Sanitizer::Address, Sanitizer::Undefined, Sanitizer::Thread]
+            }
+        };
+        let mut variants: Vec<(&'static str, BuildPlan)> = sanitizers
+            .iter()
+            .map(|&san| {
+                let plan = BuildPlan {
+                    sanitize: Some(san),
+                    coverage: false,
+                    ..self.clone()
+                };
+                (san.as_str(), plan)
+            })
+            .collect();
+        if tc != Compiler::Msvc {
+            let plan = BuildPlan {
+                sanitize: None,
+                coverage: true,
+                ..self.clone()
+            };
+            variants.push(("coverage", plan));
+        }
+        variants
+    }
+
+    /// The Ninja edges of the precompiled header, the objects and the
+    /// targets, with the targets' paths. `bind` sets the flags on each
+    /// edge instead of relying on the file's top-level variables.
+    fn ninja_edges(&self, tc: Compiler, bind: bool) -> (String, Vec<String>) {
+        let mut s = String::new();
+        let cflags = format!("  cflags = {}\n", ninja_join(tc, &self.compile_flags(tc)));
+        let mut implicit = String::new();
+        if let (Some(cmd), Some(out), Some(header)) =
+            (self.pch_command(tc), self.pch_output(tc), &self.pch)
+        {
+            s.push_str(&format!(
+                "build {}: pch {}\n",
+                ninja_escape(&out),
+                ninja_escape(header)
+            ));
+            if bind {
+                s.push_str(&format!(
+                    "  pchcflags = {}\n",
+                    ninja_join(tc, &cmd[1..cmd.len() - 3])
+                ));
+            }
+            implicit = format!(" | {}", ninja_escape(&out));
+        }
+        let msvc_unit = self.msvc_pch_unit().filter(|_| tc == Compiler::Msvc);
+        let mut last_module: Option<String> = None;
+        for src in &self.sources {
+            // Units using an MSVC precompiled header wait for the one
+            // creating it.
+            if let Some(unit) = msvc_unit.filter(|u| u != src) {
+                implicit = format!(" | {}", ninja_escape(&self.object(tc, unit)));
+            }
+            let mut deps = if msvc_unit == Some(src) {
+                String::new()
+            } else {
+                implicit.clone()
+            };
+            // Each unit waits for the modules before it, and is rebuilt
+            // with them.
+            if let Some(m) = &last_module {
+                deps = if deps.is_empty() {
+                    format!(" | {}", m)
+                } else {
+                    format!("{} {}", deps, m)
+                };
+            }
+            let rule = if is_module_unit(src) {
+                "cxxmodule"
+            } else {
+                "cxx"
+            };
+            s.push_str(&format!(
+                "build {}: {} {}{}\n",
+                ninja_escape(&self.object(tc, src)),
+                rule,
+                ninja_escape(src),
+                deps
+            ));
+            if bind {
+                s.push_str(&cflags);
+                if is_module_unit(src) {
+                    s.push_str(&format!(
+                        "  unitflags = {}\n",
+                        ninja_join(tc, &self.unit_flags(tc, src))
+                    ));
+                }
+            }
+            if is_module_unit(src) {
+                last_module = Some(ninja_escape(&self.object(tc, src)));
+            }
+            if let Some(unit) = msvc_unit {
+                if let Some(pch) = &self.pch {
+                    let mode = if unit == src { "/Yc" } else { "/Yu" };
+                    s.push_str(&format!(
+                        "  pchflags = {}{} /Fpbuild/obj/{}.pch\n",
+                        mode,
+                        file_name(pch),
+                        stem(pch)
+                    ));
+                }
+            }
+        }
+        let mut outputs = Vec::new();
+        for t in &self.targets {
+            let out = ninja_escape(&self.output(tc, t));
+            let objects: Vec<String> = t
+                .sources
+                .iter()
+                .map(|src| ninja_escape(&self.object(tc, src)))
+                .collect();
+            s.push_str(&format!("build {}: link {}\n", out, objects.join(" ")));
+            s.push_str(&format!(
+                "  args = {}\n",
+                ninja_join(tc, &self.link_command(tc, t)[1..])
+            ));
+            outputs.push(out);
+        }
+        (s, outputs)
+    }
+
+    /// A GNU Makefile for GCC or Clang with one rule per object, reading
+    /// the `-MMD` depfiles. `SANITIZE` (`address`, `undefined` or `thread`)
+    /// and `COVERAGE=1` pick a variant, defaulting to the plan's. Objects
+    /// depend on the Makefile and on a stamp of the variant, so
+    /// regenerating it with other flags or switching variants rebuilds
+    /// them.
+    pub fn render_make(&self, tc: Compiler) -> String {
+        // The variables add the sanitizer and coverage flags to a plan
+        // without them.
+        let base = BuildPlan {
+            sanitize: None,
+            coverage: false,
+            ..self.clone()
+        };
+        let sanitize = match self.sanitize {
+            Some(Sanitizer::Address) => "address",
+            Some(Sanitizer::Undefined) => "undefined",
+            Some(Sanitizer::Thread) => "thread",
+            None => "",
+        };
+        let mut s = String::new();
+        s.push_str("# Generated by ultracpp.\n");
+        s.push_str(&format!("CXX = {}\n", program(tc)));
+        s.push_str(&format!("SANITIZE = {}\n", sanitize));
+        s.push_str(&format!(
+            "COVERAGE = {}\n",
+            if self.coverage { "1" } else { "" }
+        ));
+        s.push_str("VARIANT_FLAGS = $(strip $(if $(SANITIZE),-fsanitize=$(SANITIZE)) $(if $(COVERAGE),-fprofile-arcs -ftest-coverage))\n");
+        s.push_str(&format!(
+            "CXXFLAGS = {} $(VARIANT_FLAGS)\n",
+            make_join(&base.compile_flags(tc))
+        ));
+        // Rewritten only when the variant changes, like build.sh's stamp.
+        s.push_str("$(shell mkdir -p build/obj; echo '$(VARIANT_FLAGS)' | cmp -s - build/obj/variant.txt || echo '$(VARIANT_FLAGS)' > build/obj/variant.txt)\n");
+        let outputs: Vec<String> = self
+            .targets
+            .iter()
+            .map(|t| make_escape(&self.output(tc, t)))
+            .collect();
+        s.push_str(&format!("\nall: {}\n", outputs.join(" ")));
+        let mut prereqs = "build/obj/variant.txt Makefile".to_string();
+        if let (Some(cmd), Some(out), Some(header)) =
+            (base.pch_command(tc), self.pch_output(tc), &self.pch)
+        {
+            s.push_str(&format!(
+                "\n{}: {} {}\n",
+                make_escape(&out),
+                make_escape(header),
+                prereqs
+            ));
+            s.push_str("\t@mkdir -p $(@D)\n");
+            s.push_str(&format!(
+                "\t$(CXX) {} $(VARIANT_FLAGS) $< -o $@\n",
+                make_join(&cmd[1..cmd.len() - 3])
+            ));
+            prereqs = format!("{} {}", make_escape(&out), prereqs);
+        }
+        let mut last_module: Option<String> = None;
+        for src in &self.sources {
+            // Each unit waits for the modules before it, and is rebuilt
+            // with them.
+            let after = last_module
+                .as_ref()
+                .map_or(String::new(), |m| format!(" {}", m));
+            s.push_str(&format!(
+                "\n{}: {} {}{}\n",
+                make_escape(&self.object(tc, src)),
+                make_escape(src),
+                prereqs,
+                after
+            ));
+            s.push_str("\t@mkdir -p $(@D)\n");
+            let unit = self.unit_flags(tc, src);
+            if unit.is_empty() {
+                s.push_str("\t$(CXX) $(CXXFLAGS) -MMD -MP -MF $@.d -c $< -o $@\n");
+            } else {
+                s.push_str(&format!(
+                    "\t$(CXX) $(CXXFLAGS) -MMD -MP -MF $@.d {} -c $< -o $@\n",
+                    make_join(&unit)
+                ));
+                last_module = Some(make_escape(&self.object(tc, src)));
+            }
+        }
+        for (t, out) in self.targets.iter().zip(&outputs) {
+            let objects: Vec<String> = t
+                .sources
+                .iter()
+                .map(|src| make_escape(&self.object(tc, src)))
+                .collect();
+            s.push_str(&format!("\n{}: {}\n", out, objects.join(" ")));
+            s.push_str("\t@mkdir -p $(@D)\n");
+            s.push_str(&format!(
+                "\t$(CXX) $(VARIANT_FLAGS) {}\n",
+                make_join(&base.link_command(tc, t)[1..])
+            ));
+        }
+        s.push_str("\nclean:\n");
+        s.push_str("\trm -rf build/obj build/bin\n");
+        s.push_str("\n.PHONY: all clean\n\n");
+        let depfiles: Vec<String> = self
+            .sources
+            .iter()
+            .map(|src| make_escape(&self.depfile(tc, src)))
+            .collect();
+        s.push_str(&format!("-include {}\n", depfiles.join(" ")));
+        s
+    }
+
+    /// CMake target name of `target`; the executable keeps the project
+    /// name and a shared library gets a `_lib` suffix.
+    fn cmake_target(&self, target: &Target) -> String {
+        match target.kind {
+            TargetKind::Executable => target.name.clone(),
+            TargetKind::SharedLibrary => format!("{}_lib", target.name),
+        }
+    }
+
+    /// A `CMakeLists.txt` building the plan's targets with any generator,
+    /// with `option()`s for the sanitizers and coverage (defaulting to the
+    /// plan) and install/export rules for `find_package(<name>)`.
+    pub fn render_cmake(&self) -> String {
+        let name = &self.name;
+        let prefix: String = name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_uppercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        let on = |b: bool| if b { "ON" } else { "OFF" };
+        let mut s = String::new();
+        // C++20 module file sets need CMake 3.28.
+        let version = if self.uses_modules() { "3.28" } else { "3.16" };
+        s.push_str(&format!("cmake_minimum_required(VERSION {})\n", version));
+        s.push_str(&format!("project({} LANGUAGES CXX)\n\n", name));
+        s.push_str("include(GNUInstallDirs)\n\n");
+        let std = match self.std {
+            CppStd::Cpp17 => "17",
+            CppStd::Cpp20 => "20",
+            CppStd::Cpp23 => "23",
+        };
+        s.push_str(&format!("set(CMAKE_CXX_STANDARD {})\n", std));
+        s.push_str("set(CMAKE_CXX_STANDARD_REQUIRED ON)\n");
+        s.push_str("set(CMAKE_CXX_EXTENSIONS OFF)\n");
+        s.push_str("set(CMAKE_CXX_VISIBILITY_PRESET hidden)\n");
+        s.push_str("set(CMAKE_VISIBILITY_INLINES_HIDDEN ON)\n");
+        s.push_str("if(NOT CMAKE_BUILD_TYPE AND NOT CMAKE_CONFIGURATION_TYPES)\n");
+        s.push_str(&format!(
+            "  set(CMAKE_BUILD_TYPE {} CACHE STRING \"Build type\" FORCE)\n",
+            if self.release { "Release" } else { "Debug" }
+        ));
+        s.push_str("endif()\n\n");
+        for (opt, san, doc) in [
+            ("SANITIZE_ADDRESS", Sanitizer::Address, "AddressSanitizer"),
+            (
+                "SANITIZE_UNDEFINED",
+                Sanitizer::Undefined,
+                "UndefinedBehaviorSanitizer",
+            ),
+            ("SANITIZE_THREAD", Sanitizer::Thread, "ThreadSanitizer"),
+        ] {
+            s.push_str(&format!(
+                "option({}_{} \"Build with {}\" {})\n",
+                prefix,
+                opt,
+                doc,
+                on(self.sanitize == Some(san))
+            ));
+        }
+        s.push_str(&format!(
+            "option({}_COVERAGE \"Build with coverage instrumentation\" {})\n\n",
+            prefix,
+            on(self.coverage)
+        ));
+
+        s.push_str(&format!("function({}_configure target)\n", name));
+        s.push_str("  target_include_directories(${target} PUBLIC\n");
+        s.push_str("    $<BUILD_INTERFACE:${CMAKE_CURRENT_SOURCE_DIR}/include>\n");
+        s.push_str(&format!(
+            "    $<INSTALL_INTERFACE:${{CMAKE_INSTALL_INCLUDEDIR}}/{}>)\n",
+            name
+        ));
+        let extra: Vec<&String> = self
+            .include_dirs
+            .iter()
+            .filter(|d| *d != "include")
+            .collect();
+        if !extra.is_empty() {
+            s.push_str("  target_include_directories(${target} PRIVATE");
+            for d in extra {
+                s.push_str(&format!(" {}", cmake_quote(d)));
+            }
+            s.push_str(")\n");
+        }
+        if !self.defines.is_empty() {
+            s.push_str("  target_compile_definitions(${target} PRIVATE");
+            for d in &self.defines {
+                s.push_str(&format!(" {}", cmake_quote(d)));
+            }
+            s.push_str(")\n");
+        }
+        s.push_str("  if(MSVC)\n");
+        s.push_str(
+            "    target_compile_options(${target} PRIVATE /EHsc /W4 /WX /permissive- /utf-8)\n",
+        );
+        s.push_str("  else()\n");
+        s.push_str("    target_compile_options(${target} PRIVATE -Wall -Wextra -Werror)\n");
+        s.push_str("  endif()\n");
+        if !self.flags.is_empty() {
+            s.push_str("  target_compile_options(${target} PRIVATE");
+            for f in &self.flags {
+                s.push_str(&format!(" {}", cmake_quote(f)));
+            }
+            s.push_str(")\n");
+        }
+        if let Some(pch) = &self.pch {
+            s.push_str(&format!(
+                "  target_precompile_headers(${{target}} PRIVATE {})\n",
+                cmake_quote(pch)
+            ));
+        }
+        s.push_str(&format!("  if({}_SANITIZE_ADDRESS)\n", prefix));
+        s.push_str("    if(MSVC)\n");
+        s.push_str("      target_compile_options(${target} PRIVATE /fsanitize=address)\n");
+        s.push_str("    else()\n");
+        s.push_str("      target_compile_options(${target} PRIVATE -fsanitize=address)\n");
+        s.push_str("      target_link_options(${target} PRIVATE -fsanitize=address)\n");
+        s.push_str("    endif()\n");
+        s.push_str("  endif()\n");
+        for (opt, flag) in [
+            ("SANITIZE_UNDEFINED", "-fsanitize=undefined"),
+            ("SANITIZE_THREAD", "-fsanitize=thread"),
+            ("COVERAGE", "--coverage"),
+        ] {
+            s.push_str(&format!("  if({}_{} AND NOT MSVC)\n", prefix, opt));
+            s.push_str(&format!(
+                "    target_compile_options(${{target}} PRIVATE {})\n",
+                flag
+            ));
+            s.push_str(&format!(
+                "    target_link_options(${{target}} PRIVATE {})\n",
+                flag
+            ));
+            s.push_str("  endif()\n");
+        }
+        if !self.link_libs.is_empty() {
+            s.push_str("  target_link_libraries(${target} PRIVATE");
+            for l in &self.link_libs {
+                s.push_str(&format!(" {}", cmake_quote(l)));
+            }
+            s.push_str(")\n");
+        }
+        s.push_str("endfunction()\n");
+
+        let mut installed = Vec::new();
+        for t in &self.targets {
+            let target = self.cmake_target(t);
+            let sources: Vec<String> = t
+                .sources
+                .iter()
+                .filter(|src| !is_module_unit(src))
+                .map(|src| cmake_quote(src))
+                .collect();
+            let modules: Vec<String> = t
+                .sources
+                .iter()
+                .filter(|src| is_module_unit(src))
+                .map(|src| cmake_quote(src))
+                .collect();
+            s.push('\n');
+            match t.kind {
+                TargetKind::Executable => {
+                    s.push_str(&format!("add_executable({}\n", target));
+                }
+                TargetKind::SharedLibrary => {
+                    s.push_str(&format!("add_library({} SHARED\n", target));
+                }
+            }
+            s.push_str(&format!("  {})\n", sources.join("\n  ")));
+            if !modules.is_empty() {
+                s.push_str(&format!(
+                    "target_sources({} PRIVATE FILE_SET CXX_MODULES FILES\n  {})\n",
+                    target,
+                    modules.join("\n  ")
+                ));
+            }
+            s.push_str(&format!("{}_configure({})\n", name, target));
+            if t.kind == TargetKind::SharedLibrary {
+                s.push_str(&format!(
+                    "set_target_properties({} PROPERTIES OUTPUT_NAME {} EXPORT_NAME {})\n",
+                    target, t.name, t.name
+                ));
+                // Consumers of the DLL import its symbols.
+                if self.defines.iter().any(|d| d == "UCPP_DLL") {
+                    s.push_str(&format!(
+                        "target_compile_definitions({} INTERFACE UCPP_DLL)\n",
+                        target
+                    ));
+                }
+                s.push_str(&format!(
+                    "add_library({}::{} ALIAS {})\n",
+                    name, t.name, target
+                ));
+            } else {
+                s.push_str(&format!(
+                    "set_target_properties({} PROPERTIES EXPORT_NAME {}_exe)\n",
+                    target, t.name
+                ));
+            }
+            installed.push(target);
+        }
+
+        s.push('\n');
+        if !installed.is_empty() {
+            s.push_str(&format!(
+                "install(TARGETS {} EXPORT {}Targets\n",
+                installed.join(" "),
+                name
+            ));
+            s.push_str("  RUNTIME DESTINATION ${CMAKE_INSTALL_BINDIR}\n");
+            s.push_str("  LIBRARY DESTINATION ${CMAKE_INSTALL_LIBDIR}\n");
+            s.push_str("  ARCHIVE DESTINATION ${CMAKE_INSTALL_LIBDIR})\n");
+        }
+        s.push_str(&format!(
+            "install(DIRECTORY include/ DESTINATION ${{CMAKE_INSTALL_INCLUDEDIR}}/{})\n",
+            name
+        ));
+        if !installed.is_empty() {
+            s.push_str(&format!(
+                "install(EXPORT {0}Targets NAMESPACE {0}:: DESTINATION ${{CMAKE_INSTALL_LIBDIR}}/cmake/{0})\n",
+                name
+            ));
+            s.push_str(&format!(
+                "export(EXPORT {0}Targets NAMESPACE {0}:: FILE ${{CMAKE_CURRENT_BINARY_DIR}}/{0}Targets.cmake)\n",
+                name
+            ));
+        }
+        s.push_str(&format!(
+            "configure_file({0}Config.cmake ${{CMAKE_CURRENT_BINARY_DIR}}/{0}Config.cmake COPYONLY)\n",
+            name
+        ));
+        s.push_str(&format!(
+            "install(FILES {0}Config.cmake DESTINATION ${{CMAKE_INSTALL_LIBDIR}}/cmake/{0})\n",
+            name
+        ));
+        s
+    }
+
+    /// The `<name>Config.cmake` read by `find_package(<name>)`, from the
+    /// install tree or the build tree.
+    pub fn render_cmake_config(&self) -> String {
+        let mut s = String::new();
+        s.push_str(&format!(
+            "# Package configuration for {}, generated by ultracpp.\n",
+            self.name
+        ));
+        if self.targets.is_empty() {
+            s.push_str(&format!("set({}_FOUND TRUE)\n", self.name));
+        } else {
+            s.push_str(&format!(
+                "include(\"${{CMAKE_CURRENT_LIST_DIR}}/{}Targets.cmake\")\n",
+                self.name
+            ));
+        }
+        s
+    }
+}
+
+/// Whether `output` exists and is at least as new as every input; a
+/// missing input counts as changed.
+fn newer_than(output: Option<SystemTime>, inputs: &[Option<SystemTime>]) -> bool {
+    match output {
+        None => false,
+        Some(out) => inputs.iter().all(|i| i.is_some_and(|t| t <= out)),
+    }
+}
+
+/// The headers listed in a `-MMD` depfile or an MSVC
+/// `/sourceDependencies` file.
+pub fn dependencies(tc: Compiler, text: &str) -> Vec<String> {
+    match tc {
+        Compiler::Msvc => Value::parse(text)
+            .ok()
+            .and_then(|v| {
+                Some(
+                    v.at(&["Data", "Includes"])?
+                        .as_array()?
+                        .iter()
+                        .filter_map(|i| i.as_str().map(String::from))
+                        .collect(),
+                )
+            })
+            .unwrap_or_default(),
+        Compiler::Gcc | Compiler::Clang => {
+            let joined = text.replace("\\\n", " ");
+            let Some((_, deps)) = joined.split_once(": ") else {
+                return Vec::new();
+            };
+            deps.split_whitespace().map(String::from).collect()
+        }
+    }
+}
+
+fn sh_quote(s: &str) -> String {
+    if !s.is_empty()
+        && s.chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./=+:,@%".contains(c))
+    {
+        s.to_string()
+    } else {
+        format!("'{}'", s.replace('\'', "'\\''"))
+    }
+}
+
+fn sh_command(cmd: &[String]) -> String {
+    cmd.iter()
+        .map(|a| sh_quote(a))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn bat_command(cmd: &[String]) -> String {
+    cmd.iter()
+        .map(|a| {
+            let a = a.replace('/', "\\");
+            // Keep the switches themselves (`/c`, `/Fo…`) as slashes.
+            let a = if a.starts_with('\\') && !a.starts_with("\\\\") {
+                format!("/{}", &a[1..])
+            } else {
+                a
+            };
+            if a.contains(' ') {
+                format!("\"{}\"", a)
+            } else {
+                a
+            }
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn ninja_escape(s: &str) -> String {
+    s.replace('$', "$$").replace(' ', "$ ").replace(':', "$:")
+}
+
+/// Ninja runs commands through `sh` on Unix but directly on Windows.
+fn ninja_join(tc: Compiler, args: &[String]) -> String {
+    args.iter()
+        .map(|a| {
+            let a = match tc {
+                Compiler::Msvc if a.contains(' ') => format!("\"{}\"", a),
+                Compiler::Msvc => a.clone(),
+                Compiler::Gcc | Compiler::Clang => sh_quote(a),
+            };
+            a.replace('$', "$$")
+        })
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+fn cmake_quote(s: &str) -> String {
+    if s.chars()
+        .any(|c| c.is_whitespace() || "\\\";()#$".contains(c))
+    {
+        let escaped = s
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+            .replace('$', "\\$");
+        format!("\"{}\"", escaped)
+    } else {
+        s.to_string()
+    }
+}
+
+fn make_escape(s: &str) -> String {
+    s.replace('$', "$$").replace(' ', "\\ ")
+}
+
+fn make_join(args: &[String]) -> String {
+    args.iter()
+        .map(|a| sh_quote(a).replace('$', "$$"))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
@@ -0,0 +1,79 @@
+//! Build cache: the hash of the inputs each generated file was produced
+//! from, so unchanged classes skip codegen on the next build.
+
+use std::fs;
+use std::path::Path;
+
+use crate::json::{obj, Value};
+
+pub const CACHE_NAME: &str = "hashes.json";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BuildCache {
+    /// `(generated file, input hash)`, the file relative to the project dir.
+    pub entries: Vec<(String, String)>,
+}
+
+/// Hash of everything a generated file depends on. `inputs` is usually the
+/// `Debug` text of the class AST and directives; the transpiler version is
+/// mixed in so an upgrade regenerates everything.
+pub fn input_hash(inputs: &str) -> String {
+    crate::content_hash(&format!("{}\n{}", env!("CARGO_PKG_VERSION"), inputs))
+}
+
+impl BuildCache {
+    /// Reads the cache at `path`; a missing or unreadable one is empty.
+    pub fn load(path: &Path) -> BuildCache {
+        let Some(v) = fs::read_to_string(path)
+            .ok()
+            .and_then(|s| Value::parse(&s).ok())
+        else {
+            return BuildCache::default();
+        };
+        let entries = v
+            .get("files")
+            .and_then(Value::as_array)
+            .unwrap_or(&[])
+            .iter()
+            .filter_map(|e| {
+                let file = e.get("file")?.as_str()?;
+                let hash = e.get("hash")?.as_str()?;
+                Some((file.to_string(), hash.to_string()))
+            })
+            .collect();
+        BuildCache { entries }
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let files = self
+            .entries
+            .iter()
+            .map(|(f, h)| {
+                obj(vec![
+                    ("file", f.as_str().into()),
+                    ("hash", h.as_str().into()),
+                ])
+            })
+            .collect::<Vec<Value>>();
+        let text = obj(vec![("version", 1usize.into()), ("files", files.into())]).to_string();
+        crate::write_if_changed(path, &text)
+            .map_err(|e| format!("cannot write {}: {}", path.display(), e))
+    }
+
+    /// Whether `file`, under `dir`, exists and was generated from `hash`.
+    pub fn is_fresh(&self, dir: &Path, file: &str, hash: &str) -> bool {
+        dir.join(file).is_file() && self.entries.iter().any(|(f, h)| f == file && h == hash)
+    }
+
+    pub fn record(&mut self, file: &str, hash: &str) {
+        match self.entries.iter_mut().find(|(f, _)| f == file) {
+            Some(e) => e.1 = hash.to_string(),
+            None => self.entries.push((file.to_string(), hash.to_string())),
+        }
+    }
+
+    /// Drops the entries for files that no longer exist under `dir`.
+    pub fn retain_existing(&mut self, dir: &Path) {
+        self.entries.retain(|(f, _)| dir.join(f).is_file());
+    }
+}
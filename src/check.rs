@@ -0,0 +1,394 @@
+//! Semantic analysis of a resolved project, shared by `build` and `check`.
+//!
+//! Nothing here touches the filesystem: diagnostics are located by scanning
+//! the sources already held by the [`ImportGraph`].
+
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use crate::imports::ImportGraph;
+use crate::{Class, Directives, Expr, FormatPart, Visibility};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub file: Option<PathBuf>,
+    /// 1-based line, when the location is known.
+    pub line: Option<usize>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn error(message: impl Into<String>) -> Diagnostic {
+        Diagnostic {
+            severity: Severity::Error,
+            file: None,
+            line: None,
+            message: message.into(),
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Shows `file` relative to `base` when it lies inside it.
+    pub fn relative_to(mut self, base: &Path) -> Diagnostic {
+        if let Some(f) = &self.file {
+            if let Ok(rel) = f.strip_prefix(base) {
+                self.file = Some(rel.to_path_buf());
+            }
+        }
+        self
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file.to_string_lossy().replace('\\', "/"))?;
+            if let Some(line) = self.line {
+                write!(f, "{}:", line)?;
+            }
+            write!(f, " ")?;
+        }
+        let level = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(f, "{}: {}", level, self.message)
+    }
+}
+
+pub fn is_builtin_ty(t: &str) -> bool {
+    matches!(
+        t.trim(),
+        "Int"
+            | "Float"
+            | "Bool"
+            | "String"
+            | "Void"
+            | "int"
+            | "float"
+            | "bool"
+            | "double"
+            | "Double"
+    )
+}
+
+/// A type error found in the body of `class::method`.
+struct TypeError {
+    class: String,
+    method: String,
+    message: String,
+}
+
+fn type_errors(classes: &[Class]) -> Vec<TypeError> {
+    let mut errors: Vec<TypeError> = Vec::new();
+    let mut class_map: HashMap<String, Class> = HashMap::new();
+    for c in classes {
+        class_map.insert(c.name.clone(), c.clone());
+    }
+    fn check_expr(
+        e: &Expr,
+        c: &Class,
+        method: &str,
+        classes: &HashMap<String, Class>,
+        errors: &mut Vec<TypeError>,
+    ) {
+        let mut push = |message: String| {
+            errors.push(TypeError {
+                class: c.name.clone(),
+                method: method.to_string(),
+                message,
+            })
+        };
+        match e {
+            Expr::SelfField(n) => {
+                let ok = c.fields.iter().any(|f| &f.name == n);
+                if !ok {
+                    push(format!(
+                        "Campo desconocido '{}' en {}::{}",
+                        n, c.name, method
+                    ));
+                }
+            }
+            Expr::SelfCall { name, .. } => {
+                let ok = c.methods.iter().any(|m| &m.name == name);
+                if !ok {
+                    push(format!(
+                        "Método desconocido '{}' en {}::{}",
+                        name, c.name, method
+                    ));
+                }
+            }
+            Expr::FileCall(name) => {
+                // call hola.upp -> método local hola_upp() o hola()
+                let target_method_upp = format!("{}_upp", name.replace('.', "_"));
+                let target_method_std = name.replace('.', "_");
+                let has_upp = c.methods.iter().any(|m| m.name == target_method_upp);
+                let has_std = c.methods.iter().any(|m| m.name == target_method_std);
+                if !has_upp && !has_std {
+                    push(format!(
+                        "Llamada 'call {}' no resuelta en clase {}. Define {}() o {}(). Ejemplo:\n  def {}():\n    print(\"...\")",
+                        name, c.name, target_method_upp, target_method_std, target_method_upp
+                    ));
+                }
+            }
+            Expr::SuperCall { name, .. } => {
+                if let Some(b) = &c.base {
+                    if let Some(base_cls) = classes.get(b) {
+                        let ok = base_cls.methods.iter().any(|m| &m.name == name);
+                        if !ok {
+                            push(format!(
+                                "Método '{}' no existe en base {} para {}::{}",
+                                name, b, c.name, method
+                            ));
+                        }
+                    }
+                }
+            }
+            Expr::FunctionCall { name, .. } if name.contains('.') => {
+                let parts: Vec<&str> = name.split('.').collect();
+                if parts.len() == 2 {
+                    let lhs = parts[0].to_string();
+                    let m = parts[1].to_string();
+                    // Interpret dotted calls as Class.Method only when LHS looks like a type (starts uppercase) and exists
+                    if lhs
+                        .chars()
+                        .next()
+                        .map(|ch| ch.is_uppercase())
+                        .unwrap_or(false)
+                    {
+                        if let Some(cc) = classes.get(&lhs) {
+                            let ok = cc.methods.iter().any(|mm| mm.name == m);
+                            if !ok {
+                                push(format!("Método '{}' no existe en clase {}", m, lhs));
+                            }
+                        } else {
+                            push(format!(
+                                "Clase '{}' no encontrada para llamada {}",
+                                lhs, name
+                            ));
+                        }
+                    }
+                }
+            }
+            // `Point(x=1, y=2)` names public fields, in declaration order
+            // as designated initialisers require.
+            Expr::FunctionCall { name, args } if classes.contains_key(name) => {
+                let named = args
+                    .iter()
+                    .any(|a| matches!(a, Expr::BinaryOp(_, op, _) if op == "="));
+                let target = &classes[name];
+                match crate::codegen::keyword_args(args) {
+                    None if named => push(format!(
+                        "{}(...) mezcla argumentos posicionales y con nombre en {}::{}",
+                        name, c.name, method
+                    )),
+                    None => {}
+                    Some(_) if target.ctor_params.is_some() => push(format!(
+                        "{} define __init__; constrúyelo con argumentos posicionales en {}::{}",
+                        name, c.name, method
+                    )),
+                    Some(fields) => {
+                        let mut last: Option<usize> = None;
+                        for (field, _) in fields {
+                            let at = target
+                                .fields
+                                .iter()
+                                .position(|f| f.name == field && f.vis == Visibility::Public);
+                            match at {
+                                None => push(format!(
+                                    "Campo público desconocido '{}' en {}(...) en {}::{}",
+                                    field, name, c.name, method
+                                )),
+                                Some(i) if last.is_some_and(|l| i <= l) => push(format!(
+                                    "'{}' está fuera de orden en {}(...): nombra los campos en el orden en que se declaran ({}::{})",
+                                    field, name, c.name, method
+                                )),
+                                Some(i) => last = Some(i),
+                            }
+                        }
+                    }
+                }
+                for a in args {
+                    check_expr(a, c, method, classes, errors);
+                }
+            }
+            Expr::VarDecl { ty, value, .. } => {
+                let t = ty.trim().to_string();
+                if t != "Auto" && !is_builtin_ty(&t) && !classes.contains_key(&t) {
+                    push(format!(
+                        "Tipo '{}' no resuelto en {}::{}",
+                        t, c.name, method
+                    ));
+                }
+                let checked = |v: &&Expr| match v {
+                    Expr::Format(_) => true,
+                    Expr::FunctionCall { name, .. } => classes.contains_key(name),
+                    _ => false,
+                };
+                if let Some(v) = value.as_deref().filter(checked) {
+                    check_expr(v, c, method, classes, errors);
+                }
+            }
+            Expr::BinaryOp(l, _, r) => {
+                check_expr(l, c, method, classes, errors);
+                check_expr(r, c, method, classes, errors);
+            }
+            Expr::Block(stmts) => {
+                for s in stmts {
+                    check_expr(s, c, method, classes, errors);
+                }
+            }
+            Expr::If {
+                cond,
+                then_body,
+                else_body,
+            } => {
+                check_expr(cond, c, method, classes, errors);
+                check_expr(then_body, c, method, classes, errors);
+                if let Some(e2) = else_body {
+                    check_expr(e2, c, method, classes, errors);
+                }
+            }
+            Expr::While { cond, body } => {
+                check_expr(cond, c, method, classes, errors);
+                check_expr(body, c, method, classes, errors);
+            }
+            Expr::Return(Some(v)) => {
+                check_expr(v, c, method, classes, errors);
+            }
+            Expr::Concat(l, r) => {
+                check_expr(l, c, method, classes, errors);
+                check_expr(r, c, method, classes, errors);
+            }
+            Expr::Print { args, sep, end } => {
+                for a in args.iter().chain(sep.as_deref()).chain(end.as_deref()) {
+                    check_expr(a, c, method, classes, errors);
+                }
+            }
+            Expr::Format(parts) => {
+                for p in parts {
+                    if let FormatPart::Value { expr, spec } = p {
+                        if crate::codegen::stream_spec(spec).is_none() {
+                            errors.push(TypeError {
+                                class: c.name.clone(),
+                                method: method.to_string(),
+                                message: format!(
+                                    "Formato '{{:{}}}' no soportado en {}::{}",
+                                    spec, c.name, method
+                                ),
+                            });
+                        }
+                        check_expr(expr, c, method, classes, errors);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    for c in classes {
+        for m in &c.methods {
+            check_expr(&m.body, c, &m.name, &class_map, &mut errors);
+        }
+    }
+    errors
+}
+
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+/// Line of `class <name>` in `source`, and the indentation of that line.
+pub fn class_line(source: &str, name: &str) -> Option<(usize, usize)> {
+    source.lines().enumerate().find_map(|(i, l)| {
+        let rest = l.trim_start().strip_prefix("class ")?.trim_start();
+        let after = rest.strip_prefix(name)?;
+        if after.starts_with(|c: char| c.is_alphanumeric() || c == '_') {
+            return None;
+        }
+        Some((i + 1, indent_of(l)))
+    })
+}
+
+/// Line of the field or method `member` inside `class`, falling back to the
+/// class line.
+pub fn member_line(source: &str, class: &str, member: &str) -> Option<usize> {
+    let (start, indent) = class_line(source, class)?;
+    for (i, l) in source.lines().enumerate().skip(start) {
+        if l.trim().is_empty() {
+            continue;
+        }
+        if indent_of(l) <= indent {
+            break;
+        }
+        let t = l.trim_start();
+        let t = t.strip_prefix("def ").unwrap_or(t);
+        let t = t.strip_prefix("static ").unwrap_or(t);
+        let name_end = t.find(['(', ':']).unwrap_or(t.len());
+        if name_end < t.len() && t[..name_end].trim().replace('.', "_") == member {
+            return Some(i + 1);
+        }
+    }
+    Some(start)
+}
+
+/// Runs every semantic check on `classes`, the merged classes of `graph`
+/// with `directives` applied.
+pub fn check(graph: &ImportGraph, classes: &[Class], directives: &Directives) -> Vec<Diagnostic> {
+    let mut out: Vec<Diagnostic> = Vec::new();
+    if let Some(entry) = &directives.entry {
+        if !classes.iter().any(|c| &c.name == entry) {
+            let at = graph
+                .roots()
+                .find(|f| f.directives.entry.as_ref() == Some(entry));
+            out.push(Diagnostic {
+                severity: Severity::Warning,
+                file: at.map(|f| f.path.clone()),
+                line: at.and_then(|f| {
+                    f.source
+                        .lines()
+                        .position(|l| l.trim_start().starts_with("entry "))
+                        .map(|i| i + 1)
+                }),
+                message: format!(
+                    "entry class '{}' not found; the last class is used instead",
+                    entry
+                ),
+            });
+        }
+    }
+    for e in type_errors(classes) {
+        let file = graph.defining_file(&e.class);
+        out.push(Diagnostic {
+            severity: Severity::Error,
+            file: file.map(|f| f.path.clone()),
+            line: file.and_then(|f| member_line(&f.source, &e.class, &e.method)),
+            message: e.message,
+        });
+    }
+    if let Some(cycle) = crate::codegen::dependency_cycle(classes) {
+        let file = graph.defining_file(&cycle[0]);
+        out.push(Diagnostic {
+            severity: Severity::Error,
+            file: file.map(|f| f.path.clone()),
+            line: file.and_then(|f| class_line(&f.source, &cycle[0]).map(|(l, _)| l)),
+            message: format!(
+                "class '{}' contains itself through base classes and by-value fields ({}); \
+                 hold one of them in a Vector<> instead",
+                cycle[0],
+                cycle.join(" -> ")
+            ),
+        });
+    }
+    out
+}
@@ -0,0 +1,644 @@
+//! Command-line parsing for the `ultracpp` binary.
+
+use crate::codegen::CppStd;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmitMode {
+    Classic,
+    Unity,
+    Hybrid,
+    HeaderOnly,
+    Modules,
+}
+
+impl EmitMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EmitMode::Classic => "classic",
+            EmitMode::Unity => "unity",
+            EmitMode::Hybrid => "hybrid",
+            EmitMode::HeaderOnly => "header-only",
+            EmitMode::Modules => "modules",
+        }
+    }
+}
+
+/// Extra build-system files generated next to the sources.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildFile {
+    Cmake,
+    Ninja,
+    Make,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compiler {
+    Msvc,
+    Gcc,
+    Clang,
+}
+
+impl Compiler {
+    pub fn parse(s: &str) -> Option<Compiler> {
+        match s {
+            "cl" | "msvc" => Some(Compiler::Msvc),
+            "g++" | "gcc" => Some(Compiler::Gcc),
+            "clang++" | "clang" => Some(Compiler::Clang),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Compiler::Msvc => "cl",
+            Compiler::Gcc => "g++",
+            Compiler::Clang => "clang++",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Sanitizer {
+    Address,
+    Undefined,
+    Thread,
+}
+
+impl Sanitizer {
+    pub fn parse(s: &str) -> Option<Sanitizer> {
+        match s {
+            "asan" | "address" => Some(Sanitizer::Address),
+            "ubsan" | "undefined" => Some(Sanitizer::Undefined),
+            "tsan" | "thread" => Some(Sanitizer::Thread),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Sanitizer::Address => "asan",
+            Sanitizer::Undefined => "ubsan",
+            Sanitizer::Thread => "tsan",
+        }
+    }
+}
+
+/// Bindings generated for the `--bridge` library. The C interface is
+/// always written; the other languages add a module on top of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bridge {
+    C,
+    Python,
+    Rust,
+    Node,
+}
+
+impl Bridge {
+    pub fn parse(s: &str) -> Option<Bridge> {
+        match s {
+            "c" => Some(Bridge::C),
+            "python" | "py" => Some(Bridge::Python),
+            "rust" | "rs" => Some(Bridge::Rust),
+            "node" | "nodejs" => Some(Bridge::Node),
+            _ => None,
+        }
+    }
+}
+
+/// Options shared by the commands that load a project.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BuildArgs {
+    pub input: Option<String>,
+    pub out_dir: Option<String>,
+    pub emit: Option<EmitMode>,
+    pub build_files: Vec<BuildFile>,
+    pub compile: bool,
+    pub no_main: bool,
+    pub compiler: Option<Compiler>,
+    pub std: Option<CppStd>,
+    pub release: bool,
+    pub bench: bool,
+    pub staging: bool,
+    pub gpu: Option<String>,
+    pub bridge: Option<Bridge>,
+    pub lint: bool,
+    pub format: bool,
+    pub lint_rust: bool,
+    pub sanitize: Option<Sanitizer>,
+    pub coverage: bool,
+    pub source_map: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    /// Generate C++ for a `.upp` file, a folder or an `ultra.toml` project.
+    Build(BuildArgs),
+    /// Parse and analyse without writing files.
+    Check(BuildArgs),
+    /// Build, compile and execute.
+    Run(BuildArgs),
+    /// Build, then rebuild whenever a source changes.
+    Watch(BuildArgs),
+    /// Generate only, in the emission mode given as first argument.
+    Emit(BuildArgs),
+    Init {
+        file: String,
+        template: String,
+    },
+    /// Rewrite the `.upp` sources of a project in canonical form.
+    Fmt {
+        args: BuildArgs,
+        check: bool,
+    },
+    /// Remove the generated project directory.
+    Clean(BuildArgs),
+    /// Build a folder in every emission mode (and with every compiler).
+    Test {
+        args: BuildArgs,
+        compilers: bool,
+    },
+    /// Serve the language server protocol over stdio.
+    Lsp,
+    Help(Option<String>),
+}
+
+struct Flag {
+    name: &'static str,
+    value: Option<&'static str>,
+    help: &'static str,
+}
+
+const FLAGS: &[Flag] = &[
+    Flag {
+        name: "--out-dir",
+        value: Some("DIR"),
+        help: "output root (default: dist, or staging with --staging)",
+    },
+    Flag {
+        name: "--emit",
+        value: Some("MODE"),
+        help: "classic | unity | hybrid | header-only | modules, or cmake | ninja | make for a build file (repeatable)",
+    },
+    Flag {
+        name: "--compile",
+        value: None,
+        help: "compile the generated C++",
+    },
+    Flag {
+        name: "--no-main",
+        value: None,
+        help: "do not generate an entry point",
+    },
+    Flag {
+        name: "--source-map",
+        value: None,
+        help: "emit #line directives and build/sourcemap.json mapping the C++ back to .upp",
+    },
+    Flag {
+        name: "--compiler",
+        value: Some("CXX"),
+        help: "cl | g++ | clang++",
+    },
+    Flag {
+        name: "--std",
+        value: Some("STD"),
+        help: "c++17 | c++20 | c++23",
+    },
+    Flag {
+        name: "--release",
+        value: None,
+        help: "optimised build and release archive",
+    },
+    Flag {
+        name: "--bench",
+        value: None,
+        help: "write build/report.json with timings",
+    },
+    Flag {
+        name: "--staging",
+        value: None,
+        help: "generate into staging/ without compiling",
+    },
+    Flag {
+        name: "--gpu",
+        value: Some("BACKEND"),
+        help: "GPU backend hint for the build scripts",
+    },
+    Flag {
+        name: "--bridge",
+        value: Some("LANG"),
+        help: "also build a shared library and bindings for LANG (c, python, rust or node)",
+    },
+    Flag {
+        name: "--lint",
+        value: None,
+        help: "run clang-tidy on the generated sources",
+    },
+    Flag {
+        name: "--format",
+        value: None,
+        help: "run clang-format on the generated sources",
+    },
+    Flag {
+        name: "--lint-rust",
+        value: None,
+        help: "run cargo clippy and cargo fmt",
+    },
+    Flag {
+        name: "--sanitize",
+        value: Some("SAN"),
+        help: "asan | ubsan | tsan",
+    },
+    Flag {
+        name: "--coverage",
+        value: None,
+        help: "instrument for gcov coverage",
+    },
+    Flag {
+        name: "--template",
+        value: Some("NAME"),
+        help: "default | game",
+    },
+    Flag {
+        name: "--check",
+        value: None,
+        help: "list the files that are not formatted instead of rewriting them",
+    },
+    Flag {
+        name: "--compilers",
+        value: None,
+        help: "repeat with cl, g++ and clang++",
+    },
+    Flag {
+        name: "--stdio",
+        value: None,
+        help: "communicate over stdin/stdout (the default)",
+    },
+    Flag {
+        name: "--help",
+        value: None,
+        help: "show this help",
+    },
+];
+
+const BUILD_FLAGS: &[&str] = &[
+    "--out-dir",
+    "--emit",
+    "--compile",
+    "--no-main",
+    "--source-map",
+    "--compiler",
+    "--std",
+    "--release",
+    "--bench",
+    "--staging",
+    "--gpu",
+    "--bridge",
+    "--lint",
+    "--format",
+    "--lint-rust",
+    "--sanitize",
+    "--coverage",
+    "--help",
+];
+
+struct CommandSpec {
+    name: &'static str,
+    usage: &'static str,
+    about: &'static str,
+    flags: &'static [&'static str],
+}
+
+const COMMANDS: &[CommandSpec] = &[
+    CommandSpec {
+        name: "build",
+        usage: "ultracpp build [INPUT] [OUT_DIR] [OPTIONS]",
+        about: "Generate C++ from a .upp file, a folder of .upp files or an ultra.toml project",
+        flags: BUILD_FLAGS,
+    },
+    CommandSpec {
+        name: "check",
+        usage: "ultracpp check [INPUT]",
+        about: "Parse, resolve imports and run the semantic checks without writing files",
+        flags: &["--help"],
+    },
+    CommandSpec {
+        name: "run",
+        usage: "ultracpp run [INPUT] [OUT_DIR] [OPTIONS]",
+        about: "Build, compile and execute the entry class",
+        flags: &[
+            "--out-dir",
+            "--emit",
+            "--source-map",
+            "--compiler",
+            "--std",
+            "--release",
+            "--gpu",
+            "--sanitize",
+            "--coverage",
+            "--help",
+        ],
+    },
+    CommandSpec {
+        name: "watch",
+        usage: "ultracpp watch [INPUT] [OUT_DIR] [OPTIONS]",
+        about: "Build, then rebuild whenever a source file changes",
+        flags: BUILD_FLAGS,
+    },
+    CommandSpec {
+        name: "emit",
+        usage: "ultracpp emit MODE [INPUT] [OUT_DIR] [OPTIONS]",
+        about: "Generate C++ in the given emission mode without compiling",
+        flags: &[
+            "--out-dir",
+            "--emit",
+            "--no-main",
+            "--source-map",
+            "--std",
+            "--release",
+            "--bridge",
+            "--sanitize",
+            "--coverage",
+            "--help",
+        ],
+    },
+    CommandSpec {
+        name: "init",
+        usage: "ultracpp init FILE [--template NAME]",
+        about: "Create a starter .upp file and ultra.toml",
+        flags: &["--template", "--help"],
+    },
+    CommandSpec {
+        name: "fmt",
+        usage: "ultracpp fmt [INPUT] [--check]",
+        about: "Rewrite the .upp sources of a project in canonical form",
+        flags: &["--check", "--help"],
+    },
+    CommandSpec {
+        name: "clean",
+        usage: "ultracpp clean [INPUT] [OUT_DIR]",
+        about: "Remove the generated project directory",
+        flags: &["--out-dir", "--staging", "--help"],
+    },
+    CommandSpec {
+        name: "test",
+        usage: "ultracpp test [INPUT] [--compilers]",
+        about: "Build and compile a project in every emission mode",
+        flags: &["--compilers", "--std", "--help"],
+    },
+    CommandSpec {
+        name: "lsp",
+        usage: "ultracpp lsp [--stdio]",
+        about: "Run the language server for editors",
+        flags: &["--stdio", "--help"],
+    },
+];
+
+/// Usage text for one command, or the command overview for `None`.
+pub fn help(command: Option<&str>) -> String {
+    let mut s = String::new();
+    match command.and_then(|c| COMMANDS.iter().find(|spec| spec.name == c)) {
+        Some(spec) => {
+            s.push_str(&format!(
+                "{}\n\nusage: {}\n\noptions:\n",
+                spec.about, spec.usage
+            ));
+            for name in spec.flags {
+                let f = FLAGS.iter().find(|f| f.name == *name).unwrap();
+                let left = match f.value {
+                    Some(v) => format!("{} {}", f.name, v),
+                    None => f.name.to_string(),
+                };
+                s.push_str(&format!("  {:<22} {}\n", left, f.help));
+            }
+        }
+        None => {
+            s.push_str("usage: ultracpp <command> [args]\n\ncommands:\n");
+            for spec in COMMANDS {
+                s.push_str(&format!("  {:<8} {}\n", spec.name, spec.about));
+            }
+            s.push_str("\nWithout a command, `ultracpp INPUT ...` is the same as `ultracpp build INPUT ...`.\n");
+            s.push_str("Run `ultracpp <command> --help` for the options of a command.\n");
+        }
+    }
+    s
+}
+
+fn set_once<T: PartialEq + Copy>(slot: &mut Option<T>, v: T, flag: &str) -> Result<(), String> {
+    match slot {
+        Some(prev) if *prev != v => Err(format!("conflicting values given for {}", flag)),
+        _ => {
+            *slot = Some(v);
+            Ok(())
+        }
+    }
+}
+
+fn parse_emit(a: &mut BuildArgs, v: &str) -> Result<(), String> {
+    let mode = match v {
+        "classic" => EmitMode::Classic,
+        "unity" => EmitMode::Unity,
+        "hybrid" => EmitMode::Hybrid,
+        "header-only" => EmitMode::HeaderOnly,
+        "modules" => EmitMode::Modules,
+        "cmake" | "ninja" | "make" => {
+            let file = match v {
+                "cmake" => BuildFile::Cmake,
+                "ninja" => BuildFile::Ninja,
+                _ => BuildFile::Make,
+            };
+            if !a.build_files.contains(&file) {
+                a.build_files.push(file);
+            }
+            return Ok(());
+        }
+        _ => {
+            return Err(format!(
+                "unknown emit mode '{}' (expected classic, unity, hybrid, header-only, modules, cmake, ninja or make)",
+                v
+            ))
+        }
+    };
+    if let Some(prev) = a.emit {
+        if prev != mode {
+            return Err(format!(
+                "--emit {} conflicts with --emit {}",
+                mode.as_str(),
+                prev.as_str()
+            ));
+        }
+    }
+    a.emit = Some(mode);
+    Ok(())
+}
+
+/// Parses `args` (without the program name).
+///
+/// Options accept both `--flag value` and `--flag=value`. Unknown options,
+/// options that do not apply to the command, missing values, extra
+/// positional arguments and contradicting options are all errors.
+pub fn parse_args(args: &[String]) -> Result<Command, String> {
+    let (name, rest): (&str, &[String]) = match args.first().map(|s| s.as_str()) {
+        None => ("build", args),
+        Some("-h") | Some("--help") | Some("help") => {
+            return Ok(Command::Help(args.get(1).cloned()));
+        }
+        Some(first) if COMMANDS.iter().any(|c| c.name == first) => (first, &args[1..]),
+        Some(_) => ("build", args),
+    };
+    let spec = COMMANDS.iter().find(|c| c.name == name).unwrap();
+    let mut a = BuildArgs::default();
+    let mut positionals: Vec<String> = Vec::new();
+    let mut template: Option<String> = None;
+    let mut compilers = false;
+    let mut check = false;
+    let mut i = 0usize;
+    while i < rest.len() {
+        let arg = &rest[i];
+        i += 1;
+        if !arg.starts_with("--") || arg == "--" {
+            if arg == "-h" {
+                return Ok(Command::Help(Some(name.to_string())));
+            }
+            if arg.starts_with('-') && arg.len() > 1 {
+                return Err(format!("unknown option '{}' for '{}'", arg, name));
+            }
+            positionals.push(arg.clone());
+            continue;
+        }
+        let (flag, inline) = match arg.split_once('=') {
+            Some((f, v)) => (f, Some(v.to_string())),
+            None => (arg.as_str(), None),
+        };
+        let Some(def) = FLAGS.iter().find(|f| f.name == flag) else {
+            return Err(format!("unknown option '{}' for '{}'", flag, name));
+        };
+        if !spec.flags.contains(&flag) {
+            return Err(format!("option '{}' is not valid for '{}'", flag, name));
+        }
+        let value = match (def.value, inline) {
+            (Some(_), Some(v)) => v,
+            (Some(meta), None) => {
+                let v = rest
+                    .get(i)
+                    .filter(|v| !v.starts_with("--"))
+                    .ok_or_else(|| format!("{} requires a value ({})", flag, meta))?;
+                i += 1;
+                v.clone()
+            }
+            (None, Some(_)) => return Err(format!("{} does not take a value", flag)),
+            (None, None) => String::new(),
+        };
+        match flag {
+            "--help" => return Ok(Command::Help(Some(name.to_string()))),
+            "--out-dir" => a.out_dir = Some(value),
+            "--emit" => parse_emit(&mut a, &value)?,
+            "--compile" => a.compile = true,
+            "--no-main" => a.no_main = true,
+            "--source-map" => a.source_map = true,
+            "--compiler" => {
+                let c = Compiler::parse(&value).ok_or_else(|| {
+                    format!("unknown compiler '{}' (expected cl, g++ or clang++)", value)
+                })?;
+                set_once(&mut a.compiler, c, flag)?;
+            }
+            "--std" => {
+                let s = CppStd::parse(&value).ok_or_else(|| {
+                    format!(
+                        "unsupported standard '{}' (expected c++17, c++20 or c++23)",
+                        value
+                    )
+                })?;
+                set_once(&mut a.std, s, flag)?;
+            }
+            "--release" => a.release = true,
+            "--bench" => a.bench = true,
+            "--staging" => a.staging = true,
+            "--gpu" => a.gpu = Some(value),
+            "--bridge" => {
+                let b = Bridge::parse(&value).ok_or_else(|| {
+                    format!(
+                        "unknown bridge language '{}' (expected c, python, rust or node)",
+                        value
+                    )
+                })?;
+                set_once(&mut a.bridge, b, flag)?;
+            }
+            "--lint" => a.lint = true,
+            "--format" => a.format = true,
+            "--lint-rust" => a.lint_rust = true,
+            "--sanitize" => {
+                let s = Sanitizer::parse(&value).ok_or_else(|| {
+                    format!(
+                        "unknown sanitizer '{}' (expected asan, ubsan or tsan)",
+                        value
+                    )
+                })?;
+                set_once(&mut a.sanitize, s, flag)?;
+            }
+            "--coverage" => a.coverage = true,
+            "--template" => template = Some(value),
+            "--check" => check = true,
+            "--compilers" => compilers = true,
+            "--stdio" => {}
+            _ => unreachable!(),
+        }
+    }
+    if a.staging && a.compile {
+        return Err(
+            "--staging generates without compiling and cannot be combined with --compile"
+                .to_string(),
+        );
+    }
+    if name == "emit" {
+        if positionals.is_empty() {
+            return Err("emit requires a MODE (classic, unity, hybrid, header-only, modules, cmake, ninja or make)".to_string());
+        }
+        let mode = positionals.remove(0);
+        parse_emit(&mut a, &mode)?;
+    }
+    let max_positionals = match name {
+        "lsp" => 0,
+        "init" | "check" | "test" | "fmt" => 1,
+        _ => 2,
+    };
+    if positionals.len() > max_positionals {
+        return Err(format!(
+            "unexpected argument '{}' for '{}'",
+            positionals[max_positionals], name
+        ));
+    }
+    let mut pos = positionals.into_iter();
+    a.input = pos.next();
+    if let Some(out) = pos.next() {
+        if a.out_dir.is_some() {
+            return Err("output directory given twice".to_string());
+        }
+        a.out_dir = Some(out);
+    }
+    Ok(match name {
+        "build" => Command::Build(a),
+        "check" => Command::Check(a),
+        "run" => Command::Run(a),
+        "watch" => Command::Watch(a),
+        "emit" => Command::Emit(a),
+        "fmt" => Command::Fmt { args: a, check },
+        "clean" => Command::Clean(a),
+        "test" => Command::Test { args: a, compilers },
+        "lsp" => Command::Lsp,
+        "init" => {
+            let template = template.unwrap_or_else(|| "default".to_string());
+            if template != "default" && template != "game" {
+                return Err(format!(
+                    "unknown template '{}' (expected default or game)",
+                    template
+                ));
+            }
+            Command::Init {
+                file: a.input.ok_or("init requires a FILE")?,
+                template,
+            }
+        }
+        _ => unreachable!(),
+    })
+}
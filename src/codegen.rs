@@ -1,12 +1,61 @@
-use crate::{Class, Expr, Method, Visibility};
+use crate::{Class, Expr, FormatPart, Method, SourceLines, Visibility};
+
+/// C++ standard the generated code targets; newer ones unlock idioms such
+/// as `std::format` for `print`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CppStd {
+    Cpp17,
+    Cpp20,
+    Cpp23,
+}
+
+impl CppStd {
+    pub fn parse(s: &str) -> Option<CppStd> {
+        match s.trim_start_matches("c++").trim_start_matches("C++") {
+            "17" => Some(CppStd::Cpp17),
+            "20" => Some(CppStd::Cpp20),
+            "23" => Some(CppStd::Cpp23),
+            _ => None,
+        }
+    }
+
+    /// The GCC/Clang spelling, e.g. `c++20`.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            CppStd::Cpp17 => "c++17",
+            CppStd::Cpp20 => "c++20",
+            CppStd::Cpp23 => "c++23",
+        }
+    }
+}
 
 fn is_builtin_ultra(t: &str) -> bool {
     matches!(
         t.trim(),
-        "Int" | "Float" | "Bool" | "String" | "Void" | "Auto" | "Double"
+        "Int" | "Float" | "Bool" | "String" | "Void" | "Auto" | "Double" | "Integral" | "Floating"
     )
 }
 
+/// Parameter types that make a method a template: `Auto` takes any type,
+/// `Integral` and `Floating` only integer and floating-point ones.
+fn is_generic(t: &str) -> bool {
+    matches!(t.trim(), "Auto" | "Integral" | "Floating")
+}
+
+/// The C++20 concept and the C++17 type trait constraining a generic
+/// parameter type, if any.
+fn constraint(t: &str) -> Option<(&'static str, &'static str)> {
+    match t.trim() {
+        "Integral" => Some(("std::integral", "std::is_integral_v")),
+        "Floating" => Some(("std::floating_point", "std::is_floating_point_v")),
+        _ => None,
+    }
+}
+
+fn is_generic_method(m: &Method) -> bool {
+    m.params.iter().any(|p| is_generic(&p.ty))
+}
+
 fn is_builtin_cpp(t: &str) -> bool {
     matches!(t.trim(), "int" | "float" | "double" | "bool" | "void")
 }
@@ -77,7 +126,12 @@ fn cpp_default_init(t: &str) -> String {
     }
 }
 
-pub fn unity_build(classes: &Vec<Class>) -> String {
+pub fn unity_build(classes: &[Class]) -> String {
+    unity_build_with(classes, CppStd::Cpp17)
+}
+
+/// Like [`unity_build`], for code targeting `std`.
+pub fn unity_build_with(classes: &[Class], std: CppStd) -> String {
     let mut s = String::new();
 
     // Standard headers
@@ -117,6 +171,13 @@ pub fn unity_build(classes: &Vec<Class>) -> String {
             }
         }
     }
+    let bodies: String = classes
+        .iter()
+        .map(|c| class_definition(c, std) + &definitions(c, false, std))
+        .collect();
+    s.push_str(&stream_includes(&bodies));
+    s.push_str(&generic_includes(&bodies));
+    s.push_str(&print_macro(std));
     s.push_str("\n// Forward Declarations\n");
     for c in classes {
         if let Some(ns) = &c.namespace {
@@ -130,10 +191,7 @@ pub fn unity_build(classes: &Vec<Class>) -> String {
     use std::collections::HashMap;
     let mut ns_map: HashMap<Option<String>, Vec<&Class>> = HashMap::new();
     for c in classes {
-        ns_map
-            .entry(c.namespace.clone())
-            .or_insert(Vec::new())
-            .push(c);
+        ns_map.entry(c.namespace.clone()).or_default().push(c);
     }
 
     // Sort namespaces to ensure deterministic order (None first, then alphabetical)
@@ -145,33 +203,40 @@ pub fn unity_build(classes: &Vec<Class>) -> String {
         (Some(na), Some(nb)) => na.cmp(nb),
     });
 
+    // Definitions follow the grouped order wherever dependencies allow,
+    // reopening a namespace when a class must come before the rest of it.
+    let grouped: Vec<&Class> = namespaces
+        .iter()
+        .flat_map(|ns| ns_map[ns].iter().copied())
+        .collect();
     s.push_str("\n// Class Definitions\n");
-    for ns in &namespaces {
-        if let Some(n) = ns {
-            s.push_str(&format!("namespace {} {{\n", n));
-        }
-
-        if let Some(group) = ns_map.get(ns) {
-            for c in group {
-                let h = header(c);
-                // Filter out includes, pragma once, and namespace wrappers
-                let lines: Vec<&str> = h
-                    .lines()
-                    .filter(|l| {
-                        !l.starts_with("#include")
-                            && !l.starts_with("#pragma")
-                            && !l.starts_with("namespace ")
-                            && l != &"}"
-                    })
-                    .collect();
-                s.push_str(&lines.join("\n"));
-                s.push_str("\n\n");
+    let mut open: Option<&String> = None;
+    for c in topological(&grouped).0 {
+        if open != c.namespace.as_ref() {
+            if open.is_some() {
+                s.push_str("}\n");
             }
+            if let Some(n) = &c.namespace {
+                s.push_str(&format!("namespace {} {{\n", n));
+            }
+            open = c.namespace.as_ref();
         }
-
-        if ns.is_some() {
-            s.push_str("}\n");
-        }
+        let h = header_with(c, std);
+        // Filter out includes, pragma once, and namespace wrappers
+        let lines: Vec<&str> = h
+            .lines()
+            .filter(|l| {
+                !l.starts_with("#include")
+                    && !l.starts_with("#pragma")
+                    && !l.starts_with("namespace ")
+                    && l != &"}"
+            })
+            .collect();
+        s.push_str(&lines.join("\n"));
+        s.push_str("\n\n");
+    }
+    if open.is_some() {
+        s.push_str("}\n");
     }
 
     s.push_str("\n// Class Implementations\n");
@@ -182,7 +247,7 @@ pub fn unity_build(classes: &Vec<Class>) -> String {
 
         if let Some(group) = ns_map.get(ns) {
             for c in group {
-                let src = source(c);
+                let src = source_with(c, std);
                 // Filter out includes and namespace wrappers
                 let mut lines: Vec<&str> = src
                     .lines()
@@ -199,7 +264,7 @@ pub fn unity_build(classes: &Vec<Class>) -> String {
                     lines.pop();
                 }
                 s.push_str(&lines.join("\n"));
-                s.push_str("\n");
+                s.push('\n');
             }
         }
 
@@ -211,22 +276,589 @@ pub fn unity_build(classes: &Vec<Class>) -> String {
     s
 }
 
+/// `name` made a valid C/C++ identifier: lowercase, with other characters
+/// replaced by `_`.
+pub fn identifier(name: &str) -> String {
+    let id: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    if id.is_empty() || id.starts_with(|c: char| c.is_ascii_digit()) {
+        format!("_{}", id)
+    } else {
+        id
+    }
+}
+
+/// Indices into `classes` of the classes `ty` needs complete: every class
+/// named in it except inside `Vector<...>`, `List<...>` and smart pointers,
+/// which accept incomplete types.
+fn complete_dependencies(ty: &str, classes: &[&Class]) -> Vec<usize> {
+    let mut deps = Vec::new();
+    let mut stack: Vec<bool> = Vec::new();
+    let mut word = String::new();
+    for ch in ty.chars().chain(std::iter::once(' ')) {
+        if ch.is_alphanumeric() || ch == '_' || ch == '.' || ch == ':' {
+            word.push(ch);
+            continue;
+        }
+        let name = word.rsplit(['.', ':']).next().unwrap_or("");
+        let deferred = stack.last().copied().unwrap_or(false);
+        if ch == '<' {
+            stack.push(
+                deferred
+                    || matches!(
+                        name,
+                        "Vector"
+                            | "List"
+                            | "vector"
+                            | "list"
+                            | "shared_ptr"
+                            | "unique_ptr"
+                            | "weak_ptr"
+                    ),
+            );
+        } else if !deferred {
+            if let Some(i) = classes.iter().position(|c| c.name == name) {
+                deps.push(i);
+            }
+        }
+        if ch == '>' {
+            stack.pop();
+        }
+        word.clear();
+    }
+    deps
+}
+
+/// For each class, the classes that must be defined before it: its base
+/// and the types of its by-value fields.
+fn dependency_graph(classes: &[&Class]) -> Vec<Vec<usize>> {
+    classes
+        .iter()
+        .map(|c| {
+            let mut deps = Vec::new();
+            if let Some(b) = &c.base {
+                deps.extend(complete_dependencies(b, classes));
+            }
+            for f in &c.fields {
+                deps.extend(complete_dependencies(&f.ty, classes));
+            }
+            deps
+        })
+        .collect()
+}
+
+/// Stable topological sort: repeatedly takes the first class whose
+/// dependencies are all placed. When only cycles remain, the first class
+/// left is placed anyway; the second value lists the classes that were
+/// still blocked the first time that happened.
+fn topological<'a>(classes: &[&'a Class]) -> (Vec<&'a Class>, Option<Vec<usize>>) {
+    let deps = dependency_graph(classes);
+    let mut placed = vec![false; classes.len()];
+    let mut order = Vec::with_capacity(classes.len());
+    let mut blocked = None;
+    while order.len() < classes.len() {
+        let ready = (0..classes.len()).find(|&i| !placed[i] && deps[i].iter().all(|&d| placed[d]));
+        let i = ready.unwrap_or_else(|| {
+            let left: Vec<usize> = (0..classes.len()).filter(|&i| !placed[i]).collect();
+            let first = left[0];
+            blocked.get_or_insert(left);
+            first
+        });
+        placed[i] = true;
+        order.push(classes[i]);
+    }
+    (order, blocked)
+}
+
+/// `classes` ordered so that each one follows its base and the classes it
+/// holds by value. Classes caught in a cycle keep their original order.
+pub fn definition_order(classes: &[Class]) -> Vec<&Class> {
+    let refs: Vec<&Class> = classes.iter().collect();
+    topological(&refs).0
+}
+
+/// A chain of classes that contain each other through bases and by-value
+/// fields, e.g. `["A", "B", "A"]`, which no definition order can satisfy.
+pub fn dependency_cycle(classes: &[Class]) -> Option<Vec<String>> {
+    let refs: Vec<&Class> = classes.iter().collect();
+    let deps = dependency_graph(&refs);
+    let blocked = topological(&refs).1?;
+    // Each blocked class waits on another blocked one, so following those
+    // dependencies must eventually revisit a class.
+    let mut path = vec![blocked[0]];
+    loop {
+        let last = *path.last().unwrap();
+        let next = *deps[last].iter().find(|d| blocked.contains(d)).unwrap();
+        if let Some(start) = path.iter().position(|&p| p == next) {
+            path.drain(..start);
+            path.push(next);
+            return Some(path.iter().map(|&p| classes[p].name.clone()).collect());
+        }
+        path.push(next);
+    }
+}
+
+/// Namespace wrapping a header-only library. A project called `main` or
+/// `std` would collide with `int main()` or the standard library, so those
+/// get a trailing `_`.
+pub fn library_namespace(project: &str) -> String {
+    let ns = identifier(project);
+    if matches!(ns.as_str(), "main" | "std") {
+        format!("{}_", ns)
+    } else {
+        ns
+    }
+}
+
+/// `--emit header-only`: every class in one self-contained header, inside
+/// the `project` namespace, with inline definitions.
+pub fn header_only(project: &str, classes: &[Class], std: CppStd) -> String {
+    let ns = library_namespace(project);
+    let guard = format!("ULTRACPP_{}_HPP", identifier(project).to_uppercase());
+    let mut s = String::new();
+    s.push_str(&format!("#ifndef {}\n#define {}\n\n", guard, guard));
+    s.push_str(API_MACRO);
+    s.push_str("#ifndef UCPP_NOEXCEPT\n#  define UCPP_NOEXCEPT noexcept\n#endif\n\n");
+    let refs: Vec<&Class> = classes.iter().collect();
+    s.push_str(&library_includes(&refs, std));
+    s.push_str(&print_macro(std));
+    let scope = |c: &Class| match &c.namespace {
+        Some(inner) => format!("{}::{}", ns, inner),
+        None => ns.clone(),
+    };
+    s.push_str("\n// Forward declarations\n");
+    for c in classes {
+        s.push_str(&format!("namespace {} {{ class {}; }}\n", scope(c), c.name));
+    }
+    let order = definition_order(classes);
+    s.push_str("\n// Classes\n");
+    for c in &order {
+        s.push_str(&format!("namespace {} {{\n", scope(c)));
+        s.push_str(&class_definition(c, std));
+        s.push_str("}\n\n");
+    }
+    s.push_str("// Definitions\n");
+    for c in &order {
+        s.push_str(&format!("namespace {} {{\n", scope(c)));
+        s.push_str(&definitions(c, true, std));
+        s.push_str("}\n\n");
+    }
+    s.push_str(&format!("#endif // {}\n", guard));
+    s
+}
+
+/// A C++20 module interface unit of `--emit modules`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Module {
+    /// Module name, e.g. `app.counter`, which is also the stem of its
+    /// `.cppm` file.
+    pub name: String,
+    /// The classes it exports.
+    pub classes: Vec<String>,
+    pub source: String,
+}
+
+/// Project classes `c` refers to, as found by the include analysis of
+/// `header` and `source`, each flagged when the class interface needs it.
+fn class_references(c: &Class, classes: &[&Class]) -> Vec<(usize, bool)> {
+    let mut refs: Vec<(usize, bool)> = Vec::new();
+    let mut add = |i: usize, interface: bool| match refs.iter_mut().find(|(j, _)| *j == i) {
+        Some(r) => r.1 |= interface,
+        None => refs.push((i, interface)),
+    };
+    for (text, interface) in [(header(c), true), (source(c), false)] {
+        for line in text.lines().map(str::trim) {
+            let name = line
+                .strip_prefix("#include \"")
+                .and_then(|l| l.strip_suffix(".hpp\""))
+                .or_else(|| line.strip_prefix("class ")?.strip_suffix(';'));
+            let found =
+                name.and_then(|n| classes.iter().position(|k| k.name.eq_ignore_ascii_case(n)));
+            if let Some(i) = found.filter(|&i| classes[i].name != c.name) {
+                add(i, interface);
+            }
+        }
+    }
+    refs
+}
+
+/// `--emit modules`: one interface unit per class, in the order they must
+/// be compiled. Modules cannot import each other, so classes that refer to
+/// each other, directly or not, share the unit of the first of them.
+pub fn modules(project: &str, classes: &[Class], std: CppStd) -> Vec<Module> {
+    let all: Vec<&Class> = classes.iter().collect();
+    let mut refs: Vec<Vec<(usize, bool)>> = all.iter().map(|c| class_references(c, &all)).collect();
+    for (i, deps) in dependency_graph(&all).into_iter().enumerate() {
+        for d in deps.into_iter().filter(|&d| d != i) {
+            match refs[i].iter_mut().find(|(j, _)| *j == d) {
+                Some(r) => r.1 = true,
+                None => refs[i].push((d, true)),
+            }
+        }
+    }
+    let reach: Vec<Vec<bool>> = (0..all.len())
+        .map(|from| {
+            let mut seen = vec![false; all.len()];
+            let mut stack = vec![from];
+            while let Some(i) = stack.pop() {
+                for &(j, _) in &refs[i] {
+                    if !std::mem::replace(&mut seen[j], true) {
+                        stack.push(j);
+                    }
+                }
+            }
+            seen
+        })
+        .collect();
+    // Each class joins the first class it shares a cycle with.
+    let leader: Vec<usize> = (0..all.len())
+        .map(|i| (0..i).find(|&j| reach[i][j] && reach[j][i]).unwrap_or(i))
+        .collect();
+    let prefix = match identifier(project) {
+        p if p == "std" => "std_".to_string(),
+        p => p,
+    };
+    let name = |l: usize| format!("{}.{}", prefix, all[l].name.to_lowercase());
+
+    let mut done = vec![false; all.len()];
+    let mut out = Vec::new();
+    while let Some(l) = (0..all.len()).find(|&l| {
+        leader[l] == l
+            && !done[l]
+            && (0..all.len()).filter(|&i| leader[i] == l).all(|i| {
+                refs[i]
+                    .iter()
+                    .all(|&(j, _)| leader[j] == l || done[leader[j]])
+            })
+    }) {
+        done[l] = true;
+        let members: Vec<&Class> = (0..all.len())
+            .filter(|&i| leader[i] == l)
+            .map(|i| all[i])
+            .collect();
+        let mut imports: Vec<(usize, bool)> = Vec::new();
+        for i in (0..all.len()).filter(|&i| leader[i] == l) {
+            for &(j, interface) in &refs[i] {
+                let dep = leader[j];
+                if dep == l {
+                    continue;
+                }
+                match imports.iter_mut().find(|(d, _)| *d == dep) {
+                    Some(d) => d.1 |= interface,
+                    None => imports.push((dep, interface)),
+                }
+            }
+        }
+        imports.sort_by_key(|&(d, _)| out.iter().position(|m: &Module| m.name == name(d)));
+
+        let mut s = String::new();
+        let export_open = |c: &Class| match &c.namespace {
+            Some(ns) => format!("export namespace {} {{\n", ns),
+            None => "export {\n".to_string(),
+        };
+        if members.len() > 1 {
+            for c in &members {
+                s.push_str(&export_open(c));
+                s.push_str(&format!("class {};\n}}\n", c.name));
+            }
+            s.push('\n');
+        }
+        let order = topological(&members).0;
+        for c in &order {
+            s.push_str(&export_open(c));
+            s.push_str(&class_definition(c, std));
+            s.push_str("}\n\n");
+        }
+        for c in &order {
+            match &c.namespace {
+                Some(ns) => {
+                    s.push_str(&format!("namespace {} {{\n", ns));
+                    s.push_str(&definitions(c, false, std));
+                    s.push_str("}\n");
+                }
+                None => s.push_str(&definitions(c, false, std)),
+            }
+        }
+
+        let mut unit = String::new();
+        unit.push_str("module;\n");
+        unit.push_str(API_MACRO);
+        unit.push_str("#ifndef UCPP_NOEXCEPT\n#  define UCPP_NOEXCEPT noexcept\n#endif\n");
+        let prints = s.contains("UCPP_PRINT(") || s.contains("UCPP_FORMAT(");
+        unit.push_str(&module_includes(&members, &s));
+        if prints {
+            unit.push_str(&print_includes(std));
+        }
+        unit.push_str(&format!("\nexport module {};\n", name(l)));
+        for (d, interface) in &imports {
+            let export = if *interface { "export " } else { "" };
+            unit.push_str(&format!("{}import {};\n", export, name(*d)));
+        }
+        unit.push('\n');
+        if prints {
+            unit.push_str(&print_definitions(std));
+        }
+        unit.push_str(&s);
+        out.push(Module {
+            name: name(l),
+            classes: members.iter().map(|c| c.name.clone()).collect(),
+            source: unit,
+        });
+    }
+    out
+}
+
+/// Includes for the global module fragment of a unit whose code is
+/// `body`: only the standard headers it uses, since every one of them is
+/// parsed again for each module, plus the extra and console headers.
+fn module_includes(classes: &[&Class], body: &str) -> String {
+    const USES: &[(&str, &[&str])] = &[
+        (
+            "memory",
+            &["std::shared_ptr", "std::unique_ptr", "std::make_"],
+        ),
+        (
+            "algorithm",
+            &[
+                "std::sort",
+                "std::find",
+                "std::min",
+                "std::max",
+                "std::reverse",
+                "std::count",
+            ],
+        ),
+        ("functional", &["std::function"]),
+        ("map", &["std::map"]),
+        ("list", &["std::list"]),
+        ("optional", &["std::optional", "std::nullopt"]),
+        ("thread", &["std::thread", "std::this_thread"]),
+        ("mutex", &["std::mutex", "std::lock_guard"]),
+        ("future", &["std::future", "std::promise", "std::async"]),
+        ("atomic", &["std::atomic"]),
+        ("concepts", &["std::integral", "std::floating_point"]),
+        ("filesystem", &["std::filesystem"]),
+        (
+            "fstream",
+            &["std::ofstream", "std::ifstream", "std::fstream"],
+        ),
+        ("numeric", &["std::accumulate", "std::iota"]),
+        (
+            "cmath",
+            &[
+                "std::sqrt",
+                "std::pow",
+                "std::abs",
+                "std::floor",
+                "std::ceil",
+            ],
+        ),
+        ("cstdio", &["printf"]),
+        ("sstream", &["std::ostringstream"]),
+        (
+            "iomanip",
+            &["std::setw", "std::setprecision", "std::setfill"],
+        ),
+    ];
+    let mut includes: Vec<String> = ["iostream", "string", "vector"].map(String::from).to_vec();
+    for (header, names) in USES {
+        if names.iter().any(|n| body.contains(n)) {
+            includes.push(header.to_string());
+        }
+    }
+    for c in classes {
+        for inc in &c.extra_includes {
+            if !includes.contains(inc) {
+                includes.push(inc.clone());
+            }
+        }
+    }
+    let mut s = String::new();
+    for inc in &includes {
+        s.push_str(&format!("#include <{}>\n", inc));
+    }
+    if classes
+        .iter()
+        .any(|c| source(c).contains("#include <windows.h>"))
+    {
+        s.push_str("#ifdef _WIN32\n#include <conio.h>\n#include <windows.h>\n#endif\n");
+    }
+    s
+}
+
+/// Standard and extra includes for a unit defining all of `classes`, plus
+/// the console headers when one of them uses them.
+fn library_includes(classes: &[&Class], std: CppStd) -> String {
+    let mut s = String::new();
+    let mut includes: Vec<String> = [
+        "iostream",
+        "string",
+        "vector",
+        "memory",
+        "algorithm",
+        "functional",
+        "map",
+        "list",
+        "optional",
+        "thread",
+        "mutex",
+        "future",
+        "atomic",
+        "filesystem",
+        "fstream",
+        "numeric",
+        "cmath",
+        "cstdio",
+    ]
+    .iter()
+    .map(|h| h.to_string())
+    .collect();
+    for c in classes {
+        for inc in &c.extra_includes {
+            if !includes.contains(inc) {
+                includes.push(inc.clone());
+            }
+        }
+    }
+    for inc in &includes {
+        s.push_str(&format!("#include <{}>\n", inc));
+    }
+    let bodies: String = classes
+        .iter()
+        .map(|c| class_definition(c, std) + &definitions(c, true, std))
+        .collect();
+    s.push_str(&stream_includes(&bodies));
+    s.push_str(&generic_includes(&bodies));
+    // `source` includes the console headers for classes using them.
+    if classes
+        .iter()
+        .any(|c| source(c).contains("#include <windows.h>"))
+    {
+        s.push_str("#ifdef _WIN32\n#include <conio.h>\n#include <windows.h>\n#endif\n");
+    }
+    s
+}
+
+/// `doc` as a Doxygen `///` block, one line per doc line.
+fn doc_comment(doc: &Option<String>, indent: &str) -> String {
+    let mut s = String::new();
+    for line in doc.iter().flat_map(|d| d.lines()) {
+        if line.is_empty() {
+            s.push_str(&format!("{}///\n", indent));
+        } else {
+            s.push_str(&format!("{}/// {}\n", indent, line));
+        }
+    }
+    s
+}
+
+/// Defines `UCPP_API`, which exports a declaration from the bridge library.
+pub const API_MACRO: &str = "#ifndef UCPP_API
+#  if defined(_WIN32) && defined(UCPP_DLL)
+#    ifdef UCPP_BUILD
+#      define UCPP_API __declspec(dllexport)
+#    else
+#      define UCPP_API __declspec(dllimport)
+#    endif
+#  elif defined(__GNUC__)
+#    define UCPP_API __attribute__((visibility(\"default\")))
+#  else
+#    define UCPP_API
+#  endif
+#endif
+";
+
+/// Defines `UCPP_PRINT` and `UCPP_FORMAT`, which `print` and f-strings
+/// lower to from C++20 on. Each takes the `std::format` arguments and the
+/// equivalent stream code, both parenthesised, and expands to `std::print`
+/// or `std::format` when the standard library ships them and to the stream
+/// code otherwise. Empty for C++17.
+fn print_macro(std: CppStd) -> String {
+    format!("{}{}", print_includes(std), print_definitions(std))
+}
+
+/// The `<format>`/`<print>` part of [`print_macro`], for units that keep
+/// includes apart from code.
+fn print_includes(std: CppStd) -> String {
+    let mut s = String::new();
+    if std >= CppStd::Cpp23 {
+        s.push_str("#if __has_include(<print>)\n#  include <print>\n#endif\n");
+    }
+    if std >= CppStd::Cpp20 {
+        s.push_str("#if __has_include(<format>)\n#  include <format>\n#endif\n");
+    }
+    s
+}
+
+fn print_definitions(std: CppStd) -> String {
+    if std < CppStd::Cpp20 {
+        return String::new();
+    }
+    let mut s = String::new();
+    s.push_str("#ifndef UCPP_PRINT\n");
+    if std >= CppStd::Cpp23 {
+        s.push_str("#  if defined(__cpp_lib_print)\n");
+        s.push_str("#    define UCPP_PRINT(f, s) std::print f\n");
+        s.push_str("#    define UCPP_FORMAT(f, s) std::format f\n");
+        s.push_str("#  elif defined(__cpp_lib_format)\n");
+    } else {
+        s.push_str("#  if defined(__cpp_lib_format)\n");
+    }
+    s.push_str("#    define UCPP_PRINT(f, s) (std::cout << std::format f)\n");
+    s.push_str("#    define UCPP_FORMAT(f, s) std::format f\n");
+    s.push_str("#  else\n");
+    s.push_str("#    define UCPP_PRINT(f, s) s\n");
+    s.push_str("#    define UCPP_FORMAT(f, s) s\n");
+    s.push_str("#  endif\n");
+    s.push_str("#endif\n");
+    s
+}
+
+/// Headers the stream lowering of `print` and f-strings needs in a unit
+/// whose code is `body`.
+fn stream_includes(body: &str) -> String {
+    let mut s = String::new();
+    if body.contains("std::ostringstream") {
+        s.push_str("#include <sstream>\n");
+    }
+    if ["std::setw", "std::setprecision", "std::setfill"]
+        .iter()
+        .any(|m| body.contains(m))
+    {
+        s.push_str("#include <iomanip>\n");
+    }
+    s
+}
+
+/// Headers the constraints of generic methods in `code` need.
+fn generic_includes(code: &str) -> String {
+    let mut s = String::new();
+    if code.contains("std::integral") || code.contains("std::floating_point") {
+        s.push_str("#include <concepts>\n");
+    }
+    if code.contains("std::enable_if_t") {
+        s.push_str("#include <type_traits>\n");
+    }
+    s
+}
+
 pub fn header(c: &Class) -> String {
+    header_with(c, CppStd::Cpp17)
+}
+
+/// Like [`header`], for code targeting `std`.
+pub fn header_with(c: &Class, std: CppStd) -> String {
     let mut h = String::new();
     h.push_str("#pragma once\n");
-    h.push_str("#ifndef UCPP_API\n");
-    h.push_str("#  if defined(_WIN32) && defined(UCPP_DLL)\n");
-    h.push_str("#    ifdef UCPP_BUILD\n");
-    h.push_str("#      define UCPP_API __declspec(dllexport)\n");
-    h.push_str("#    else\n");
-    h.push_str("#      define UCPP_API __declspec(dllimport)\n");
-    h.push_str("#    endif\n");
-    h.push_str("#  elif defined(__GNUC__)\n");
-    h.push_str("#    define UCPP_API __attribute__((visibility(\"default\")))\n");
-    h.push_str("#  else\n");
-    h.push_str("#    define UCPP_API\n");
-    h.push_str("#  endif\n");
-    h.push_str("#endif\n");
+    h.push_str(API_MACRO);
     h.push_str("#ifndef UCPP_NOEXCEPT\n");
     h.push_str("#  ifdef _MSC_VER\n");
     h.push_str("#    define UCPP_NOEXCEPT noexcept\n");
@@ -259,6 +891,11 @@ pub fn header(c: &Class) -> String {
     for sh in &std_headers {
         h.push_str(&format!("#include <{}>\n", sh));
     }
+    h.push_str(&print_macro(std));
+    // Generic methods are defined in the class.
+    let class_code = class_definition(c, std);
+    h.push_str(&stream_includes(&class_code));
+    h.push_str(&generic_includes(&class_code));
     // Collect types used by value (require full include) vs by reference (can forward declare)
     let mut value_types: Vec<String> = Vec::new();
     let mut param_types: Vec<String> = Vec::new();
@@ -316,7 +953,7 @@ pub fn header(c: &Class) -> String {
         } else {
             if t.starts_with("Vector<") && t.ends_with(">") {
                 let inner = &t[7..t.len() - 1].trim();
-                if !["Int", "Float", "Bool", "String", "Void", "Auto"].contains(&inner) {
+                if !["Int", "Float", "Bool", "String", "Void", "Auto"].contains(inner) {
                     let inc = format!("{}.hpp", inner.to_lowercase());
                     if !seen_includes.contains(&inc)
                         && inc != format!("{}.hpp", c.name.to_lowercase())
@@ -327,7 +964,7 @@ pub fn header(c: &Class) -> String {
                 }
             } else if t.starts_with("List<") && t.ends_with(">") {
                 let inner = &t[5..t.len() - 1].trim();
-                if !["Int", "Float", "Bool", "String", "Void", "Auto"].contains(&inner) {
+                if !["Int", "Float", "Bool", "String", "Void", "Auto"].contains(inner) {
                     let inc = format!("{}.hpp", inner.to_lowercase());
                     if !seen_includes.contains(&inc)
                         && inc != format!("{}.hpp", c.name.to_lowercase())
@@ -338,7 +975,7 @@ pub fn header(c: &Class) -> String {
                 }
             } else if t.starts_with("Optional<") && t.ends_with(">") {
                 let inner = &t[9..t.len() - 1].trim();
-                if !["Int", "Float", "Bool", "String", "Void", "Auto"].contains(&inner) {
+                if !["Int", "Float", "Bool", "String", "Void", "Auto"].contains(inner) {
                     let inc = format!("{}.hpp", inner.to_lowercase());
                     if !seen_includes.contains(&inc)
                         && inc != format!("{}.hpp", c.name.to_lowercase())
@@ -408,7 +1045,7 @@ pub fn header(c: &Class) -> String {
     let mut fwd: Vec<String> = Vec::new();
     for t in &param_types {
         // Handle generics: Vector<Rect> -> Rect
-        let clean = t.replace('<', " ").replace('>', " ").replace(',', " ");
+        let clean = t.replace(['<', '>', ','], " ");
         for part in clean.split_whitespace() {
             let p = part.trim();
             if [
@@ -439,10 +1076,8 @@ pub fn header(c: &Class) -> String {
             }
             // If already included, no need to forward declare
             let inc_name = format!("{}.hpp", p.to_lowercase());
-            if !seen_includes.contains(&inc_name) && p != c.name {
-                if !fwd.contains(&p.to_string()) {
-                    fwd.push(p.to_string());
-                }
+            if !seen_includes.contains(&inc_name) && p != c.name && !fwd.contains(&p.to_string()) {
+                fwd.push(p.to_string());
             }
         }
     }
@@ -462,6 +1097,122 @@ pub fn header(c: &Class) -> String {
     if let Some(ns) = &c.namespace {
         h.push_str(&format!("namespace {} {{\n", ns));
     }
+    h.push_str(&class_definition(c, std));
+    if c.namespace.is_some() {
+        h.push_str("}\n");
+    }
+    h
+}
+
+/// Whether `name` reads like a query: `get_x`, `getX`, `is_x` or `has_x`.
+fn is_query(name: &str) -> bool {
+    let get = name.strip_prefix("get").is_some_and(|rest| {
+        rest.starts_with('_') || rest.starts_with(|ch: char| ch.is_ascii_uppercase())
+    });
+    get || name.starts_with("is_") || name.starts_with("has_")
+}
+
+/// `[[nodiscard]] ` for queries that return a value, where ignoring the
+/// result is always a mistake. From C++20 on it also says why.
+fn nodiscard(m: &Method, std: CppStd) -> String {
+    if !is_query(&m.name) || cpp_type(&m.return_type) == "void" {
+        String::new()
+    } else if std >= CppStd::Cpp20 {
+        format!("[[nodiscard(\"{} is a query\")]] ", m.name)
+    } else {
+        "[[nodiscard]] ".to_string()
+    }
+}
+
+/// Whether from C++20 on `c` gets a `Fields` aggregate of its public
+/// fields, so `Point(x=1)` can construct it with a designated
+/// initialiser. Classes with their own `__init__` do not.
+fn has_fields_struct(c: &Class, std: CppStd) -> bool {
+    std >= CppStd::Cpp20
+        && c.ctor_params.is_none()
+        && c.fields.iter().any(|f| f.vis == Visibility::Public)
+}
+
+/// A parameter as declared: builtins by value, other types by const
+/// reference.
+fn param_decl(p: &crate::Param) -> String {
+    let ct = cpp_type(&p.ty);
+    let pass_const_ref = !is_builtin_ultra(&p.ty) || is_std_like(&ct) || is_container_cpp(&ct);
+    if pass_const_ref && !is_builtin_cpp(&ct) {
+        format!("const {}& {}", ct, p.name)
+    } else {
+        format!("{} {}", ct, p.name)
+    }
+}
+
+/// A method with generic parameters, defined inside its class since it is
+/// a template. From C++20 on it is an abbreviated template constrained
+/// with concepts; C++17 names a type per parameter and constrains them
+/// with `std::enable_if_t`.
+fn generic_method(c: &Class, m: &Method, std: CppStd) -> String {
+    let mut head: Vec<String> = Vec::new();
+    let mut conditions: Vec<String> = Vec::new();
+    let mut params: Vec<String> = Vec::new();
+    for p in &m.params {
+        if !is_generic(&p.ty) {
+            params.push(param_decl(p));
+        } else if std >= CppStd::Cpp20 {
+            match constraint(&p.ty) {
+                Some((concept, _)) => params.push(format!("{} auto {}", concept, p.name)),
+                None => params.push(format!("auto {}", p.name)),
+            }
+        } else {
+            let t = format!("Auto{}", head.len() + 1);
+            if let Some((_, trait_)) = constraint(&p.ty) {
+                conditions.push(format!("{}<{}>", trait_, t));
+            }
+            params.push(format!("{} {}", t, p.name));
+            head.push(format!("typename {}", t));
+        }
+    }
+    if !conditions.is_empty() {
+        head.push(format!(
+            "std::enable_if_t<{}, int> = 0",
+            conditions.join(" && ")
+        ));
+    }
+    let mut s = doc_comment(&m.doc, "  ");
+    if !head.is_empty() {
+        s.push_str(&format!("  template <{}>\n", head.join(", ")));
+    }
+    let attr = if m.vis == Visibility::Public {
+        nodiscard(m, std)
+    } else {
+        String::new()
+    };
+    s.push_str(&format!(
+        "  {}{}{} {}({}) {{\n",
+        attr,
+        if m.is_static { "static " } else { "" },
+        cpp_type(&m.return_type),
+        m.name,
+        params.join(", ")
+    ));
+    // Headers carry no `#line` directives.
+    let unmapped = Class {
+        source_file: None,
+        ..c.clone()
+    };
+    s.push_str(&gen_stmt(
+        &m.body,
+        &unmapped,
+        std,
+        2,
+        &mut LineMarks::new(&unmapped, &m.lines),
+    ));
+    s.push_str("  }\n");
+    s
+}
+
+/// The class itself, from its doc comment to the closing `};`.
+fn class_definition(c: &Class, std: CppStd) -> String {
+    let mut h = String::new();
+    h.push_str(&doc_comment(&c.doc, ""));
     if let Some(b) = &c.base {
         h.push_str(&format!("class UCPP_API {} : public {} {{\n", c.name, b));
     } else {
@@ -469,8 +1220,22 @@ pub fn header(c: &Class) -> String {
     }
     h.push_str("public:\n");
     for f in c.fields.iter().filter(|f| f.vis == Visibility::Public) {
+        h.push_str(&doc_comment(&f.doc, "  "));
         h.push_str(&format!("  {} {};\n", cpp_type(&f.ty), f.name));
     }
+    if has_fields_struct(c, std) {
+        h.push_str("  struct Fields {\n");
+        for f in c.fields.iter().filter(|f| f.vis == Visibility::Public) {
+            h.push_str(&format!(
+                "    {} {} = {};\n",
+                cpp_type(&f.ty),
+                f.name,
+                cpp_default_init(&f.ty)
+            ));
+        }
+        h.push_str("  };\n");
+        h.push_str(&format!("  explicit {}(const Fields& fields);\n", c.name));
+    }
     let ctor_needed = c.ctor_params.is_some() || !c.fields.is_empty();
     if ctor_needed {
         let has_custom_default = if let Some(params) = &c.ctor_params {
@@ -501,26 +1266,27 @@ pub fn header(c: &Class) -> String {
         }
     }
     for m in c.methods.iter().filter(|m| m.vis == Visibility::Public) {
+        if is_generic_method(m) {
+            h.push_str(&generic_method(c, m, std));
+            continue;
+        }
+        h.push_str(&doc_comment(&m.doc, "  "));
         if m.is_static {
             h.push_str(&format!(
-                "  static {} {}(",
+                "  {}static {} {}(",
+                nodiscard(m, std),
                 cpp_type(&m.return_type),
                 m.name
             ));
         } else {
-            h.push_str(&format!("  {} {}(", cpp_type(&m.return_type), m.name));
-        }
-        let mut params: Vec<String> = Vec::new();
-        for p in &m.params {
-            let ct = cpp_type(&p.ty);
-            let pass_const_ref =
-                !is_builtin_ultra(&p.ty) || is_std_like(&ct) || is_container_cpp(&ct);
-            if pass_const_ref && !is_builtin_cpp(&ct) {
-                params.push(format!("const {}& {}", ct, p.name));
-            } else {
-                params.push(format!("{} {}", ct, p.name));
-            }
+            h.push_str(&format!(
+                "  {}{} {}(",
+                nodiscard(m, std),
+                cpp_type(&m.return_type),
+                m.name
+            ));
         }
+        let params: Vec<String> = m.params.iter().map(param_decl).collect();
         h.push_str(&params.join(", "));
         // Const-correctness: mark known read-only methods as const
         let needs_const = !m.is_static
@@ -562,9 +1328,15 @@ pub fn header(c: &Class) -> String {
     if !priv_fields.is_empty() || !priv_methods.is_empty() {
         h.push_str("private:\n");
         for f in priv_fields {
+            h.push_str(&doc_comment(&f.doc, "  "));
             h.push_str(&format!("  {} {};\n", cpp_type(&f.ty), f.name));
         }
         for m in priv_methods {
+            if is_generic_method(m) {
+                h.push_str(&generic_method(c, m, std));
+                continue;
+            }
+            h.push_str(&doc_comment(&m.doc, "  "));
             if m.is_static {
                 h.push_str(&format!(
                     "  static {} {}(",
@@ -583,17 +1355,265 @@ pub fn header(c: &Class) -> String {
         }
     }
     h.push_str("};\n");
-    if c.namespace.is_some() {
-        h.push_str("}\n");
-    }
     h
 }
 
-fn gen_expr(e: &Expr, c: &Class) -> String {
+/// `s` as a C++ string literal, with quotes, backslashes and control
+/// characters escaped. Non-ASCII text gets `u8` up to C++17 so it is UTF-8
+/// whatever the execution character set; from C++20 on `u8` literals are
+/// `char8_t`, which `std::string` does not take, and builds rely on the
+/// compiler's UTF-8 default (`/utf-8` for MSVC) instead.
+fn cpp_string(s: &str, std: CppStd) -> String {
+    let mut out = String::new();
+    for ch in s.chars() {
+        match ch {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            '\r' => out.push_str("\\r"),
+            c if c.is_ascii_control() => out.push_str(&format!("\\{:03o}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    let prefix = if std < CppStd::Cpp20 && !s.is_ascii() {
+        "u8"
+    } else {
+        ""
+    };
+    format!("{}\"{}\"", prefix, out)
+}
+
+/// A piece of what `print` or an f-string writes: the contents of a string
+/// literal, or a value with its format spec.
+enum Piece<'a> {
+    Text(&'a str),
+    Value(&'a Expr, &'a str),
+}
+
+impl<'a> Piece<'a> {
+    /// `e`, or the literal `default` when it is unset; string literals
+    /// become text.
+    fn of(e: Option<&'a Expr>, default: &'a str) -> Piece<'a> {
+        match e {
+            None => Piece::Text(default),
+            Some(Expr::LiteralString(s)) => Piece::Text(s),
+            Some(e) => Piece::Value(e, ""),
+        }
+    }
+}
+
+impl<'a> From<&'a FormatPart> for Piece<'a> {
+    fn from(p: &'a FormatPart) -> Piece<'a> {
+        match p {
+            FormatPart::Text(t) => Piece::Text(t),
+            FormatPart::Value { expr, spec } => Piece::Value(expr, spec),
+        }
+    }
+}
+
+/// `value` where an operator of lower precedence than `<<` cannot escape.
+fn operand(e: &Expr, c: &Class, std: CppStd) -> String {
     match e {
-        Expr::LiteralString(s) => format!("\"{}\"", s.replace('"', "\\\"")),
+        Expr::BinaryOp(..) | Expr::Concat(..) => format!("({})", gen_expr(e, c, std)),
+        _ => gen_expr(e, c, std),
+    }
+}
+
+/// The `std::format` arguments writing `pieces`: the format string, then
+/// the values.
+fn format_args(pieces: &[Piece], c: &Class, std: CppStd) -> String {
+    let mut f = String::new();
+    let mut args = Vec::new();
+    for p in pieces {
+        match p {
+            Piece::Text(t) => f.push_str(&t.replace('{', "{{").replace('}', "}}")),
+            Piece::Value(e, spec) => {
+                f.push('{');
+                if !spec.is_empty() {
+                    f.push(':');
+                    f.push_str(spec);
+                }
+                f.push('}');
+                args.push(gen_expr(e, c, std));
+            }
+        }
+    }
+    args.insert(0, cpp_string(&f, std));
+    args.join(", ")
+}
+
+/// The `<<` chain writing `pieces` to a stream. Values may be `Bool`, so it
+/// starts with `std::boolalpha`; each spec is undone after its value.
+fn stream_chain(pieces: &[Piece], c: &Class, std: CppStd) -> String {
+    let mut s = String::new();
+    if pieces.iter().any(|p| matches!(p, Piece::Value(..))) {
+        s.push_str(" << std::boolalpha");
+    }
+    for p in pieces {
+        match p {
+            Piece::Text(t) => s.push_str(&format!(" << {}", cpp_string(t, std))),
+            Piece::Value(e, spec) => {
+                let (set, reset) = stream_spec(spec).unwrap_or_default();
+                s.push_str(&format!("{} << {}{}", set, operand(e, c, std), reset));
+            }
+        }
+    }
+    s
+}
+
+/// The stream manipulators applying the format `spec`
+/// (`[[fill]align][+][#][0][width][.precision][type]`), then the ones
+/// restoring the defaults. `None` when iostreams cannot express it, as
+/// with centring (`^`) or binary (`b`).
+pub fn stream_spec(spec: &str) -> Option<(String, String)> {
+    let chars: Vec<char> = spec.chars().collect();
+    let mut set: Vec<String> = Vec::new();
+    let mut reset: Vec<String> = Vec::new();
+    let mut i = 0;
+    let align = |ch: char| match ch {
+        '<' => Some("std::left"),
+        '>' => Some("std::right"),
+        _ => None,
+    };
+    let mut fill = None;
+    let mut adjust = None;
+    if let Some(a) = chars.get(1).and_then(|&ch| align(ch)) {
+        fill = Some(chars[0]);
+        adjust = Some(a);
+        i = 2;
+    } else if let Some(a) = chars.first().and_then(|&ch| align(ch)) {
+        adjust = Some(a);
+        i = 1;
+    } else if matches!(chars.get(1), Some('^' | '=')) || matches!(chars.first(), Some('^' | '=')) {
+        return None;
+    }
+    match chars.get(i) {
+        Some('+') => {
+            set.push("std::showpos".into());
+            reset.push("std::noshowpos".into());
+            i += 1;
+        }
+        Some('-') => i += 1,
+        Some(' ') => return None,
+        _ => {}
+    }
+    let alternate = chars.get(i) == Some(&'#');
+    if alternate {
+        i += 1;
+    }
+    if chars.get(i) == Some(&'0') && adjust.is_none() {
+        fill = Some('0');
+        adjust = Some("std::internal");
+        i += 1;
+    }
+    let start = i;
+    while chars.get(i).is_some_and(|ch| ch.is_ascii_digit()) {
+        i += 1;
+    }
+    let width: String = chars[start..i].iter().collect();
+    if chars.get(i) == Some(&'.') {
+        let start = i + 1;
+        i += 1;
+        while chars.get(i).is_some_and(|ch| ch.is_ascii_digit()) {
+            i += 1;
+        }
+        if i == start {
+            return None;
+        }
+        let precision: String = chars[start..i].iter().collect();
+        set.push(format!("std::setprecision({})", precision));
+        reset.push("std::setprecision(6)".into());
+    }
+    let (base, upper) = match &chars[i..] {
+        [] | ['d'] | ['s'] | ['g'] => (None, false),
+        ['G'] => (None, true),
+        ['f'] => (Some(("std::fixed", "std::defaultfloat")), false),
+        ['F'] => (Some(("std::fixed", "std::defaultfloat")), true),
+        ['e'] => (Some(("std::scientific", "std::defaultfloat")), false),
+        ['E'] => (Some(("std::scientific", "std::defaultfloat")), true),
+        ['x'] => (Some(("std::hex", "std::dec")), false),
+        ['X'] => (Some(("std::hex", "std::dec")), true),
+        ['o'] => (Some(("std::oct", "std::dec")), false),
+        _ => return None,
+    };
+    if alternate {
+        if !matches!(&chars[i..], ['x'] | ['X'] | ['o']) {
+            return None;
+        }
+        set.push("std::showbase".into());
+        reset.push("std::noshowbase".into());
+    }
+    if let Some((on, off)) = base {
+        set.push(on.into());
+        reset.push(off.into());
+    }
+    if upper {
+        set.push("std::uppercase".into());
+        reset.push("std::nouppercase".into());
+    }
+    if let Some(a) = adjust {
+        set.push(a.into());
+        reset.push("std::right".into());
+    }
+    if let Some(f) = fill {
+        let f = if f == '\'' || f == '\\' {
+            format!("\\{}", f)
+        } else {
+            f.to_string()
+        };
+        set.push(format!("std::setfill('{}')", f));
+        reset.push("std::setfill(' ')".into());
+    }
+    if !width.is_empty() {
+        set.push(format!("std::setw({})", width));
+    }
+    let chain = |v: Vec<String>| v.iter().map(|m| format!(" << {}", m)).collect::<String>();
+    Some((chain(set), chain(reset)))
+}
+
+/// The `field=value` arguments of a call made only of them.
+pub fn keyword_args(args: &[Expr]) -> Option<Vec<(&str, &Expr)>> {
+    if args.is_empty() {
+        return None;
+    }
+    args.iter()
+        .map(|a| match a {
+            Expr::BinaryOp(l, op, r) if op == "=" => match l.as_ref() {
+                Expr::Variable(name) => Some((name.as_str(), r.as_ref())),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect()
+}
+
+/// `Point(x=1, y=2)`: the named public fields get the values and the
+/// rest their defaults. From C++20 on it is a designated initialiser of
+/// the class's `Fields`; C++17 assigns them on a default-constructed
+/// object.
+fn keyword_construction(class: &str, fields: &[(&str, &Expr)], c: &Class, std: CppStd) -> String {
+    if std >= CppStd::Cpp20 {
+        let inits: Vec<String> = fields
+            .iter()
+            .map(|(name, value)| format!(".{} = {}", name, gen_expr(value, c, std)))
+            .collect();
+        format!("{}({}::Fields{{{}}})", class, class, inits.join(", "))
+    } else {
+        let sets: String = fields
+            .iter()
+            .map(|(name, value)| format!("ucpp_obj.{} = {}; ", name, gen_expr(value, c, std)))
+            .collect();
+        format!("[&] {{ {} ucpp_obj; {}return ucpp_obj; }}()", class, sets)
+    }
+}
+
+fn gen_expr(e: &Expr, c: &Class, std: CppStd) -> String {
+    match e {
+        Expr::LiteralString(s) => cpp_string(s, std),
         Expr::LiteralInt(n) => format!("{}", n),
-        Expr::LiteralFloat(f) => format!("{}", f),
+        // `{:?}` keeps the point of `5.0`, which must stay a double.
+        Expr::LiteralFloat(f) => format!("{:?}", f),
         Expr::LiteralBool(b) => {
             if *b {
                 "true".to_string()
@@ -606,7 +1626,7 @@ fn gen_expr(e: &Expr, c: &Class) -> String {
                 "not" => "!",
                 _ => op.as_str(),
             };
-            format!("({}{})", cpp, gen_expr(x, c))
+            format!("({}{})", cpp, gen_expr(x, c, std))
         }
         Expr::Variable(s) => {
             // Map generics like Vector<Rect>() into std::vector<Rect>()
@@ -625,7 +1645,7 @@ fn gen_expr(e: &Expr, c: &Class) -> String {
         }
         Expr::SelfField(n) => format!("this->{}", n),
         Expr::SelfCall { name, args } => {
-            let a: Vec<String> = args.iter().map(|x| gen_expr(x, c)).collect();
+            let a: Vec<String> = args.iter().map(|x| gen_expr(x, c, std)).collect();
             // Basic static check: if method is in class and static, use ClassName::
             // We need to find if 'name' is a static method in 'c'.
             let is_static = c.methods.iter().any(|m| m.name == *name && m.is_static);
@@ -636,7 +1656,7 @@ fn gen_expr(e: &Expr, c: &Class) -> String {
             }
         }
         Expr::SuperCall { name, args } => {
-            let a: Vec<String> = args.iter().map(|x| gen_expr(x, c)).collect();
+            let a: Vec<String> = args.iter().map(|x| gen_expr(x, c, std)).collect();
             if let Some(b) = &c.base {
                 format!("{}::{}({})", b, name, a.join(", "))
             } else {
@@ -644,16 +1664,10 @@ fn gen_expr(e: &Expr, c: &Class) -> String {
             }
         }
         Expr::FunctionCall { name, args } => {
-            if name == "print" {
-                let mut s = "std::cout".to_string();
-                for arg in args {
-                    s.push_str(" << ");
-                    s.push_str(&gen_expr(arg, c));
-                }
-                s.push_str(" << std::endl");
-                return s;
+            if let Some(fields) = keyword_args(args) {
+                return keyword_construction(name, &fields, c, std);
             }
-            let a: Vec<String> = args.iter().map(|x| gen_expr(x, c)).collect();
+            let a: Vec<String> = args.iter().map(|x| gen_expr(x, c, std)).collect();
             // Replace dot with double colon for likely static calls if it looks like Class.Method
             let cpp_name = if name.contains('.') && name.chars().next().unwrap().is_uppercase() {
                 name.replace('.', "::")
@@ -662,6 +1676,55 @@ fn gen_expr(e: &Expr, c: &Class) -> String {
             };
             format!("{}({})", cpp_name, a.join(", "))
         }
+        Expr::Print { args, sep, end } => {
+            let mut pieces = Vec::new();
+            for (i, arg) in args.iter().enumerate() {
+                if i > 0 {
+                    pieces.push(Piece::of(sep.as_deref(), " "));
+                }
+                match arg {
+                    Expr::Format(parts) => pieces.extend(parts.iter().map(Piece::from)),
+                    _ => pieces.push(Piece::of(Some(arg), "")),
+                }
+            }
+            pieces.push(Piece::of(end.as_deref(), "\n"));
+            let stream = format!("std::cout{}", stream_chain(&pieces, c, std));
+            if std >= CppStd::Cpp20 {
+                format!(
+                    "UCPP_PRINT(({}), ({}))",
+                    format_args(&pieces, c, std),
+                    stream
+                )
+            } else {
+                stream
+            }
+        }
+        Expr::Format(parts) => {
+            let pieces: Vec<Piece> = parts.iter().map(Piece::from).collect();
+            if !pieces.iter().any(|p| matches!(p, Piece::Value(..))) {
+                let text: String = parts
+                    .iter()
+                    .map(|p| match p {
+                        FormatPart::Text(t) => t.as_str(),
+                        FormatPart::Value { .. } => "",
+                    })
+                    .collect();
+                return format!("std::string({})", cpp_string(&text, std));
+            }
+            let stream = format!(
+                "[&] {{ std::ostringstream ucpp_out; ucpp_out{}; return ucpp_out.str(); }}()",
+                stream_chain(&pieces, c, std)
+            );
+            if std >= CppStd::Cpp20 {
+                format!(
+                    "UCPP_FORMAT(({}), ({}))",
+                    format_args(&pieces, c, std),
+                    stream
+                )
+            } else {
+                stream
+            }
+        }
         Expr::BinaryOp(l, op, r) => {
             let cpp_op = match op.as_str() {
                 "and" => "&&",
@@ -671,30 +1734,150 @@ fn gen_expr(e: &Expr, c: &Class) -> String {
             if op == "+" {
                 match (&**l, &**r) {
                     (Expr::LiteralString(_), _) => {
-                        format!("std::string({}) + {}", gen_expr(l, c), gen_expr(r, c))
+                        format!(
+                            "std::string({}) + {}",
+                            gen_expr(l, c, std),
+                            gen_expr(r, c, std)
+                        )
                     }
                     (_, Expr::LiteralString(_)) => {
-                        format!("{} + std::string({})", gen_expr(l, c), gen_expr(r, c))
+                        format!(
+                            "{} + std::string({})",
+                            gen_expr(l, c, std),
+                            gen_expr(r, c, std)
+                        )
                     }
-                    _ => format!("{} + {}", gen_expr(l, c), gen_expr(r, c)),
+                    _ => format!("{} + {}", gen_expr(l, c, std), gen_expr(r, c, std)),
                 }
             } else {
-                format!("{} {} {}", gen_expr(l, c), cpp_op, gen_expr(r, c))
+                format!("{} {} {}", gen_expr(l, c, std), cpp_op, gen_expr(r, c, std))
             }
         }
-        Expr::Concat(l, r) => format!("std::string({}) + {}", gen_expr(l, c), gen_expr(r, c)),
-        Expr::Native(s) => s.replace("\\\"", "\""),
+        Expr::Concat(l, r) => format!(
+            "std::string({}) + {}",
+            gen_expr(l, c, std),
+            gen_expr(r, c, std)
+        ),
+        Expr::Native(s) => s.clone(),
         _ => "".to_string(),
     }
 }
 
-fn gen_stmt(e: &Expr, c: &Class, indent: usize) -> String {
+/// Pseudo-file of the `#line` directive that closes a mapped method;
+/// [`finish_line_directives`] points it back at the generated file.
+const GENERATED_FILE: &str = "<ultracpp-generated>";
+
+fn line_directive(line: usize, file: &str) -> String {
+    format!(
+        "#line {} \"{}\"\n",
+        line,
+        file.replace('\\', "\\\\").replace('"', "\\\"")
+    )
+}
+
+/// Hands out the `#line` directive of each statement while `gen_stmt` walks
+/// a body, in [`SourceLines::stmts`] order. Yields nothing for classes
+/// without a `source_file`.
+struct LineMarks<'a> {
+    file: Option<&'a str>,
+    lines: std::slice::Iter<'a, usize>,
+}
+
+impl<'a> LineMarks<'a> {
+    fn new(c: &'a Class, lines: &'a SourceLines) -> Self {
+        LineMarks {
+            file: c.source_file.as_deref(),
+            lines: lines.stmts.iter(),
+        }
+    }
+
+    fn none() -> Self {
+        LineMarks {
+            file: None,
+            lines: [].iter(),
+        }
+    }
+
+    fn next(&mut self) -> String {
+        match (self.file, self.lines.next()) {
+            (Some(f), Some(&n)) if n > 0 => line_directive(n, f),
+            _ => String::new(),
+        }
+    }
+}
+
+/// Directive placed before the `def` of a method, when the class is mapped.
+fn def_directive(c: &Class, lines: &SourceLines) -> String {
+    match &c.source_file {
+        Some(f) if lines.def > 0 => line_directive(lines.def, f),
+        _ => String::new(),
+    }
+}
+
+/// Directive returning to generated code after a mapped method.
+fn end_directive(c: &Class) -> String {
+    match &c.source_file {
+        Some(_) => line_directive(1, GENERATED_FILE),
+        None => String::new(),
+    }
+}
+
+/// Gives the directives that close mapped methods their real line in
+/// `text`, once it is complete and about to be saved as `name`.
+pub fn finish_line_directives(text: &str, name: &str) -> String {
+    let end = line_directive(1, GENERATED_FILE);
+    let end = end.trim_end();
+    let mut out = String::with_capacity(text.len());
+    for (i, l) in text.lines().enumerate() {
+        if l == end {
+            out.push_str(&line_directive(i + 2, name));
+        } else {
+            out.push_str(l);
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Where each line of a finished generated file comes from: one
+/// `(generated line, .upp file, .upp line)` entry per `#line` directive,
+/// valid until the next one. Lines before the first entry are generated.
+pub fn line_map(text: &str) -> Vec<(usize, String, usize)> {
+    let mut out = Vec::new();
+    for (i, l) in text.lines().enumerate() {
+        let Some(rest) = l.strip_prefix("#line ") else {
+            continue;
+        };
+        let Some((n, file)) = rest.split_once(' ') else {
+            continue;
+        };
+        let (Ok(n), Some(file)) = (
+            n.parse::<usize>(),
+            file.strip_prefix('"').and_then(|f| f.strip_suffix('"')),
+        ) else {
+            continue;
+        };
+        let file = file.replace("\\\"", "\"").replace("\\\\", "\\");
+        out.push((i + 2, file, n));
+    }
+    out
+}
+
+fn gen_stmt(e: &Expr, c: &Class, std: CppStd, indent: usize, marks: &mut LineMarks) -> String {
+    let mark = match e {
+        Expr::Block(_) => String::new(),
+        _ => marks.next(),
+    };
+    mark + &gen_stmt_code(e, c, std, indent, marks)
+}
+
+fn gen_stmt_code(e: &Expr, c: &Class, std: CppStd, indent: usize, marks: &mut LineMarks) -> String {
     let prefix = "  ".repeat(indent);
     match e {
         Expr::Block(stmts) => {
             let mut out = String::new();
             for s in stmts {
-                out.push_str(&gen_stmt(s, c, indent));
+                out.push_str(&gen_stmt(s, c, std, indent, marks));
             }
             out
         }
@@ -753,20 +1936,20 @@ fn gen_stmt(e: &Expr, c: &Class, indent: usize) -> String {
             then_body,
             else_body,
         } => {
-            let mut out = format!("{}if ({}) {{\n", prefix, gen_expr(cond, c));
-            out.push_str(&gen_stmt(then_body, c, indent + 1));
+            let mut out = format!("{}if ({}) {{\n", prefix, gen_expr(cond, c, std));
+            out.push_str(&gen_stmt(then_body, c, std, indent + 1, marks));
             out.push_str(&format!("{}}}", prefix));
             if let Some(else_b) = else_body {
                 out.push_str(" else {\n");
-                out.push_str(&gen_stmt(else_b, c, indent + 1));
+                out.push_str(&gen_stmt(else_b, c, std, indent + 1, marks));
                 out.push_str(&format!("{}}}", prefix));
             }
             out.push('\n');
             out
         }
         Expr::While { cond, body } => {
-            let mut out = format!("{}while ({}) {{\n", prefix, gen_expr(cond, c));
-            out.push_str(&gen_stmt(body, c, indent + 1));
+            let mut out = format!("{}while ({}) {{\n", prefix, gen_expr(cond, c, std));
+            out.push_str(&gen_stmt(body, c, std, indent + 1, marks));
             out.push_str(&format!("{}}}\n", prefix));
             out
         }
@@ -777,7 +1960,7 @@ fn gen_stmt(e: &Expr, c: &Class, indent: usize) -> String {
                     prefix,
                     cpp_type(ty),
                     name,
-                    gen_expr(v, c)
+                    gen_expr(v, c, std)
                 )
             } else {
                 format!("{}{} {};\n", prefix, cpp_type(ty), name)
@@ -785,7 +1968,7 @@ fn gen_stmt(e: &Expr, c: &Class, indent: usize) -> String {
         }
         Expr::Return(val) => {
             if let Some(v) = val {
-                format!("{}return {};\n", prefix, gen_expr(v, c))
+                format!("{}return {};\n", prefix, gen_expr(v, c, std))
             } else {
                 format!("{}return;\n", prefix)
             }
@@ -794,17 +1977,22 @@ fn gen_stmt(e: &Expr, c: &Class, indent: usize) -> String {
             let lines: Vec<&str> = s.lines().collect();
             let mut out = String::new();
             for l in lines {
-                out.push_str(&format!("{}{}\n", prefix, l.replace("\\\"", "\"")));
+                out.push_str(&format!("{}{}\n", prefix, l));
             }
             out
         }
         _ => {
-            format!("{}{};\n", prefix, gen_expr(e, c))
+            format!("{}{};\n", prefix, gen_expr(e, c, std))
         }
     }
 }
 
 pub fn source(c: &Class) -> String {
+    source_with(c, CppStd::Cpp17)
+}
+
+/// Like [`source`], for code targeting `std`.
+pub fn source_with(c: &Class, std: CppStd) -> String {
     let mut s = String::new();
     s.push_str("#include \"pch.hpp\"\n");
     s.push_str(&format!("#include \"{}.hpp\"\n", c.name.to_lowercase()));
@@ -823,6 +2011,8 @@ pub fn source(c: &Class) -> String {
                 | "double"
                 | "Double"
                 | "Auto"
+                | "Integral"
+                | "Floating"
                 | "Vector"
                 | "Map"
                 | "List"
@@ -836,10 +2026,7 @@ pub fn source(c: &Class) -> String {
         )
     }
     fn extract_types(t: &str) -> Vec<String> {
-        let clean = t
-            .replace('<', " ")
-            .replace('>', " ")
-            .replace(',', " ");
+        let clean = t.replace(['<', '>', ','], " ");
         let mut out: Vec<String> = Vec::new();
         for part in clean.split_whitespace() {
             let p = part.trim();
@@ -856,9 +2043,7 @@ pub fn source(c: &Class) -> String {
         for p in &m.params {
             for ty in extract_types(&p.ty) {
                 let low = ty.to_lowercase();
-                if low != c.name.to_lowercase()
-                    && !sig_includes.iter().any(|x| x == &low)
-                {
+                if low != c.name.to_lowercase() && !sig_includes.iter().any(|x| x == &low) {
                     sig_includes.push(low);
                 }
             }
@@ -867,9 +2052,7 @@ pub fn source(c: &Class) -> String {
         if !is_builtin_sig(rt) && rt != "Void" {
             for ty in extract_types(rt) {
                 let low = ty.to_lowercase();
-                if low != c.name.to_lowercase()
-                    && !sig_includes.iter().any(|x| x == &low)
-                {
+                if low != c.name.to_lowercase() && !sig_includes.iter().any(|x| x == &low) {
                     sig_includes.push(low);
                 }
             }
@@ -1000,11 +2183,7 @@ pub fn source(c: &Class) -> String {
             }
             Expr::While { body, .. } => collect_class_refs(body, acc, self_lower),
             Expr::Return(Some(v)) => collect_class_refs(v, acc, self_lower),
-            Expr::VarDecl { value, .. } => {
-                if let Some(v) = value {
-                    collect_class_refs(v, acc, self_lower);
-                }
-            }
+            Expr::VarDecl { value: Some(v), .. } => collect_class_refs(v, acc, self_lower),
             _ => {}
         }
     }
@@ -1043,7 +2222,7 @@ pub fn source(c: &Class) -> String {
             }
         }
         // Fallback: scan generated code for static class usages like Utils::Version::...
-        let body_code = gen_stmt(&m.body, c, 1);
+        let body_code = gen_stmt(&m.body, c, std, 1, &mut LineMarks::none());
         let mut scan_refs: Vec<String> = Vec::new();
         let bytes = body_code.as_bytes();
         let mut i = 0usize;
@@ -1073,9 +2252,11 @@ pub fn source(c: &Class) -> String {
                         break;
                     }
                 }
-                // Prefer 'name' when it looks like a type; otherwise fall back to 'pre'
+                // Prefer 'name' when it looks like a type; otherwise fall back to 'pre'.
+                // A designated initialiser names the class's own `Fields`.
                 let mut candidate: Option<String> = None;
-                if !name.is_empty() {
+                let fields = name == "Fields" && bytes.get(j) == Some(&b'{');
+                if !name.is_empty() && !fields {
                     if let Some(first) = name.chars().next() {
                         if first.is_uppercase() && !is_builtin_class_name(&name) {
                             candidate = Some(name.to_lowercase());
@@ -1111,6 +2292,8 @@ pub fn source(c: &Class) -> String {
     s.push_str("#include <string>\n");
     s.push_str("#include <vector>\n");
     s.push_str("#include <iostream>\n");
+    let defs = definitions(c, false, std);
+    s.push_str(&stream_includes(&defs));
     if need_win {
         s.push_str("#ifdef _WIN32\n");
         s.push_str("#include <conio.h>\n");
@@ -1121,6 +2304,18 @@ pub fn source(c: &Class) -> String {
     if let Some(ns) = &c.namespace {
         s.push_str(&format!("namespace {} {{\n", ns));
     }
+    s.push_str(&defs);
+    if c.namespace.is_some() {
+        s.push_str("}\n");
+    }
+    s
+}
+
+/// Constructors and methods of `c`, defined outside the class. `inline`
+/// marks them for a header included by several translation units.
+fn definitions(c: &Class, inline: bool, std: CppStd) -> String {
+    let prefix = if inline { "inline " } else { "" };
+    let mut s = String::new();
     let ctor_needed = c.ctor_params.is_some() || !c.fields.is_empty();
     if ctor_needed {
         let has_custom_default = if let Some(params) = &c.ctor_params {
@@ -1130,7 +2325,7 @@ pub fn source(c: &Class) -> String {
         };
 
         if !has_custom_default {
-            s.push_str(&format!("{}::{}() : ", c.name, c.name));
+            s.push_str(&format!("{}{}::{}() : ", prefix, c.name, c.name));
             let mut inits: Vec<String> = Vec::new();
             for f in &c.fields {
                 inits.push(format!("{}({})", f.name, cpp_default_init(&f.ty)));
@@ -1139,7 +2334,10 @@ pub fn source(c: &Class) -> String {
             s.push_str(" {}\n");
         }
 
-        s.push_str(&format!("{}::{}(", c.name, c.name));
+        if c.ctor_body.is_some() {
+            s.push_str(&def_directive(c, &c.ctor_lines));
+        }
+        s.push_str(&format!("{}{}::{}(", prefix, c.name, c.name));
         let mut params: Vec<String> = Vec::new();
         let mut param_names: Vec<String> = Vec::new();
         if let Some(ps) = &c.ctor_params {
@@ -1166,38 +2364,54 @@ pub fn source(c: &Class) -> String {
         s.push_str(&inits.join(", "));
         s.push_str(" {\n");
         if let Some(body) = &c.ctor_body {
-            s.push_str(&gen_stmt(body, c, 1));
+            s.push_str(&gen_stmt(
+                body,
+                c,
+                std,
+                1,
+                &mut LineMarks::new(c, &c.ctor_lines),
+            ));
         }
         s.push_str("}\n");
+        if c.ctor_body.is_some() {
+            s.push_str(&end_directive(c));
+        }
     }
-    for m in &c.methods {
-        s.push_str(&method_impl(c, m));
-        // Ensure method is closed properly if method_impl didn't close it (it does now)
+    if has_fields_struct(c, std) {
+        let inits: Vec<String> = c
+            .fields
+            .iter()
+            .map(|f| match f.vis {
+                Visibility::Public => format!("{}(fields.{})", f.name, f.name),
+                Visibility::Private => format!("{}({})", f.name, cpp_default_init(&f.ty)),
+            })
+            .collect();
+        s.push_str(&format!(
+            "{}{}::{}(const Fields& fields) : {} {{}}\n",
+            prefix,
+            c.name,
+            c.name,
+            inits.join(", ")
+        ));
     }
-    if c.namespace.is_some() {
-        s.push_str("}\n");
+    // Generic methods are defined in the class.
+    for m in c.methods.iter().filter(|m| !is_generic_method(m)) {
+        s.push_str(&method_impl(c, m, inline, std));
+        // Ensure method is closed properly if method_impl didn't close it (it does now)
     }
     s
 }
 
-fn method_impl(c: &Class, m: &Method) -> String {
-    let mut out = String::new();
+fn method_impl(c: &Class, m: &Method, inline: bool, std: CppStd) -> String {
+    let mut out = def_directive(c, &m.lines);
     out.push_str(&format!(
-        "{} {}::{}(",
+        "{}{} {}::{}(",
+        if inline { "inline " } else { "" },
         cpp_type(&m.return_type),
         c.name,
         m.name
     ));
-    let mut params: Vec<String> = Vec::new();
-    for p in &m.params {
-        let ct = cpp_type(&p.ty);
-        let pass_const_ref = !is_builtin_ultra(&p.ty) || is_std_like(&ct) || is_container_cpp(&ct);
-        if pass_const_ref && !is_builtin_cpp(&ct) {
-            params.push(format!("const {}& {}", ct, p.name));
-        } else {
-            params.push(format!("{} {}", ct, p.name));
-        }
-    }
+    let params: Vec<String> = m.params.iter().map(param_decl).collect();
     out.push_str(&params.join(", "));
     // Const-correctness in implementation
     let needs_const = !m.is_static
@@ -1223,7 +2437,14 @@ fn method_impl(c: &Class, m: &Method) -> String {
     } else {
         out.push_str(") {\n");
     }
-    out.push_str(&gen_stmt(&m.body, c, 1));
+    out.push_str(&gen_stmt(
+        &m.body,
+        c,
+        std,
+        1,
+        &mut LineMarks::new(c, &m.lines),
+    ));
     out.push_str("}\n");
+    out.push_str(&end_directive(c));
     out
 }
@@ -0,0 +1,224 @@
+//! Structured diagnostics parsed from the output of g++, clang++ and cl.exe.
+
+use std::fmt;
+use std::path::Path;
+
+use crate::json::{obj, Value};
+use crate::sourcemap::SourceMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Severity {
+    fn parse(s: &str) -> Option<Severity> {
+        match s {
+            "error" | "fatal error" => Some(Severity::Error),
+            "warning" => Some(Severity::Warning),
+            "note" => Some(Severity::Note),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+            Severity::Note => "note",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompilerDiagnostic {
+    /// `None` for messages without a location, e.g. from the linker.
+    pub file: Option<String>,
+    pub line: Option<usize>,
+    /// 1-based column in the file the compiler read; dropped once the
+    /// location is mapped to `.upp`, where it would not line up.
+    pub column: Option<usize>,
+    pub severity: Severity,
+    pub message: String,
+}
+
+const SEVERITIES: [&str; 4] = ["fatal error", "error", "warning", "note"];
+
+/// The diagnostics in compiler output, in order. Lines that are not
+/// diagnostics (source excerpts, "In file included from", ...) are skipped.
+pub fn parse(output: &str) -> Vec<CompilerDiagnostic> {
+    output
+        .lines()
+        .filter_map(|l| {
+            parse_gnu(l)
+                .or_else(|| parse_msvc(l))
+                .or_else(|| parse_linker(l))
+        })
+        .collect()
+}
+
+/// GNU ld names the missing symbol on a line of its own.
+fn parse_linker(line: &str) -> Option<CompilerDiagnostic> {
+    let at = line.find("undefined reference to")?;
+    Some(CompilerDiagnostic {
+        file: None,
+        line: None,
+        column: None,
+        severity: Severity::Error,
+        message: line[at..].trim().to_string(),
+    })
+}
+
+/// `file:line:col: error: message`, `file:line: error: message` or
+/// `tool: error: message`.
+fn parse_gnu(line: &str) -> Option<CompilerDiagnostic> {
+    let (at, sev) = SEVERITIES
+        .iter()
+        .filter_map(|s| line.find(&format!(": {}: ", s)).map(|i| (i, *s)))
+        .min_by_key(|(i, _)| *i)?;
+    let prefix = &line[..at];
+    // `file(line): note: ...` is MSVC.
+    if prefix.ends_with(')') {
+        return None;
+    }
+    let message = line[at + sev.len() + 4..].trim().to_string();
+    let severity = Severity::parse(sev)?;
+    let numbers: Vec<&str> = prefix.rsplitn(3, ':').collect();
+    let num = |s: &str| s.parse::<usize>().ok();
+    let (file, line_no, column) = match numbers.as_slice() {
+        [col, line, file] if num(col).is_some() && num(line).is_some() => {
+            (file.to_string(), num(line), num(col))
+        }
+        [line, ..] if num(line).is_some() => {
+            let file = prefix[..prefix.len() - line.len() - 1].to_string();
+            (file, num(line), None)
+        }
+        _ => {
+            return Some(CompilerDiagnostic {
+                file: None,
+                line: None,
+                column: None,
+                severity,
+                message: format!("{}: {}", prefix, message),
+            })
+        }
+    };
+    Some(CompilerDiagnostic {
+        file: Some(file),
+        line: line_no,
+        column,
+        severity,
+        message,
+    })
+}
+
+/// `file(line): error C2065: message`, `file(line,col): warning C4100:
+/// message`, `file(line): note: message` or `LINK : fatal error LNK1120:
+/// message`.
+fn parse_msvc(line: &str) -> Option<CompilerDiagnostic> {
+    let (at, sev) = SEVERITIES
+        .iter()
+        .filter_map(|s| {
+            let i = line
+                .find(&format!(": {} ", s))
+                .or_else(|| line.find(&format!(": {}:", s)))?;
+            Some((i, *s))
+        })
+        .min_by_key(|(i, _)| *i)?;
+    let severity = Severity::parse(sev)?;
+    let rest = line[at + sev.len() + 2..].trim_start_matches(':').trim();
+    // The code (`C2065`, `LNK1120`) stays in the message.
+    let message = rest.to_string();
+    let prefix = line[..at].trim_end();
+    let location = prefix.strip_suffix(')').and_then(|p| {
+        let (file, pos) = p.rsplit_once('(')?;
+        let mut nums = pos.split(',').map(|n| n.trim().parse::<usize>().ok());
+        Some((file.to_string(), nums.next()??, nums.next().flatten()))
+    });
+    Some(match location {
+        Some((file, line, column)) => CompilerDiagnostic {
+            file: Some(file),
+            line: Some(line),
+            column,
+            severity,
+            message,
+        },
+        // Tools write `LINK : error`; anything else is not a diagnostic.
+        None if line[..at].ends_with(' ') && !prefix.is_empty() => CompilerDiagnostic {
+            file: None,
+            line: None,
+            column: None,
+            severity,
+            message: format!("{}: {}", prefix, message),
+        },
+        None => return None,
+    })
+}
+
+impl CompilerDiagnostic {
+    /// Points a location in mapped generated code at the `.upp` line
+    /// behind it.
+    pub fn map_to_source(mut self, map: &SourceMap) -> CompilerDiagnostic {
+        if let (Some(file), Some(line)) = (&self.file, self.line) {
+            if let Some((source, source_line)) = map.lookup(file, line) {
+                self.file = Some(source.to_string());
+                self.line = Some(source_line);
+            }
+        }
+        if self.file.as_deref().is_some_and(|f| f.ends_with(".upp")) {
+            self.column = None;
+        }
+        self
+    }
+
+    /// Resolves a relative `file` against `dir`, the directory the
+    /// compiler ran in.
+    pub fn located_in(mut self, dir: &Path) -> CompilerDiagnostic {
+        if let Some(f) = &self.file {
+            self.file = Some(dir.join(f).to_string_lossy().replace('\\', "/"));
+        }
+        self
+    }
+
+    /// Shows `file` relative to `base` when it lies inside it.
+    pub fn relative_to(mut self, base: &Path) -> CompilerDiagnostic {
+        if let Some(f) = &self.file {
+            if let Ok(rel) = Path::new(f).strip_prefix(base) {
+                self.file = Some(rel.to_string_lossy().replace('\\', "/"));
+            }
+        }
+        self
+    }
+
+    pub fn to_json(&self) -> Value {
+        let opt = |n: Option<usize>| n.map_or(Value::Null, Value::from);
+        obj(vec![
+            (
+                "file",
+                self.file.as_deref().map_or(Value::Null, Value::from),
+            ),
+            ("line", opt(self.line)),
+            ("column", opt(self.column)),
+            ("severity", self.severity.as_str().into()),
+            ("message", self.message.as_str().into()),
+        ])
+    }
+}
+
+impl fmt::Display for CompilerDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file)?;
+            if let Some(line) = self.line {
+                write!(f, "{}:", line)?;
+            }
+            if let Some(column) = self.column {
+                write!(f, "{}:", column)?;
+            }
+            write!(f, " ")?;
+        }
+        write!(f, "{}: {}", self.severity.as_str(), self.message)
+    }
+}
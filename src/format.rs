@@ -0,0 +1,639 @@
+//! Canonical formatting of `.upp` sources, used by `ultracpp fmt`.
+//!
+//! The source is read as a tree of lines nested by indentation, keeping
+//! comments on the lines they belong to, and printed back with four spaces
+//! per level, spaced operators, `class Child(Base):` headers and the
+//! top-level directives grouped at the start of the file. The result is
+//! parsed again and rejected if its meaning differs from the input.
+
+use crate::parser::{self, split_comment};
+use crate::{Class, SourceLines};
+
+const INDENT: usize = 4;
+
+fn indent_of(line: &str) -> usize {
+    let mut count = 0;
+    for ch in line.chars() {
+        match ch {
+            ' ' => count += 1,
+            '\t' => count += 4,
+            _ => break,
+        }
+    }
+    count
+}
+
+fn collapse_ws(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn is_ident(s: &str) -> bool {
+    !s.is_empty() && s.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// What a line is, which decides how its children are read.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Class,
+    Method,
+    Statement,
+    /// Lines the formatter only re-indents, e.g. the legacy type blocks.
+    Other,
+}
+
+/// Order of the directive groups at the top of a file.
+fn directive_group(line: &str) -> Option<usize> {
+    let word = line.split_whitespace().next()?;
+    let bare = line.split_whitespace().count() == 1;
+    match word {
+        "namespace" if !bare => Some(0),
+        "std" | "math" | "global" if bare => Some(1),
+        "profile" if !bare => Some(1),
+        "use" if !bare => Some(2),
+        "io" | "string" | "vector" if bare => Some(3),
+        "capability" if !bare => Some(3),
+        "import" if !bare => Some(4),
+        "entry" | "run" if !bare => Some(5),
+        _ => None,
+    }
+}
+
+/// One printed line.
+#[derive(Debug, Clone)]
+struct Out {
+    level: usize,
+    text: String,
+    blank_before: bool,
+    comment: bool,
+    class_header: bool,
+    /// Extra spaces kept from the source, for the body of `native` blocks.
+    extra: usize,
+    verbatim: bool,
+}
+
+impl Out {
+    fn new(level: usize, text: String, blank_before: bool) -> Out {
+        Out {
+            level,
+            text,
+            blank_before,
+            comment: false,
+            class_header: false,
+            extra: 0,
+            verbatim: false,
+        }
+    }
+}
+
+fn with_comment(code: String, comment: Option<&str>) -> String {
+    match comment {
+        Some(c) if code.is_empty() => c.to_string(),
+        Some(c) => format!("{}  {}", code, c),
+        None => code,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Word(String),
+    Str(String),
+    Op(String),
+    Open(char),
+    Close(char),
+    Comma,
+    Other(char),
+}
+
+const OPS: &[&str] = &[
+    "==", "!=", "<=", ">=", "&&", "||", "+=", "-=", "*=", "/=", "%=", "->", "::", "=", "<", ">",
+    "+", "-", "*", "/", "%", "!",
+];
+
+fn tokenize(s: &str) -> Vec<(Tok, bool)> {
+    let chars: Vec<char> = s.chars().collect();
+    let mut toks: Vec<(Tok, bool)> = Vec::new();
+    let mut i = 0;
+    let mut ws = false;
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() {
+            ws = true;
+            i += 1;
+            continue;
+        }
+        let tok = if c == '"' {
+            let start = i;
+            let close: &[char] = if chars[i..].starts_with(&['"'; 3]) {
+                &['"'; 3]
+            } else {
+                &['"']
+            };
+            i += close.len();
+            while i < chars.len() && !chars[i..].starts_with(close) {
+                // An escaped character never ends the literal.
+                i += if chars[i] == '\\' { 2 } else { 1 };
+            }
+            i = (i + close.len()).min(chars.len());
+            Tok::Str(chars[start..i].iter().collect())
+        } else if c.is_alphanumeric() || c == '_' || c == '.' {
+            let start = i;
+            while i < chars.len() {
+                let ch = chars[i];
+                if ch.is_alphanumeric() || ch == '_' || ch == '.' {
+                    i += 1;
+                } else if (ch == '-' || ch == '+')
+                    && chars[start].is_ascii_digit()
+                    && matches!(chars[i - 1], 'e' | 'E')
+                    && chars.get(i + 1).is_some_and(|d| d.is_ascii_digit())
+                {
+                    // Exponent of a float literal such as `1e-5`.
+                    i += 1;
+                } else {
+                    break;
+                }
+            }
+            Tok::Word(chars[start..i].iter().collect())
+        } else if c == '(' || c == '[' {
+            i += 1;
+            Tok::Open(c)
+        } else if c == ')' || c == ']' {
+            i += 1;
+            Tok::Close(c)
+        } else if c == ',' {
+            i += 1;
+            Tok::Comma
+        } else if let Some(op) = OPS.iter().find(|op| {
+            op.chars()
+                .enumerate()
+                .all(|(k, oc)| chars.get(i + k) == Some(&oc))
+        }) {
+            i += op.chars().count();
+            Tok::Op(op.to_string())
+        } else {
+            i += 1;
+            Tok::Other(c)
+        };
+        toks.push((tok, ws));
+        ws = false;
+    }
+    toks
+}
+
+/// Normalises the spacing of an expression: one space around binary
+/// operators and after commas, none inside brackets. Anything else keeps
+/// whether it was separated by whitespace.
+fn expr(s: &str) -> String {
+    let toks = tokenize(s);
+    let mut out = String::new();
+    // Whether each operator token is used as a spaced binary operator.
+    let mut binary: Vec<bool> = Vec::with_capacity(toks.len());
+    let mut depth = 0;
+    for (k, (t, ws)) in toks.iter().enumerate() {
+        let prev = if k == 0 { None } else { Some(&toks[k - 1].0) };
+        let next_ws = toks.get(k + 1).map(|(_, w)| *w).unwrap_or(false);
+        match t {
+            Tok::Open(_) => depth += 1,
+            Tok::Close(_) => depth -= 1,
+            _ => {}
+        }
+        // `name=value` opening an argument is a keyword, written tight.
+        let keyword = depth > 0
+            && matches!(t, Tok::Op(op) if op == "=")
+            && matches!(prev, Some(Tok::Word(_)))
+            && k >= 2
+            && matches!(toks[k - 2].0, Tok::Open(_) | Tok::Comma);
+        let is_binary = match t {
+            Tok::Op(_) if keyword => false,
+            Tok::Op(op) => {
+                let operand_before = match prev {
+                    Some(Tok::Word(w)) => !matches!(w.as_str(), "and" | "or" | "not"),
+                    Some(Tok::Str(_)) | Some(Tok::Close(_)) => true,
+                    _ => false,
+                };
+                match op.as_str() {
+                    "::" | "->" | "!" => false,
+                    // `List<Int>` stays tight when written tight after a type name.
+                    "<" | ">" => {
+                        let type_like = matches!(prev, Some(Tok::Word(w))
+                            if w.starts_with(|c: char| c.is_uppercase()));
+                        operand_before && !(type_like && !ws && !next_ws)
+                    }
+                    _ => operand_before,
+                }
+            }
+            _ => false,
+        };
+        binary.push(is_binary);
+        let space = match prev {
+            None => false,
+            Some(p) => {
+                let prev_binary = binary[k - 1];
+                let prev_unary = !prev_binary && matches!(p, Tok::Op(o) if o != "::" && o != "->");
+                match (p, t) {
+                    (_, Tok::Comma) => false,
+                    (Tok::Comma, _) => true,
+                    (Tok::Open(_), _) | (_, Tok::Close(_)) => false,
+                    _ if keyword => false,
+                    _ if is_binary || prev_binary => true,
+                    _ if prev_unary => false,
+                    _ => *ws,
+                }
+            }
+        };
+        if space {
+            out.push(' ');
+        }
+        match t {
+            Tok::Word(w) | Tok::Str(w) | Tok::Op(w) => out.push_str(w),
+            Tok::Open(c) | Tok::Close(c) | Tok::Other(c) => out.push(*c),
+            Tok::Comma => out.push(','),
+        }
+    }
+    out
+}
+
+fn class_header(rest: &str) -> String {
+    let mut cls = rest.trim().to_string();
+    if cls.ends_with(':') {
+        cls.pop();
+    }
+    let (name, base) = if let Some(paren) = cls.find('(') {
+        let base = cls
+            .find(')')
+            .map(|end| cls[paren + 1..end].trim().to_string());
+        (cls[..paren].trim().to_string(), base)
+    } else if let Some(colon) = cls.find(':') {
+        let right = cls[colon + 1..].trim();
+        (
+            cls[..colon].trim().to_string(),
+            (!right.is_empty()).then(|| right.to_string()),
+        )
+    } else {
+        (cls.trim().to_string(), None)
+    };
+    match base {
+        Some(b) if !b.is_empty() => format!("class {}({}):", name, b),
+        _ => format!("class {}:", name),
+    }
+}
+
+fn is_method_syntax(c: &str) -> bool {
+    match (c.find('('), c.find(':')) {
+        (Some(p), Some(col)) => col > p,
+        _ => false,
+    }
+}
+
+fn method_header(c: &str) -> String {
+    let (def, sig) = match c.strip_prefix("def ") {
+        Some(rest) => ("def ", rest.trim()),
+        None => ("", c),
+    };
+    let Some(paren) = sig.find('(') else {
+        return format!("{}{}", def, collapse_ws(sig));
+    };
+    let Some(end) = sig[paren + 1..].find(')').map(|e| paren + 1 + e) else {
+        return c.to_string();
+    };
+    let params: Vec<String> = sig[paren + 1..end]
+        .split(',')
+        .map(str::trim)
+        .filter(|p| !p.is_empty())
+        .map(|p| match p.split_once(':') {
+            Some((n, t)) if !t.contains(':') => format!("{}: {}", n.trim(), t.trim()),
+            _ => p.to_string(),
+        })
+        .collect();
+    let rest = &sig[end + 1..];
+    let ret = match rest.find("->") {
+        Some(arrow) => format!(
+            " -> {}",
+            rest[arrow + 2..].trim().trim_end_matches(':').trim()
+        ),
+        None => String::new(),
+    };
+    format!(
+        "{}{}({}){}:",
+        def,
+        collapse_ws(&sig[..paren]),
+        params.join(", "),
+        ret
+    )
+}
+
+fn member(c: &str) -> String {
+    if c == "public:" || c == "private:" {
+        return c.to_string();
+    }
+    if c.starts_with("def ") || is_method_syntax(c) {
+        return method_header(c);
+    }
+    if let Some(colon) = c.find(':') {
+        let ty = c[colon + 1..].trim();
+        if ty.is_empty() {
+            return format!("{}:", c[..colon].trim());
+        }
+        return format!("{}: {}", c[..colon].trim(), ty);
+    }
+    c.to_string()
+}
+
+/// Formats a declaration `name: Type[ = value]`, if `code` is one the
+/// parser reads as such.
+fn declaration(code: &str) -> Option<String> {
+    let colon = code.find(':')?;
+    let bytes = code.as_bytes();
+    let double = bytes.get(colon + 1) == Some(&b':') || (colon > 0 && bytes[colon - 1] == b':');
+    let name = code[..colon].trim();
+    if double || !is_ident(name) || code[..colon].contains('"') {
+        return None;
+    }
+    match code.find('=') {
+        Some(eq) if colon < eq => Some(format!(
+            "{}: {} = {}",
+            name,
+            code[colon + 1..eq].trim(),
+            expr(&code[eq + 1..])
+        )),
+        Some(_) => None,
+        None => Some(format!("{}: {}", name, code[colon + 1..].trim())),
+    }
+}
+
+fn statement(code: &str) -> String {
+    for kw in ["if ", "elif ", "while "] {
+        if let Some(rest) = code.strip_prefix(kw) {
+            if let Some(cond) = rest.strip_suffix(':') {
+                return format!("{}{}:", kw, expr(cond));
+            }
+            return code.to_string();
+        }
+    }
+    if code == "else:" || code == "return" {
+        return code.to_string();
+    }
+    if let Some(rest) = code.strip_prefix("return ") {
+        return format!("return {}", expr(rest));
+    }
+    if let Some(rest) = code.strip_prefix("call ") {
+        return format!("call {}", rest.trim());
+    }
+    if let Some(rest) = code.strip_prefix("let ") {
+        return match declaration(rest.trim()) {
+            Some(d) => format!("let {}", d),
+            None => code.to_string(),
+        };
+    }
+    if let Some(walrus) = code.find(":=") {
+        let name = code[..walrus].trim();
+        if is_ident(name) {
+            return format!("{} := {}", name, expr(&code[walrus + 2..]));
+        }
+    }
+    declaration(code).unwrap_or_else(|| expr(code))
+}
+
+/// How a `native` statement continues onto the following lines.
+fn native_block(code: &str) -> Option<&'static str> {
+    let code = code.strip_prefix("native ")?.trim();
+    if code.starts_with("\"\"\"") {
+        Some("\"\"\"")
+    } else if code.starts_with('"') && !code[1..].contains('"') {
+        Some("\"")
+    } else {
+        None
+    }
+}
+
+/// Pretty-prints `src` without checking that the meaning is preserved.
+fn print(src: &str) -> String {
+    let lines: Vec<&str> = src.lines().collect();
+    // Open lines: (source indent, kind).
+    let mut stack: Vec<(usize, Kind)> = Vec::new();
+    let mut body: Vec<Out> = Vec::new();
+    let mut directives: Vec<(usize, Vec<Out>)> = Vec::new();
+    let mut pending: Vec<(usize, Out)> = Vec::new();
+    // Length of `body` when the first directive was read.
+    let mut before_directives: Option<usize> = None;
+    let mut blank = false;
+    let mut i = 0;
+    while i < lines.len() {
+        let raw = lines[i];
+        let t = raw.trim();
+        i += 1;
+        if t.is_empty() {
+            blank = true;
+            continue;
+        }
+        let indent = indent_of(raw);
+        if t.starts_with("//") || t.starts_with('#') {
+            let level = stack.iter().filter(|(ind, _)| *ind < indent).count();
+            let mut o = Out::new(level, t.to_string(), blank);
+            o.comment = true;
+            pending.push((indent, o));
+            blank = false;
+            continue;
+        }
+        while stack.last().is_some_and(|(ind, _)| *ind >= indent) {
+            stack.pop();
+        }
+        let level = stack.len();
+        let parent = stack.last().map(|(_, k)| *k);
+        let (code, comment) = split_comment(t);
+        let (text, kind) = match parent {
+            None => {
+                if let Some(rest) = code.strip_prefix("class ") {
+                    (class_header(rest), Kind::Class)
+                } else if directive_group(code).is_some() {
+                    (collapse_ws(code), Kind::Other)
+                } else {
+                    (code.to_string(), Kind::Other)
+                }
+            }
+            Some(Kind::Class) => {
+                let kind = if code.starts_with("def ") || is_method_syntax(code) {
+                    Kind::Method
+                } else {
+                    Kind::Other
+                };
+                (member(code), kind)
+            }
+            Some(Kind::Method) | Some(Kind::Statement) => {
+                if code.starts_with("def ") || code.starts_with("class ") {
+                    (code.to_string(), Kind::Other)
+                } else if code.starts_with("native ") || parser::opens_triple_quote(code) {
+                    // Native code, and a string running onto the next
+                    // lines, is kept exactly as written.
+                    (code.to_string(), Kind::Statement)
+                } else {
+                    (statement(code), Kind::Statement)
+                }
+            }
+            Some(Kind::Other) => (code.to_string(), Kind::Other),
+        };
+        let text = with_comment(text, comment);
+        // Comments indented at least as deep as this line belong to it.
+        for (ind, o) in pending.iter_mut() {
+            if *ind >= indent {
+                o.level = level;
+            }
+        }
+        let mut o = Out::new(level, text, blank);
+        o.class_header = kind == Kind::Class;
+        blank = false;
+
+        let group = if parent.is_none() {
+            directive_group(code)
+        } else {
+            None
+        };
+        if let Some(g) = group {
+            // Comments directly above a directive move with it.
+            let mut split = pending.len();
+            if !o.blank_before {
+                while split > 0 && pending[split - 1].1.level == 0 {
+                    split -= 1;
+                    if pending[split].1.blank_before {
+                        break;
+                    }
+                }
+            }
+            let attached: Vec<Out> = pending.drain(split..).map(|(_, o)| o).collect();
+            body.extend(pending.drain(..).map(|(_, o)| o));
+            before_directives.get_or_insert(body.len());
+            let mut entry = attached;
+            entry.push(o);
+            directives.push((g, entry));
+            continue;
+        }
+        body.extend(pending.drain(..).map(|(_, o)| o));
+        body.push(o);
+
+        if parent == Some(Kind::Method) || parent == Some(Kind::Statement) {
+            if let Some(close) = native_block(code) {
+                let start = i;
+                while i < lines.len() {
+                    let nt = lines[i].trim();
+                    i += 1;
+                    let done = if close == "\"\"\"" {
+                        nt.contains("\"\"\"")
+                    } else {
+                        nt.ends_with('"')
+                    };
+                    if done {
+                        break;
+                    }
+                }
+                let block = &lines[start..i];
+                let base = block
+                    .iter()
+                    .filter(|l| !l.trim().is_empty())
+                    .map(|l| indent_of(l))
+                    .min()
+                    .unwrap_or(0);
+                for l in block {
+                    let mut o = Out::new(level + 1, l.trim().to_string(), false);
+                    o.verbatim = true;
+                    if !l.trim().is_empty() {
+                        o.extra = indent_of(l) - base;
+                    }
+                    body.push(o);
+                }
+            } else if parser::opens_triple_quote(code) {
+                // The rest of a triple-quoted string is part of its value.
+                let mut joined = code.to_string();
+                while i < lines.len() && parser::opens_triple_quote(&joined) {
+                    joined.push('\n');
+                    joined.push_str(lines[i]);
+                    let mut o = Out::new(0, lines[i].to_string(), false);
+                    o.verbatim = true;
+                    body.push(o);
+                    i += 1;
+                }
+            }
+        }
+        stack.push((indent, kind));
+    }
+    body.extend(pending.into_iter().map(|(_, o)| o));
+
+    // Leading comments stay above the directives; everything else follows.
+    let head = body
+        .iter()
+        .take(before_directives.unwrap_or(0))
+        .take_while(|o| o.comment)
+        .count();
+    let mut items: Vec<Out> = body.drain(..head).collect();
+    directives.sort_by_key(|(g, _)| *g);
+    let mut first_directive = true;
+    for (_, entry) in directives {
+        for mut o in entry {
+            o.blank_before = first_directive && !items.is_empty();
+            first_directive = false;
+            items.push(o);
+        }
+    }
+    if let Some(first) = body.first_mut() {
+        if !first_directive {
+            first.blank_before = true;
+        }
+    }
+    items.extend(body);
+
+    // A blank line before each top-level class, above its leading comments.
+    for k in 0..items.len() {
+        if items[k].class_header && items[k].level == 0 {
+            let mut j = k;
+            while j > 0 && items[j - 1].comment && items[j - 1].level == 0 && !items[j].blank_before
+            {
+                j -= 1;
+            }
+            items[j].blank_before = true;
+        }
+    }
+
+    let mut out = String::new();
+    let mut prev_level: Option<usize> = None;
+    for o in &items {
+        if !o.verbatim {
+            if let Some(p) = prev_level {
+                if o.blank_before && p >= o.level {
+                    out.push('\n');
+                }
+            }
+        }
+        if !o.text.is_empty() {
+            out.push_str(&" ".repeat(o.level * INDENT + o.extra));
+            out.push_str(&o.text);
+        }
+        out.push('\n');
+        if !o.verbatim {
+            prev_level = Some(o.level);
+        }
+    }
+    out
+}
+
+/// Formats a `.upp` source. Fails, rather than changing the program, when
+/// the formatted text would parse differently from `src`.
+pub fn format_source(src: &str) -> Result<String, String> {
+    let out = print(src);
+    if without_lines(parser::parse_all(&out)) != without_lines(parser::parse_all(src))
+        || parser::scan_directives(&out) != parser::scan_directives(src)
+    {
+        return Err(
+            "the formatted source would parse differently; leaving it unchanged".to_string(),
+        );
+    }
+    Ok(out)
+}
+
+/// Drops the line numbers, which blank-line changes are allowed to shift.
+fn without_lines(mut classes: Vec<Class>) -> Vec<Class> {
+    for c in &mut classes {
+        c.ctor_lines = SourceLines::default();
+        for m in &mut c.methods {
+            m.lines = SourceLines::default();
+        }
+    }
+    classes
+}
@@ -0,0 +1,454 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::packages::{self, LockEntry, Lockfile, Version, VersionReq};
+use crate::{content_hash, format, parser, Class, Directives};
+
+/// A `.upp` file reached from the root input, parsed exactly once.
+#[derive(Debug, Clone)]
+pub struct SourceFile {
+    pub path: PathBuf,
+    pub source: String,
+    pub directives: Directives,
+    pub classes: Vec<Class>,
+    /// Exact version of the first versioned `import` that reached this
+    /// file: the one picked from `packages/<name>/`, or the one a local
+    /// import names.
+    pub version: Option<String>,
+    /// Whether the file was given as an input rather than reached by `import`.
+    pub root: bool,
+}
+
+/// Every file reachable from the roots: the roots first, in the order they
+/// were given, then imported files in discovery order.
+#[derive(Debug, Clone)]
+pub struct ImportGraph {
+    pub files: Vec<SourceFile>,
+    /// Exact versions and hashes of every versioned import, for `ultra.lock`.
+    pub locked: Vec<LockEntry>,
+}
+
+impl ImportGraph {
+    pub fn root(&self) -> &SourceFile {
+        &self.files[0]
+    }
+
+    pub fn roots(&self) -> impl Iterator<Item = &SourceFile> {
+        self.files.iter().filter(|f| f.root)
+    }
+
+    pub fn imported(&self) -> impl Iterator<Item = &SourceFile> {
+        self.files.iter().filter(|f| !f.root)
+    }
+
+    /// Directives of the roots merged together: lists are unioned, and the
+    /// first root that sets the entry or the namespace wins.
+    pub fn merged_directives(&self) -> Directives {
+        let mut merged = Directives::default();
+        for file in self.roots() {
+            let d = &file.directives;
+            for u in &d.uses {
+                if !merged.uses.contains(u) {
+                    merged.uses.push(u.clone());
+                }
+            }
+            for p in &d.profiles {
+                if !merged.profiles.contains(p) {
+                    merged.profiles.push(p.clone());
+                }
+            }
+            for c in &d.capabilities {
+                if !merged.capabilities.contains(c) {
+                    merged.capabilities.push(c.clone());
+                }
+            }
+            if d.global_base {
+                merged.global_base = true;
+            }
+            if merged.namespace.is_none() {
+                merged.namespace = d.namespace.clone();
+            }
+            if merged.entry.is_none() {
+                merged.entry = d.entry.clone();
+            }
+        }
+        merged
+    }
+
+    /// Classes of every file, roots first, each carrying the namespace and
+    /// version of its file. Among the roots the first definition of a class
+    /// is kept (`principal.upp` comes first in a folder build); a definition
+    /// in an imported file replaces an earlier one in place.
+    pub fn merged_classes(&self) -> Vec<Class> {
+        let mut classes: Vec<Class> = Vec::new();
+        for file in self.roots().chain(self.imported()) {
+            for cls in &file.classes {
+                let mut c = cls.clone();
+                c.namespace = file.directives.namespace.clone();
+                c.module_version = file.version.clone();
+                match classes.iter().position(|m| m.name == c.name) {
+                    Some(_) if file.root => {}
+                    Some(pos) => classes[pos] = c,
+                    None => classes.push(c),
+                }
+            }
+        }
+        classes
+    }
+
+    /// The file whose definition of `class` is used by [`merged_classes`](Self::merged_classes).
+    pub fn defining_file(&self, class: &str) -> Option<&SourceFile> {
+        let defines = |f: &&SourceFile| f.classes.iter().any(|c| c.name == class);
+        self.imported()
+            .filter(defines)
+            .last()
+            .or_else(|| self.roots().find(defines))
+    }
+
+    pub fn lockfile(&self) -> Lockfile {
+        Lockfile {
+            packages: self.locked.clone(),
+        }
+    }
+}
+
+pub fn is_safe_rel_path(s: &str) -> bool {
+    let st = s.trim();
+    if st.is_empty() {
+        return false;
+    }
+    if st.starts_with('/') || st.starts_with('\\') {
+        return false;
+    }
+    if st.contains("..") {
+        return false;
+    }
+    if st.contains(':') {
+        return false;
+    }
+    true
+}
+
+/// The hash `ultra.lock` keeps for a source: that of its `ultracpp fmt`
+/// form, so that reformatting or other layout-only edits keep the pin valid
+/// while any change to the code fails the build until the lock is updated.
+pub fn lock_hash(source: &str) -> String {
+    content_hash(&format::format_source(source).unwrap_or_else(|_| source.to_string()))
+}
+
+/// The exact version a local `import path@req` stands for. Ranges only
+/// make sense when there is a `packages/` directory to pick from.
+fn local_version(imp_path: &str, req: &str) -> Result<Version, String> {
+    Version::parse(req.trim().trim_start_matches('=')).ok_or_else(|| {
+        format!(
+            "import {}@{}: a local file needs an exact version such as @1.0.0; ranges select from packages/",
+            imp_path, req
+        )
+    })
+}
+
+pub fn parse_import_spec(s: &str) -> (String, Option<String>) {
+    if let Some(pos) = s.rfind('@') {
+        let p = s[..pos].trim();
+        let v = s[pos + 1..].trim();
+        let mut path = p.to_string();
+        if !path.ends_with(".upp") {
+            path.push_str(".upp");
+        }
+        return (
+            path,
+            if v.is_empty() {
+                None
+            } else {
+                Some(v.to_string())
+            },
+        );
+    }
+    let mut path = s.trim().to_string();
+    if !path.ends_with(".upp") {
+        path.push_str(".upp");
+    }
+    (path, None)
+}
+
+/// Resolves `root` and all of its `import` lines recursively.
+///
+/// Relative import paths are resolved against the directory of the file
+/// that contains the `import`. A cycle is reported with the full chain,
+/// e.g. `import cycle: a.upp -> b.upp -> a.upp`.
+///
+/// `import foo.upp@<req>` selects the highest version under
+/// `packages/foo/<version>/` (next to `root`) that satisfies `<req>`,
+/// preferring a version already pinned in `ultra.lock`. A local file
+/// imported with a version must name an exact one. Versioned imports whose
+/// content no longer matches the hash in `ultra.lock` (see [`lock_hash`])
+/// are rejected.
+pub fn resolve(root: &Path) -> Result<ImportGraph, String> {
+    resolve_with(root, &[])
+}
+
+/// Like [`resolve`], with `extra_imports` (in `import` syntax) resolved as
+/// package dependencies of the project, e.g. the `[dependencies]` of
+/// `ultra.toml`.
+pub fn resolve_with(root: &Path, extra_imports: &[String]) -> Result<ImportGraph, String> {
+    let project_dir = root
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .map(|p| p.to_path_buf())
+        .unwrap_or_else(|| PathBuf::from("."));
+    resolve_roots(&project_dir, &[root.to_path_buf()], extra_imports)
+}
+
+/// Resolves several root files of one project (a folder build) together, so
+/// that a file imported by one root and listed as another is parsed once.
+/// `ultra.lock` and `packages/` are looked up in `project_dir`, and every
+/// dependency in `extra_imports` must exist under `packages/`.
+pub fn resolve_roots(
+    project_dir: &Path,
+    roots: &[PathBuf],
+    extra_imports: &[String],
+) -> Result<ImportGraph, String> {
+    resolve_overlay(project_dir, roots, extra_imports, &HashMap::new())
+}
+
+/// Like [`resolve_roots`], reading the files present in `open` (keyed by
+/// canonical path) from memory instead of disk, e.g. unsaved editor buffers.
+pub fn resolve_overlay(
+    project_dir: &Path,
+    roots: &[PathBuf],
+    extra_imports: &[String],
+    open: &HashMap<PathBuf, String>,
+) -> Result<ImportGraph, String> {
+    let mut r = Resolver::new(project_dir.to_path_buf(), open);
+    for root in roots {
+        let key = r.register(root, None)?;
+        let idx = r.seen[&key];
+        r.files[idx].root = true;
+    }
+    for root in roots {
+        r.visit(root, None)?;
+    }
+    for dep in extra_imports {
+        let (imp_path, _) = parse_import_spec(dep);
+        let name = Path::new(&imp_path)
+            .file_stem()
+            .unwrap()
+            .to_string_lossy()
+            .to_string();
+        if !r.packages_dir.join(&name).is_dir() {
+            return Err(format!(
+                "dependency '{}' not found in {}",
+                name,
+                r.packages_dir.display()
+            ));
+        }
+    }
+    r.visit_imports(project_dir, extra_imports)?;
+    Ok(ImportGraph {
+        files: r.files,
+        locked: r.locked,
+    })
+}
+
+struct Resolver<'a> {
+    overlay: &'a HashMap<PathBuf, String>,
+    files: Vec<SourceFile>,
+    seen: HashMap<PathBuf, usize>,
+    expanded: HashSet<PathBuf>,
+    stack: Vec<PathBuf>,
+    display_base: PathBuf,
+    packages_dir: PathBuf,
+    lock: Lockfile,
+    locked: Vec<LockEntry>,
+}
+
+impl<'a> Resolver<'a> {
+    fn new(project_dir: PathBuf, overlay: &'a HashMap<PathBuf, String>) -> Resolver<'a> {
+        let lock = Lockfile::read(&project_dir.join(packages::LOCKFILE_NAME));
+        Resolver {
+            overlay,
+            files: Vec::new(),
+            seen: HashMap::new(),
+            expanded: HashSet::new(),
+            stack: Vec::new(),
+            packages_dir: project_dir.join("packages"),
+            display_base: project_dir,
+            lock,
+            locked: Vec::new(),
+        }
+    }
+
+    fn display(&self, p: &Path) -> String {
+        let base =
+            fs::canonicalize(&self.display_base).unwrap_or_else(|_| self.display_base.clone());
+        p.strip_prefix(&base)
+            .unwrap_or(p)
+            .to_string_lossy()
+            .replace('\\', "/")
+    }
+
+    /// Canonical path of `path`; buffers that only exist in the overlay
+    /// keep the path they were opened with.
+    fn key(&self, path: &Path) -> Result<PathBuf, String> {
+        match fs::canonicalize(path) {
+            Ok(k) => Ok(k),
+            Err(_) if self.overlay.contains_key(path) => Ok(path.to_path_buf()),
+            Err(e) => Err(format!("cannot open {}: {}", path.display(), e)),
+        }
+    }
+
+    /// Reads and parses `path` the first time it is seen.
+    fn register(&mut self, path: &Path, version: Option<String>) -> Result<PathBuf, String> {
+        let key = self.key(path)?;
+        if self.seen.contains_key(&key) {
+            return Ok(key);
+        }
+        let source = match self.overlay.get(&key) {
+            Some(s) => s.clone(),
+            None => fs::read_to_string(&key)
+                .map_err(|e| format!("cannot read {}: {}", path.display(), e))?,
+        };
+        let directives = parser::scan_directives(&source);
+        let classes = parser::parse_all(&source);
+        self.seen.insert(key.clone(), self.files.len());
+        self.files.push(SourceFile {
+            path: key.clone(),
+            source,
+            directives,
+            classes,
+            version,
+            root: false,
+        });
+        Ok(key)
+    }
+
+    /// Registers `path` and follows its imports, once per file.
+    fn visit(&mut self, path: &Path, version: Option<String>) -> Result<(), String> {
+        let key = self.register(path, version)?;
+        if let Some(pos) = self.stack.iter().position(|p| p == &key) {
+            let mut chain: Vec<String> =
+                self.stack[pos..].iter().map(|p| self.display(p)).collect();
+            chain.push(self.display(&key));
+            return Err(format!("import cycle: {}", chain.join(" -> ")));
+        }
+        if !self.expanded.insert(key.clone()) {
+            return Ok(());
+        }
+        let imports = self.files[self.seen[&key]].directives.imports.clone();
+        self.stack.push(key.clone());
+        let dir = key.parent().map(|p| p.to_path_buf()).unwrap_or_default();
+        self.visit_imports(&dir, &imports)?;
+        self.stack.pop();
+        Ok(())
+    }
+
+    fn visit_imports(&mut self, dir: &Path, imports: &[String]) -> Result<(), String> {
+        for imp in imports {
+            let (imp_path, ver) = parse_import_spec(imp);
+            if !is_safe_rel_path(&imp_path) {
+                eprintln!("import path rejected (unsafe): {}", imp_path);
+                continue;
+            }
+            let name = Path::new(&imp_path)
+                .file_stem()
+                .unwrap()
+                .to_string_lossy()
+                .to_string();
+            if let Some(req) = &ver {
+                let pkg_dir = self.packages_dir.join(&name);
+                if pkg_dir.is_dir() {
+                    self.visit_package(&name, &pkg_dir, req)?;
+                    continue;
+                }
+            }
+            let ip = dir.join(&imp_path);
+            if !ip.exists() && !self.overlay.contains_key(&ip) {
+                continue;
+            }
+            // A local file has no versions to choose from, so its import
+            // names the one version it stands for.
+            let exact = match &ver {
+                Some(req) => Some(local_version(&imp_path, req)?),
+                None => None,
+            };
+            self.visit(&ip, exact.map(|v| v.to_string()))?;
+            if let Some(v) = exact {
+                let key = self.key(&ip)?;
+                let hash = lock_hash(&self.files[self.seen[&key]].source);
+                self.pin(&name, &v.to_string(), hash)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn visit_package(&mut self, name: &str, pkg_dir: &Path, req: &str) -> Result<(), String> {
+        let vreq = VersionReq::parse(req)
+            .ok_or_else(|| format!("invalid version requirement '{}' for {}", req, name))?;
+        let available = packages::available_versions(pkg_dir);
+        let pinned: Option<Version> = self
+            .lock
+            .pinned(name)
+            .iter()
+            .filter_map(|v| Version::parse(v))
+            .find(|v| vreq.matches(v) && available.contains(v));
+        let picked = pinned
+            .or_else(|| available.iter().find(|v| vreq.matches(v)).copied())
+            .ok_or_else(|| {
+                let list: Vec<String> = available.iter().map(|v| v.to_string()).collect();
+                format!(
+                    "no version of package '{}' matches '{}' (available: {})",
+                    name,
+                    req,
+                    if list.is_empty() {
+                        "none".to_string()
+                    } else {
+                        list.join(", ")
+                    }
+                )
+            })?;
+        let version = picked.to_string();
+        let files = packages::package_sources(&pkg_dir.join(&version), name);
+        if files.is_empty() {
+            return Err(format!("package {}@{} has no .upp files", name, version));
+        }
+        let mut all = String::new();
+        for f in &files {
+            let s =
+                fs::read_to_string(f).map_err(|e| format!("cannot read {}: {}", f.display(), e))?;
+            all.push_str(&format::format_source(&s).unwrap_or(s));
+        }
+        self.pin(name, &version, content_hash(&all))?;
+        for f in &files {
+            self.visit(f, Some(version.clone()))?;
+        }
+        Ok(())
+    }
+
+    fn pin(&mut self, name: &str, version: &str, hash: String) -> Result<(), String> {
+        if let Some(prev) = self.lock.get(name, version) {
+            if prev.hash != hash {
+                return Err(format!(
+                    "content of {}@{} changed since it was locked ({} != {}); bump its version or remove the entry from {}",
+                    name,
+                    version,
+                    hash,
+                    prev.hash,
+                    packages::LOCKFILE_NAME
+                ));
+            }
+        }
+        if !self
+            .locked
+            .iter()
+            .any(|e| e.name == name && e.version == version)
+        {
+            self.locked.push(LockEntry {
+                name: name.to_string(),
+                version: version.to_string(),
+                hash,
+            });
+        }
+        Ok(())
+    }
+}
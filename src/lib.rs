@@ -1,5 +1,18 @@
+pub mod bridge;
+pub mod buildplan;
+pub mod cache;
+pub mod check;
+pub mod cli;
 pub mod codegen;
+pub mod compiler_output;
+pub mod format;
+pub mod imports;
+pub mod json;
+pub mod lsp;
+pub mod manifest;
+pub mod packages;
 pub mod parser;
+pub mod sourcemap;
 pub mod tool_detector;
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -13,6 +26,8 @@ pub struct Field {
     pub name: String,
     pub ty: String,
     pub vis: Visibility,
+    /// Text of the `##` comment lines right above the declaration.
+    pub doc: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -21,6 +36,14 @@ pub struct Param {
     pub ty: String,
 }
 
+/// A piece of an f-string: literal text, or `{expr:spec}` with `spec` in
+/// the `std::format` mini-language (empty when absent).
+#[derive(Debug, Clone, PartialEq)]
+pub enum FormatPart {
+    Text(String),
+    Value { expr: Expr, spec: String },
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Expr {
     LiteralString(String),
@@ -42,6 +65,15 @@ pub enum Expr {
         name: String,
         args: Vec<Expr>,
     },
+    /// `print(args, sep=..., end=...)`; unset keywords keep Python's
+    /// defaults, `" "` and `"\n"`.
+    Print {
+        args: Vec<Expr>,
+        sep: Option<Box<Expr>>,
+        end: Option<Box<Expr>>,
+    },
+    /// An f-string, `f"score: {score:.2f}"`.
+    Format(Vec<FormatPart>),
     BinaryOp(Box<Expr>, String, Box<Expr>),
     Block(Vec<Expr>),
     If {
@@ -72,6 +104,17 @@ pub struct Method {
     pub body: Expr,
     pub is_static: bool,
     pub vis: Visibility,
+    pub doc: Option<String>,
+    pub lines: SourceLines,
+}
+
+/// 1-based `.upp` line numbers of a method: its `def` line and one entry
+/// per statement of the body, in the order codegen visits them (an `elif`
+/// counts as a statement of its own). Zero means unknown.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SourceLines {
+    pub def: usize,
+    pub stmts: Vec<usize>,
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -82,9 +125,13 @@ pub struct Class {
     pub methods: Vec<Method>,
     pub ctor_params: Option<Vec<Param>>,
     pub ctor_body: Option<Expr>,
+    pub ctor_lines: SourceLines,
     pub extra_includes: Vec<String>,
     pub namespace: Option<String>,
     pub module_version: Option<String>,
+    pub doc: Option<String>,
+    /// Path written into `#line` directives; codegen emits none when unset.
+    pub source_file: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Default)]
@@ -156,3 +203,22 @@ pub fn resolve_includes(d: &Directives) -> Vec<String> {
     }
     set
 }
+
+/// Stable FNV-1a hash of `s`, formatted as `fnv1a64:<hex>`.
+pub fn content_hash(s: &str) -> String {
+    let mut h: u64 = 0xcbf29ce484222325;
+    for b in s.as_bytes() {
+        h ^= *b as u64;
+        h = h.wrapping_mul(0x100000001b3);
+    }
+    format!("fnv1a64:{:016x}", h)
+}
+
+/// Like `fs::write`, but leaves the file, and so its mtime, untouched when
+/// it already holds `contents`.
+pub fn write_if_changed(path: &std::path::Path, contents: &str) -> std::io::Result<()> {
+    if std::fs::read(path).is_ok_and(|existing| existing == contents.as_bytes()) {
+        return Ok(());
+    }
+    std::fs::write(path, contents)
+}
@@ -0,0 +1,931 @@
+//! Language server for `.upp` files, spoken over stdio by `ultracpp lsp`.
+//!
+//! Documents are synchronised in full. Every open buffer overrides the file
+//! on disk when imports are resolved, so diagnostics follow unsaved edits.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{BufRead, Write};
+use std::path::{Path, PathBuf};
+
+use crate::check::{self, Severity};
+use crate::imports::{self, ImportGraph, SourceFile};
+use crate::json::{obj, Value};
+use crate::manifest::Manifest;
+use crate::{parser, Class, Directives, Field, Method};
+
+const DIRECTIVES: &[(&str, &str)] = &[
+    ("use", "use <module>: include a standard module"),
+    ("profile", "profile <name>: enable a library profile"),
+    ("capability", "capability <name>: declare a capability"),
+    (
+        "entry",
+        "entry <Class>: class whose entry method main() calls",
+    ),
+    ("import", "import <file.upp>[@<version>]"),
+    (
+        "namespace",
+        "namespace <Name>: namespace of the classes of this file",
+    ),
+    ("global", "global: derive every class from Object"),
+    ("run", "run <Class>: entry class, same as entry"),
+    ("class", "class <Name>(<Base>):"),
+];
+
+mod kind {
+    pub const METHOD: u64 = 2;
+    pub const FIELD: u64 = 5;
+    pub const CLASS: u64 = 7;
+    pub const MODULE: u64 = 9;
+    pub const KEYWORD: u64 = 14;
+}
+
+mod symbol_kind {
+    pub const CLASS: u64 = 5;
+    pub const METHOD: u64 = 6;
+    pub const FIELD: u64 = 8;
+}
+
+/// Reads one `Content-Length` framed message. Returns `None` at end of input.
+pub fn read_message(r: &mut impl BufRead) -> Result<Option<Value>, String> {
+    let mut length: Option<usize> = None;
+    loop {
+        let mut line = String::new();
+        if r.read_line(&mut line).map_err(|e| e.to_string())? == 0 {
+            return Ok(None);
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            if length.is_some() {
+                break;
+            }
+            continue;
+        }
+        if let Some((k, v)) = line.split_once(':') {
+            if k.trim().eq_ignore_ascii_case("content-length") {
+                length = Some(
+                    v.trim()
+                        .parse()
+                        .map_err(|_| format!("invalid Content-Length: {}", v.trim()))?,
+                );
+            }
+        }
+    }
+    let mut body = vec![0u8; length.unwrap_or(0)];
+    r.read_exact(&mut body).map_err(|e| e.to_string())?;
+    let text = String::from_utf8(body).map_err(|_| "message is not UTF-8".to_string())?;
+    Value::parse(&text).map(Some)
+}
+
+pub fn write_message(w: &mut impl Write, msg: &Value) -> Result<(), String> {
+    let body = msg.to_string();
+    write!(w, "Content-Length: {}\r\n\r\n{}", body.len(), body).map_err(|e| e.to_string())?;
+    w.flush().map_err(|e| e.to_string())
+}
+
+/// Runs the server until the client sends `exit` or closes the input.
+pub fn serve(mut input: impl BufRead, mut output: impl Write) -> Result<(), String> {
+    let mut server = Server::default();
+    while let Some(msg) = read_message(&mut input)? {
+        for reply in server.handle(&msg) {
+            write_message(&mut output, &reply)?;
+        }
+        if server.exited {
+            break;
+        }
+    }
+    Ok(())
+}
+
+pub fn uri_to_path(uri: &str) -> PathBuf {
+    let raw = uri.strip_prefix("file://").unwrap_or(uri);
+    let bytes = raw.as_bytes();
+    let mut out: Vec<u8> = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let Ok(b) = u8::from_str_radix(&raw[i + 1..i + 3], 16) {
+                out.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        out.push(bytes[i]);
+        i += 1;
+    }
+    let s = String::from_utf8_lossy(&out).to_string();
+    // file:///C:/x -> C:/x
+    let b = s.as_bytes();
+    if b.len() > 2 && b[0] == b'/' && b[2] == b':' && b[1].is_ascii_alphabetic() {
+        return PathBuf::from(&s[1..]);
+    }
+    PathBuf::from(s)
+}
+
+pub fn path_to_uri(path: &Path) -> String {
+    let s = path.to_string_lossy().replace('\\', "/");
+    let mut out = String::from("file://");
+    if !s.starts_with('/') {
+        out.push('/');
+    }
+    for b in s.bytes() {
+        if b.is_ascii_alphanumeric() || b"-._~/:".contains(&b) {
+            out.push(b as char);
+        } else {
+            out.push_str(&format!("%{:02X}", b));
+        }
+    }
+    out
+}
+
+fn utf16_len(s: &str) -> usize {
+    s.chars().map(char::len_utf16).sum()
+}
+
+/// Byte offset in `line` of the UTF-16 column `col`.
+fn byte_col(line: &str, col: usize) -> usize {
+    let mut units = 0;
+    for (i, c) in line.char_indices() {
+        if units >= col {
+            return i;
+        }
+        units += c.len_utf16();
+    }
+    line.len()
+}
+
+fn indent_of(line: &str) -> usize {
+    line.len() - line.trim_start().len()
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+fn position(line: usize, character: usize) -> Value {
+    obj(vec![("line", line.into()), ("character", character.into())])
+}
+
+/// Range covering the text of the 1-based `line` of `source`.
+fn line_range(source: &str, line: usize) -> Value {
+    let text = source.lines().nth(line.saturating_sub(1)).unwrap_or("");
+    let l = line.saturating_sub(1);
+    obj(vec![
+        ("start", position(l, utf16_len(&text[..indent_of(text)]))),
+        ("end", position(l, utf16_len(text))),
+    ])
+}
+
+fn location(uri: String, source: &str, line: usize) -> Value {
+    obj(vec![
+        ("uri", uri.into()),
+        ("range", line_range(source, line)),
+    ])
+}
+
+fn class_name_of(line: &str) -> Option<String> {
+    let rest = line.trim_start().strip_prefix("class ")?.trim_start();
+    let name: String = rest.chars().take_while(|c| is_word(*c)).collect();
+    if name.is_empty() {
+        None
+    } else {
+        Some(name)
+    }
+}
+
+/// The class whose body contains the 0-based `line`.
+fn enclosing_class(source: &str, line: usize) -> Option<String> {
+    let lines: Vec<&str> = source.lines().collect();
+    let current = lines.get(line)?;
+    if let Some(name) = class_name_of(current) {
+        return Some(name);
+    }
+    let mut indent = if current.trim().is_empty() {
+        usize::MAX
+    } else {
+        indent_of(current)
+    };
+    for l in lines[..line].iter().rev() {
+        if l.trim().is_empty() {
+            continue;
+        }
+        let i = indent_of(l);
+        if i < indent {
+            if let Some(name) = class_name_of(l) {
+                return Some(name);
+            }
+            if i == 0 {
+                return None;
+            }
+            indent = i;
+        }
+    }
+    None
+}
+
+/// Last 1-based line of the block of the class declared at `start`.
+fn class_end(source: &str, start: usize) -> usize {
+    let lines: Vec<&str> = source.lines().collect();
+    let indent = lines.get(start - 1).map(|l| indent_of(l)).unwrap_or(0);
+    let mut end = start;
+    for (i, l) in lines.iter().enumerate().skip(start) {
+        if l.trim().is_empty() {
+            continue;
+        }
+        if indent_of(l) <= indent {
+            break;
+        }
+        end = i + 1;
+    }
+    end
+}
+
+fn signature(owner: &Class, m: &Method) -> String {
+    let params: Vec<String> = m
+        .params
+        .iter()
+        .map(|p| format!("{}: {}", p.name, p.ty))
+        .collect();
+    format!(
+        "{}def {}.{}({}) -> {}",
+        if m.is_static { "static " } else { "" },
+        owner.name,
+        m.name,
+        params.join(", "),
+        m.return_type
+    )
+}
+
+/// Resolved, merged view of one open document and everything it imports.
+struct Analysis {
+    graph: ImportGraph,
+    classes: Vec<Class>,
+    directives: Directives,
+}
+
+enum Symbol<'a> {
+    Class(&'a Class),
+    Field(&'a Class, &'a Field),
+    Method(&'a Class, &'a Method),
+    Namespace(String),
+    File(PathBuf),
+}
+
+impl Analysis {
+    fn class(&self, name: &str) -> Option<&Class> {
+        self.classes.iter().find(|c| c.name == name)
+    }
+
+    fn has_namespace(&self, ns: &str) -> bool {
+        self.classes
+            .iter()
+            .any(|c| c.namespace.as_deref() == Some(ns))
+    }
+
+    /// Finds `member` on `class` or one of its bases.
+    fn member<'a>(&'a self, class: &'a Class, member: &str) -> Option<Symbol<'a>> {
+        let mut cur = Some(class);
+        let mut depth = 0;
+        while let Some(c) = cur {
+            if let Some(f) = c.fields.iter().find(|f| f.name == member) {
+                return Some(Symbol::Field(c, f));
+            }
+            if let Some(m) = c.methods.iter().find(|m| m.name == member) {
+                return Some(Symbol::Method(c, m));
+            }
+            depth += 1;
+            if depth > 32 {
+                break;
+            }
+            cur = c.base.as_deref().and_then(|b| self.class(b));
+        }
+        None
+    }
+
+    /// Fields and methods of `class` and its bases, nearest first.
+    fn members<'a>(&'a self, class: &'a Class) -> Vec<Symbol<'a>> {
+        let mut out: Vec<Symbol> = Vec::new();
+        let mut seen: Vec<&str> = Vec::new();
+        let mut cur = Some(class);
+        let mut depth = 0;
+        while let Some(c) = cur {
+            for f in &c.fields {
+                if !seen.contains(&f.name.as_str()) {
+                    seen.push(&f.name);
+                    out.push(Symbol::Field(c, f));
+                }
+            }
+            for m in &c.methods {
+                if !seen.contains(&m.name.as_str()) {
+                    seen.push(&m.name);
+                    out.push(Symbol::Method(c, m));
+                }
+            }
+            depth += 1;
+            if depth > 32 {
+                break;
+            }
+            cur = c.base.as_deref().and_then(|b| self.class(b));
+        }
+        out
+    }
+
+    /// Resolves a dotted path such as `self.pos.x` or `Utils.Version.current`.
+    /// The second value tells whether the path was reached through a type
+    /// name (a static access) rather than an instance.
+    fn resolve_path<'a>(
+        &'a self,
+        segments: &[&str],
+        enclosing: Option<&'a Class>,
+    ) -> Option<(Symbol<'a>, bool)> {
+        let (first, rest) = segments.split_first()?;
+        let mut cur = if *first == "self" {
+            (Symbol::Class(enclosing?), false)
+        } else if let Some(c) = self.class(first) {
+            (Symbol::Class(c), true)
+        } else if self.has_namespace(first) {
+            (Symbol::Namespace(first.to_string()), true)
+        } else {
+            (enclosing.and_then(|c| self.member(c, first))?, false)
+        };
+        for seg in rest {
+            cur = match cur.0 {
+                Symbol::Namespace(ns) => {
+                    let c = self
+                        .classes
+                        .iter()
+                        .find(|c| c.name == *seg && c.namespace.as_deref() == Some(ns.as_str()))?;
+                    (Symbol::Class(c), true)
+                }
+                Symbol::Class(c) => (self.member(c, seg)?, cur.1),
+                Symbol::Field(_, f) => {
+                    let c = self.class(f.ty.trim())?;
+                    (self.member(c, seg)?, false)
+                }
+                _ => return None,
+            };
+        }
+        Some(cur)
+    }
+}
+
+#[derive(Default)]
+pub struct Server {
+    /// Open documents by canonical path.
+    docs: HashMap<PathBuf, String>,
+    /// The URI each open document was opened with.
+    uris: HashMap<PathBuf, String>,
+    shutting_down: bool,
+    pub exited: bool,
+}
+
+impl Server {
+    fn key(path: &Path) -> PathBuf {
+        fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+    }
+
+    fn uri_of(&self, path: &Path) -> String {
+        self.uris
+            .get(path)
+            .cloned()
+            .unwrap_or_else(|| path_to_uri(path))
+    }
+
+    fn text_of(&self, path: &Path) -> Option<String> {
+        self.docs
+            .get(path)
+            .cloned()
+            .or_else(|| fs::read_to_string(path).ok())
+    }
+
+    fn analyse(&self, path: &Path) -> Result<Analysis, String> {
+        let manifest = Manifest::locate(path)
+            .and_then(|m| Manifest::read(&m).ok())
+            .unwrap_or_default();
+        let dir = path
+            .parent()
+            .filter(|p| !p.as_os_str().is_empty())
+            .unwrap_or(Path::new("."));
+        let graph = imports::resolve_overlay(
+            dir,
+            &[path.to_path_buf()],
+            &manifest.dependency_imports(),
+            &self.docs,
+        )?;
+        let mut directives = graph.merged_directives();
+        manifest.apply_to(&mut directives);
+        let classes = graph.merged_classes();
+        Ok(Analysis {
+            graph,
+            classes,
+            directives,
+        })
+    }
+
+    /// Like `analyse`, falling back to the document alone when its imports
+    /// cannot be resolved, so that navigation keeps working.
+    fn analyse_lenient(&self, path: &Path) -> Option<Analysis> {
+        if let Ok(a) = self.analyse(path) {
+            return Some(a);
+        }
+        let source = self.text_of(path)?;
+        let directives = parser::scan_directives(&source);
+        let file = SourceFile {
+            path: path.to_path_buf(),
+            classes: parser::parse_all(&source),
+            directives: directives.clone(),
+            source,
+            version: None,
+            root: true,
+        };
+        let graph = ImportGraph {
+            files: vec![file],
+            locked: Vec::new(),
+        };
+        let classes = graph.merged_classes();
+        Some(Analysis {
+            graph,
+            classes,
+            directives,
+        })
+    }
+
+    fn diagnostics(&self, path: &Path) -> Value {
+        let source = self.docs.get(path).cloned().unwrap_or_default();
+        let mut items: Vec<Value> = Vec::new();
+        let mut push = |severity: Severity, line: usize, message: &str| {
+            items.push(obj(vec![
+                ("range", line_range(&source, line)),
+                (
+                    "severity",
+                    match severity {
+                        Severity::Error => 1u64,
+                        Severity::Warning => 2u64,
+                    }
+                    .into(),
+                ),
+                ("source", "ultracpp".into()),
+                ("message", message.into()),
+            ]));
+        };
+        match self.analyse(path) {
+            Err(e) => push(Severity::Error, 1, &e),
+            Ok(a) => {
+                for d in check::check(&a.graph, &a.classes, &a.directives) {
+                    match &d.file {
+                        Some(f) if f != path => {}
+                        _ => push(d.severity, d.line.unwrap_or(1), &d.message),
+                    }
+                }
+            }
+        }
+        obj(vec![
+            ("jsonrpc", "2.0".into()),
+            ("method", "textDocument/publishDiagnostics".into()),
+            (
+                "params",
+                obj(vec![
+                    ("uri", self.uri_of(path).into()),
+                    ("diagnostics", items.into()),
+                ]),
+            ),
+        ])
+    }
+
+    /// Handles one message and returns the responses and notifications to
+    /// send back.
+    pub fn handle(&mut self, msg: &Value) -> Vec<Value> {
+        let method = msg.get("method").and_then(Value::as_str).unwrap_or("");
+        let params = msg.get("params").cloned().unwrap_or(Value::Null);
+        let Some(id) = msg.get("id").cloned() else {
+            return self.notify(method, &params);
+        };
+        if method.is_empty() {
+            // A response to a request we never send.
+            return Vec::new();
+        }
+        let result = if self.shutting_down && method != "shutdown" {
+            Err((-32600, "server is shutting down".to_string()))
+        } else {
+            self.request(method, &params)
+        };
+        let mut reply = vec![("jsonrpc", Value::from("2.0")), ("id", id)];
+        match result {
+            Ok(v) => reply.push(("result", v)),
+            Err((code, message)) => reply.push((
+                "error",
+                obj(vec![
+                    ("code", (code as i64).into()),
+                    ("message", message.into()),
+                ]),
+            )),
+        }
+        vec![obj(reply)]
+    }
+
+    fn notify(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        let uri = params
+            .at(&["textDocument", "uri"])
+            .and_then(Value::as_str)
+            .unwrap_or("");
+        let path = Server::key(&uri_to_path(uri));
+        match method {
+            "textDocument/didOpen" => {
+                let text = params
+                    .at(&["textDocument", "text"])
+                    .and_then(Value::as_str)
+                    .unwrap_or("");
+                self.docs.insert(path.clone(), text.to_string());
+                self.uris.insert(path, uri.to_string());
+                self.publish_all()
+            }
+            "textDocument/didChange" => {
+                let text = params
+                    .get("contentChanges")
+                    .and_then(Value::as_array)
+                    .and_then(|c| c.last())
+                    .and_then(|c| c.get("text"))
+                    .and_then(Value::as_str);
+                if let Some(t) = text {
+                    self.docs.insert(path, t.to_string());
+                }
+                self.publish_all()
+            }
+            "textDocument/didSave" => self.publish_all(),
+            "textDocument/didClose" => {
+                self.docs.remove(&path);
+                self.uris.remove(&path);
+                let mut out = vec![obj(vec![
+                    ("jsonrpc", "2.0".into()),
+                    ("method", "textDocument/publishDiagnostics".into()),
+                    (
+                        "params",
+                        obj(vec![
+                            ("uri", uri.into()),
+                            ("diagnostics", Value::Array(Vec::new())),
+                        ]),
+                    ),
+                ])];
+                out.extend(self.publish_all());
+                out
+            }
+            "exit" => {
+                self.exited = true;
+                Vec::new()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Open documents may import each other, so an edit can change the
+    /// diagnostics of every one of them.
+    fn publish_all(&self) -> Vec<Value> {
+        let mut paths: Vec<&PathBuf> = self.docs.keys().collect();
+        paths.sort();
+        paths.into_iter().map(|p| self.diagnostics(p)).collect()
+    }
+
+    fn request(&mut self, method: &str, params: &Value) -> Result<Value, (i32, String)> {
+        match method {
+            "initialize" => Ok(obj(vec![
+                (
+                    "capabilities",
+                    obj(vec![
+                        ("textDocumentSync", 1u64.into()),
+                        ("hoverProvider", true.into()),
+                        ("definitionProvider", true.into()),
+                        (
+                            "completionProvider",
+                            obj(vec![("triggerCharacters", vec![Value::from(".")].into())]),
+                        ),
+                        ("documentSymbolProvider", true.into()),
+                    ]),
+                ),
+                (
+                    "serverInfo",
+                    obj(vec![
+                        ("name", "ultracpp".into()),
+                        ("version", env!("CARGO_PKG_VERSION").into()),
+                    ]),
+                ),
+            ])),
+            "shutdown" => {
+                self.shutting_down = true;
+                Ok(Value::Null)
+            }
+            "textDocument/hover" => Ok(self.hover(params).unwrap_or(Value::Null)),
+            "textDocument/definition" => Ok(self.definition(params).unwrap_or(Value::Null)),
+            "textDocument/completion" => {
+                Ok(self.completion(params).unwrap_or(Value::Array(Vec::new())))
+            }
+            "textDocument/documentSymbol" => Ok(self
+                .document_symbols(params)
+                .unwrap_or(Value::Array(Vec::new()))),
+            _ => Err((-32601, format!("method not found: {}", method))),
+        }
+    }
+
+    /// Document path, its text and the 0-based line and byte column of the
+    /// position in `params`.
+    fn cursor(&self, params: &Value) -> Option<(PathBuf, String, usize, usize)> {
+        let uri = params.at(&["textDocument", "uri"])?.as_str()?;
+        let path = Server::key(&uri_to_path(uri));
+        let text = self.text_of(&path)?;
+        let line = params.at(&["position", "line"])?.as_u64()? as usize;
+        let character = params.at(&["position", "character"])?.as_u64()? as usize;
+        let col = byte_col(text.lines().nth(line).unwrap_or(""), character);
+        Some((path, text, line, col))
+    }
+
+    fn symbol_at<'a>(
+        &self,
+        a: &'a Analysis,
+        text: &str,
+        line: usize,
+        col: usize,
+    ) -> Option<Symbol<'a>> {
+        let l = text.lines().nth(line)?;
+        if let Some(spec) = l.trim_start().strip_prefix("import ") {
+            let (imp, _) = imports::parse_import_spec(spec.trim());
+            let stem = Path::new(&imp).file_stem()?.to_string_lossy().to_string();
+            let file = a.graph.imported().find(|f| {
+                f.path
+                    .file_stem()
+                    .map(|s| s.to_string_lossy() == stem.as_str())
+                    == Some(true)
+            })?;
+            return Some(Symbol::File(file.path.clone()));
+        }
+        if let Some(name) = l
+            .trim_start()
+            .strip_prefix("entry ")
+            .or_else(|| l.trim_start().strip_prefix("run "))
+        {
+            if let Some(c) = a.class(name.trim()) {
+                return Some(Symbol::Class(c));
+            }
+        }
+        let start = l[..col]
+            .rfind(|c: char| !(is_word(c) || c == '.'))
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let end = l[col..]
+            .find(|c: char| !is_word(c))
+            .map(|i| col + i)
+            .unwrap_or(l.len());
+        if start >= end {
+            return None;
+        }
+        let segments: Vec<&str> = l[start..end].split('.').collect();
+        // Only the segments up to the one under the cursor matter.
+        let upto = l[start..col].matches('.').count();
+        let enclosing = enclosing_class(text, line).and_then(|n| a.class(&n));
+        a.resolve_path(&segments[..=upto.min(segments.len() - 1)], enclosing)
+            .map(|(s, _)| s)
+    }
+
+    fn hover(&self, params: &Value) -> Option<Value> {
+        let (path, text, line, col) = self.cursor(params)?;
+        let a = self.analyse_lenient(&path)?;
+        let symbol = self.symbol_at(&a, &text, line, col)?;
+        let doc = match &symbol {
+            Symbol::Class(c) => c.doc.clone(),
+            Symbol::Field(_, f) => f.doc.clone(),
+            Symbol::Method(_, m) => m.doc.clone(),
+            _ => None,
+        };
+        let body = match symbol {
+            Symbol::Class(c) => {
+                let mut s = format!("class {}", c.name);
+                if let Some(b) = &c.base {
+                    s.push_str(&format!("({})", b));
+                }
+                if let Some(ns) = &c.namespace {
+                    s = format!("namespace {}\n{}", ns, s);
+                }
+                for f in &c.fields {
+                    s.push_str(&format!("\n    {}: {}", f.name, f.ty));
+                }
+                for m in &c.methods {
+                    s.push_str(&format!("\n    {}", signature(c, m)));
+                }
+                if let Some(v) = &c.module_version {
+                    s.push_str(&format!("\n# version {}", v));
+                }
+                s
+            }
+            Symbol::Field(c, f) => format!("{}.{}: {}", c.name, f.name, f.ty),
+            Symbol::Method(c, m) => signature(c, m),
+            Symbol::Namespace(ns) => format!("namespace {}", ns),
+            Symbol::File(p) => p.to_string_lossy().to_string(),
+        };
+        Some(obj(vec![(
+            "contents",
+            obj(vec![
+                ("kind", "markdown".into()),
+                (
+                    "value",
+                    match doc {
+                        Some(d) => format!("```python\n{}\n```\n\n{}", body, d),
+                        None => format!("```python\n{}\n```", body),
+                    }
+                    .into(),
+                ),
+            ]),
+        )]))
+    }
+
+    fn definition(&self, params: &Value) -> Option<Value> {
+        let (path, text, line, col) = self.cursor(params)?;
+        let a = self.analyse_lenient(&path)?;
+        let (class, member) = match self.symbol_at(&a, &text, line, col)? {
+            Symbol::Class(c) => (c, None),
+            Symbol::Field(c, f) => (c, Some(f.name.as_str())),
+            Symbol::Method(c, m) => (c, Some(m.name.as_str())),
+            Symbol::File(p) => {
+                let source = self.text_of(&p).unwrap_or_default();
+                return Some(location(self.uri_of(&p), &source, 1));
+            }
+            Symbol::Namespace(_) => return None,
+        };
+        let file = a.graph.defining_file(&class.name)?;
+        let at = match member {
+            Some(m) => check::member_line(&file.source, &class.name, m),
+            None => check::class_line(&file.source, &class.name).map(|(l, _)| l),
+        }?;
+        Some(location(self.uri_of(&file.path), &file.source, at))
+    }
+
+    fn completion(&self, params: &Value) -> Option<Value> {
+        let (path, text, line, col) = self.cursor(params)?;
+        let a = self.analyse_lenient(&path)?;
+        let l = text.lines().nth(line).unwrap_or("");
+        let before = &l[..col];
+        let start = before
+            .rfind(|c: char| !(is_word(c) || c == '.'))
+            .map(|i| i + 1)
+            .unwrap_or(0);
+        let expr = &before[start..];
+        let item = |label: &str, kind: u64, detail: String| {
+            obj(vec![
+                ("label", label.into()),
+                ("kind", kind.into()),
+                ("detail", detail.into()),
+            ])
+        };
+        let mut items: Vec<Value> = Vec::new();
+        let enclosing = enclosing_class(&text, line).and_then(|n| a.class(&n));
+        if let Some(dot) = expr.rfind('.') {
+            let prefix = &expr[dot + 1..];
+            let segments: Vec<&str> = expr[..dot].split('.').collect();
+            let (target, via_type) = a.resolve_path(&segments, enclosing)?;
+            let class = match target {
+                Symbol::Namespace(ns) => {
+                    for c in a
+                        .classes
+                        .iter()
+                        .filter(|c| c.namespace.as_deref() == Some(&ns))
+                    {
+                        if c.name.starts_with(prefix) {
+                            items.push(item(&c.name, kind::CLASS, format!("class {}", c.name)));
+                        }
+                    }
+                    return Some(items.into());
+                }
+                Symbol::Class(c) => Some(c),
+                Symbol::Field(_, f) => a.class(f.ty.trim()),
+                _ => None,
+            }?;
+            for m in a.members(class) {
+                match m {
+                    Symbol::Field(_, f) if !via_type && f.name.starts_with(prefix) => {
+                        items.push(item(&f.name, kind::FIELD, f.ty.clone()))
+                    }
+                    Symbol::Method(c, m)
+                        if (!via_type || m.is_static) && m.name.starts_with(prefix) =>
+                    {
+                        items.push(item(&m.name, kind::METHOD, signature(c, m)))
+                    }
+                    _ => {}
+                }
+            }
+            return Some(items.into());
+        }
+        if indent_of(l) == 0 || l[..start].trim().is_empty() && enclosing.is_none() {
+            for (kw, doc) in DIRECTIVES {
+                if kw.starts_with(expr) {
+                    items.push(item(kw, kind::KEYWORD, doc.to_string()));
+                }
+            }
+        }
+        if enclosing.is_some() || indent_of(l) > 0 {
+            if "self".starts_with(expr) {
+                items.push(item(
+                    "self",
+                    kind::KEYWORD,
+                    "the current instance".to_string(),
+                ));
+            }
+            if let Some(c) = enclosing {
+                for m in a.members(c) {
+                    match m {
+                        Symbol::Field(_, f) if f.name.starts_with(expr) => {
+                            items.push(item(&f.name, kind::FIELD, f.ty.clone()))
+                        }
+                        Symbol::Method(owner, m) if m.name.starts_with(expr) => {
+                            items.push(item(&m.name, kind::METHOD, signature(owner, m)))
+                        }
+                        _ => {}
+                    }
+                }
+            }
+        }
+        let mut namespaces: Vec<&str> = Vec::new();
+        for c in &a.classes {
+            if c.name.starts_with(expr) {
+                items.push(item(&c.name, kind::CLASS, format!("class {}", c.name)));
+            }
+            if let Some(ns) = c.namespace.as_deref() {
+                if ns.starts_with(expr) && !namespaces.contains(&ns) {
+                    namespaces.push(ns);
+                    items.push(item(ns, kind::MODULE, format!("namespace {}", ns)));
+                }
+            }
+        }
+        Some(items.into())
+    }
+
+    fn document_symbols(&self, params: &Value) -> Option<Value> {
+        let uri = params.at(&["textDocument", "uri"])?.as_str()?;
+        let path = Server::key(&uri_to_path(uri));
+        let text = self.text_of(&path)?;
+        let symbol = |name: &str,
+                      detail: String,
+                      kind: u64,
+                      line: usize,
+                      end: usize,
+                      children: Vec<Value>| {
+            let last = text.lines().nth(end - 1).unwrap_or("");
+            let range = obj(vec![
+                ("start", position(line - 1, 0)),
+                ("end", position(end - 1, utf16_len(last))),
+            ]);
+            let mut pairs = vec![
+                ("name", name.into()),
+                ("detail", detail.into()),
+                ("kind", kind.into()),
+                ("range", range),
+                ("selectionRange", line_range(&text, line)),
+            ];
+            if !children.is_empty() {
+                pairs.push(("children", children.into()));
+            }
+            obj(pairs)
+        };
+        let mut out: Vec<Value> = Vec::new();
+        for c in parser::parse_all(&text) {
+            let Some((start, _)) = check::class_line(&text, &c.name) else {
+                continue;
+            };
+            let mut children: Vec<Value> = Vec::new();
+            for f in &c.fields {
+                if let Some(l) = check::member_line(&text, &c.name, &f.name) {
+                    children.push(symbol(
+                        &f.name,
+                        f.ty.clone(),
+                        symbol_kind::FIELD,
+                        l,
+                        l,
+                        Vec::new(),
+                    ));
+                }
+            }
+            for m in &c.methods {
+                if let Some(l) = check::member_line(&text, &c.name, &m.name) {
+                    children.push(symbol(
+                        &m.name,
+                        signature(&c, m),
+                        symbol_kind::METHOD,
+                        l,
+                        l,
+                        Vec::new(),
+                    ));
+                }
+            }
+            let detail = c
+                .base
+                .as_deref()
+                .map(|b| format!("({})", b))
+                .unwrap_or_default();
+            out.push(symbol(
+                &c.name,
+                detail,
+                symbol_kind::CLASS,
+                start,
+                class_end(&text, start),
+                children,
+            ));
+        }
+        Some(out.into())
+    }
+}
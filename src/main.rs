@@ -1,253 +1,69 @@
 use std::env;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::Command;
 
-use std::collections::HashMap;
-use std::time::Instant;
-use ultracpp::Directives;
-use ultracpp::{codegen, parser, tool_detector};
-
-fn read_to_string(path: &str) -> String {
-    fs::read_to_string(path).expect("read failed")
-}
+use std::time::{Instant, SystemTime};
+use ultracpp::bridge;
+use ultracpp::buildplan::{self, BuildPlan, Target, TargetKind};
+use ultracpp::cache::{self, BuildCache};
+use ultracpp::check::{self, Diagnostic};
+use ultracpp::cli::{self, Bridge, BuildArgs, BuildFile, Compiler, EmitMode, Sanitizer};
+use ultracpp::codegen::CppStd;
+use ultracpp::compiler_output::{self, CompilerDiagnostic};
+use ultracpp::manifest::{self, Manifest};
+use ultracpp::sourcemap::{self, SourceMap};
+use ultracpp::{codegen, format, imports, lsp, packages, tool_detector};
+use ultracpp::{write_if_changed, Directives};
 
 fn write(path: &str, contents: &str) {
-    if let Ok(existing) = fs::read_to_string(path) {
-        if existing == contents {
-            return;
-        }
-    }
-    fs::write(path, contents).expect("write failed")
-}
-
-fn is_safe_rel_path(s: &str) -> bool {
-    let st = s.trim();
-    if st.is_empty() { return false; }
-    if st.starts_with('/') || st.starts_with('\\') { return false; }
-    if st.contains("..") { return false; }
-    if st.contains(':') { return false; }
-    true
+    write_if_changed(Path::new(path), contents).expect("write failed")
 }
 
 fn stem(p: &Path) -> String {
     p.file_stem().unwrap().to_string_lossy().to_string()
 }
 
-fn is_builtin_ty(t: &str) -> bool {
-    matches!(
-        t.trim(),
-        "Int"
-            | "Float"
-            | "Bool"
-            | "String"
-            | "Void"
-            | "int"
-            | "float"
-            | "bool"
-            | "double"
-            | "Double"
-    )
-}
-
-fn parse_import_spec(s: &str) -> (String, Option<String>) {
-    if let Some(pos) = s.rfind('@') {
-        let p = s[..pos].trim();
-        let v = s[pos + 1..].trim();
-        let mut path = p.to_string();
-        if !path.ends_with(".upp") {
-            path.push_str(".upp");
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let command = match cli::parse_args(&args) {
+        Ok(c) => c,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            eprintln!("Run `ultracpp --help` for usage.");
+            std::process::exit(2);
         }
-        return (
-            path,
-            if v.is_empty() {
-                None
-            } else {
-                Some(v.to_string())
-            },
-        );
-    }
-    let mut path = s.trim().to_string();
-    if !path.ends_with(".upp") {
-        path.push_str(".upp");
-    }
-    (path, None)
-}
-
-fn type_check(classes: &[ultracpp::Class]) -> Vec<String> {
-    let mut errors: Vec<String> = Vec::new();
-    let mut class_map: HashMap<String, ultracpp::Class> = HashMap::new();
-    for c in classes {
-        class_map.insert(c.name.clone(), c.clone());
-    }
-    fn check_expr(
-        e: &ultracpp::Expr,
-        c: &ultracpp::Class,
-        method: &str,
-        classes: &HashMap<String, ultracpp::Class>,
-        errors: &mut Vec<String>,
-    ) {
-        match e {
-            ultracpp::Expr::SelfField(n) => {
-                let ok = c.fields.iter().any(|f| &f.name == n);
-                if !ok {
-                    errors.push(format!(
-                        "Campo desconocido '{}' en {}::{}",
-                        n, c.name, method
-                    ));
-                }
-            }
-            ultracpp::Expr::SelfCall { name, .. } => {
-                let ok = c.methods.iter().any(|m| &m.name == name);
-                if !ok {
-                    errors.push(format!(
-                        "Método desconocido '{}' en {}::{}",
-                        name, c.name, method
-                    ));
-                }
-            }
-            ultracpp::Expr::FileCall(name) => {
-                // call hola.upp -> método local hola_upp() o hola()
-                let target_method_upp = format!("{}_upp", name.replace('.', "_"));
-                let target_method_std = name.replace('.', "_");
-                let has_upp = c.methods.iter().any(|m| m.name == target_method_upp);
-                let has_std = c.methods.iter().any(|m| m.name == target_method_std);
-                if !has_upp && !has_std {
-                    errors.push(format!(
-                        "Llamada 'call {}' no resuelta en clase {}. Define {}() o {}(). Ejemplo:\n  def {}():\n    print(\"...\")",
-                        name, c.name, target_method_upp, target_method_std, target_method_upp
-                    ));
-                }
-            }
-            ultracpp::Expr::SuperCall { name, .. } => {
-                if let Some(b) = &c.base {
-                    if let Some(base_cls) = classes.get(b) {
-                        let ok = base_cls.methods.iter().any(|m| &m.name == name);
-                        if !ok {
-                            errors.push(format!(
-                                "Método '{}' no existe en base {} para {}::{}",
-                                name, b, c.name, method
-                            ));
-                        }
-                    }
-                }
-            }
-            ultracpp::Expr::FunctionCall { name, .. } => {
-                if name.contains('.') {
-                    let parts: Vec<&str> = name.split('.').collect();
-                    if parts.len() == 2 {
-                        let lhs = parts[0].to_string();
-                        let m = parts[1].to_string();
-                        // Interpret dotted calls as Class.Method only when LHS looks like a type (starts uppercase) and exists
-                        if lhs
-                            .chars()
-                            .next()
-                            .map(|ch| ch.is_uppercase())
-                            .unwrap_or(false)
-                        {
-                            if let Some(cc) = classes.get(&lhs) {
-                                let ok = cc.methods.iter().any(|mm| mm.name == m);
-                                if !ok {
-                                    errors
-                                        .push(format!("Método '{}' no existe en clase {}", m, lhs));
-                                }
-                            } else {
-                                errors.push(format!(
-                                    "Clase '{}' no encontrada para llamada {}",
-                                    lhs, name
-                                ));
-                            }
-                        }
-                    }
-                }
-            }
-            ultracpp::Expr::VarDecl { ty, .. } => {
-                let t = ty.trim().to_string();
-                if t != "Auto" && !is_builtin_ty(&t) && !classes.contains_key(&t) {
-                    errors.push(format!(
-                        "Tipo '{}' no resuelto en {}::{}",
-                        t, c.name, method
-                    ));
-                }
-            }
-            ultracpp::Expr::BinaryOp(l, _, r) => {
-                check_expr(l, c, method, classes, errors);
-                check_expr(r, c, method, classes, errors);
-            }
-            ultracpp::Expr::Block(stmts) => {
-                for s in stmts {
-                    check_expr(s, c, method, classes, errors);
-                }
-            }
-            ultracpp::Expr::If {
-                cond,
-                then_body,
-                else_body,
-            } => {
-                check_expr(cond, c, method, classes, errors);
-                check_expr(then_body, c, method, classes, errors);
-                if let Some(e2) = else_body {
-                    check_expr(e2, c, method, classes, errors);
-                }
-            }
-            ultracpp::Expr::While { cond, body } => {
-                check_expr(cond, c, method, classes, errors);
-                check_expr(body, c, method, classes, errors);
-            }
-            ultracpp::Expr::Return(Some(v)) => {
-                check_expr(v, c, method, classes, errors);
-            }
-            ultracpp::Expr::Concat(l, r) => {
-                check_expr(l, c, method, classes, errors);
-                check_expr(r, c, method, classes, errors);
-            }
-            _ => {}
+    };
+    let result = match command {
+        cli::Command::Help(topic) => {
+            print!("{}", cli::help(topic.as_deref()));
+            Ok(())
         }
-    }
-    for c in classes {
-        for m in &c.methods {
-            check_expr(&m.body, c, &m.name, &class_map, &mut errors);
+        cli::Command::Init { file, template } => init(&file, &template),
+        cli::Command::Build(a) | cli::Command::Emit(a) => {
+            load(&a).and_then(|(project, opts)| build(&project, &opts))
         }
-    }
-    errors
-}
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        eprintln!("usage: ultracpp <input.upp|input_dir> [outdir] [--compile] [--no-main] [--compiler cl|g++|clang++] [--std c++17|c++20]");
-        eprintln!("       ultracpp init <filename> [--template game]");
+        cli::Command::Run(a) => load(&a).and_then(|(project, mut opts)| {
+            opts.compile = true;
+            opts.run = true;
+            build(&project, &opts)
+        }),
+        cli::Command::Check(a) => check_project(&a),
+        cli::Command::Watch(a) => watch(&a),
+        cli::Command::Fmt { args, check } => format_sources(&args, check),
+        cli::Command::Clean(a) => clean(&a),
+        cli::Command::Test { args, compilers } => test(&args, compilers),
+        cli::Command::Lsp => lsp::serve(std::io::stdin().lock(), std::io::stdout().lock()),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
         std::process::exit(1);
     }
-    if args[1] == "init" {
-        if args.len() < 3 {
-            eprintln!("usage: ultracpp init <filename> [--template game]");
-            std::process::exit(1);
-        }
-        let filename = &args[2];
-        let mut template = "default";
-        for a in args.iter().skip(3) {
-            if a.starts_with("--template") {
-                let parts: Vec<&str> = a.split_whitespace().collect();
-                if parts.len() > 1 {
-                    template = parts[1];
-                } else if let Some(pos) = args.iter().position(|x| x == a) {
-                    if pos + 1 < args.len() {
-                        template = &args[pos + 1];
-                    }
-                }
-            }
-        }
-        // Handle explicit "--template game" separate args
-        let mut idx = 3;
-        while idx < args.len() {
-            if args[idx] == "--template" && idx + 1 < args.len() {
-                template = &args[idx + 1];
-            }
-            idx += 1;
-        }
+}
 
-        let content = if template == "game" {
-            r#"global
+fn init(filename: &str, template: &str) -> Result<(), String> {
+    let content = if template == "game" {
+        r#"global
 profile math
 capability io
 
@@ -274,698 +90,368 @@ class Game:
 
 run Game
 "#
-        } else {
-            r#"class Main:
+    } else {
+        r#"class Main:
     def hello(self) -> String:
         return "Hello World"
 run Main
 "#
+    };
+    write(filename, content);
+    println!("Generated {} with template '{}'", filename, template);
+    let file_path = Path::new(filename);
+    let manifest_path = parent_dir(file_path).join(manifest::MANIFEST_NAME);
+    if !manifest_path.exists() {
+        let m = Manifest {
+            name: Some(stem(file_path).to_lowercase()),
+            sources: vec![file_path.file_name().unwrap().to_string_lossy().to_string()],
+            entry: Some(if template == "game" { "Game" } else { "Main" }.to_string()),
+            profiles: if template == "game" {
+                vec!["math".to_string()]
+            } else {
+                Vec::new()
+            },
+            ..Manifest::default()
         };
-        write(filename, content);
-        println!("Generated {} with template '{}'", filename, template);
-        return;
-    }
-    let input_path = &args[1];
-    let mut outdir_root = "dist".to_string();
-    let mut do_compile = false;
-    let mut no_main = false;
-    let mut compiler: Option<String> = None;
-    let mut stdver: String = "c++17".to_string();
-    let mut bench = false;
-    let mut staging = false;
-    let mut watch = false;
-    let mut unity = false;
-    let mut hybrid = false;
-    let mut emit_cmake = false;
-    let mut release = false;
-    let mut gpu_backend: Option<String> = None;
-    let mut bridge: Option<String> = None;
-    let mut lint = false;
-    let mut format = false;
-    let mut lint_rust = false;
-    let mut smoke = false;
-    let mut smoke_compilers = false;
-    let mut sanitize: Option<String> = None;
-    let mut coverage = false;
-    for a in args.iter().skip(2) {
-        if a.starts_with("--compile") {
-            do_compile = true;
-        } else if a.starts_with("--no-main") {
-            no_main = true;
-        } else if let Some(v) = a.strip_prefix("--compiler ") {
-            if !v.is_empty() {
-                compiler = Some(v.to_string());
-            }
-        } else if let Some(v) = a.strip_prefix("--std ") {
-            if !v.is_empty() {
-                stdver = v.to_string();
-            }
-        } else if a.starts_with("--bench") {
-            bench = true;
-        } else if a.starts_with("--staging") {
-            staging = true;
-        } else if a.starts_with("--watch") {
-            watch = true;
-        } else if a.starts_with("--unity") {
-            unity = true;
-        } else if a.starts_with("--release") {
-            release = true;
-        } else if a.starts_with("--emit cmake") {
-            emit_cmake = true;
-        } else if let Some(v) = a.strip_prefix("--emit ") {
-            match v {
-                "unity" => {
-                    unity = true;
-                    hybrid = false;
-                }
-                "classic" => {
-                    unity = false;
-                    hybrid = false;
-                }
-                "hybrid" => {
-                    unity = false;
-                    hybrid = true;
-                }
-                "cmake" => {
-                    emit_cmake = true;
-                }
-                _ => {}
-            }
-        } else if let Some(v) = a.strip_prefix("--gpu ") {
-            if !v.is_empty() {
-                gpu_backend = Some(v.to_string());
-            }
-        } else if let Some(v) = a.strip_prefix("--bridge ") {
-            if !v.is_empty() {
-                bridge = Some(v.to_string());
-            }
-        } else if a.starts_with("--lint") {
-            lint = true;
-        } else if a.starts_with("--format") {
-            format = true;
-        } else if a.starts_with("--lint-rust") {
-            lint_rust = true;
-        } else if a.starts_with("--smoke") {
-            smoke = true;
-        } else if a.starts_with("--smoke-compilers") {
-            smoke_compilers = true;
-        } else if let Some(v) = a.strip_prefix("--sanitize ") {
-            if !v.is_empty() {
-                sanitize = Some(v.to_string());
-            }
-        } else if a.starts_with("--coverage") {
-            coverage = true;
-        } else if !a.starts_with("--") && outdir_root == "dist" {
-            outdir_root = a.to_string();
-        }
+        write(manifest_path.to_str().unwrap(), &m.render());
+        println!("Generated {}", manifest_path.display());
     }
-    // Handle "--emit <mode>" split into two args
-    let mut idx = 2usize;
-    while idx < args.len() {
-        if args[idx] == "--emit" && idx + 1 < args.len() {
-            match args[idx + 1].as_str() {
-                "unity" => {
-                    unity = true;
-                    hybrid = false;
-                }
-                "classic" => {
-                    unity = false;
-                    hybrid = false;
-                }
-                "hybrid" => {
-                    unity = false;
-                    hybrid = true;
-                }
-                "cmake" => {
-                    emit_cmake = true;
+    Ok(())
+}
+
+fn parent_dir(p: &Path) -> PathBuf {
+    p.parent()
+        .filter(|d| !d.as_os_str().is_empty())
+        .unwrap_or(Path::new("."))
+        .to_path_buf()
+}
+
+/// The `.upp` files directly inside `dir`, with `principal.upp` first.
+fn upp_files_in(dir: &Path) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    if let Ok(rd) = fs::read_dir(dir) {
+        for e in rd.flatten() {
+            let p = e.path();
+            if let Some(ext) = p.extension() {
+                if ext.to_string_lossy().eq_ignore_ascii_case("upp") && p.is_file() {
+                    files.push(p);
                 }
-                _ => {}
             }
-        } else if args[idx] == "--gpu" && idx + 1 < args.len() {
-            let v = args[idx + 1].to_string();
-            if !v.is_empty() {
-                gpu_backend = Some(v);
-            }
-        } else if args[idx] == "--bridge" && idx + 1 < args.len() {
-            let v = args[idx + 1].to_string();
-            if !v.is_empty() {
-                bridge = Some(v);
-            }
-        } else if args[idx] == "--sanitize" && idx + 1 < args.len() {
-            let v = args[idx + 1].to_string();
-            if !v.is_empty() {
-                sanitize = Some(v);
-            }
-        } else if args[idx] == "--coverage" {
-            coverage = true;
-        } else if args[idx] == "--lint" {
-            lint = true;
-        } else if args[idx] == "--format" {
-            format = true;
-        } else if args[idx] == "--lint-rust" {
-            lint_rust = true;
-        } else if args[idx] == "--smoke" {
-            smoke = true;
-        } else if args[idx] == "--smoke-compilers" {
-            smoke_compilers = true;
         }
-        idx += 1;
-    }
-    if staging {
-        outdir_root = "staging".to_string();
-    }
-    let path_meta = std::fs::metadata(input_path);
-    if let Ok(md) = path_meta {
-        if md.is_dir() {
-            let mut outdir_root_arg = outdir_root.clone();
-            for a in args.iter().skip(2) {
-                if !a.starts_with("--") && outdir_root_arg == "dist" {
-                    outdir_root_arg = a.to_string();
-                }
-            }
-            if Path::new(&outdir_root_arg).is_file() {
-                eprintln!("Error: Output root '{}' is a file, cannot create directory inside it.", outdir_root_arg);
-                std::process::exit(1);
-            }
-            let dir_path = Path::new(input_path);
-            let base = dir_path
-                .file_name()
-                .unwrap()
-                .to_string_lossy()
-                .to_lowercase();
-            let dir = Path::new(&outdir_root_arg).join(&base);
-            let src_dir = dir.join("src");
-            let include_dir = dir.join("include");
-            let build_dir = dir.join("build");
-            let obj_dir = build_dir.join("obj");
-            let bin_dir = build_dir.join("bin");
-            fs::create_dir_all(&src_dir).expect("create src dir failed");
-            fs::create_dir_all(&include_dir).expect("create include dir failed");
-            fs::create_dir_all(&obj_dir).expect("create obj dir failed");
-            fs::create_dir_all(&bin_dir).expect("create bin dir failed");
-            // Write MSVC PCH source stub
-            {
-                let pch_src = src_dir.join("pch.cpp");
-                let _ = fs::write(&pch_src, "#include \"pch.hpp\"\n");
-            }
-            // Write precompiled header
-            {
-                let pch = include_dir.join("pch.hpp");
-                let mut p = String::new();
-                p.push_str("#pragma once\n");
-                p.push_str("#include <iostream>\n");
-                p.push_str("#include <string>\n");
-                p.push_str("#include <vector>\n");
-                p.push_str("#include <memory>\n");
-                p.push_str("#include <map>\n");
-                p.push_str("#include <list>\n");
-                p.push_str("#include <optional>\n");
-                p.push_str("#include <thread>\n");
-                p.push_str("#include <mutex>\n");
-                p.push_str("#include <future>\n");
-                p.push_str("#include <atomic>\n");
-                p.push_str("#include <filesystem>\n");
-                p.push_str("#include <fstream>\n");
-                p.push_str("#include <algorithm>\n");
-                p.push_str("#include <numeric>\n");
-                p.push_str("#include <cmath>\n");
-                p.push_str("#include <cstdio>\n");
-                p.push_str("#include <functional>\n");
-                let _ = fs::write(&pch, p);
-            }
-            let mut files: Vec<(String, String)> = Vec::new();
-            if let Ok(rd) = fs::read_dir(dir_path) {
-                for e in rd.flatten() {
-                    let p = e.path();
-                    if let Some(ext) = p.extension() {
-                        if ext.to_string_lossy().eq_ignore_ascii_case("upp") {
-                            if let Ok(s) = fs::read_to_string(&p) {
-                                files.push((
-                                    p.file_name().unwrap().to_string_lossy().to_string(),
-                                    s,
-                                ));
-                            }
-                        }
-                    }
-                }
-            }
-            if files.is_empty() {
-                eprintln!("no .upp files in directory");
-                std::process::exit(1);
-            }
-            files.sort_by(|a, b| {
-                let sa = a.0.to_lowercase();
-                let sb = b.0.to_lowercase();
-                if sa == "principal.upp" && sb != "principal.upp" {
-                    std::cmp::Ordering::Less
-                } else if sb == "principal.upp" && sa != "principal.upp" {
-                    std::cmp::Ordering::Greater
-                } else {
-                    sa.cmp(&sb)
-                }
-            });
-            let mut merged = Directives::default();
-            let mut classes: Vec<ultracpp::Class> = Vec::new();
-            let mut names: Vec<String> = Vec::new();
-            for (fname, content) in &files {
-                let d = parser::scan_directives(content);
-                for u in d.uses {
-                    if !merged.uses.contains(&u) {
-                        merged.uses.push(u);
-                    }
-                }
-                for p in d.profiles {
-                    if !merged.profiles.contains(&p) {
-                        merged.profiles.push(p);
-                    }
-                }
-                for c in d.capabilities {
-                    if !merged.capabilities.contains(&c) {
-                        merged.capabilities.push(c);
-                    }
-                }
-                if d.global_base {
-                    merged.global_base = true;
-                }
-                if merged.namespace.is_none() {
-                    merged.namespace = d.namespace.clone();
-                }
-                if fname.to_lowercase() == "principal.upp" {
-                    merged.entry = d.entry.or(merged.entry.clone());
-                } else if merged.entry.is_none() {
-                    merged.entry = d.entry;
-                }
-                let mut parsed = parser::parse_all(content);
-                for cls in parsed.iter_mut() {
-                    cls.namespace = d.namespace.clone();
-                }
-                for cls in parsed.into_iter() {
-                    if let Some(pos) = names.iter().position(|n| n == &cls.name) {
-                        if fname.to_lowercase() == "principal.upp" {
-                            classes[pos] = cls.clone();
-                        }
-                    } else {
-                        names.push(cls.name.clone());
-                        classes.push(cls.clone());
-                    }
-                }
-            }
-            let mut needs_object_base = merged.global_base;
-            let type_errors = type_check(&classes);
-            if !type_errors.is_empty() {
-                for e in type_errors {
-                    eprintln!("{}", e);
-                }
-                std::process::exit(1);
-            }
-            for class in classes.iter_mut() {
-                if merged.global_base && class.base.is_none() {
-                    class.base = Some("Object".to_string());
-                    needs_object_base = true;
-                }
-                class.extra_includes = ultracpp::resolve_includes(&merged);
-                if class.namespace.is_none() {
-                    class.namespace = merged.namespace.clone();
-                }
-            }
-
-            if unity {
-                // Clean up existing .cpp files to avoid duplicates/conflicts
-                if let Ok(rd) = fs::read_dir(&src_dir) {
-                    for e in rd.flatten() {
-                        let p = e.path();
-                        if let Some(ext) = p.extension() {
-                            if ext.to_string_lossy().eq_ignore_ascii_case("cpp") {
-                                let _ = fs::remove_file(p);
-                            }
-                        }
-                    }
-                }
-                let mut content = codegen::unity_build(&classes);
-                if needs_object_base {
-                    // In unity build, Object base should be included or defined.
-                    // For simplicity, we assume Object.hpp is not needed if we define it inline or use std types.
-                    // But if we really need it, we might need to inject it.
-                    // Let's just include "Object.hpp" and hope it exists or generated.
-                    // Actually, write_object_base generates Object.hpp/cpp.
-                    // We should probably inline Object class in unity build if possible, but let's stick to include for now.
-                    content.insert_str(0, "#include \"Object.hpp\"\n");
-                }
+    }
+    files.sort_by_key(|p| {
+        let f = p.file_name().unwrap().to_string_lossy().to_lowercase();
+        (f != "principal.upp", f)
+    });
+    files
+}
 
-                if !no_main {
-                    let target = select_entry_target(&classes, &merged);
-                    let qname = if let Some(ns) = &target.namespace {
-                        format!("{}::{}", ns, target.name)
-                    } else {
-                        target.name.clone()
-                    };
-                    // Append main
-                    content.push_str("\n\n");
-                    content.push_str("#ifdef _WIN32\n");
-                    content.push_str("#include <windows.h>\n");
-                    content.push_str("#endif\n");
-                    content.push_str("\nint main() {\n");
-                    content.push_str("  #ifdef _WIN32\n");
-                    content.push_str("    SetConsoleOutputCP(65001);\n");
-                    content.push_str("  #endif\n");
-                    content.push_str("  try {\n");
-                    if target
-                        .methods
-                        .iter()
-                        .any(|m| m.name == "run" && m.is_static)
-                    {
-                        content.push_str(&format!("    {}::run();\n", qname));
-                    } else {
-                        content.push_str(&format!("    {} app;\n", qname));
-                        content.push_str("    app.run();\n");
-                    }
-                    content.push_str("  } catch (const std::exception& e) {\n");
-                    content.push_str("    std::cerr << e.what() << std::endl;\n");
-                    content.push_str("    return 1;\n");
-                    content.push_str("  }\n");
-                    content.push_str("  return 0;\n");
-                    content.push_str("}\n");
-                }
+/// What to build: the root sources of a `.upp` file, a folder or an
+/// `ultra.toml` project.
+struct Project {
+    /// Directory holding `ultra.toml`, `ultra.lock` and `packages/`.
+    dir: PathBuf,
+    base: String,
+    roots: Vec<PathBuf>,
+    manifest: Manifest,
+}
 
-                let all_cpp = src_dir.join("all.cpp");
-                write(all_cpp.to_str().unwrap(), &content);
-                println!("generated unity build: {}", all_cpp.display());
-            } else if hybrid {
-                // Hybrid: generate headers only, plus a unity all.cpp for sources
-                for class in &classes {
-                    let hpp = codegen::header(class);
-                    let hpp_path = include_dir.join(format!("{}.hpp", class.name.to_lowercase()));
-                    write(hpp_path.to_str().unwrap(), &hpp);
-                    println!("generated: {}", hpp_path.display());
-                }
-                if needs_object_base {
-                    write_object_base(&src_dir, &include_dir);
-                }
-                // Unity source with optional main appended
-                let mut content = codegen::unity_build(&classes);
-                if !no_main {
-                    let target = select_entry_target(&classes, &merged);
-                    let qname = if let Some(ns) = &target.namespace {
-                        format!("{}::{}", ns, target.name)
-                    } else {
-                        target.name.clone()
-                    };
-                    content.push_str("\n\n");
-                    content.push_str("#ifdef _WIN32\n#include <windows.h>\n#endif\n");
-                    content.push_str("int main() {\n");
-                    content.push_str("  #ifdef _WIN32\n    // SetConsoleOutputCP(65001); // disabled for broader toolchain compatibility\n  #endif\n");
-                    content.push_str("  try {\n");
-                    if target
-                        .methods
-                        .iter()
-                        .any(|m| m.name == "run" && m.is_static)
-                    {
-                        content.push_str(&format!("    {}::run();\n", qname));
-                    } else {
-                        content.push_str(&format!("    {} app;\n", qname));
-                        content.push_str("    app.run();\n");
-                    }
-                    content.push_str("  } catch (const std::exception& e) {\n    std::cerr << e.what() << std::endl;\n    return 1;\n  }\n  return 0;\n}\n");
-                }
-                let all_cpp = src_dir.join("all.cpp");
-                write(all_cpp.to_str().unwrap(), &content);
-                println!("generated hybrid build: {}", all_cpp.display());
+fn load_project(input: Option<&str>) -> Result<Project, String> {
+    let input = match input {
+        Some(i) => PathBuf::from(i),
+        None if Path::new(manifest::MANIFEST_NAME).is_file() => {
+            PathBuf::from(manifest::MANIFEST_NAME)
+        }
+        None => {
+            return Err(format!(
+                "no input given and no {} in the current directory",
+                manifest::MANIFEST_NAME
+            ))
+        }
+    };
+    if !input.exists() {
+        return Err(format!(
+            "cannot open {}: no such file or directory",
+            input.display()
+        ));
+    }
+    let manifest = match Manifest::locate(&input) {
+        Some(mp) => Manifest::read(&mp)?,
+        None => Manifest::default(),
+    };
+    let names_manifest = input
+        .file_name()
+        .map(|n| n == manifest::MANIFEST_NAME)
+        .unwrap_or(false);
+    let is_project = input.is_dir() || names_manifest;
+    let dir = if input.is_dir() {
+        input.clone()
+    } else {
+        parent_dir(&input)
+    };
+    // An explicit `.upp` file builds just that file (and its imports); a
+    // folder or manifest builds the declared sources, or every `.upp` file.
+    let mut roots: Vec<PathBuf> = Vec::new();
+    if !is_project {
+        roots.push(input.clone());
+    } else if !manifest.sources.is_empty() {
+        for s in &manifest.sources {
+            let p = dir.join(s);
+            if p.is_dir() {
+                roots.extend(upp_files_in(&p));
+            } else if p.is_file() {
+                roots.push(p);
             } else {
-                for class in &classes {
-                    let hpp = codegen::header(class);
-                    let cpp = codegen::source(class);
-                    let hpp_path = include_dir.join(format!("{}.hpp", class.name.to_lowercase()));
-                    let cpp_path = src_dir.join(format!("{}.cpp", class.name.to_lowercase()));
-                    write(hpp_path.to_str().unwrap(), &hpp);
-                    write(cpp_path.to_str().unwrap(), &cpp);
-                    println!("generated: {}, {}", hpp_path.display(), cpp_path.display());
-                }
-            }
-
-            if needs_object_base {
-                write_object_base(&src_dir, &include_dir);
-            }
-            if emit_cmake {
-                let cmake_path = dir.join("CMakeLists.txt");
-                let mut cm = String::new();
-                cm.push_str("cmake_minimum_required(VERSION 3.15)\n");
-                cm.push_str(&format!("project({} LANGUAGES CXX)\n", base));
-                cm.push_str("set(CMAKE_CXX_STANDARD 17)\nset(CMAKE_CXX_STANDARD_REQUIRED ON)\n");
-                cm.push_str("include_directories(${CMAKE_SOURCE_DIR}/include)\n");
-                // Detect unity source
-                let use_unity_src = src_dir.join("all.cpp").exists();
-                if use_unity_src {
-                    cm.push_str(&format!("add_executable({} src/all.cpp)\n", base));
-                } else {
-                    cm.push_str(&format!(
-                        "file(GLOB SRCS \"src/*.cpp\")\nadd_executable({} ${{SRCS}})\n",
-                        base
-                    ));
-                }
-                let _ = fs::write(&cmake_path, cm);
-                println!("generated cmake file: {}", cmake_path.display());
-            }
-            if let Some(_br) = bridge.clone() {
-                let exp_h = include_dir.join("exports.hpp");
-                let mut h = String::new();
-                h.push_str("#pragma once\n");
-                h.push_str("extern \"C\" {\n");
-                h.push_str("void principal_run();\n");
-                h.push_str("void hola_greet();\n");
-                h.push_str("}\n");
-                write(exp_h.to_str().unwrap(), &h);
-                let exp_cpp = src_dir.join("exports.cpp");
-                let mut cxx = String::new();
-                let target = select_entry_target(&classes, &merged);
-                let qname = if let Some(ns) = &target.namespace {
-                    format!("{}::{}", ns, target.name)
-                } else {
-                    target.name.clone()
-                };
-                let hola_q =
-                    if let Some(hc) = classes.iter().find(|x| x.name.to_lowercase() == "hola") {
-                        if let Some(ns) = &hc.namespace {
-                            format!("{}::{}", ns, hc.name)
-                        } else {
-                            hc.name.clone()
-                        }
-                    } else {
-                        "Hola".to_string()
-                    };
-                cxx.push_str(&format!(
-                    "#include \"{}.hpp\"\n",
-                    target.name.to_lowercase()
-                ));
-                cxx.push_str(&format!(
-                    "#include \"{}.hpp\"\n",
-                    hola_q.split("::").last().unwrap().to_lowercase()
-                ));
-                cxx.push_str("extern \"C\" {\n");
-                cxx.push_str(&format!(
-                    "void principal_run() {{ {} obj; obj.run(); }}\n",
-                    qname
-                ));
-                cxx.push_str(&format!(
-                    "void hola_greet() {{ {} h; h.greet(); }}\n",
-                    hola_q
+                return Err(format!(
+                    "{}: source '{}' not found",
+                    manifest::MANIFEST_NAME,
+                    s
                 ));
-                cxx.push_str("}\n");
-                write(exp_cpp.to_str().unwrap(), &cxx);
-                println!("generated: {}, {}", exp_h.display(), exp_cpp.display());
             }
-            let exe_name = if cfg!(windows) {
-                format!("{}.exe", base)
-            } else {
-                base.to_string()
-            };
-            let exe_path = bin_dir.join(&exe_name);
+        }
+    } else {
+        roots = upp_files_in(&dir);
+    }
+    if roots.is_empty() {
+        return Err(format!("no .upp files in {}", dir.display()));
+    }
+    let base = match &manifest.name {
+        Some(n) => n.to_lowercase(),
+        None if is_project => fs::canonicalize(&dir)
+            .ok()
+            .and_then(|d| d.file_name().map(|n| n.to_string_lossy().to_lowercase()))
+            .unwrap_or_else(|| "project".to_string()),
+        None => stem(&input).to_lowercase(),
+    };
+    Ok(Project {
+        dir,
+        base,
+        roots,
+        manifest,
+    })
+}
 
-            if !unity && !hybrid && !no_main {
-                let main_cpp_path = src_dir.join("entry.cpp");
-                let target = select_entry_target(&classes, &merged);
-                let main_cpp = demo_main_cpp(target, &target.name.to_lowercase());
-                write(main_cpp_path.to_str().unwrap(), &main_cpp);
-            }
-            let _ = tool_detector::write_build_script_opts(
-                &dir,
-                &base,
-                release,
-                gpu_backend.as_deref(),
-                bridge.as_deref(),
-                sanitize.as_deref(),
-                coverage,
-            );
-            if staging {
-                do_compile = false;
-            }
-            if do_compile {
-                match compile_cpp(
-                    &dir,
-                    &base,
-                    compiler.as_deref(),
-                    &stdver,
-                    release,
-                    !no_main,
-                    sanitize.as_deref(),
-                    coverage,
-                ) {
-                    Ok(()) => {
-                        println!("compiled: {}", exe_path.display());
-                        let _ = Command::new(exe_path.to_str().unwrap())
-                            .current_dir(&dir)
-                            .status();
-                    }
-                    Err(e) => {
-                        eprintln!("skip compile: {}", e);
-                    }
-                }
-            }
-            if lint {
-                let _ = run_clang_tidy(&dir, &stdver);
-            }
-            if format {
-                let _ = run_clang_format(&dir);
-            }
-            if lint_rust {
-                let _ = run_rust_tools();
-            }
-            if smoke {
-                let _ = run_smoke_tests(input_path);
-            }
-            if smoke_compilers {
-                let _ = run_smoke_tests_compilers(input_path);
-            }
-            if watch {
-                if let Ok(_rd) = fs::read_dir(dir_path) {
-                    let mut last = std::time::SystemTime::now();
-                    if let Ok(md) = std::fs::metadata(dir_path) {
-                        if let Ok(m) = md.modified() {
-                            last = m;
-                        }
-                    }
-                    loop {
-                        std::thread::sleep(std::time::Duration::from_millis(1200));
-                        let mut changed = false;
-                        if let Ok(rd2) = fs::read_dir(dir_path) {
-                            for e in rd2.flatten() {
-                                let p = e.path();
-                                if let Some(ext) = p.extension() {
-                                    if ext.to_string_lossy().eq_ignore_ascii_case("upp") {
-                                        if let Ok(md2) = std::fs::metadata(&p) {
-                                            if let Ok(now) = md2.modified() {
-                                                if now > last {
-                                                    changed = true;
-                                                    last = now;
-                                                    break;
-                                                }
-                                            }
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                        if changed {
-                            let exe = std::env::current_exe().unwrap();
-                            let args_next: Vec<String> = std::env::args().skip(1).collect();
-                            let _ = Command::new(exe).args(&args_next).status();
-                        }
-                    }
-                }
-            }
-            return;
+/// Command-line flags layered over the `[build]` section of `ultra.toml`.
+#[derive(Clone)]
+struct Options {
+    out_root: String,
+    emit: EmitMode,
+    /// Extra build files: `CMakeLists.txt`, `build.ninja`, `Makefile`.
+    build_files: Vec<BuildFile>,
+    compile: bool,
+    /// Execute the binary after a successful compile.
+    run: bool,
+    no_main: bool,
+    compiler: Option<Compiler>,
+    std: CppStd,
+    release: bool,
+    bench: bool,
+    staging: bool,
+    bridge: Option<Bridge>,
+    lint: bool,
+    format: bool,
+    lint_rust: bool,
+    sanitize: Option<Sanitizer>,
+    coverage: bool,
+    /// Emit `#line` directives and `build/sourcemap.json`.
+    source_map: bool,
+    include_dirs: Vec<String>,
+    link_libs: Vec<String>,
+}
+
+fn options(a: &BuildArgs, m: &Manifest, project_dir: &Path) -> Result<Options, String> {
+    fn from_manifest<T>(
+        flag: Option<T>,
+        value: Option<&str>,
+        key: &str,
+        parse: fn(&str) -> Option<T>,
+    ) -> Result<Option<T>, String> {
+        if flag.is_some() {
+            return Ok(flag);
+        }
+        match value {
+            None => Ok(None),
+            Some(v) => parse(v)
+                .map(Some)
+                .ok_or_else(|| format!("{}: invalid {} '{}'", manifest::MANIFEST_NAME, key, v)),
         }
     }
-    let src = read_to_string(input_path);
-    let t0 = Instant::now();
-    let directives = parser::scan_directives(&src);
-    let mut classes = parser::parse_all(&src);
-    let mut import_cache: Vec<(String, String, String)> = Vec::new();
-    if !directives.imports.is_empty() {
-        let base_dir = Path::new(input_path).parent().unwrap_or(Path::new("."));
-        for imp in &directives.imports {
-            let (imp_path, ver) = parse_import_spec(imp);
-            if !is_safe_rel_path(&imp_path) {
-                eprintln!("import path rejected (unsafe): {}", imp_path);
-                continue;
-            }
-            let ip = base_dir.join(&imp_path);
-            if ip.exists() {
-                if let Ok(s) = fs::read_to_string(&ip) {
-                    let more = parser::parse_all(&s);
-                    let di = parser::scan_directives(&s);
-                    for mut m in more {
-                        m.namespace = di.namespace.clone();
-                        m.module_version = ver.clone();
-                        if let Some(pos) = classes.iter().position(|c| c.name == m.name) {
-                            classes[pos] = m.clone();
-                        } else {
-                            classes.push(m.clone());
-                        }
-                    }
-                    let stem = Path::new(&imp_path)
-                        .file_stem()
-                        .unwrap()
-                        .to_string_lossy()
-                        .to_string();
-                    let v = ver.clone().unwrap_or_else(|| "latest".to_string());
-                    import_cache.push((stem, v, s));
-                }
-            }
+    let manifest_emit = match m.emit.as_deref() {
+        None | Some("cmake" | "ninja" | "make") => None,
+        Some("classic") => Some(EmitMode::Classic),
+        Some("unity") => Some(EmitMode::Unity),
+        Some("hybrid") => Some(EmitMode::Hybrid),
+        Some("header-only") => Some(EmitMode::HeaderOnly),
+        Some("modules") => Some(EmitMode::Modules),
+        Some(other) => {
+            return Err(format!(
+                "{}: invalid emit '{}'",
+                manifest::MANIFEST_NAME,
+                other
+            ))
         }
+    };
+    let include_dirs = m
+        .include_dirs
+        .iter()
+        .map(|d| {
+            let p = project_dir.join(d);
+            fs::canonicalize(&p)
+                .unwrap_or(p)
+                .to_string_lossy()
+                .to_string()
+        })
+        .collect();
+    let mut build_files = a.build_files.clone();
+    let manifest_file = match m.emit.as_deref() {
+        Some("cmake") => Some(BuildFile::Cmake),
+        Some("ninja") => Some(BuildFile::Ninja),
+        Some("make") => Some(BuildFile::Make),
+        _ => None,
+    };
+    if let Some(f) = manifest_file.filter(|f| !build_files.contains(f)) {
+        build_files.push(f);
     }
-    let parse_ms = t0.elapsed().as_millis();
-    let base = stem(Path::new(input_path)).to_lowercase();
-    let dir = Path::new(&outdir_root).join(&base);
-    let src_dir = dir.join("src");
-    let include_dir = dir.join("include");
-    let build_dir = dir.join("build");
-    let obj_dir = build_dir.join("obj");
-    let bin_dir = build_dir.join("bin");
+    let emit = a.emit.or(manifest_emit).unwrap_or(EmitMode::Classic);
+    // The bridge wrappers include the class declarations, which unity and
+    // module builds keep out of headers.
+    if a.bridge.is_some() && matches!(emit, EmitMode::Unity | EmitMode::Modules) {
+        return Err(
+            "--bridge needs class headers; use --emit classic, hybrid or header-only".to_string(),
+        );
+    }
+    Ok(Options {
+        out_root: a
+            .out_dir
+            .clone()
+            .unwrap_or_else(|| if a.staging { "staging" } else { "dist" }.to_string()),
+        emit,
+        build_files,
+        compile: a.compile,
+        run: false,
+        no_main: a.no_main,
+        compiler: from_manifest(
+            a.compiler,
+            m.compiler.as_deref(),
+            "compiler",
+            Compiler::parse,
+        )?,
+        std: from_manifest(a.std, m.std.as_deref(), "std", CppStd::parse)?.unwrap_or(CppStd::Cpp17),
+        release: a.release || m.release.unwrap_or(false),
+        bench: a.bench,
+        staging: a.staging,
+        bridge: a.bridge,
+        lint: a.lint,
+        format: a.format,
+        lint_rust: a.lint_rust,
+        sanitize: from_manifest(
+            a.sanitize,
+            m.sanitize.as_deref(),
+            "sanitize",
+            Sanitizer::parse,
+        )?,
+        coverage: a.coverage || m.coverage.unwrap_or(false),
+        source_map: a.source_map,
+        include_dirs,
+        link_libs: m.link_libs.clone(),
+    })
+}
 
-    fs::create_dir_all(&src_dir).expect("create src dir failed");
-    fs::create_dir_all(&include_dir).expect("create include dir failed");
-    fs::create_dir_all(&obj_dir).expect("create obj dir failed");
-    fs::create_dir_all(&bin_dir).expect("create bin dir failed");
-    {
-        let pch_src = src_dir.join("pch.cpp");
-        let _ = fs::write(&pch_src, "#include \"pch.hpp\"\n");
+fn load(a: &BuildArgs) -> Result<(Project, Options), String> {
+    let project = load_project(a.input.as_deref())?;
+    let opts = options(a, &project.manifest, &project.dir)?;
+    Ok((project, opts))
+}
+
+/// Parsed, merged and type-checked sources of a project.
+struct Analysis {
+    graph: imports::ImportGraph,
+    classes: Vec<ultracpp::Class>,
+    directives: Directives,
+    warnings: Vec<Diagnostic>,
+    parse_ms: u128,
+}
+
+/// Diagnostics one per line, with paths relative to the working directory.
+fn render(diagnostics: &[Diagnostic]) -> String {
+    let cwd = env::current_dir()
+        .and_then(fs::canonicalize)
+        .unwrap_or_default();
+    diagnostics
+        .iter()
+        .map(|d| d.clone().relative_to(&cwd).to_string())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Resolves, merges and checks the sources of `p` without writing anything.
+fn analyse(p: &Project) -> Result<Analysis, Vec<Diagnostic>> {
+    let t0 = Instant::now();
+    let graph = imports::resolve_roots(&p.dir, &p.roots, &p.manifest.dependency_imports())
+        .map_err(|e| vec![Diagnostic::error(e)])?;
+    let mut directives = graph.merged_directives();
+    p.manifest.apply_to(&mut directives);
+    let mut classes = graph.merged_classes();
+    if classes.is_empty() {
+        return Err(vec![Diagnostic::error(format!(
+            "no classes found in {}",
+            p.dir.display()
+        ))]);
     }
-    {
-        let pch = include_dir.join("pch.hpp");
-        let mut p = String::new();
-        p.push_str("#pragma once\n");
-        p.push_str("#include <iostream>\n");
-        p.push_str("#include <string>\n");
-        p.push_str("#include <vector>\n");
-        p.push_str("#include <memory>\n");
-        p.push_str("#include <map>\n");
-        p.push_str("#include <list>\n");
-        p.push_str("#include <optional>\n");
-        p.push_str("#include <thread>\n");
-        p.push_str("#include <mutex>\n");
-        p.push_str("#include <future>\n");
-        p.push_str("#include <atomic>\n");
-        p.push_str("#include <filesystem>\n");
-        p.push_str("#include <fstream>\n");
-        p.push_str("#include <algorithm>\n");
-        p.push_str("#include <numeric>\n");
-        p.push_str("#include <cmath>\n");
-        p.push_str("#include <cstdio>\n");
-        p.push_str("#include <functional>\n");
-        let _ = fs::write(&pch, p);
-    }
-    if !import_cache.is_empty() {
-        let cache_dir = build_dir.join("cache");
-        fs::create_dir_all(&cache_dir).expect("create cache dir failed");
-        for (name, ver, contents) in &import_cache {
-            let fname = format!("{}@{}.upp", name.to_lowercase(), ver);
-            let fpath = cache_dir.join(fname);
-            write(fpath.to_str().unwrap(), contents);
+    let diagnostics = check::check(&graph, &classes, &directives);
+    if diagnostics.iter().any(|d| d.is_error()) {
+        return Err(diagnostics);
+    }
+    let includes = ultracpp::resolve_includes(&directives);
+    for class in classes.iter_mut() {
+        if directives.global_base && class.base.is_none() {
+            class.base = Some("Object".to_string());
+        }
+        class.extra_includes = includes.clone();
+        if class.namespace.is_none() {
+            class.namespace = directives.namespace.clone();
         }
     }
+    Ok(Analysis {
+        graph,
+        classes,
+        directives,
+        warnings: diagnostics,
+        parse_ms: t0.elapsed().as_millis(),
+    })
+}
 
-    // Write README.md
-    let readme_content = format!(
+fn write_pch(src_dir: &Path, include_dir: &Path) {
+    let _ = write_if_changed(&src_dir.join("pch.cpp"), "#include \"pch.hpp\"\n");
+    let mut p = String::new();
+    p.push_str("#pragma once\n");
+    for h in [
+        "iostream",
+        "string",
+        "vector",
+        "memory",
+        "map",
+        "list",
+        "optional",
+        "thread",
+        "mutex",
+        "future",
+        "atomic",
+        "filesystem",
+        "fstream",
+        "algorithm",
+        "numeric",
+        "cmath",
+        "cstdio",
+        "functional",
+    ] {
+        p.push_str(&format!("#include <{}>\n", h));
+    }
+    let _ = write_if_changed(&include_dir.join("pch.hpp"), &p);
+}
+
+fn readme(base: &str) -> String {
+    format!(
         r#"# Estructura del Proyecto
  
  Este proyecto ha sido generado con una estructura organizada:
@@ -978,10 +464,10 @@ run Main
  
  Para compilar, ejecute `build.bat` (Windows) o `./build.sh` (Linux/Mac).
  
- ## Modos y Flags útiles
- - `--emit classic|unity|hybrid` selecciona el modo de emisión.
- - `--release` habilita optimizaciones: `/O2` (MSVC) o `-O2` (g++/clang++).
- - `--watch` recompila cuando cambian los `.upp` (modo carpeta).
+ ## Comandos útiles
+ - `ultracpp emit classic|unity|hybrid|header-only|modules` selecciona el modo de emisión (`modules` requiere `--std c++20`).
+ - `ultracpp build --release` habilita optimizaciones: `/O2` (MSVC) o `-O2` (g++/clang++).
+ - `ultracpp watch` recompila cuando cambian los `.upp`.
  - `--bench`/`--staging` generan reporte de métricas en `build/report.json`.
  
  ## Puentes (Bridge) — DLL/SO para integraciones
@@ -989,279 +475,491 @@ run Main
  - Windows: `build/bin/{}.dll`
  - Linux: `build/bin/lib{}.so`
  
+ La interfaz C está en `include/exports.hpp`: cada clase es un handle opaco
+ (`<clase>_new`, `<clase>_free`) y cada método público devuelve un `ucpp_status`.
+ 
  Ejemplos de uso:
  
- ### Python (ctypes)
+ ### Python (`--bridge python`)
+ Se genera `python/{}`, que carga la librería con `ctypes` y envuelve cada clase:
  ```python
- import ctypes, os, sys
- libpath = os.path.join('build', 'bin', '{}.dll' if sys.platform.startswith('win') else 'lib{}.so')
- lib = ctypes.CDLL(libpath)
- lib.principal_run()
- lib.hola_greet()
+ import sys
+ sys.path.insert(0, 'python')
+ import {}
+ ```
+ 
+ ### Rust (`--bridge rust`)
+ Se genera el crate `rust/`, con las declaraciones `extern "C"` en `sys` y
+ estructuras seguras que liberan su handle en `Drop`:
+ ```toml
+ [dependencies]
+ {} = {{ path = "rust" }}
  ```
  
- ### Node.js (ffi-napi o N-API)
+ ### Node.js (`--bridge node`)
+ Se genera `node/` con un addon N-API (`binding.cc`, `binding.gyp`) que expone
+ cada clase como clase de JavaScript, y sus tipos en `index.d.ts`:
+ ```sh
+ cd node && npm install
+ ```
  ```js
- const os = require('os');
- const path = require('path');
- const ffi = require('ffi-napi');
- const libname = os.platform().startsWith('win') ? '{}.dll' : 'lib{}.so';
- const libpath = path.join('build', 'bin', libname);
- const lib = ffi.Library(libpath, {{
-   'principal_run': [ 'void', [] ],
-   'hola_greet': [ 'void', [] ]
- }});
- lib.principal_run();
- lib.hola_greet();
+ const lib = require('./node');
  ```
 "#,
-        base, base, base, base, base, base
-    );
-    let _ = fs::write(dir.join("README.md"), readme_content);
+        base,
+        base,
+        bridge::python_module_name(base),
+        bridge::python_module_name(base).trim_end_matches(".py"),
+        bridge::rust_crate_name(base)
+    )
+}
 
-    let mut needs_object_base = directives.global_base;
-    let type_errors = type_check(&classes);
-    if !type_errors.is_empty() {
-        for e in type_errors {
-            eprintln!("{}", e);
+fn remove_sources(src_dir: &Path, keep: impl Fn(&str) -> bool) {
+    if let Ok(rd) = fs::read_dir(src_dir) {
+        for e in rd.flatten() {
+            let p = e.path();
+            let name = p.file_name().unwrap().to_string_lossy().to_string();
+            let lower = name.to_lowercase();
+            if (lower.ends_with(".cpp") || lower.ends_with(".cppm")) && !keep(&name) {
+                let _ = fs::remove_file(p);
+            }
         }
-        std::process::exit(1);
     }
-    if classes.is_empty() {
-        let class = parser::parse(&src);
-        let mut class = class;
-        if directives.global_base && class.base.is_none() {
-            class.base = Some("Object".to_string());
-            needs_object_base = true;
-        }
-        class.extra_includes = ultracpp::resolve_includes(&directives);
-        class.namespace = directives.namespace.clone();
-        let hpp = codegen::header(&class);
-        let cpp = codegen::source(&class);
-        let hpp_path = include_dir.join(format!("{}.hpp", class.name.to_lowercase()));
-        let cpp_path = src_dir.join(format!("{}.cpp", class.name.to_lowercase()));
-        write(hpp_path.to_str().unwrap(), &hpp);
-        write(cpp_path.to_str().unwrap(), &cpp);
-        println!("generated: {}, {}", hpp_path.display(), cpp_path.display());
-        let exe_name = if cfg!(windows) {
-            format!("{}.exe", base)
-        } else {
-            base.to_string()
-        };
-        let exe_path = bin_dir.join(&exe_name);
-        let main_cpp_path = src_dir.join("main.cpp");
-        if !no_main {
-            let main_cpp = demo_main_cpp(&class, &class.name.to_lowercase());
-            write(main_cpp_path.to_str().unwrap(), &main_cpp);
-        }
-        if needs_object_base {
-            write_object_base(&src_dir, &include_dir);
-        }
-        let _ = tool_detector::write_build_script_opts(
-            &dir,
-            &base,
-            false,
-            None,
-            None,
-            sanitize.as_deref(),
-            coverage,
+}
+
+fn unity_main(classes: &[ultracpp::Class], d: &Directives, set_console_cp: bool) -> String {
+    let target = select_entry_target(classes, d);
+    let qname = if let Some(ns) = &target.namespace {
+        format!("{}::{}", ns, target.name)
+    } else {
+        target.name.clone()
+    };
+    let mut content = String::new();
+    content.push_str("\n\n");
+    content.push_str("#ifdef _WIN32\n");
+    content.push_str("#include <windows.h>\n");
+    content.push_str("#endif\n");
+    content.push_str("\nint main() {\n");
+    content.push_str("  #ifdef _WIN32\n");
+    if set_console_cp {
+        content.push_str("    SetConsoleOutputCP(65001);\n");
+    } else {
+        content.push_str(
+            "    // SetConsoleOutputCP(65001); // disabled for broader toolchain compatibility\n",
         );
-        if staging {
-            do_compile = false;
+    }
+    content.push_str("  #endif\n");
+    content.push_str("  try {\n");
+    if target
+        .methods
+        .iter()
+        .any(|m| m.name == "run" && m.is_static)
+    {
+        content.push_str(&format!("    {}::run();\n", qname));
+    } else {
+        content.push_str(&format!("    {} app;\n", qname));
+        content.push_str("    app.run();\n");
+    }
+    content.push_str("  } catch (const std::exception& e) {\n");
+    content.push_str("    std::cerr << e.what() << std::endl;\n");
+    content.push_str("    return 1;\n");
+    content.push_str("  }\n");
+    content.push_str("  return 0;\n");
+    content.push_str("}\n");
+    content
+}
+
+/// `class` as the header-only library declares it: the header puts every
+/// class inside the project namespace.
+fn library_scoped(class: &ultracpp::Class, project: &str) -> ultracpp::Class {
+    let ns = codegen::library_namespace(project);
+    ultracpp::Class {
+        namespace: Some(match &class.namespace {
+            Some(inner) => format!("{}::{}", ns, inner),
+            None => ns,
+        }),
+        ..class.clone()
+    }
+}
+
+/// Writes the C interface of the bridge library and the bindings for `lang`.
+/// The classes are declared in `headers`.
+fn write_bridge(
+    dir: &Path,
+    project: &str,
+    classes: &[ultracpp::Class],
+    headers: &[String],
+    lang: Bridge,
+) {
+    let exp_h = dir.join("include").join(bridge::HEADER_NAME);
+    write(exp_h.to_str().unwrap(), &bridge::header(project, classes));
+    let exp_cpp = dir.join("src").join(bridge::SOURCE_NAME);
+    write(
+        exp_cpp.to_str().unwrap(),
+        &bridge::source_with(project, classes, headers),
+    );
+    println!("generated: {}, {}", exp_h.display(), exp_cpp.display());
+    match lang {
+        Bridge::C => {}
+        Bridge::Python => {
+            let py_dir = dir.join(bridge::PYTHON_DIR);
+            let _ = fs::create_dir_all(&py_dir);
+            let module = py_dir.join(bridge::python_module_name(project));
+            write(module.to_str().unwrap(), &bridge::python(project, classes));
+            println!("generated: {}", module.display());
         }
-        let mut compiled = false;
-        if no_main && !do_compile {
-            if bench || staging {
-                let report_path = dir.join("report.json");
-                let mut report = String::new();
-                report.push('{');
-                report.push_str(&format!("\"classes\":{},", 1));
-                report.push_str(&format!("\"fields\":{},", class.fields.len()));
-                report.push_str(&format!("\"methods\":{},", class.methods.len()));
-                report.push_str(&format!("\"includes\":{},", class.extra_includes.len()));
-                report.push_str(&format!("\"parse_ms\":{},", parse_ms));
-                report.push_str(&format!("\"codegen_ms\":{}", 0));
-                report.push('}');
-                let _ = fs::write(report_path, report);
-            }
-            return;
+        Bridge::Rust => {
+            let crate_dir = dir.join(bridge::RUST_DIR);
+            let _ = fs::create_dir_all(crate_dir.join("src"));
+            let lib = crate_dir.join("src").join("lib.rs");
+            let _ = write_if_changed(
+                &crate_dir.join("Cargo.toml"),
+                &bridge::rust_manifest(project),
+            );
+            let _ = write_if_changed(
+                &crate_dir.join("build.rs"),
+                &bridge::rust_build_script(project),
+            );
+            write(lib.to_str().unwrap(), &bridge::rust(project, classes));
+            println!("generated: {}", crate_dir.display());
         }
-        let t1 = Instant::now();
-        match compile_cpp(
-            &dir,
-            &base,
-            compiler.as_deref(),
-            &stdver,
-            release,
-            !no_main,
-            sanitize.as_deref(),
-            coverage,
-        ) {
-            Ok(()) => {
-                println!("compiled: {}", exe_path.display());
-                compiled = true;
-                let _ = Command::new(exe_path.to_str().unwrap())
-                    .current_dir(&dir)
-                    .status();
-            }
-            Err(e) => {
-                eprintln!("skip compile: {}", e);
-            }
+        Bridge::Node => {
+            let node_dir = dir.join(bridge::NODE_DIR);
+            let _ = fs::create_dir_all(&node_dir);
+            let binding = node_dir.join("binding.cc");
+            write(
+                binding.to_str().unwrap(),
+                &bridge::node_binding(project, classes),
+            );
+            let _ = write_if_changed(&node_dir.join("binding.gyp"), &bridge::node_gyp(project));
+            let _ = write_if_changed(
+                &node_dir.join("package.json"),
+                &bridge::node_package(project),
+            );
+            let _ = write_if_changed(&node_dir.join("index.js"), &bridge::node_index(project));
+            write(
+                node_dir.join("index.d.ts").to_str().unwrap(),
+                &bridge::node_typings(project, classes),
+            );
+            println!("generated: {}", node_dir.display());
         }
-        if lint {
-            let _ = run_clang_tidy(&dir, &stdver);
+    }
+}
+
+/// Generates `<out_root>/<base>` and, when asked, compiles and runs it.
+fn build(p: &Project, o: &Options) -> Result<(), String> {
+    let a = analyse(p).map_err(|d| render(&d))?;
+    if !a.warnings.is_empty() {
+        eprintln!("{}", render(&a.warnings));
+    }
+    let classes = if o.source_map {
+        with_source_files(&a)
+    } else {
+        a.classes.clone()
+    };
+    let classes = &classes;
+    let mut map = SourceMap::default();
+    let t1 = Instant::now();
+    if Path::new(&o.out_root).is_file() {
+        return Err(format!(
+            "output root '{}' is a file, cannot create a directory inside it",
+            o.out_root
+        ));
+    }
+    let dir = Path::new(&o.out_root).join(&p.base);
+    let src_dir = dir.join("src");
+    let include_dir = dir.join("include");
+    let build_dir = dir.join("build");
+    for d in [
+        &src_dir,
+        &include_dir,
+        &build_dir.join("obj"),
+        &build_dir.join("bin"),
+    ] {
+        fs::create_dir_all(d).map_err(|e| format!("cannot create {}: {}", d.display(), e))?;
+    }
+    write_pch(&src_dir, &include_dir);
+    if !a.graph.locked.is_empty() {
+        let lock_path = p.dir.join(packages::LOCKFILE_NAME);
+        write(lock_path.to_str().unwrap(), &a.graph.lockfile().render());
+    }
+    let imported: Vec<&imports::SourceFile> = a.graph.imported().collect();
+    if !imported.is_empty() {
+        let cache_dir = build_dir.join("cache");
+        fs::create_dir_all(&cache_dir).expect("create cache dir failed");
+        for file in imported {
+            let ver = file.version.as_deref().unwrap_or("latest");
+            let fname = format!("{}@{}.upp", stem(&file.path).to_lowercase(), ver);
+            write(cache_dir.join(fname).to_str().unwrap(), &file.source);
         }
-        if format {
-            let _ = run_clang_format(&dir);
+    }
+    let _ = write_if_changed(&dir.join("README.md"), &readme(&p.base));
+
+    let needs_object_base = a.directives.global_base;
+    let cache_path = build_dir.join(cache::CACHE_NAME);
+    let mut hashes = BuildCache::load(&cache_path);
+    let mut gen = |file: &str, inputs: &str, generate: &dyn Fn() -> String| {
+        let text = generate_cached(&dir, &mut hashes, file, inputs, generate);
+        map.add(file, &text);
+    };
+    // Module interfaces, in the order they have to be compiled.
+    let mut module_units: Vec<String> = Vec::new();
+    match o.emit {
+        EmitMode::Unity => {
+            // Clean up existing .cpp files to avoid duplicates/conflicts
+            remove_sources(&src_dir, |n| n == "all.cpp");
+            let inputs = format!(
+                "unity {:?} {:?} {:?} {} {}",
+                o.std, classes, a.directives, needs_object_base, o.no_main
+            );
+            gen("src/all.cpp", &inputs, &|| {
+                let mut content = codegen::unity_build_with(classes, o.std);
+                if needs_object_base {
+                    content.insert_str(0, "#include \"object.hpp\"\n");
+                }
+                if !o.no_main {
+                    content.push_str(&unity_main(classes, &a.directives, true));
+                }
+                codegen::finish_line_directives(&content, "src/all.cpp")
+            });
+            println!(
+                "generated unity build: {}",
+                src_dir.join("all.cpp").display()
+            );
         }
-        if lint_rust {
-            let _ = run_rust_tools();
+        EmitMode::Hybrid => {
+            // Hybrid: generate headers only, plus a unity all.cpp for sources
+            for class in classes {
+                let hpp = format!("include/{}.hpp", class.name.to_lowercase());
+                gen(&hpp, &format!("{:?} {:?}", o.std, class), &|| {
+                    codegen::header_with(class, o.std)
+                });
+                println!("generated: {}", dir.join(&hpp).display());
+            }
+            remove_sources(&src_dir, |n| {
+                n == "all.cpp" || n == "pch.cpp" || (n == "entry.cpp" && !o.no_main)
+            });
+            let inputs = format!("hybrid {:?} {:?}", o.std, classes);
+            gen("src/all.cpp", &inputs, &|| {
+                let content = codegen::unity_build_with(classes, o.std);
+                codegen::finish_line_directives(&content, "src/all.cpp")
+            });
+            println!(
+                "generated hybrid build: {}",
+                src_dir.join("all.cpp").display()
+            );
+            if !o.no_main {
+                // `main` has its own unit, so a bridge library can link all.cpp.
+                let target = select_entry_target(classes, &a.directives);
+                let inputs = format!("hybrid entry {:?} {:?}", classes, a.directives);
+                gen("src/entry.cpp", &inputs, &|| {
+                    format!(
+                        "#include \"pch.hpp\"\n#include \"{}.hpp\"{}",
+                        target.name.to_lowercase(),
+                        unity_main(classes, &a.directives, false)
+                    )
+                });
+            }
         }
-        if smoke {
-            let _ = run_smoke_tests(input_path);
+        EmitMode::HeaderOnly => {
+            remove_sources(&src_dir, |n| n == "entry.cpp" || n == "pch.cpp");
+            let hpp = format!("include/{}.hpp", p.base);
+            let inputs = format!(
+                "header-only {:?} {:?} {}",
+                o.std, classes, needs_object_base
+            );
+            gen(&hpp, &inputs, &|| {
+                let mut content = codegen::header_only(&p.base, classes, o.std);
+                if needs_object_base {
+                    // The header ships alone, so it defines the base itself.
+                    content = content.replacen(
+                        "\n// Forward declarations\n",
+                        &format!("\n{}\n// Forward declarations\n", OBJECT_BASE),
+                        1,
+                    );
+                }
+                codegen::finish_line_directives(&content, &hpp)
+            });
+            println!(
+                "generated header-only library: {}",
+                dir.join(&hpp).display()
+            );
+            if !o.no_main {
+                let target = select_entry_target(classes, &a.directives);
+                let scoped = library_scoped(target, &p.base);
+                gen("src/entry.cpp", &format!("entry {:?}", scoped), &|| {
+                    demo_main_cpp(&scoped, Some(&p.base), None)
+                });
+            }
         }
-        if smoke_compilers {
-            let _ = run_smoke_tests_compilers(input_path);
+        EmitMode::Modules => {
+            if o.std == CppStd::Cpp17 {
+                return Err("--emit modules needs --std c++20 or newer".to_string());
+            }
+            let modules = codegen::modules(&p.base, classes, o.std);
+            let files: Vec<String> = modules.iter().map(|m| format!("{}.cppm", m.name)).collect();
+            remove_sources(&src_dir, |n| {
+                n == "entry.cpp" || files.iter().any(|f| f == n)
+            });
+            for (m, file) in modules.iter().zip(&files) {
+                let path = format!("src/{}", file);
+                let inputs = format!("module {} {}", m.source, needs_object_base);
+                gen(&path, &inputs, &|| {
+                    let mut content = m.source.clone();
+                    if needs_object_base {
+                        content =
+                            content.replacen("module;\n", "module;\n#include \"object.hpp\"\n", 1);
+                    }
+                    codegen::finish_line_directives(&content, &path)
+                });
+                println!("generated module {}: {}", m.name, dir.join(&path).display());
+            }
+            if !o.no_main {
+                let target = select_entry_target(classes, &a.directives);
+                let module = modules.iter().find(|m| m.classes.contains(&target.name));
+                gen(
+                    "src/entry.cpp",
+                    &format!("entry {:?} {:?}", target, module.map(|m| &m.name)),
+                    &|| demo_main_cpp(target, None, module.map(|m| m.name.as_str())),
+                );
+            }
+            module_units = files.iter().map(|f| format!("src/{}", f)).collect();
         }
-        if bench || staging {
-            let report_path = dir.join("report.json");
-            let mut report = String::new();
-            report.push('{');
-            report.push_str(&format!("\"classes\":{},", 1));
-            report.push_str(&format!("\"fields\":{},", class.fields.len()));
-            report.push_str(&format!("\"methods\":{},", class.methods.len()));
-            report.push_str(&format!("\"includes\":{},", class.extra_includes.len()));
-            report.push_str(&format!("\"parse_ms\":{},", parse_ms));
-            report.push_str(&format!("\"codegen_ms\":{},", t1.elapsed().as_millis()));
-            report.push_str(&format!(
-                "\"compiled\":{}",
-                if compiled { "true" } else { "false" }
-            ));
-            report.push('}');
-            let _ = fs::write(report_path, report);
+        EmitMode::Classic => {
+            remove_sources(&src_dir, |n| n != "all.cpp" && !n.ends_with(".cppm"));
+            for class in classes {
+                let name = class.name.to_lowercase();
+                let (hpp, cpp) = (format!("include/{}.hpp", name), format!("src/{}.cpp", name));
+                let inputs = format!("{:?} {:?}", o.std, class);
+                gen(&hpp, &inputs, &|| codegen::header_with(class, o.std));
+                gen(&cpp, &inputs, &|| {
+                    codegen::finish_line_directives(&codegen::source_with(class, o.std), &cpp)
+                });
+                println!(
+                    "generated: {}, {}",
+                    dir.join(&hpp).display(),
+                    dir.join(&cpp).display()
+                );
+            }
+            if !o.no_main {
+                let target = select_entry_target(classes, &a.directives);
+                gen("src/entry.cpp", &format!("entry {:?}", target), &|| {
+                    demo_main_cpp(target, Some(&target.name.to_lowercase()), None)
+                });
+            }
         }
-        return;
     }
-    let t1 = Instant::now();
-    let mut fixed_classes: Vec<ultracpp::Class> = Vec::new();
-    for class in &classes {
-        let mut cl = class.clone();
-        if directives.global_base && cl.base.is_none() {
-            cl.base = Some("Object".to_string());
-            needs_object_base = true;
-        }
-        cl.extra_includes = ultracpp::resolve_includes(&directives);
-        if cl.namespace.is_none() {
-            cl.namespace = directives.namespace.clone();
-        }
-        let hpp = codegen::header(&cl);
-        let cpp = codegen::source(&cl);
-        let hpp_path = include_dir.join(format!("{}.hpp", cl.name.to_lowercase()));
-        let cpp_path = src_dir.join(format!("{}.cpp", cl.name.to_lowercase()));
-        write(hpp_path.to_str().unwrap(), &hpp);
-        write(cpp_path.to_str().unwrap(), &cpp);
-        println!("generated: {}, {}", hpp_path.display(), cpp_path.display());
-        fixed_classes.push(cl);
-    }
-    if needs_object_base {
+    hashes.retain_existing(&dir);
+    let _ = hashes.save(&cache_path);
+    if needs_object_base && o.emit != EmitMode::HeaderOnly {
         write_object_base(&src_dir, &include_dir);
     }
-
-    let exe_name = if cfg!(windows) {
-        format!("{}.exe", base)
+    let map_path = build_dir.join(sourcemap::SOURCE_MAP_NAME);
+    if o.source_map {
+        write(map_path.to_str().unwrap(), &map.to_json().to_string());
     } else {
-        base.to_string()
-    };
-    let exe_path = bin_dir.join(&exe_name);
-    let main_cpp_path = src_dir.join("entry.cpp");
-    if !no_main && !unity {
-        let target = select_entry_target(&fixed_classes, &directives);
-        let main_cpp = demo_main_cpp(target, &target.name.to_lowercase());
-        write(main_cpp_path.to_str().unwrap(), &main_cpp);
-    }
-    let _ = tool_detector::write_build_script_opts(
-        &dir,
-        &base,
-        release,
-        gpu_backend.as_deref(),
-        bridge.as_deref(),
-        sanitize.as_deref(),
-        coverage,
-    );
-    if staging {
-        do_compile = false;
-    }
-    if no_main && !do_compile {
-        if bench || staging {
-            let mut fields = 0usize;
-            let mut methods = 0usize;
-            for c in &classes {
-                fields += c.fields.len();
-                methods += c.methods.len();
-            }
-            let report_path = dir.join("report.json");
-            let mut report = String::new();
-            report.push('{');
-            report.push_str(&format!("\"classes\":{},", classes.len()));
-            report.push_str(&format!("\"fields\":{},", fields));
-            report.push_str(&format!("\"methods\":{},", methods));
-            report.push_str(&format!(
-                "\"includes\":{},",
-                ultracpp::resolve_includes(&directives).len()
-            ));
-            report.push_str(&format!("\"parse_ms\":{},", parse_ms));
-            report.push_str(&format!("\"codegen_ms\":{}", t1.elapsed().as_millis()));
-            report.push('}');
-            let _ = fs::write(report_path, report);
+        let _ = fs::remove_file(&map_path);
+    }
+    if let Some(lang) = o.bridge {
+        if o.emit == EmitMode::HeaderOnly {
+            let scoped: Vec<ultracpp::Class> =
+                classes.iter().map(|c| library_scoped(c, &p.base)).collect();
+            let headers = [format!("{}.hpp", p.base)];
+            write_bridge(&dir, &p.base, &scoped, &headers, lang);
+        } else {
+            let headers: Vec<String> = classes
+                .iter()
+                .map(|c| format!("{}.hpp", c.name.to_lowercase()))
+                .collect();
+            write_bridge(&dir, &p.base, classes, &headers, lang);
         }
-        return;
     }
-    let t2 = Instant::now();
+    let plan = build_plan(&dir, &p.base, o, &module_units);
+    let vsdevcmd = tool_detector::find_vs_dev_cmd();
+    let _ = write_if_changed(
+        &dir.join("build.bat"),
+        &plan.render_bat(vsdevcmd.as_deref()),
+    );
+    let _ = write_if_changed(&dir.join("build.sh"), &plan.render_sh(Compiler::Gcc));
+    let abs_dir = fs::canonicalize(&dir).unwrap_or_else(|_| dir.clone());
+    let database = plan.compile_database(&abs_dir, toolchain(o));
+    write(
+        dir.join(buildplan::COMPILE_DATABASE_NAME).to_str().unwrap(),
+        &database.to_string(),
+    );
+    if o.build_files.contains(&BuildFile::Cmake) {
+        let path = dir.join("CMakeLists.txt");
+        write(path.to_str().unwrap(), &plan.render_cmake());
+        let config = dir.join(format!("{}Config.cmake", p.base));
+        write(config.to_str().unwrap(), &plan.render_cmake_config());
+        println!("generated cmake file: {}", path.display());
+    }
+    if o.build_files.contains(&BuildFile::Ninja) {
+        let path = dir.join("build.ninja");
+        write(path.to_str().unwrap(), &plan.render_ninja(toolchain(o)));
+        println!("generated ninja file: {}", path.display());
+    }
+    if o.build_files.contains(&BuildFile::Make) {
+        // Make always drives GCC or Clang.
+        let tc = match toolchain(o) {
+            Compiler::Msvc => Compiler::Gcc,
+            tc => tc,
+        };
+        let path = dir.join("Makefile");
+        write(path.to_str().unwrap(), &plan.render_make(tc));
+        println!("generated makefile: {}", path.display());
+    }
+    let codegen_ms = t1.elapsed().as_millis();
+
+    let mut compile_ms = None;
     let mut compiled = false;
-    match compile_cpp(
-        &dir,
-        &base,
-        compiler.as_deref(),
-        &stdver,
-        release,
-        !no_main,
-        sanitize.as_deref(),
-        coverage,
-    ) {
-        Ok(()) => {
-            println!("compiled: {}", exe_path.display());
-            compiled = true;
-            let _ = Command::new(exe_path.to_str().unwrap())
-                .current_dir(&dir)
-                .status();
-        }
-        Err(e) => {
-            eprintln!("skip compile: {}", e);
+    let mut failure = None;
+    let mut compiler_diagnostics = Vec::new();
+    if o.compile && !o.staging {
+        let t2 = Instant::now();
+        let tc = toolchain(o);
+        let result = compile_cpp(&dir, &plan, tc);
+        compile_ms = Some(t2.elapsed().as_millis());
+        match result {
+            Ok(()) => {
+                let exe_path = plan
+                    .targets
+                    .iter()
+                    .find(|t| t.kind == TargetKind::Executable)
+                    .map(|t| dir.join(plan.output(tc, t)));
+                if let Some(exe_path) = &exe_path {
+                    println!("compiled: {}", exe_path.display());
+                }
+                compiled = true;
+                if let (true, Some(exe_path)) = (o.run, exe_path) {
+                    let exe = fs::canonicalize(&exe_path).unwrap_or(exe_path);
+                    let status = Command::new(&exe)
+                        .current_dir(&dir)
+                        .status()
+                        .map_err(|e| format!("cannot run {}: {}", exe.display(), e))?;
+                    if !status.success() {
+                        return Err(format!("{} exited with {}", exe.display(), status));
+                    }
+                }
+            }
+            Err((e, diagnostics)) => {
+                failure = Some(format!("compile failed: {}", e));
+                compiler_diagnostics = diagnostics;
+            }
         }
     }
-    if lint {
-        let _ = run_clang_tidy(&dir, &stdver);
+    if o.lint && failure.is_none() {
+        let _ = run_clang_tidy(&dir);
     }
-    if format {
+    if o.format && failure.is_none() {
         let _ = run_clang_format(&dir);
     }
-    if lint_rust {
+    if o.lint_rust && failure.is_none() {
         let _ = run_rust_tools();
     }
-    if smoke {
-        let _ = run_smoke_tests(input_path);
-    }
-    if smoke_compilers {
-        let _ = run_smoke_tests_compilers(input_path);
-    }
-    if bench || staging {
-        let mut fields = 0usize;
-        let mut methods = 0usize;
-        for c in &classes {
-            fields += c.fields.len();
-            methods += c.methods.len();
-        }
-        let report_path = dir.join("report.json");
+    if o.bench || o.staging {
+        let fields: usize = classes.iter().map(|c| c.fields.len()).sum();
+        let methods: usize = classes.iter().map(|c| c.methods.len()).sum();
         let mut report = String::new();
         report.push('{');
         report.push_str(&format!("\"classes\":{},", classes.len()));
@@ -1269,40 +967,212 @@ run Main
         report.push_str(&format!("\"methods\":{},", methods));
         report.push_str(&format!(
             "\"includes\":{},",
-            ultracpp::resolve_includes(&directives).len()
-        ));
-        report.push_str(&format!("\"parse_ms\":{},", parse_ms));
-        report.push_str(&format!("\"codegen_ms\":{},", t1.elapsed().as_millis()));
-        report.push_str(&format!("\"compile_ms\":{},", t2.elapsed().as_millis()));
-        report.push_str(&format!(
-            "\"compiled\":{}",
-            if compiled { "true" } else { "false" }
+            ultracpp::resolve_includes(&a.directives).len()
         ));
+        report.push_str(&format!("\"parse_ms\":{},", a.parse_ms));
+        report.push_str(&format!("\"codegen_ms\":{}", codegen_ms));
+        if let Some(ms) = compile_ms {
+            report.push_str(&format!(",\"compile_ms\":{}", ms));
+            report.push_str(&format!(",\"compiled\":{}", compiled));
+            let diagnostics: Vec<ultracpp::json::Value> =
+                compiler_diagnostics.iter().map(|d| d.to_json()).collect();
+            report.push_str(&format!(
+                ",\"diagnostics\":{}",
+                ultracpp::json::Value::from(diagnostics)
+            ));
+        }
         report.push('}');
-        let _ = fs::write(report_path, report);
-    }
-    if watch {
-        if let Ok(md) = std::fs::metadata(input_path) {
-            if let Ok(mut last) = md.modified() {
-                loop {
-                    std::thread::sleep(std::time::Duration::from_millis(1200));
-                    if let Ok(md2) = std::fs::metadata(input_path) {
-                        if let Ok(now) = md2.modified() {
-                            if now > last {
-                                last = now;
-                                let exe = std::env::current_exe().unwrap();
-                                let args_next: Vec<String> = std::env::args().skip(1).collect();
-                                let _ = Command::new(exe).args(&args_next).status();
-                            }
-                        }
-                    }
+        let _ = fs::write(dir.join("report.json"), report);
+    }
+    match failure {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// Reports the diagnostics of a project; fails when any of them is an error.
+fn check_project(a: &BuildArgs) -> Result<(), String> {
+    let project = load_project(a.input.as_deref())?;
+    let analysis = analyse(&project).map_err(|d| {
+        let errors = d.iter().filter(|d| d.is_error()).count();
+        format!("{}\ncheck failed: {} error(s)", render(&d), errors)
+    })?;
+    if !analysis.warnings.is_empty() {
+        eprintln!("{}", render(&analysis.warnings));
+    }
+    println!(
+        "ok: {} classes in {} files",
+        analysis.classes.len(),
+        analysis.graph.files.len()
+    );
+    Ok(())
+}
+
+/// Newest modification time among the sources a build would read.
+fn sources_mtime(p: &Project) -> Option<SystemTime> {
+    let mut paths: Vec<PathBuf> = p.roots.clone();
+    paths.push(p.dir.join(manifest::MANIFEST_NAME));
+    if let Ok(g) = imports::resolve_roots(&p.dir, &p.roots, &p.manifest.dependency_imports()) {
+        paths.extend(g.files.into_iter().map(|f| f.path));
+    }
+    paths
+        .iter()
+        .filter_map(|f| fs::metadata(f).and_then(|m| m.modified()).ok())
+        .max()
+}
+
+fn watch(a: &BuildArgs) -> Result<(), String> {
+    let (mut project, mut opts) = load(a)?;
+    let mut last: Option<(Vec<PathBuf>, Option<SystemTime>)> = None;
+    loop {
+        let state = (project.roots.clone(), sources_mtime(&project));
+        if last.as_ref() != Some(&state) {
+            if last.is_some() {
+                println!("change detected, rebuilding");
+            }
+            if let Err(e) = build(&project, &opts) {
+                eprintln!("{}", e);
+            }
+            last = Some(state);
+        }
+        std::thread::sleep(std::time::Duration::from_millis(1200));
+        match load(a) {
+            Ok((p, o)) => {
+                project = p;
+                opts = o;
+            }
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+}
+
+/// The project's own `.upp` files: the roots and their local imports,
+/// without the vendored packages. Imports pinned in `ultra.lock` are
+/// included, as their lock hash covers the formatted source.
+fn own_sources(p: &Project) -> Vec<PathBuf> {
+    let mut files = p.roots.clone();
+    if let Ok(g) = imports::resolve_roots(&p.dir, &p.roots, &[]) {
+        let packages = fs::canonicalize(p.dir.join("packages")).ok();
+        for f in g.imported() {
+            let inside = packages.as_ref().is_some_and(|d| f.path.starts_with(d));
+            if !inside && !files.contains(&f.path) {
+                files.push(f.path.clone());
+            }
+        }
+    }
+    files
+}
+
+fn format_sources(a: &BuildArgs, check: bool) -> Result<(), String> {
+    let project = load_project(a.input.as_deref())?;
+    let mut changed: Vec<PathBuf> = Vec::new();
+    let mut errors: Vec<String> = Vec::new();
+    for path in own_sources(&project) {
+        let src = fs::read_to_string(&path)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        match format::format_source(&src) {
+            Ok(out) if out != src => {
+                if !check {
+                    fs::write(&path, out)
+                        .map_err(|e| format!("cannot write {}: {}", path.display(), e))?;
                 }
+                changed.push(path);
             }
+            Ok(_) => {}
+            Err(e) => errors.push(format!("{}: error: {}", path.display(), e)),
         }
     }
+    for path in &changed {
+        let verb = if check { "not formatted" } else { "formatted" };
+        println!("{}: {}", verb, path.display());
+    }
+    if !errors.is_empty() {
+        return Err(errors.join("\n"));
+    }
+    if check && !changed.is_empty() {
+        return Err(format!(
+            "{} file(s) need formatting; run `ultracpp fmt`",
+            changed.len()
+        ));
+    }
+    Ok(())
+}
+
+fn clean(a: &BuildArgs) -> Result<(), String> {
+    let (project, opts) = load(a)?;
+    let dir = Path::new(&opts.out_root).join(&project.base);
+    if dir.is_dir() {
+        fs::remove_dir_all(&dir).map_err(|e| format!("cannot remove {}: {}", dir.display(), e))?;
+        println!("removed: {}", dir.display());
+    } else {
+        println!("nothing to clean at {}", dir.display());
+    }
+    Ok(())
+}
+
+/// Builds and compiles the project in every emission mode, and with every
+/// compiler when `all_compilers` is set.
+fn test(a: &BuildArgs, all_compilers: bool) -> Result<(), String> {
+    let (project, opts) = load(a)?;
+    let compilers: Vec<Option<Compiler>> = if all_compilers {
+        vec![
+            Some(Compiler::Msvc),
+            Some(Compiler::Gcc),
+            Some(Compiler::Clang),
+        ]
+    } else {
+        vec![opts.compiler]
+    };
+    let mut failed: Vec<String> = Vec::new();
+    let mut total = 0usize;
+    for compiler in &compilers {
+        let mut modes = vec![
+            EmitMode::Classic,
+            EmitMode::Unity,
+            EmitMode::Hybrid,
+            EmitMode::HeaderOnly,
+        ];
+        if opts.std != CppStd::Cpp17 {
+            modes.push(EmitMode::Modules);
+        }
+        for emit in modes {
+            let o = Options {
+                emit,
+                compiler: *compiler,
+                compile: true,
+                ..opts.clone()
+            };
+            let label = format!(
+                "{} with {}",
+                emit.as_str(),
+                compiler.map(|c| c.as_str()).unwrap_or("default compiler")
+            );
+            total += 1;
+            match build(&project, &o) {
+                Ok(()) => println!("ok: {}", label),
+                Err(e) => {
+                    eprintln!("FAILED: {}: {}", label, e);
+                    failed.push(label);
+                }
+            }
+        }
+    }
+    if failed.is_empty() {
+        println!("{} builds passed", total);
+        Ok(())
+    } else {
+        Err(format!(
+            "{} of {} builds failed: {}",
+            failed.len(),
+            total,
+            failed.join(", ")
+        ))
+    }
 }
 
-fn demo_main_cpp(class: &ultracpp::Class, base: &str) -> String {
+/// `main()` running the entry method of `class`, which comes from the
+/// header `<header>.hpp` or from `module`.
+fn demo_main_cpp(class: &ultracpp::Class, header: Option<&str>, module: Option<&str>) -> String {
     let ctor_args = if let Some(ps) = &class.ctor_params {
         let mut a: Vec<String> = Vec::new();
         for p in ps {
@@ -1317,12 +1187,18 @@ fn demo_main_cpp(class: &ultracpp::Class, base: &str) -> String {
         a.join(", ")
     };
     let mut s = String::new();
-    s.push_str("#include \"pch.hpp\"\n");
-    s.push_str(&format!("#include \"{}.hpp\"\n", base));
+    if let Some(header) = header {
+        s.push_str("#include \"pch.hpp\"\n");
+        s.push_str(&format!("#include \"{}.hpp\"\n", header));
+    }
     s.push_str("#include <iostream>\n");
     s.push_str("#ifdef _WIN32\n");
     s.push_str("#include <windows.h>\n");
     s.push_str("#endif\n");
+    // Headers must come before imports that may hold them too.
+    if let Some(module) = module {
+        s.push_str(&format!("import {};\n", module));
+    }
     s.push_str("int main() {\n");
     s.push_str("  #ifdef _WIN32\n");
     s.push_str("    SetConsoleOutputCP(65001);\n");
@@ -1405,146 +1281,166 @@ fn default_cpp_value(ty: &str) -> String {
     }
 }
 
-fn compile_cpp(
-    dir: &Path,
-    base: &str,
-    compiler: Option<&str>,
-    stdver: &str,
-    release: bool,
-    _has_main: bool,
-    san: Option<&str>,
-    coverage: bool,
-) -> Result<(), String> {
-    let exe_name = if cfg!(windows) {
-        format!("{}.exe", base)
-    } else {
-        base.to_string()
-    };
-
-    if compiler.is_none() || compiler == Some("cl") {
-        match tool_detector::compile_with_msvc(dir, base) {
-            Ok(()) => return Ok(()),
-            Err(e) => eprintln!("MSVC compile failed: {}", e),
+/// The merged classes, each pointing at the `.upp` file that defines it.
+fn with_source_files(a: &Analysis) -> Vec<ultracpp::Class> {
+    let mut classes = a.classes.clone();
+    for c in &mut classes {
+        if let Some(f) = a.graph.defining_file(&c.name) {
+            let path = fs::canonicalize(&f.path).unwrap_or_else(|_| f.path.clone());
+            c.source_file = Some(path.to_string_lossy().to_string());
         }
     }
-    // Try other compilers if requested explicitly
-    if let Some(comp) = compiler {
-        let src_dir = dir.join("src");
-        match comp {
-            "g++" => {
-                let mut files: Vec<String> = Vec::new();
-                if let Ok(rd) = fs::read_dir(&src_dir) {
-                    for e in rd.flatten() {
-                        let p = e.path();
-                        if let Some(ext) = p.extension() {
-                            if ext.to_string_lossy().eq_ignore_ascii_case("cpp") {
-                                if let Some(n) = p.file_name() {
-                                    files.push(format!("src/{}", n.to_string_lossy()));
-                                }
-                            }
-                        }
-                    }
-                }
-                if files.is_empty() {
-                    return Err("no .cpp files to compile".to_string());
-                }
-                let mut args: Vec<String> = Vec::new();
-                args.push(format!("-std={}", stdver));
-                args.push("-Wall".to_string());
-                args.push("-Wextra".to_string());
-                args.push("-Werror".to_string());
-                if release {
-                    args.push("-O2".to_string());
-                }
-                if let Some(s) = san {
-                    match s {
-                        "asan" => args.push("-fsanitize=address".to_string()),
-                        "ubsan" => args.push("-fsanitize=undefined".to_string()),
-                        "tsan" => args.push("-fsanitize=thread".to_string()),
-                        _ => {}
-                    }
-                }
-                if coverage {
-                    args.push("-fprofile-arcs".to_string());
-                    args.push("-ftest-coverage".to_string());
-                }
-                args.push("-I".to_string());
-                args.push("include".to_string());
-                for f in files {
-                    args.push(f);
-                }
-                args.push("-o".to_string());
-                args.push(format!("build/bin/{}", exe_name));
-                let arg_refs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
-                if let Ok(out) = Command::new("g++")
-                    .current_dir(dir)
-                    .args(&arg_refs)
-                    .output()
-                {
-                    if out.status.success() {
-                        return Ok(());
-                    }
-                }
-            }
-            "clang++" => {
-                let mut files: Vec<String> = Vec::new();
-                if let Ok(rd) = fs::read_dir(&src_dir) {
-                    for e in rd.flatten() {
-                        let p = e.path();
-                        if let Some(ext) = p.extension() {
-                            if ext.to_string_lossy().eq_ignore_ascii_case("cpp") {
-                                if let Some(n) = p.file_name() {
-                                    files.push(format!("src/{}", n.to_string_lossy()));
-                                }
-                            }
-                        }
-                    }
-                }
-                if files.is_empty() {
-                    return Err("no .cpp files to compile".to_string());
-                }
-                let mut args: Vec<String> = Vec::new();
-                args.push(format!("-std={}", stdver));
-                args.push("-Wall".to_string());
-                args.push("-Wextra".to_string());
-                args.push("-Werror".to_string());
-                if release {
-                    args.push("-O2".to_string());
-                }
-                if let Some(s) = san {
-                    match s {
-                        "asan" => args.push("-fsanitize=address".to_string()),
-                        "ubsan" => args.push("-fsanitize=undefined".to_string()),
-                        "tsan" => args.push("-fsanitize=thread".to_string()),
-                        _ => {}
-                    }
-                }
-                if coverage {
-                    args.push("-fprofile-arcs".to_string());
-                    args.push("-ftest-coverage".to_string());
-                }
-                args.push("-I".to_string());
-                args.push("include".to_string());
-                for f in files {
-                    args.push(f);
-                }
-                args.push("-o".to_string());
-                args.push(format!("build/bin/{}", exe_name));
-                let arg_refs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
-                if let Ok(out) = Command::new("clang++")
-                    .current_dir(dir)
-                    .args(&arg_refs)
-                    .output()
-                {
-                    if out.status.success() {
-                        return Ok(());
-                    }
-                }
-            }
-            _ => {}
+    classes
+}
+
+/// Contents of the generated `file` (relative to `dir`). `generate` only
+/// runs, and the file is only written, when the hash of `inputs` differs
+/// from the one recorded in `hashes` for it.
+fn generate_cached(
+    dir: &Path,
+    hashes: &mut BuildCache,
+    file: &str,
+    inputs: &str,
+    generate: &dyn Fn() -> String,
+) -> String {
+    let hash = cache::input_hash(inputs);
+    if hashes.is_fresh(dir, file, &hash) {
+        if let Ok(text) = fs::read_to_string(dir.join(file)) {
+            return text;
         }
     }
-    Err("no available compiler completed successfully".to_string())
+    let text = generate();
+    write(dir.join(file).to_str().unwrap(), &text);
+    hashes.record(file, &hash);
+    text
+}
+
+/// Parses the output of a failed compiler run into diagnostics, pointing
+/// locations in mapped generated code back at the `.upp` sources, and
+/// prints them. Output without recognisable diagnostics is printed as is.
+fn report_compiler_output(dir: &Path, out: &std::process::Output) -> Vec<CompilerDiagnostic> {
+    let text = format!(
+        "{}{}",
+        String::from_utf8_lossy(&out.stdout),
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let map = fs::read_to_string(dir.join("build").join(sourcemap::SOURCE_MAP_NAME))
+        .ok()
+        .and_then(|s| ultracpp::json::Value::parse(&s).ok())
+        .and_then(|v| SourceMap::from_json(&v).ok())
+        .unwrap_or_default();
+    let cwd = env::current_dir()
+        .and_then(fs::canonicalize)
+        .unwrap_or_default();
+    // Unmapped locations are relative to the generated project.
+    let dir = fs::canonicalize(dir).unwrap_or_else(|_| dir.to_path_buf());
+    let diagnostics: Vec<CompilerDiagnostic> = compiler_output::parse(&text)
+        .into_iter()
+        .map(|d| d.map_to_source(&map).located_in(&dir).relative_to(&cwd))
+        .collect();
+    if diagnostics.is_empty() {
+        eprint!("{}", map.rewrite(&text, &cwd));
+    }
+    for d in &diagnostics {
+        eprintln!("{}", d);
+    }
+    diagnostics
+}
+
+/// What to compile: every generated source into the entry executable
+/// (unless `--no-main`), plus the shared library when bridging. The
+/// `modules` interface units go first, in the given order.
+fn build_plan(dir: &Path, base: &str, o: &Options, modules: &[String]) -> BuildPlan {
+    let mut sources: Vec<String> = fs::read_dir(dir.join("src"))
+        .map(|rd| {
+            rd.flatten()
+                .map(|e| e.file_name().to_string_lossy().to_string())
+                .filter(|n| n.to_lowercase().ends_with(".cpp"))
+                .map(|n| format!("src/{}", n))
+                .collect()
+        })
+        .unwrap_or_default();
+    sources.sort();
+    sources.splice(0..0, modules.iter().cloned());
+    let mut include_dirs = vec!["include".to_string()];
+    include_dirs.extend(o.include_dirs.iter().cloned());
+    let mut defines = Vec::new();
+    let mut targets = Vec::new();
+    if !o.no_main {
+        targets.push(Target {
+            kind: TargetKind::Executable,
+            name: base.to_string(),
+            sources: sources.clone(),
+        });
+    }
+    if o.bridge.is_some() {
+        defines.extend(["UCPP_DLL".to_string(), "UCPP_BUILD".to_string()]);
+        targets.push(Target {
+            kind: TargetKind::SharedLibrary,
+            name: base.to_string(),
+            sources: sources
+                .iter()
+                .filter(|s| !s.ends_with("/entry.cpp"))
+                .cloned()
+                .collect(),
+        });
+    }
+    BuildPlan {
+        name: base.to_string(),
+        pch: Some("include/pch.hpp".to_string())
+            .filter(|p| modules.is_empty() && dir.join(p).is_file()),
+        sources,
+        include_dirs,
+        defines,
+        flags: Vec::new(),
+        std: o.std,
+        release: o.release,
+        sanitize: o.sanitize,
+        coverage: o.coverage,
+        link_libs: o.link_libs.clone(),
+        targets,
+    }
+}
+
+/// Without an explicit compiler, MSVC is used on Windows and g++ elsewhere.
+fn toolchain(o: &Options) -> Compiler {
+    o.compiler.unwrap_or(if cfg!(windows) {
+        Compiler::Msvc
+    } else {
+        Compiler::Gcc
+    })
+}
+
+/// Fails with a summary and the diagnostics of the failing step.
+fn compile_cpp(
+    dir: &Path,
+    plan: &BuildPlan,
+    tc: Compiler,
+) -> Result<(), (String, Vec<CompilerDiagnostic>)> {
+    if plan.sources.is_empty() {
+        return Err(("no .cpp files to compile".to_string(), Vec::new()));
+    }
+    let env = if tc == Compiler::Msvc {
+        tool_detector::msvc_environment()
+    } else {
+        Vec::new()
+    };
+    let jobs = std::thread::available_parallelism().map_or(1, |n| n.get());
+    plan.execute(dir, tc, &env, jobs).map_err(|e| {
+        let diagnostics = match &e.output {
+            Some(out) => report_compiler_output(dir, out),
+            None => Vec::new(),
+        };
+        let errors = diagnostics
+            .iter()
+            .filter(|d| d.severity == compiler_output::Severity::Error)
+            .count();
+        let summary = match errors {
+            0 => e.to_string(),
+            n => format!("{} error(s) from {}", n, e.program),
+        };
+        (summary, diagnostics)
+    })
 }
 
 fn select_entry_target<'a>(classes: &'a [ultracpp::Class], d: &Directives) -> &'a ultracpp::Class {
@@ -1556,27 +1452,37 @@ fn select_entry_target<'a>(classes: &'a [ultracpp::Class], d: &Directives) -> &'
     classes.last().expect("no classes parsed")
 }
 
-fn write_object_base(src_dir: &Path, include_dir: &Path) {
-    let hpp = r#"#pragma once
-class Object {
+/// The base class the `global` directive gives classes without one.
+const OBJECT_BASE: &str = "class Object {
 public:
   virtual ~Object() = default;
 };
-"#;
+";
+
+fn write_object_base(src_dir: &Path, include_dir: &Path) {
+    let hpp = format!("#pragma once\n{}", OBJECT_BASE);
     let cpp = r#"#include "object.hpp"
 "#;
     let hpp_path = include_dir.join("object.hpp");
     let cpp_path = src_dir.join("object.cpp");
-    let _ = fs::write(&hpp_path, hpp);
-    let _ = fs::write(&cpp_path, cpp);
+    let _ = write_if_changed(&hpp_path, &hpp);
+    let _ = write_if_changed(&cpp_path, cpp);
 }
 
-fn run_clang_tidy(dir: &Path, stdver: &str) -> bool {
+/// Runs clang-tidy on every generated source, with the flags from the
+/// project's `compile_commands.json`.
+fn run_clang_tidy(dir: &Path) -> bool {
     let src_dir = dir.join("src");
     let mut files: Vec<String> = Vec::new();
     let cfg_project = Path::new(".").join(".clang-tidy");
     let cfg_local = dir.join(".clang-tidy");
-    let cfg_path = if cfg_local.exists() { Some(cfg_local) } else if cfg_project.exists() { Some(cfg_project) } else { None };
+    let cfg_path = if cfg_local.exists() {
+        Some(cfg_local)
+    } else if cfg_project.exists() {
+        Some(cfg_project)
+    } else {
+        None
+    };
     if let Ok(rd) = fs::read_dir(&src_dir) {
         for e in rd.flatten() {
             let p = e.path();
@@ -1596,8 +1502,8 @@ fn run_clang_tidy(dir: &Path, stdver: &str) -> bool {
     for f in &files {
         let mut args: Vec<String> = Vec::new();
         args.push(f.clone());
-        args.push(format!("-extra-arg=-std={}", stdver));
-        args.push("-extra-arg=-Iinclude".to_string());
+        args.push("-p".to_string());
+        args.push(".".to_string());
         if let Some(cfg) = &cfg_path {
             args.push(format!("-config-file={}", cfg.display()));
         } else {
@@ -1605,7 +1511,11 @@ fn run_clang_tidy(dir: &Path, stdver: &str) -> bool {
             args.push("-warnings-as-errors=*".to_string());
         }
         let arg_refs: Vec<&str> = args.iter().map(|s| s.as_str()).collect();
-        if let Ok(out) = Command::new("clang-tidy").current_dir(dir).args(&arg_refs).output() {
+        if let Ok(out) = Command::new("clang-tidy")
+            .current_dir(dir)
+            .args(&arg_refs)
+            .output()
+        {
             if !out.status.success() {
                 ok = false;
             }
@@ -1698,52 +1608,3 @@ fn run_rust_tools() -> bool {
     }
     ok
 }
-
-fn run_smoke_tests(input_dir: &str) -> bool {
-    let exe = std::env::current_exe().unwrap();
-    let modes = ["classic", "unity", "hybrid"];
-    let mut ok = true;
-    for m in &modes {
-        let mut args: Vec<String> = Vec::new();
-        args.push("combine".to_string());
-        args.push(input_dir.to_string());
-        args.push("--emit".to_string());
-        args.push(m.to_string());
-        args.push("--compile".to_string());
-        if let Ok(st) = Command::new(&exe).args(&args).status() {
-            if !st.success() {
-                ok = false;
-            }
-        } else {
-            ok = false;
-        }
-    }
-    ok
-}
-
-fn run_smoke_tests_compilers(input_dir: &str) -> bool {
-    let exe = std::env::current_exe().unwrap();
-    let modes = ["classic", "unity", "hybrid"];
-    let comps = ["cl", "g++", "clang++"];
-    let mut ok = true;
-    for comp in &comps {
-        for m in &modes {
-            let mut args: Vec<String> = Vec::new();
-            args.push("combine".to_string());
-            args.push(input_dir.to_string());
-            args.push("--emit".to_string());
-            args.push(m.to_string());
-            args.push("--compiler".to_string());
-            args.push(comp.to_string());
-            args.push("--compile".to_string());
-            if let Ok(st) = Command::new(&exe).args(&args).status() {
-                if !st.success() {
-                    ok = false;
-                }
-            } else {
-                ok = false;
-            }
-        }
-    }
-    ok
-}
@@ -0,0 +1,265 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::Directives;
+
+pub const MANIFEST_NAME: &str = "ultra.toml";
+
+/// Project configuration read from `ultra.toml`.
+///
+/// Every field is optional so that command-line flags can fill or override
+/// whatever the manifest leaves out.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Manifest {
+    pub name: Option<String>,
+    pub sources: Vec<String>,
+    pub entry: Option<String>,
+    pub emit: Option<String>,
+    pub std: Option<String>,
+    pub compiler: Option<String>,
+    pub include_dirs: Vec<String>,
+    pub link_libs: Vec<String>,
+    pub profiles: Vec<String>,
+    pub capabilities: Vec<String>,
+    pub release: Option<bool>,
+    pub sanitize: Option<String>,
+    pub coverage: Option<bool>,
+    /// `name = "requirement"` pairs from `[dependencies]`.
+    pub dependencies: Vec<(String, String)>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Str(String),
+    Bool(bool),
+    List(Vec<String>),
+}
+
+fn parse_value(raw: &str) -> Option<Value> {
+    let raw = raw.trim();
+    if raw == "true" {
+        return Some(Value::Bool(true));
+    }
+    if raw == "false" {
+        return Some(Value::Bool(false));
+    }
+    if raw.len() >= 2 && raw.starts_with('"') && raw.ends_with('"') {
+        return Some(Value::Str(raw[1..raw.len() - 1].to_string()));
+    }
+    if raw.starts_with('[') && raw.ends_with(']') {
+        let mut items: Vec<String> = Vec::new();
+        for part in raw[1..raw.len() - 1].split(',') {
+            let p = part.trim();
+            if p.is_empty() {
+                continue;
+            }
+            match parse_value(p)? {
+                Value::Str(s) => items.push(s),
+                _ => return None,
+            }
+        }
+        return Some(Value::List(items));
+    }
+    None
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_quote = false;
+    for (i, c) in line.char_indices() {
+        if c == '"' {
+            in_quote = !in_quote;
+        } else if c == '#' && !in_quote {
+            return &line[..i];
+        }
+    }
+    line
+}
+
+impl Manifest {
+    /// Parses the subset of TOML used by `ultra.toml`: `[section]` headers,
+    /// and `key = value` pairs whose value is a string, a boolean or an array
+    /// of strings (arrays may span several lines).
+    pub fn parse(input: &str) -> Result<Manifest, String> {
+        let mut m = Manifest::default();
+        let mut section = String::new();
+        let lines: Vec<&str> = input.lines().collect();
+        let mut i = 0usize;
+        while i < lines.len() {
+            let lineno = i + 1;
+            let mut l = strip_comment(lines[i]).trim().to_string();
+            i += 1;
+            if l.is_empty() {
+                continue;
+            }
+            if l.starts_with('[') && l.ends_with(']') {
+                section = l[1..l.len() - 1].trim().to_string();
+                continue;
+            }
+            let Some(eq) = l.find('=') else {
+                return Err(format!(
+                    "{}:{}: expected `key = value`",
+                    MANIFEST_NAME, lineno
+                ));
+            };
+            if l[eq + 1..].trim().starts_with('[') {
+                while !l.trim_end().ends_with(']') && i < lines.len() {
+                    l.push(' ');
+                    l.push_str(strip_comment(lines[i]).trim());
+                    i += 1;
+                }
+            }
+            let key = l[..eq].trim().trim_matches('"').to_string();
+            let value = parse_value(&l[eq + 1..]).ok_or_else(|| {
+                format!("{}:{}: invalid value for `{}`", MANIFEST_NAME, lineno, key)
+            })?;
+            m.set(&section, &key, value)
+                .map_err(|e| format!("{}:{}: {}", MANIFEST_NAME, lineno, e))?;
+        }
+        Ok(m)
+    }
+
+    fn set(&mut self, section: &str, key: &str, value: Value) -> Result<(), String> {
+        fn string(key: &str, v: Value) -> Result<String, String> {
+            match v {
+                Value::Str(s) => Ok(s),
+                _ => Err(format!("`{}` must be a string", key)),
+            }
+        }
+        fn list(key: &str, v: Value) -> Result<Vec<String>, String> {
+            match v {
+                Value::List(l) => Ok(l),
+                Value::Str(s) => Ok(vec![s]),
+                _ => Err(format!("`{}` must be an array of strings", key)),
+            }
+        }
+        fn boolean(key: &str, v: Value) -> Result<bool, String> {
+            match v {
+                Value::Bool(b) => Ok(b),
+                _ => Err(format!("`{}` must be true or false", key)),
+            }
+        }
+        match (section, key) {
+            ("project", "name") => self.name = Some(string(key, value)?),
+            ("project", "sources") => self.sources = list(key, value)?,
+            ("project", "entry") => self.entry = Some(string(key, value)?),
+            ("build", "emit") => self.emit = Some(string(key, value)?),
+            ("build", "std") => self.std = Some(string(key, value)?),
+            ("build", "compiler") => self.compiler = Some(string(key, value)?),
+            ("build", "include_dirs") => self.include_dirs = list(key, value)?,
+            ("build", "link_libs") => self.link_libs = list(key, value)?,
+            ("build", "profiles") => self.profiles = list(key, value)?,
+            ("build", "capabilities") => self.capabilities = list(key, value)?,
+            ("build", "release") => self.release = Some(boolean(key, value)?),
+            ("build", "sanitize") => self.sanitize = Some(string(key, value)?),
+            ("build", "coverage") => self.coverage = Some(boolean(key, value)?),
+            ("dependencies", _) => self
+                .dependencies
+                .push((key.to_string(), string(key, value)?)),
+            _ => return Err(format!("unknown key `{}` in [{}]", key, section)),
+        }
+        Ok(())
+    }
+
+    pub fn read(path: &Path) -> Result<Manifest, String> {
+        let s = fs::read_to_string(path)
+            .map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+        Manifest::parse(&s)
+    }
+
+    /// Looks for `ultra.toml` for `input`: the file itself, inside it when it
+    /// is a directory, or next to it when it is a `.upp` file.
+    pub fn locate(input: &Path) -> Option<PathBuf> {
+        let candidate = if input
+            .file_name()
+            .map(|n| n == MANIFEST_NAME)
+            .unwrap_or(false)
+        {
+            input.to_path_buf()
+        } else if input.is_dir() {
+            input.join(MANIFEST_NAME)
+        } else {
+            input
+                .parent()
+                .filter(|p| !p.as_os_str().is_empty())
+                .unwrap_or(Path::new("."))
+                .join(MANIFEST_NAME)
+        };
+        if candidate.is_file() {
+            Some(candidate)
+        } else {
+            None
+        }
+    }
+
+    /// Imports implied by `[dependencies]`, in `import` syntax.
+    pub fn dependency_imports(&self) -> Vec<String> {
+        self.dependencies
+            .iter()
+            .map(|(n, req)| format!("{}.upp@{}", n, req))
+            .collect()
+    }
+
+    /// Merges manifest-level profiles, capabilities and entry into the
+    /// directives scanned from source. Directives written in the source win.
+    pub fn apply_to(&self, d: &mut Directives) {
+        for p in &self.profiles {
+            if !d.profiles.contains(p) {
+                d.profiles.push(p.clone());
+            }
+        }
+        for c in &self.capabilities {
+            if !d.capabilities.contains(c) {
+                d.capabilities.push(c.clone());
+            }
+        }
+        if d.entry.is_none() {
+            d.entry = self.entry.clone();
+        }
+    }
+
+    pub fn render(&self) -> String {
+        fn list(v: &[String]) -> String {
+            let items: Vec<String> = v.iter().map(|s| format!("\"{}\"", s)).collect();
+            format!("[{}]", items.join(", "))
+        }
+        let mut s = String::new();
+        s.push_str("[project]\n");
+        if let Some(n) = &self.name {
+            s.push_str(&format!("name = \"{}\"\n", n));
+        }
+        s.push_str(&format!("sources = {}\n", list(&self.sources)));
+        if let Some(e) = &self.entry {
+            s.push_str(&format!("entry = \"{}\"\n", e));
+        }
+        s.push_str("\n[build]\n");
+        s.push_str(&format!(
+            "emit = \"{}\"\n",
+            self.emit.as_deref().unwrap_or("classic")
+        ));
+        s.push_str(&format!(
+            "std = \"{}\"\n",
+            self.std.as_deref().unwrap_or("c++17")
+        ));
+        if let Some(c) = &self.compiler {
+            s.push_str(&format!("compiler = \"{}\"\n", c));
+        }
+        s.push_str(&format!("include_dirs = {}\n", list(&self.include_dirs)));
+        s.push_str(&format!("link_libs = {}\n", list(&self.link_libs)));
+        s.push_str(&format!("profiles = {}\n", list(&self.profiles)));
+        if !self.capabilities.is_empty() {
+            s.push_str(&format!("capabilities = {}\n", list(&self.capabilities)));
+        }
+        s.push_str(&format!("release = {}\n", self.release.unwrap_or(false)));
+        if let Some(san) = &self.sanitize {
+            s.push_str(&format!("sanitize = \"{}\"\n", san));
+        }
+        if let Some(cov) = self.coverage {
+            s.push_str(&format!("coverage = {}\n", cov));
+        }
+        s.push_str("\n[dependencies]\n");
+        for (n, req) in &self.dependencies {
+            s.push_str(&format!("{} = \"{}\"\n", n, req));
+        }
+        s
+    }
+}
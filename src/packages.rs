@@ -0,0 +1,262 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const LOCKFILE_NAME: &str = "ultra.lock";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+}
+
+impl Version {
+    /// Parses a full `major.minor.patch` version.
+    pub fn parse(s: &str) -> Option<Version> {
+        let parts: Vec<&str> = s.trim().split('.').collect();
+        if parts.len() != 3 {
+            return None;
+        }
+        Some(Version {
+            major: parts[0].parse().ok()?,
+            minor: parts[1].parse().ok()?,
+            patch: parts[2].parse().ok()?,
+        })
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReqOp {
+    /// `1.2.0` or `=1.2`: matches the given components exactly.
+    Exact,
+    /// `^1.2`: compatible updates that keep the left-most non-zero component.
+    Caret,
+    /// `~1.2`: patch-level updates only (minor-level when only the major is given).
+    Tilde,
+    /// `>=1.2`
+    AtLeast,
+    /// `*` or `latest`
+    Any,
+}
+
+/// A version requirement as written after `@` in `import foo.upp@^1.2`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionReq {
+    pub op: ReqOp,
+    pub major: u64,
+    pub minor: Option<u64>,
+    pub patch: Option<u64>,
+}
+
+impl VersionReq {
+    pub fn parse(s: &str) -> Option<VersionReq> {
+        let s = s.trim();
+        if s == "*" || s == "latest" {
+            return Some(VersionReq {
+                op: ReqOp::Any,
+                major: 0,
+                minor: None,
+                patch: None,
+            });
+        }
+        let (op, rest) = if let Some(r) = s.strip_prefix(">=") {
+            (ReqOp::AtLeast, r)
+        } else if let Some(r) = s.strip_prefix('^') {
+            (ReqOp::Caret, r)
+        } else if let Some(r) = s.strip_prefix('~') {
+            (ReqOp::Tilde, r)
+        } else if let Some(r) = s.strip_prefix('=') {
+            (ReqOp::Exact, r)
+        } else {
+            (ReqOp::Exact, s)
+        };
+        let parts: Vec<&str> = rest.trim().split('.').collect();
+        if parts.is_empty() || parts.len() > 3 {
+            return None;
+        }
+        let major = parts[0].parse().ok()?;
+        let minor = match parts.get(1) {
+            Some(p) => Some(p.parse().ok()?),
+            None => None,
+        };
+        let patch = match parts.get(2) {
+            Some(p) => Some(p.parse().ok()?),
+            None => None,
+        };
+        Some(VersionReq {
+            op,
+            major,
+            minor,
+            patch,
+        })
+    }
+
+    fn lower(&self) -> Version {
+        Version {
+            major: self.major,
+            minor: self.minor.unwrap_or(0),
+            patch: self.patch.unwrap_or(0),
+        }
+    }
+
+    pub fn matches(&self, v: &Version) -> bool {
+        let lo = self.lower();
+        match self.op {
+            ReqOp::Any => true,
+            ReqOp::AtLeast => *v >= lo,
+            ReqOp::Exact => {
+                v.major == self.major
+                    && self.minor.map(|m| v.minor == m).unwrap_or(true)
+                    && self.patch.map(|p| v.patch == p).unwrap_or(true)
+            }
+            ReqOp::Tilde => {
+                *v >= lo
+                    && v.major == self.major
+                    && self.minor.map(|m| v.minor == m).unwrap_or(true)
+            }
+            ReqOp::Caret => {
+                if *v < lo {
+                    return false;
+                }
+                if self.major > 0 || self.minor.is_none() {
+                    v.major == self.major
+                } else if self.minor != Some(0) || self.patch.is_none() {
+                    v.major == 0 && Some(v.minor) == self.minor
+                } else {
+                    *v == lo
+                }
+            }
+        }
+    }
+}
+
+/// Lists the versions available under `packages/<name>/`, highest first.
+pub fn available_versions(pkg_dir: &Path) -> Vec<Version> {
+    let mut out: Vec<Version> = Vec::new();
+    if let Ok(rd) = fs::read_dir(pkg_dir) {
+        for e in rd.flatten() {
+            if e.path().is_dir() {
+                if let Some(v) = Version::parse(&e.file_name().to_string_lossy()) {
+                    out.push(v);
+                }
+            }
+        }
+    }
+    out.sort();
+    out.reverse();
+    out
+}
+
+/// The `.upp` files of one package version, with `<name>.upp` first.
+pub fn package_sources(version_dir: &Path, name: &str) -> Vec<PathBuf> {
+    let mut files: Vec<PathBuf> = Vec::new();
+    if let Ok(rd) = fs::read_dir(version_dir) {
+        for e in rd.flatten() {
+            let p = e.path();
+            if let Some(ext) = p.extension() {
+                if ext.to_string_lossy().eq_ignore_ascii_case("upp") {
+                    files.push(p);
+                }
+            }
+        }
+    }
+    let entry = format!("{}.upp", name.to_lowercase());
+    files.sort_by_key(|p| {
+        let f = p.file_name().unwrap().to_string_lossy().to_lowercase();
+        (f != entry, f)
+    });
+    files
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockEntry {
+    pub name: String,
+    pub version: String,
+    pub hash: String,
+}
+
+/// Contents of `ultra.lock`: the exact version and content hash of every
+/// versioned import. Hashes cover the formatted source, so edits that only
+/// change the layout keep a pin valid.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Lockfile {
+    pub packages: Vec<LockEntry>,
+}
+
+impl Lockfile {
+    pub fn read(path: &Path) -> Lockfile {
+        fs::read_to_string(path)
+            .map(|s| Lockfile::parse(&s))
+            .unwrap_or_default()
+    }
+
+    pub fn parse(input: &str) -> Lockfile {
+        let mut packages: Vec<LockEntry> = Vec::new();
+        let mut cur: Option<LockEntry> = None;
+        for line in input.lines() {
+            let l = line.trim();
+            if l == "[[package]]" {
+                if let Some(e) = cur.take() {
+                    packages.push(e);
+                }
+                cur = Some(LockEntry {
+                    name: String::new(),
+                    version: String::new(),
+                    hash: String::new(),
+                });
+                continue;
+            }
+            let Some(e) = cur.as_mut() else { continue };
+            if let Some((k, v)) = l.split_once('=') {
+                let v = v.trim().trim_matches('"').to_string();
+                match k.trim() {
+                    "name" => e.name = v,
+                    "version" => e.version = v,
+                    "hash" => e.hash = v,
+                    _ => {}
+                }
+            }
+        }
+        if let Some(e) = cur.take() {
+            packages.push(e);
+        }
+        Lockfile { packages }
+    }
+
+    pub fn render(&self) -> String {
+        let mut s = String::new();
+        s.push_str("# This file is generated by ultracpp. Do not edit it by hand.\n");
+        s.push_str("version = 1\n");
+        let mut pkgs = self.packages.clone();
+        pkgs.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+        for p in &pkgs {
+            s.push_str("\n[[package]]\n");
+            s.push_str(&format!("name = \"{}\"\n", p.name));
+            s.push_str(&format!("version = \"{}\"\n", p.version));
+            s.push_str(&format!("hash = \"{}\"\n", p.hash));
+        }
+        s
+    }
+
+    pub fn get(&self, name: &str, version: &str) -> Option<&LockEntry> {
+        self.packages
+            .iter()
+            .find(|p| p.name == name && p.version == version)
+    }
+
+    /// Versions of `name` pinned by this lockfile.
+    pub fn pinned(&self, name: &str) -> Vec<&str> {
+        self.packages
+            .iter()
+            .filter(|p| p.name == name)
+            .map(|p| p.version.as_str())
+            .collect()
+    }
+}
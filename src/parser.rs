@@ -1,5 +1,5 @@
 use crate::Directives;
-use crate::{Class, Expr, Field, Method, Param, Visibility};
+use crate::{Class, Expr, Field, FormatPart, Method, Param, SourceLines, Visibility};
 
 fn trim(s: &str) -> String {
     s.trim().to_string()
@@ -17,6 +17,191 @@ fn indent_of(line: &str) -> usize {
     count
 }
 
+/// The byte ranges of the string literals in `s`, quotes included, and
+/// whether each is closed. A backslash escapes the next character and `"""`
+/// opens a triple-quoted literal; an unclosed literal runs to the end.
+fn string_spans(s: &str) -> Vec<(usize, usize, bool)> {
+    let b = s.as_bytes();
+    let mut spans = Vec::new();
+    let mut i = 0;
+    while i < b.len() {
+        if b[i] != b'"' {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        let triple = b[i..].starts_with(b"\"\"\"");
+        i += if triple { 3 } else { 1 };
+        let mut closed = false;
+        while i < b.len() {
+            if b[i] == b'\\' {
+                i += 2;
+            } else if triple && b[i..].starts_with(b"\"\"\"") {
+                i += 3;
+                closed = true;
+                break;
+            } else if !triple && b[i] == b'"' {
+                i += 1;
+                closed = true;
+                break;
+            } else {
+                i += 1;
+            }
+        }
+        i = i.min(b.len());
+        spans.push((start, i, closed));
+    }
+    spans
+}
+
+/// Whether byte `at` of the text `spans` came from is part of a string
+/// literal.
+fn in_string(spans: &[(usize, usize, bool)], at: usize) -> bool {
+    spans.iter().any(|&(start, end, _)| at >= start && at < end)
+}
+
+/// Whether `line` ends inside a triple-quoted string, which then goes on
+/// over the following lines.
+pub fn opens_triple_quote(line: &str) -> bool {
+    string_spans(line)
+        .last()
+        .is_some_and(|&(start, _, closed)| !closed && line[start..].starts_with("\"\"\""))
+}
+
+/// Splits `line` into its code and a trailing `#` or `//` comment. Comment
+/// markers inside string literals are part of the code.
+pub fn split_comment(line: &str) -> (&str, Option<&str>) {
+    let spans = string_spans(line);
+    for (i, c) in line.char_indices() {
+        if !in_string(&spans, i) && (c == '#' || line[i..].starts_with("//")) {
+            return (line[..i].trim_end(), Some(&line[i..]));
+        }
+    }
+    (line, None)
+}
+
+/// The value of the body of a string literal. Python's escapes apply:
+/// `\\`, `\'`, `\"`, `\a`, `\b`, `\f`, `\n`, `\r`, `\t`, `\v`, octal `\ooo`,
+/// `\xhh`, `\uXXXX` and `\UXXXXXXXX`, and a backslash before a line break
+/// joins the lines. Any other backslash is kept.
+fn unescape(body: &str) -> String {
+    let mut out = String::new();
+    let mut chars = body.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        let Some(e) = chars.next() else {
+            out.push('\\');
+            break;
+        };
+        match e {
+            '\n' => {}
+            '\\' | '\'' | '"' => out.push(e),
+            'a' => out.push('\x07'),
+            'b' => out.push('\x08'),
+            'f' => out.push('\x0c'),
+            'n' => out.push('\n'),
+            'r' => out.push('\r'),
+            't' => out.push('\t'),
+            'v' => out.push('\x0b'),
+            '0'..='7' => {
+                let mut code = e.to_digit(8).unwrap_or(0);
+                for _ in 0..2 {
+                    match chars.peek().and_then(|d| d.to_digit(8)) {
+                        Some(d) => {
+                            code = code * 8 + d;
+                            chars.next();
+                        }
+                        None => break,
+                    }
+                }
+                out.extend(char::from_u32(code));
+            }
+            'x' | 'u' | 'U' => {
+                let len = match e {
+                    'x' => 2,
+                    'u' => 4,
+                    _ => 8,
+                };
+                let mut digits = String::new();
+                while digits.len() < len && chars.peek().is_some_and(|d| d.is_ascii_hexdigit()) {
+                    digits.extend(chars.next());
+                }
+                let value = u32::from_str_radix(&digits, 16)
+                    .ok()
+                    .and_then(char::from_u32);
+                match value {
+                    Some(ch) if digits.len() == len => out.push(ch),
+                    _ => {
+                        out.push('\\');
+                        out.push(e);
+                        out.push_str(&digits);
+                    }
+                }
+            }
+            _ => {
+                out.push('\\');
+                out.push(e);
+            }
+        }
+    }
+    out
+}
+
+/// `s` as a string literal, `"..."` or `"""..."""`, when it is one. An `r`
+/// prefix keeps backslashes as written and an `f` prefix interpolates.
+fn string_literal(s: &str) -> Option<Expr> {
+    let quote = s.find('"')?;
+    let prefix = s[..quote].to_ascii_lowercase();
+    if !matches!(prefix.as_str(), "" | "r" | "f" | "rf" | "fr") {
+        return None;
+    }
+    let lit = &s[quote..];
+    if string_spans(lit) != [(0, lit.len(), true)] {
+        return None;
+    }
+    let q = if lit.len() >= 6 && lit.starts_with("\"\"\"") {
+        3
+    } else {
+        1
+    };
+    let body = &lit[q..lit.len() - q];
+    let raw = prefix.contains('r');
+    if prefix.contains('f') {
+        Some(Expr::Format(format_parts(body, raw)))
+    } else if raw {
+        Some(Expr::LiteralString(body.to_string()))
+    } else {
+        Some(Expr::LiteralString(unescape(body)))
+    }
+}
+
+/// `line` without its comment.
+pub fn strip_comment(line: &str) -> &str {
+    split_comment(line).0
+}
+
+/// The text of a `##` doc comment line.
+pub fn doc_text(line: &str) -> Option<&str> {
+    let rest = line.trim().strip_prefix("##")?;
+    Some(rest.strip_prefix(' ').unwrap_or(rest).trim_end())
+}
+
+/// Joins the doc lines collected so far, leaving `pending` empty.
+fn take_doc(pending: &mut Vec<String>) -> Option<String> {
+    if pending.is_empty() {
+        None
+    } else {
+        Some(std::mem::take(pending).join("\n"))
+    }
+}
+
+fn is_class_header(line: &str) -> bool {
+    strip_comment(line.trim()).starts_with("class ")
+}
+
 fn parse_expr(s: &str) -> Expr {
     let s = s.trim();
     if s.is_empty() {
@@ -67,18 +252,16 @@ fn parse_expr(s: &str) -> Expr {
         vec!["*", "/", "%"],
     ];
 
+    let spans = string_spans(s);
     for level in &ops {
         for op in level {
             // Find op not in parens/quotes
             let mut depth = 0;
-            let mut in_quote = false;
             let char_indices: Vec<(usize, char)> = s.char_indices().collect();
             let mut k = 0;
             while k < char_indices.len() {
                 let (byte_idx, c) = char_indices[k];
-                if c == '"' {
-                    in_quote = !in_quote;
-                } else if !in_quote {
+                if !in_string(&spans, byte_idx) {
                     if c == '(' {
                         depth += 1;
                     } else if c == ')' {
@@ -87,7 +270,8 @@ fn parse_expr(s: &str) -> Expr {
                         let is_match = if op.len() == 1 {
                             if k + 1 < char_indices.len() {
                                 let (_, next) = char_indices[k + 1];
-                                !(next == '=' && (*op == "<" || *op == ">" || *op == "!" || *op == "="))
+                                !(next == '='
+                                    && (*op == "<" || *op == ">" || *op == "!" || *op == "="))
                             } else {
                                 true
                             }
@@ -152,6 +336,9 @@ fn parse_expr(s: &str) -> Expr {
         let char_indices: Vec<(usize, char)> = s.char_indices().collect();
         for k in (0..char_indices.len()).rev() {
             let (byte_idx, c) = char_indices[k];
+            if in_string(&spans, byte_idx) {
+                continue;
+            }
             if c == ')' {
                 depth += 1;
             } else if c == '(' {
@@ -186,13 +373,9 @@ fn parse_expr(s: &str) -> Expr {
                 let mut args = Vec::new();
                 let mut start = 0;
                 let mut depth = 0;
-                let mut in_quote = false;
-                let a_chars: Vec<char> = args_part.chars().collect();
-                for i in 0..a_chars.len() {
-                    let c = a_chars[i];
-                    if c == '"' {
-                        in_quote = !in_quote;
-                    } else if !in_quote {
+                let arg_spans = string_spans(args_part);
+                for (i, c) in args_part.char_indices() {
+                    if !in_string(&arg_spans, i) {
                         if c == '(' {
                             depth += 1;
                         } else if c == ')' {
@@ -216,6 +399,8 @@ fn parse_expr(s: &str) -> Expr {
                 } else if let Some(rest) = name_part.strip_prefix("super().") {
                     let mname = rest.to_string();
                     return Expr::SuperCall { name: mname, args };
+                } else if name_part == "print" {
+                    return print_call(args);
                 } else {
                     // Includes obj.method(args) and empty name (grouping)
                     return Expr::FunctionCall {
@@ -228,8 +413,8 @@ fn parse_expr(s: &str) -> Expr {
     }
 
     // Literals
-    if s.starts_with('"') && s.ends_with('"') {
-        return Expr::LiteralString(s[1..s.len() - 1].to_string());
+    if let Some(lit) = string_literal(s) {
+        return lit;
     }
     if s == "true" {
         return Expr::LiteralBool(true);
@@ -252,32 +437,107 @@ fn parse_expr(s: &str) -> Expr {
     Expr::Variable(s.to_string())
 }
 
-fn parse_block(lines: &[&str], base_indent: usize) -> (Expr, usize) {
+/// `print(args)` with its `sep=` and `end=` keywords, which parse as
+/// assignments, taken out of the arguments.
+fn print_call(args: Vec<Expr>) -> Expr {
+    let mut positional = Vec::new();
+    let mut sep = None;
+    let mut end = None;
+    for arg in args {
+        match arg {
+            Expr::BinaryOp(l, op, r) if op == "=" && *l == Expr::Variable("sep".to_string()) => {
+                sep = Some(r)
+            }
+            Expr::BinaryOp(l, op, r) if op == "=" && *l == Expr::Variable("end".to_string()) => {
+                end = Some(r)
+            }
+            other => positional.push(other),
+        }
+    }
+    Expr::Print {
+        args: positional,
+        sep,
+        end,
+    }
+}
+
+/// The pieces of an f-string body: `{{` and `}}` are literal braces, and
+/// `{expr:spec}` splits at the first `:` outside brackets. Text is unescaped
+/// unless the f-string is `raw`.
+fn format_parts(body: &str, raw: bool) -> Vec<FormatPart> {
+    let text_part = |t: String| FormatPart::Text(if raw { t } else { unescape(&t) });
+    let mut parts = Vec::new();
+    let mut text = String::new();
+    let chars: Vec<char> = body.chars().collect();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if (c == '{' || c == '}') && chars.get(i + 1) == Some(&c) {
+            text.push(c);
+            i += 2;
+            continue;
+        }
+        if c != '{' {
+            text.push(c);
+            i += 1;
+            continue;
+        }
+        let mut depth = 0;
+        let mut colon = None;
+        let mut close = None;
+        for (j, &d) in chars.iter().enumerate().skip(i + 1) {
+            match d {
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' => depth -= 1,
+                '}' if depth > 0 => depth -= 1,
+                '}' => {
+                    close = Some(j);
+                    break;
+                }
+                ':' if depth == 0 && colon.is_none() => colon = Some(j),
+                _ => {}
+            }
+        }
+        let Some(close) = close else {
+            // An unclosed `{` is kept as text.
+            text.extend(&chars[i..]);
+            break;
+        };
+        let split = colon.unwrap_or(close);
+        if !text.is_empty() {
+            parts.push(text_part(std::mem::take(&mut text)));
+        }
+        parts.push(FormatPart::Value {
+            expr: parse_expr(&chars[i + 1..split].iter().collect::<String>()),
+            spec: chars[(split + 1).min(close)..close].iter().collect(),
+        });
+        i = close + 1;
+    }
+    if !text.is_empty() {
+        parts.push(text_part(text));
+    }
+    parts
+}
+
+/// Parses the statements indented past `base_indent`. `first` is the line
+/// number of `lines[0]`; the returned lines follow [`SourceLines::stmts`].
+fn parse_block(lines: &[&str], base_indent: usize, first: usize) -> (Expr, usize, Vec<usize>) {
     let mut stmts = Vec::new();
+    let mut at = Vec::new();
     let mut i = 0;
     while i < lines.len() {
         let line = lines[i];
-        let mut trimmed = line.trim();
-        // Remove comments
-        if let Some(comment_start) = trimmed.find("//") {
-            // ensure not inside string?
-            // simplistic approach: split on // if not in string.
-            // But for now, let's just assume // starts comment if it's not inside a string logic which is hard to check here easily without full parse.
-            // However, `parse_expr` handles strings.
-            // Let's just check if it STARTS with //
-            if comment_start == 0 {
-                i += 1;
-                continue;
-            }
-            // If it has // later, we should probably strip it.
-            trimmed = trimmed[..comment_start].trim();
+        let indent = indent_of(line);
+        // A doc comment at or left of the enclosing indentation documents
+        // the next member, not this block.
+        if indent <= base_indent && doc_text(line).is_some() {
+            break;
         }
-
+        let trimmed = strip_comment(line.trim());
         if trimmed.is_empty() {
             i += 1;
             continue;
         }
-        let indent = indent_of(line);
         // Stop body when encountering new method/class definitions
         if trimmed.starts_with("def ") || trimmed.starts_with("class ") {
             break;
@@ -285,6 +545,25 @@ fn parse_block(lines: &[&str], base_indent: usize) -> (Expr, usize) {
         if indent <= base_indent {
             break;
         }
+        // Every remaining branch pushes exactly one statement.
+        at.push(first + i);
+        // A triple-quoted string takes the following lines as written.
+        let joined;
+        let trimmed = if !trimmed.starts_with("native ") && opens_triple_quote(trimmed) {
+            let mut text = line.trim_start().to_string();
+            while i + 1 < lines.len() {
+                i += 1;
+                text.push('\n');
+                text.push_str(lines[i]);
+                if !opens_triple_quote(&text) {
+                    break;
+                }
+            }
+            joined = text;
+            strip_comment(joined.trim_end())
+        } else {
+            trimmed
+        };
 
         if let Some(rest) = trimmed.strip_prefix("return ") {
             let val = trim(rest);
@@ -303,18 +582,23 @@ fn parse_block(lines: &[&str], base_indent: usize) -> (Expr, usize) {
             let cond_str = trim(&rest[..rest.len() - 1]); // assume ends with :
             let cond = parse_expr(&cond_str);
             i += 1;
-            let (then_block, consumed) = parse_block(&lines[i..], indent);
+            let (then_block, consumed, then_at) = parse_block(&lines[i..], indent, first + i);
             i += consumed;
+            at.extend(then_at);
             let mut elifs: Vec<(Expr, Expr)> = Vec::new();
             while i < lines.len() {
                 let nline = lines[i];
                 let ntrim = nline.trim();
                 if indent_of(nline) == indent && ntrim.starts_with("elif ") {
-                    let cstr = trim(&ntrim.strip_prefix("elif ").unwrap()[..ntrim.len() - "elif ".len() - 1]);
+                    let cstr = trim(
+                        &ntrim.strip_prefix("elif ").unwrap()[..ntrim.len() - "elif ".len() - 1],
+                    );
                     let cexpr = parse_expr(&cstr);
+                    at.push(first + i);
                     i += 1;
-                    let (eblock, econsumed) = parse_block(&lines[i..], indent);
+                    let (eblock, econsumed, e_at) = parse_block(&lines[i..], indent, first + i);
                     i += econsumed;
+                    at.extend(e_at);
                     elifs.push((cexpr, eblock));
                 } else {
                     break;
@@ -326,9 +610,10 @@ fn parse_block(lines: &[&str], base_indent: usize) -> (Expr, usize) {
                 let next_trim = next_line.trim();
                 if indent_of(next_line) == indent && next_trim.starts_with("else:") {
                     i += 1;
-                    let (e_block, e_consumed) = parse_block(&lines[i..], indent);
+                    let (e_block, e_consumed, e_at) = parse_block(&lines[i..], indent, first + i);
                     else_block = Some(Box::new(e_block));
                     i += e_consumed;
+                    at.extend(e_at);
                 }
             }
             let mut tail = else_block;
@@ -348,8 +633,9 @@ fn parse_block(lines: &[&str], base_indent: usize) -> (Expr, usize) {
             let cond_str = trim(&rest[..rest.len() - 1]);
             let cond = parse_expr(&cond_str);
             i += 1;
-            let (body, consumed) = parse_block(&lines[i..], indent);
+            let (body, consumed, body_at) = parse_block(&lines[i..], indent, first + i);
             i += consumed;
+            at.extend(body_at);
             stmts.push(Expr::While {
                 cond: Box::new(cond),
                 body: Box::new(body),
@@ -383,7 +669,7 @@ fn parse_block(lines: &[&str], base_indent: usize) -> (Expr, usize) {
                 while i < lines.len() {
                     let nl = lines[i];
                     let nt = nl.trim();
-                if let Some(stripped) = nt.strip_suffix('"') {
+                    if let Some(stripped) = nt.strip_suffix('"') {
                         content.push_str(stripped);
                         i += 1;
                         break;
@@ -393,12 +679,13 @@ fn parse_block(lines: &[&str], base_indent: usize) -> (Expr, usize) {
                         i += 1;
                     }
                 }
-                stmts.push(Expr::Native(content));
+                stmts.push(Expr::Native(content.replace("\\\"", "\"")));
             } else {
-                let content = if code.starts_with('"') && code.ends_with('"') && code.len() >= 2 {
-                    code[1..code.len() - 1].to_string()
-                } else {
-                    code
+                // The C++ is copied verbatim; only the `\"` that keeps a quote
+                // from closing the string is undone.
+                let content = match code.strip_prefix('"').and_then(|c| c.strip_suffix('"')) {
+                    Some(inner) => inner.replace("\\\"", "\""),
+                    None => code,
                 };
                 stmts.push(Expr::Native(content));
                 i += 1;
@@ -500,10 +787,15 @@ fn parse_block(lines: &[&str], base_indent: usize) -> (Expr, usize) {
             }
         }
     }
-    (Expr::Block(stmts), i)
+    (Expr::Block(stmts), i, at)
 }
 
 pub fn parse(input: &str) -> Class {
+    parse_at(input, 1)
+}
+
+/// [`parse`] for text whose first line is line `first` of its file.
+fn parse_at(input: &str, first: usize) -> Class {
     let raw_lines: Vec<&str> = input.lines().collect();
     let mut i = 0usize;
     let mut name = String::new();
@@ -512,16 +804,24 @@ pub fn parse(input: &str) -> Class {
     let mut base: Option<String> = None;
     let mut ctor_params: Option<Vec<Param>> = None;
     let mut ctor_body: Option<Expr> = None;
+    let mut ctor_lines = SourceLines::default();
+    let mut pending_doc: Vec<String> = Vec::new();
 
     while i < raw_lines.len() {
         let line = raw_lines[i];
-        let content = line.trim();
+        if let Some(d) = doc_text(line) {
+            pending_doc.push(d.to_string());
+            i += 1;
+            continue;
+        }
+        let content = strip_comment(line.trim());
         if content.is_empty() {
             i += 1;
             continue;
         }
 
         if let Some(stripped) = content.strip_prefix("class ") {
+            let class_doc = take_doc(&mut pending_doc);
             let class_indent = indent_of(line);
             let mut cls = stripped.to_string();
             if cls.ends_with(':') {
@@ -547,12 +847,20 @@ pub fn parse(input: &str) -> Class {
             let mut current_vis = Visibility::Public;
             while i < raw_lines.len() {
                 let l = raw_lines[i];
-                let c = l.trim();
+                let ind = indent_of(l);
+                if let Some(d) = doc_text(l) {
+                    if ind <= class_indent {
+                        break;
+                    }
+                    pending_doc.push(d.to_string());
+                    i += 1;
+                    continue;
+                }
+                let c = strip_comment(l.trim());
                 if c.is_empty() {
                     i += 1;
                     continue;
                 }
-                let ind = indent_of(l);
                 if ind <= class_indent {
                     break;
                 }
@@ -582,12 +890,14 @@ pub fn parse(input: &str) -> Class {
                 };
 
                 if c.starts_with("def ") || is_method_syntax {
+                    let doc = take_doc(&mut pending_doc);
                     let def_indent = ind;
-                let sig = if let Some(rest) = c.strip_prefix("def ") {
-                    rest.to_string()
-                } else {
-                    c.to_string()
-                };
+                    let def_line = first + i;
+                    let sig = if let Some(rest) = c.strip_prefix("def ") {
+                        rest.to_string()
+                    } else {
+                        c.to_string()
+                    };
 
                     // parse signature
                     let mut mname;
@@ -638,12 +948,18 @@ pub fn parse(input: &str) -> Class {
 
                     i += 1;
                     // Parse Body using parse_block
-                    let (body, consumed) = parse_block(&raw_lines[i..], def_indent);
+                    let (body, consumed, stmts) =
+                        parse_block(&raw_lines[i..], def_indent, first + i);
                     i += consumed;
+                    let lines = SourceLines {
+                        def: def_line,
+                        stmts,
+                    };
 
                     if mname == "__init__" {
                         ctor_params = Some(params);
                         ctor_body = Some(body);
+                        ctor_lines = lines;
                     } else {
                         methods.push(Method {
                             name: mname,
@@ -652,20 +968,17 @@ pub fn parse(input: &str) -> Class {
                             body,
                             is_static: !has_self,
                             vis: current_vis.clone(),
+                            doc,
+                            lines,
                         });
                     }
                 } else if let Some(colon) = c.find(':') {
                     // Field
-                    let n = trim(&c[..colon]);
-                    let mut t_str = c[colon + 1..].to_string();
-                    if let Some(hash) = t_str.find('#') {
-                        t_str = t_str[..hash].to_string();
-                    }
-                    let t = trim(&t_str);
                     fields.push(Field {
-                        name: n,
-                        ty: t,
+                        name: trim(&c[..colon]),
+                        ty: trim(&c[colon + 1..]),
                         vis: current_vis.clone(),
+                        doc: take_doc(&mut pending_doc),
                     });
                     i += 1;
                 } else {
@@ -679,11 +992,15 @@ pub fn parse(input: &str) -> Class {
                 methods,
                 ctor_params,
                 ctor_body,
+                ctor_lines,
                 extra_includes: Vec::new(),
                 namespace: None,
                 module_version: None,
+                doc: class_doc,
+                source_file: None,
             };
         }
+        pending_doc.clear();
         i += 1;
     }
     Class {
@@ -693,40 +1010,40 @@ pub fn parse(input: &str) -> Class {
         methods,
         ctor_params: None,
         ctor_body: None,
+        ctor_lines: SourceLines::default(),
         extra_includes: Vec::new(),
         namespace: None,
         module_version: None,
+        doc: None,
+        source_file: None,
     }
 }
 
 pub fn parse_all(input: &str) -> Vec<Class> {
-    if input.contains("class ") {
-        let mut src = input.to_string();
+    let lines: Vec<&str> = input.lines().collect();
+    let headers: Vec<usize> = (0..lines.len())
+        .filter(|&i| is_class_header(lines[i]))
+        .collect();
+    if !headers.is_empty() {
+        // Each class is parsed from the end of the previous header, so the
+        // doc comment right above it is included.
         let mut out: Vec<Class> = Vec::new();
-        loop {
-            let c = parse(&src);
+        let mut from = 0;
+        for h in headers {
+            let c = parse_at(&lines[from..].join("\n"), from + 1);
             if c.name.is_empty() {
                 break;
             }
-            out.push(c.clone());
-            if let Some(pos) = src.find("class ") {
-                let rest = &src[pos + 6..];
-                if let Some(next_pos) = rest.find("class ") {
-                    let cut = pos + 6 + next_pos;
-                    src = src[cut..].to_string();
-                    continue;
-                }
-            }
-            break;
+            out.push(c);
+            from = h + 1;
         }
         out
     } else {
-        let lines: Vec<&str> = input.lines().collect();
         let mut i = 0usize;
         let mut out: Vec<Class> = Vec::new();
         while i < lines.len() {
             let l = lines[i];
-            let c = l.trim();
+            let c = strip_comment(l.trim());
             if c.is_empty() {
                 i += 1;
                 continue;
@@ -750,7 +1067,7 @@ pub fn parse_all(input: &str) -> Vec<Class> {
                 let mut j = i + 1;
                 while j < lines.len() {
                     let lj = lines[j];
-                    let cj = lj.trim();
+                    let cj = strip_comment(lj.trim());
                     if cj.is_empty() {
                         j += 1;
                         continue;
@@ -779,6 +1096,8 @@ pub fn parse_all(input: &str) -> Vec<Class> {
                             body: Expr::Block(vec![Expr::Return(Some(Box::new(body_expr)))]),
                             is_static: true,
                             vis: Visibility::Public,
+                            doc: None,
+                            lines: SourceLines::default(),
                         });
                         j += 1;
                     } else {
@@ -788,6 +1107,7 @@ pub fn parse_all(input: &str) -> Vec<Class> {
                                 name: trim(parts[0]),
                                 ty: trim(parts[1]),
                                 vis: Visibility::Public,
+                                doc: None,
                             });
                         }
                         j += 1;
@@ -800,9 +1120,12 @@ pub fn parse_all(input: &str) -> Vec<Class> {
                     methods,
                     ctor_params: None,
                     ctor_body: None,
+                    ctor_lines: SourceLines::default(),
                     extra_includes: Vec::new(),
                     namespace: None,
                     module_version: None,
+                    doc: None,
+                    source_file: None,
                 });
                 i = j;
                 continue;
@@ -816,10 +1139,7 @@ pub fn parse_all(input: &str) -> Vec<Class> {
 pub fn scan_directives(input: &str) -> Directives {
     let mut d = Directives::default();
     for line in input.lines() {
-        let mut c = line.trim();
-        if let Some(comment_start) = c.find("//") {
-            c = c[..comment_start].trim();
-        }
+        let c = strip_comment(line.trim());
         if c.is_empty() {
             continue;
         }
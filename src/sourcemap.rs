@@ -0,0 +1,175 @@
+//! Source maps from generated C++ back to the `.upp` lines it came from.
+//!
+//! The map is read off the `#line` directives codegen emits for classes with
+//! a `source_file`, saved next to the build as `sourcemap.json` and used to
+//! point compiler diagnostics at the Ultra source.
+
+use std::path::Path;
+
+use crate::codegen;
+use crate::json::{obj, Value};
+
+pub const SOURCE_MAP_NAME: &str = "sourcemap.json";
+
+/// From generated line `line` on, until the next segment, generated line
+/// `line + k` comes from line `source_line + k` of `source`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub line: usize,
+    pub source: String,
+    pub source_line: usize,
+}
+
+/// Segments per generated file, keyed by its path relative to the project
+/// directory (e.g. `src/main.cpp`), the way the compiler is invoked.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SourceMap {
+    pub files: Vec<(String, Vec<Segment>)>,
+}
+
+impl SourceMap {
+    /// Records the finished contents of the generated file `file`.
+    pub fn add(&mut self, file: &str, text: &str) {
+        let segments: Vec<Segment> = codegen::line_map(text)
+            .into_iter()
+            .map(|(line, source, source_line)| Segment {
+                line,
+                source,
+                source_line,
+            })
+            .collect();
+        if !segments.is_empty() {
+            self.files.push((file.to_string(), segments));
+        }
+    }
+
+    /// The `.upp` file and line behind line `line` of the generated `file`,
+    /// or `None` when that line is plain generated code.
+    pub fn lookup(&self, file: &str, line: usize) -> Option<(&str, usize)> {
+        let file = file.replace('\\', "/");
+        let (key, segments) = self
+            .files
+            .iter()
+            .find(|(k, _)| file == *k || file.ends_with(&format!("/{}", k)))?;
+        let seg = segments.iter().rev().find(|s| s.line <= line)?;
+        if seg.source == *key {
+            return None;
+        }
+        Some((&seg.source, seg.source_line + (line - seg.line)))
+    }
+
+    pub fn to_json(&self) -> Value {
+        let files = self
+            .files
+            .iter()
+            .map(|(file, segments)| {
+                let segments = segments
+                    .iter()
+                    .map(|s| {
+                        obj(vec![
+                            ("line", s.line.into()),
+                            ("source", s.source.as_str().into()),
+                            ("source_line", s.source_line.into()),
+                        ])
+                    })
+                    .collect::<Vec<Value>>();
+                obj(vec![
+                    ("file", file.as_str().into()),
+                    ("segments", segments.into()),
+                ])
+            })
+            .collect::<Vec<Value>>();
+        obj(vec![("version", 1usize.into()), ("files", files.into())])
+    }
+
+    pub fn from_json(v: &Value) -> Result<SourceMap, String> {
+        let bad = || "malformed source map".to_string();
+        let mut map = SourceMap::default();
+        for f in v.get("files").and_then(Value::as_array).ok_or_else(bad)? {
+            let file = f.get("file").and_then(Value::as_str).ok_or_else(bad)?;
+            let mut segments = Vec::new();
+            for s in f
+                .get("segments")
+                .and_then(Value::as_array)
+                .ok_or_else(bad)?
+            {
+                let num = |k: &str| s.get(k).and_then(Value::as_u64).map(|n| n as usize);
+                segments.push(Segment {
+                    line: num("line").ok_or_else(bad)?,
+                    source: s
+                        .get("source")
+                        .and_then(Value::as_str)
+                        .ok_or_else(bad)?
+                        .to_string(),
+                    source_line: num("source_line").ok_or_else(bad)?,
+                });
+            }
+            map.files.push((file.to_string(), segments));
+        }
+        Ok(map)
+    }
+
+    /// Rewrites the `file:line` (gcc, clang) and `file(line)` (MSVC)
+    /// locations in compiler output that point into mapped generated code,
+    /// and shows `.upp` paths relative to `cwd`.
+    pub fn rewrite(&self, output: &str, cwd: &Path) -> String {
+        let cwd = format!("{}/", cwd.to_string_lossy().replace('\\', "/"));
+        let mut out = String::with_capacity(output.len());
+        for line in output.lines() {
+            let mut line = self.rewrite_locations(line);
+            for (_, segments) in &self.files {
+                for s in segments {
+                    if let Some(rel) = s.source.replace('\\', "/").strip_prefix(&cwd) {
+                        line = line.replace(&s.source, rel);
+                    }
+                }
+            }
+            out.push_str(&line);
+            out.push('\n');
+        }
+        out
+    }
+
+    fn rewrite_locations(&self, line: &str) -> String {
+        let mut out = String::new();
+        let mut rest = line;
+        'scan: while !rest.is_empty() {
+            // MSVC reports Windows paths.
+            let keys = self
+                .files
+                .iter()
+                .flat_map(|(k, _)| [k.clone(), k.replace('/', "\\")]);
+            for key in keys {
+                let Some(at) = rest.find(key.as_str()) else {
+                    continue;
+                };
+                let after = &rest[at + key.len()..];
+                let Some(sep) = after.chars().next().filter(|c| *c == ':' || *c == '(') else {
+                    continue;
+                };
+                let digits = after[1..].len()
+                    - after[1..]
+                        .trim_start_matches(|c: char| c.is_ascii_digit())
+                        .len();
+                let Ok(n) = after[1..1 + digits].parse::<usize>() else {
+                    continue;
+                };
+                // The path starts at the last whitespace before the key.
+                let start = rest[..at].rfind(char::is_whitespace).map_or(0, |i| i + 1);
+                let path = &rest[start..at + key.len()];
+                match self.lookup(path, n) {
+                    Some((source, source_line)) => {
+                        out.push_str(&rest[..start]);
+                        out.push_str(&format!("{}{}{}", source, sep, source_line));
+                    }
+                    None => out.push_str(&rest[..at + key.len() + 1 + digits]),
+                }
+                rest = &after[1 + digits..];
+                continue 'scan;
+            }
+            break;
+        }
+        out.push_str(rest);
+        out
+    }
+}
@@ -0,0 +1,350 @@
+use std::fs;
+use std::path::PathBuf;
+use std::process::Command;
+
+use ultracpp::{bridge, parser};
+
+mod common;
+use common::{scratch, Scratch};
+
+const COUNTER: &str = "\
+entry Main
+
+class Main:
+  def run(self):
+    print(\"hi\")
+
+class Counter:
+  count: Int
+  label: String
+
+  ## Adds n to the count.
+  def add(self, n: Int) -> Int:
+    if n < 0:
+      native \"throw std::runtime_error(\\\"negative\\\");\"
+    count = count + n
+    return count
+
+  def name(self) -> String:
+    return label
+
+  def merge(self, other: Counter) -> Counter:
+    return Counter(count + other.count, label)
+
+  def zero() -> Counter:
+    return Counter(0, \"zero\")
+
+  def sizes(self, v: Vector<Int>):
+    print(\"x\")
+";
+
+#[test]
+fn header_wraps_public_methods() {
+    let classes = parser::parse_all(COUNTER);
+    let h = bridge::header("app", &classes);
+    for expected in [
+        "typedef struct CounterHandle CounterHandle;\n",
+        "UCPP_API ucpp_status counter_new(CounterHandle** out);\n",
+        "UCPP_API ucpp_status counter_new_with(int count, const char* label, CounterHandle** out);\n",
+        "/// Adds n to the count.\nUCPP_API ucpp_status counter_add(CounterHandle* self, int n, int* out);\n",
+        "UCPP_API ucpp_status counter_name(CounterHandle* self, char** out);\n",
+        "UCPP_API ucpp_status counter_merge(CounterHandle* self, const CounterHandle* other, CounterHandle** out);\n",
+        "UCPP_API ucpp_status counter_zero(CounterHandle** out);\n",
+        "// not exported: counter_sizes: parameter 'v' has type Vector<Int>\n",
+        "UCPP_API void counter_free(CounterHandle* self);\n",
+        "UCPP_API const char* app_last_error(void);\n",
+        "UCPP_API void app_string_free(char* s);\n",
+    ] {
+        assert!(h.contains(expected), "missing {:?} in\n{}", expected, h);
+    }
+    let cpp = bridge::source("app", &classes);
+    assert!(cpp.contains("#include \"counter.hpp\"\n"), "{}", cpp);
+    assert!(!cpp.contains("counter_sizes"), "{}", cpp);
+    assert!(
+        cpp.contains("    auto result = new Counter(count, std::string(label));\n"),
+        "{}",
+        cpp
+    );
+}
+
+fn have(tool: &str) -> bool {
+    Command::new(tool).arg("--version").output().is_ok()
+}
+
+/// Compiles the counter library with `--bridge lang` and `--emit emit` in a
+/// fresh directory.
+fn build_counter(name: &str, lang: &str, emit: &str) -> Scratch {
+    let dir = scratch(name);
+    let source = COUNTER.replace(
+        "\n  def sizes(self, v: Vector<Int>):\n    print(\"x\")\n",
+        "\n",
+    );
+    fs::write(dir.join("main.upp"), source).unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_ultracpp"))
+        .current_dir(&dir)
+        .args(["build", "main.upp", "out", "--bridge", lang, "--compile"])
+        .args(["--compiler", "g++", "--emit", emit])
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    dir
+}
+
+#[test]
+fn c_program_calls_the_bridge() {
+    c_program_runs("classic");
+}
+
+#[test]
+fn hybrid_library_defines_the_methods() {
+    c_program_runs("hybrid");
+}
+
+#[test]
+fn header_only_library_has_a_bridge() {
+    c_program_runs("header-only");
+}
+
+/// Builds the C bridge with `--emit emit` and runs a C program using it.
+fn c_program_runs(emit: &str) {
+    if !have("g++") || !have("gcc") || cfg!(windows) {
+        return;
+    }
+    let dir = build_counter(&format!("c_{}", emit), "c", emit);
+    fs::write(
+        dir.join("use.c"),
+        r#"#include <stdio.h>
+#include "exports.hpp"
+int main(void) {
+  CounterHandle* c = NULL;
+  if (counter_new_with(1, "apples", &c) != UCPP_OK) return 1;
+  int n = 0;
+  counter_add(c, 4, &n);
+  char* name = NULL;
+  counter_name(c, &name);
+  printf("%s %d\n", name, n);
+  main_string_free(name);
+  ucpp_status s = counter_add(c, -1, &n);
+  printf("%d %s %d\n", s == UCPP_EXCEPTION, main_last_error(), n);
+  printf("%d\n", counter_name(c, NULL) == UCPP_NULL_ARGUMENT);
+  CounterHandle* m = NULL;
+  counter_merge(c, c, &m);
+  counter_add(m, 0, &n);
+  printf("%d\n", n);
+  counter_free(m);
+  counter_free(c);
+  return 0;
+}
+"#,
+    )
+    .unwrap();
+    let gen = dir.join("out/main");
+    let cc = Command::new("gcc")
+        .current_dir(&dir)
+        .args([
+            "-std=c99",
+            "-Wall",
+            "-Werror",
+            "-Iout/main/include",
+            "use.c",
+        ])
+        .args(["-Lout/main/build/bin", "-lmain", "-o", "use"])
+        .output()
+        .unwrap();
+    assert!(
+        cc.status.success(),
+        "{}",
+        String::from_utf8_lossy(&cc.stderr)
+    );
+    let run = Command::new(dir.join("use"))
+        .env("LD_LIBRARY_PATH", gen.join("build/bin"))
+        .env("DYLD_LIBRARY_PATH", gen.join("build/bin"))
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&run.stdout),
+        "apples 5\n1 negative 5\n1\n10\n"
+    );
+}
+
+#[test]
+fn unity_builds_reject_the_bridge() {
+    let dir = scratch("unity");
+    fs::write(dir.join("main.upp"), COUNTER).unwrap();
+    for emit in ["unity", "modules"] {
+        let out = Command::new(env!("CARGO_BIN_EXE_ultracpp"))
+            .current_dir(&dir)
+            .args(["build", "main.upp", "out", "--bridge", "c", "--emit", emit])
+            .output()
+            .unwrap();
+        assert!(!out.status.success());
+        assert!(
+            String::from_utf8_lossy(&out.stderr).contains("--bridge needs class headers"),
+            "{}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+    }
+}
+
+#[test]
+fn python_module_loads_the_library() {
+    if !have("g++") || !have("python3") || cfg!(windows) {
+        return;
+    }
+    let dir = build_counter("python", "python", "classic");
+    let script = r#"
+import main
+c = main.Counter(1, "apples")
+print(c.add(4), c.name())
+try:
+    c.add(-1)
+except main.BridgeError as e:
+    print(e.status, e)
+print(c.merge(main.Counter.zero()).add(1))
+print(main.Counter.add.__annotations__)
+"#;
+    let run = Command::new("python3")
+        .current_dir(dir.join("out/main/python"))
+        .args(["-c", script])
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&run.stdout),
+        "5 apples\n2 negative\n6\n{'n': <class 'int'>, 'return': <class 'int'>}\n",
+        "{}",
+        String::from_utf8_lossy(&run.stderr)
+    );
+}
+
+#[test]
+fn rust_crate_wraps_the_library() {
+    if !have("g++") || !have("rustc") || cfg!(windows) {
+        return;
+    }
+    let dir = build_counter("rust", "rust", "classic");
+    let gen = dir.join("out/main");
+    let manifest = fs::read_to_string(gen.join("rust/Cargo.toml")).unwrap();
+    assert!(manifest.contains("links = \"main\""), "{}", manifest);
+    let bin = gen.join("build/bin");
+    let rustc = |args: &[&str]| {
+        let out = Command::new("rustc")
+            .current_dir(&dir)
+            .args(["--edition", "2021", "-L"])
+            .arg(format!("native={}", bin.display()))
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(
+            out.status.success(),
+            "{}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+    };
+    rustc(&[
+        "--crate-type",
+        "rlib",
+        "--crate-name",
+        "main",
+        "out/main/rust/src/lib.rs",
+    ]);
+    fs::write(
+        dir.join("demo.rs"),
+        r#"fn main() {
+    let mut c = main::Counter::new_with(1, "apples").unwrap();
+    println!("{} {}", c.add(4).unwrap(), c.name().unwrap());
+    let e = c.add(-1).unwrap_err();
+    println!("{} {}", e.status == main::EXCEPTION, e);
+    let mut merged = c.merge(&main::Counter::zero().unwrap()).unwrap();
+    println!("{}", merged.add(1).unwrap());
+}
+"#,
+    )
+    .unwrap();
+    rustc(&["demo.rs", "--extern", "main=libmain.rlib"]);
+    let run = Command::new(dir.join("demo"))
+        .env("LD_LIBRARY_PATH", &bin)
+        .env("DYLD_LIBRARY_PATH", &bin)
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&run.stdout),
+        "5 apples\ntrue negative\n6\n",
+        "{}",
+        String::from_utf8_lossy(&run.stderr)
+    );
+}
+
+#[test]
+fn node_addon_wraps_each_class() {
+    let headers = Command::new("node")
+        .args([
+            "-p",
+            "require('path').resolve(process.execPath, '../../include/node')",
+        ])
+        .output();
+    let headers = match headers {
+        Ok(out) if out.status.success() => String::from_utf8_lossy(&out.stdout).trim().to_string(),
+        _ => return,
+    };
+    if !have("g++") || cfg!(windows) || !PathBuf::from(&headers).join("node_api.h").is_file() {
+        return;
+    }
+    let dir = build_counter("node", "node", "classic");
+    let node = dir.join("out/main/node");
+    let typings = fs::read_to_string(node.join("index.d.ts")).unwrap();
+    assert!(
+        typings.contains("  constructor(count: number, label: string);\n"),
+        "{}",
+        typings
+    );
+    assert!(
+        typings.contains("  static zero(): Counter;\n"),
+        "{}",
+        typings
+    );
+    let bin = dir.join("out/main/build/bin");
+    let cc = Command::new("g++")
+        .current_dir(&node)
+        .args([
+            "-std=c++17",
+            "-shared",
+            "-fPIC",
+            "-Wall",
+            "-Wextra",
+            "-Werror",
+        ])
+        .arg(format!("-I{}", headers))
+        .args(["-I../include", "-DNODE_GYP_MODULE_NAME=main", "binding.cc"])
+        .args(["-L../build/bin", "-lmain", "-o", "main.node"])
+        .output()
+        .unwrap();
+    assert!(
+        cc.status.success(),
+        "{}",
+        String::from_utf8_lossy(&cc.stderr)
+    );
+    let script = r#"
+const m = require('./main.node');
+const c = new m.Counter(1, 'apples');
+console.log(c.add(4), c.name());
+try { c.add(-1); } catch (e) { console.log(e.code, e.message); }
+console.log(c.merge(m.Counter.zero()).add(1));
+try { c.add('x'); } catch (e) { console.log(e.name); }
+"#;
+    let run = Command::new("node")
+        .current_dir(&node)
+        .env("LD_LIBRARY_PATH", &bin)
+        .args(["-e", script])
+        .output()
+        .unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&run.stdout),
+        "5 apples\nUCPP_EXCEPTION negative\n6\nTypeError\n",
+        "{}",
+        String::from_utf8_lossy(&run.stderr)
+    );
+}
@@ -0,0 +1,256 @@
+use std::fs;
+use std::process::Command;
+
+use ultracpp::buildplan::{BuildPlan, Target, TargetKind};
+use ultracpp::cli::{Compiler, Sanitizer};
+use ultracpp::codegen::CppStd;
+
+mod common;
+use common::scratch;
+
+fn plan(sources: &[&str]) -> BuildPlan {
+    let sources: Vec<String> = sources.iter().map(|s| s.to_string()).collect();
+    BuildPlan {
+        name: "app".to_string(),
+        sources: sources.clone(),
+        include_dirs: vec!["include".to_string()],
+        defines: vec!["GREETING=\"hi\"".to_string()],
+        flags: Vec::new(),
+        std: CppStd::Cpp20,
+        release: false,
+        sanitize: None,
+        coverage: false,
+        pch: None,
+        link_libs: Vec::new(),
+        targets: vec![Target {
+            kind: TargetKind::Executable,
+            name: "app".to_string(),
+            sources,
+        }],
+    }
+}
+
+#[test]
+fn scripts_follow_the_plan() {
+    let dir = scratch("scripts");
+    fs::write(
+        dir.join("main.upp"),
+        "entry Main\n\nclass Main:\n    def run(self):\n        print(\"hi\")\n",
+    )
+    .unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_ultracpp"))
+        .current_dir(&dir)
+        .args(["build", "main.upp", "out", "--std", "c++20"])
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let sh = fs::read_to_string(dir.join("out/main/build.sh")).unwrap();
+    assert!(sh.contains("-std=c++20") && !sh.contains("c++17"), "{}", sh);
+    assert!(sh.contains("-o build/bin/main\n"), "{}", sh);
+    let bat = fs::read_to_string(dir.join("out/main/build.bat")).unwrap();
+    assert!(
+        bat.contains("/std:c++20") && bat.contains("/Febuild\\bin\\main.exe"),
+        "{}",
+        bat
+    );
+
+    let ninja = plan(&["src/a.cpp", "src/b.cpp"]).render_ninja(Compiler::Clang);
+    assert!(
+        ninja.contains("build build/obj/a.o: cxx src/a.cpp\n"),
+        "{}",
+        ninja
+    );
+    assert!(
+        ninja.contains("build build/obj/b.o: cxx src/b.cpp\n"),
+        "{}",
+        ninja
+    );
+    assert!(ninja.contains("deps = gcc"), "{}", ninja);
+}
+
+#[test]
+fn ninja_builds_sanitizer_and_coverage_variants_apart() {
+    let mut p = plan(&["src/a.cpp"]);
+    p.sanitize = Some(Sanitizer::Thread);
+    let ninja = p.render_ninja(Compiler::Gcc);
+    assert!(ninja.contains("\ndefault build/bin/app\n"), "{}", ninja);
+    assert!(
+        ninja.contains("build build/asan/obj/a.o: cxx src/a.cpp\n  cflags = ")
+            && ninja.contains("build build/asan/bin/app: link build/asan/obj/a.o\n")
+            && ninja.contains("build asan: phony build/asan/bin/app\n"),
+        "{}",
+        ninja
+    );
+    let asan = ninja.split("# asan\n").nth(1).unwrap();
+    assert!(
+        asan.contains("-fsanitize=address") && !asan.contains(" build/obj"),
+        "{}",
+        ninja
+    );
+    assert!(
+        ninja.contains("-fprofile-arcs -ftest-coverage")
+            && ninja.contains("build coverage: phony build/coverage/bin/app\n"),
+        "{}",
+        ninja
+    );
+    // The plan's own variant is the default build.
+    assert!(
+        ninja.contains("build tsan: phony build/bin/app\n"),
+        "{}",
+        ninja
+    );
+
+    let msvc = plan(&["src/a.cpp"]).render_ninja(Compiler::Msvc);
+    assert!(
+        msvc.contains("build asan: phony build/asan/bin/app.exe\n") && !msvc.contains("coverage"),
+        "{}",
+        msvc
+    );
+}
+
+#[test]
+fn execute_compiles_and_skips_fresh_objects() {
+    if Command::new("g++").arg("--version").output().is_err() {
+        return;
+    }
+    let dir = scratch("execute");
+    fs::create_dir_all(dir.join("src")).unwrap();
+    fs::create_dir_all(dir.join("include")).unwrap();
+    fs::write(
+        dir.join("include/b.hpp"),
+        "#pragma once\nconst char* b();\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("src/a.cpp"),
+        "#include <cstdio>\n#include \"b.hpp\"\nint main() { std::printf(\"%s %s\\n\", GREETING, b()); }\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("src/b.cpp"),
+        "#include \"b.hpp\"\nconst char* b() { return \"there\"; }\n",
+    )
+    .unwrap();
+    let p = plan(&["src/a.cpp", "src/b.cpp"]);
+    p.execute(&dir, Compiler::Gcc, &[], 4).unwrap();
+    let exe = dir.join(p.output(Compiler::Gcc, &p.targets[0]));
+    let run = Command::new(&exe).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "hi there\n");
+
+    let mtime = |f: &str| fs::metadata(dir.join(f)).unwrap().modified().unwrap();
+    let a = mtime("build/obj/a.o");
+    p.execute(&dir, Compiler::Gcc, &[], 4).unwrap();
+    assert_eq!(mtime("build/obj/a.o"), a);
+
+    fs::write(dir.join("src/b.cpp"), "int broken(\n").unwrap();
+    let err = p.execute(&dir, Compiler::Gcc, &[], 4).unwrap_err();
+    let stderr = String::from_utf8_lossy(&err.output.unwrap().stderr).to_string();
+    assert!(stderr.contains("src/b.cpp"), "{}", stderr);
+    assert_eq!(mtime("build/obj/a.o"), a);
+}
+
+#[test]
+fn emitted_makefile_builds_incrementally() {
+    let have = |tool: &str| Command::new(tool).arg("--version").output().is_ok();
+    if !have("make") || !have("g++") {
+        return;
+    }
+    let dir = scratch("make");
+    fs::write(
+        dir.join("main.upp"),
+        "entry Main\n\nclass Main:\n    def run(self):\n        print(\"hi\")\n",
+    )
+    .unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_ultracpp"))
+        .current_dir(&dir)
+        .args(["emit", "make", "main.upp", "out", "--emit", "ninja"])
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let gen = dir.join("out/main");
+    assert!(gen.join("build.ninja").is_file());
+    let make = |args: &[&str]| {
+        Command::new("make")
+            .current_dir(&gen)
+            .args(args)
+            .output()
+            .unwrap()
+    };
+    let built = make(&[]);
+    assert!(
+        built.status.success(),
+        "{}",
+        String::from_utf8_lossy(&built.stderr)
+    );
+    let run = Command::new(gen.join("build/bin/main")).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "hi\n");
+    assert!(make(&["-q"]).status.success());
+
+    // Switching variants rebuilds with their flags; switching back
+    // rebuilds again.
+    assert!(!make(&["-q", "COVERAGE=1"]).status.success());
+    let covered = make(&["COVERAGE=1"]);
+    assert!(
+        covered.status.success(),
+        "{}",
+        String::from_utf8_lossy(&covered.stderr)
+    );
+    let log = String::from_utf8_lossy(&covered.stdout).to_string();
+    assert!(log.contains("-ftest-coverage"), "{}", log);
+    let run = Command::new(gen.join("build/bin/main")).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "hi\n");
+    assert!(gen.join("build/obj/main.gcda").is_file());
+    assert!(make(&["-q", "COVERAGE=1"]).status.success());
+    assert!(!make(&["-q"]).status.success());
+    assert!(make(&[]).status.success());
+
+    // A header change makes the objects including it stale.
+    let header = gen.join("include/main.hpp");
+    let text = fs::read_to_string(&header).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(20));
+    fs::write(&header, text).unwrap();
+    assert!(!make(&["-q"]).status.success());
+}
+
+#[test]
+fn cmake_project_exports_the_bridge_library() {
+    let dir = scratch("cmake");
+    fs::write(
+        dir.join("main.upp"),
+        "entry Main\n\nclass Main:\n    def run(self):\n        print(\"hi\")\n",
+    )
+    .unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_ultracpp"))
+        .current_dir(&dir)
+        .args(["emit", "cmake", "main.upp", "out", "--std", "c++20"])
+        .args(["--bridge", "python", "--sanitize", "asan"])
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let cm = fs::read_to_string(dir.join("out/main/CMakeLists.txt")).unwrap();
+    for expected in [
+        "set(CMAKE_CXX_STANDARD 20)",
+        "target_precompile_headers(${target} PRIVATE include/pch.hpp)",
+        "option(MAIN_SANITIZE_ADDRESS \"Build with AddressSanitizer\" ON)",
+        "option(MAIN_COVERAGE \"Build with coverage instrumentation\" OFF)",
+        "add_library(main_lib SHARED\n",
+        "install(EXPORT mainTargets NAMESPACE main::",
+    ] {
+        assert!(cm.contains(expected), "missing {:?} in\n{}", expected, cm);
+    }
+    assert!(!cm.contains("file(GLOB"), "{}", cm);
+    let config = fs::read_to_string(dir.join("out/main/mainConfig.cmake")).unwrap();
+    assert!(config.contains("mainTargets.cmake"), "{}", config);
+}
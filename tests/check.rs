@@ -0,0 +1,121 @@
+use std::fs;
+use std::process::Command;
+
+use ultracpp::check::{self, Severity};
+use ultracpp::{imports, Directives};
+
+mod common;
+use common::scratch;
+
+#[test]
+fn type_errors_point_at_the_method() {
+    let dir = scratch("located");
+    fs::write(
+        dir.join("main.upp"),
+        "import util.upp\nclass Main:\n  x: Int\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("util.upp"),
+        "class Util:\n  def ok(self):\n    return 1\n  def f(self):\n    v: Thing = 1\n",
+    )
+    .unwrap();
+    let g = imports::resolve(&dir.join("main.upp")).unwrap();
+    let classes: Vec<_> = g.files.iter().flat_map(|f| f.classes.clone()).collect();
+    let diags = check::check(&g, &classes, &Directives::default());
+    assert_eq!(diags.len(), 1, "{:?}", diags);
+    assert_eq!(diags[0].severity, Severity::Error);
+    assert_eq!(
+        diags[0].file.as_ref().unwrap().file_name().unwrap(),
+        "util.upp"
+    );
+    assert_eq!(diags[0].line, Some(4));
+    assert!(diags[0].message.contains("Thing"));
+}
+
+#[test]
+fn check_command_writes_nothing() {
+    let dir = scratch("readonly");
+    fs::write(
+        dir.join("principal.upp"),
+        "import util@^1.0\nclass Main:\n  def run(self):\n    v: Thing = 1\n",
+    )
+    .unwrap();
+    fs::create_dir_all(dir.join("packages/util/1.0.0")).unwrap();
+    fs::write(
+        dir.join("packages/util/1.0.0/util.upp"),
+        "class Util:\n  x: Int\n",
+    )
+    .unwrap();
+    let listing = || {
+        let mut v: Vec<String> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().to_string())
+            .collect();
+        v.sort();
+        v
+    };
+    let before = listing();
+    let out = Command::new(env!("CARGO_BIN_EXE_ultracpp"))
+        .args(["check", "."])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert_eq!(out.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("principal.upp:3: error:"), "{}", stderr);
+    assert_eq!(listing(), before);
+
+    fs::write(
+        dir.join("principal.upp"),
+        "import util@^1.0\nclass Main:\n  def run(self):\n    v: Int = 1\n",
+    )
+    .unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_ultracpp"))
+        .args(["check", "."])
+        .current_dir(&dir)
+        .output()
+        .unwrap();
+    assert!(out.status.success());
+    assert_eq!(listing(), before);
+}
+
+#[test]
+fn by_value_cycles_are_errors() {
+    let dir = scratch("cycle");
+    fs::write(
+        dir.join("main.upp"),
+        "class Main:\n  x: Int\n\nclass Wheel(Part):\n  car: Car\n\nclass Car:\n  wheels: Vector<Wheel>\n  engine: Engine\n\nclass Engine:\n  car: Car\n\nclass Part:\n  x: Int\n",
+    )
+    .unwrap();
+    let g = imports::resolve(&dir.join("main.upp")).unwrap();
+    let classes: Vec<_> = g.files.iter().flat_map(|f| f.classes.clone()).collect();
+    let diags = check::check(&g, &classes, &Directives::default());
+    assert_eq!(diags.len(), 1, "{:?}", diags);
+    assert_eq!(diags[0].severity, Severity::Error);
+    assert_eq!(diags[0].line, Some(7));
+    assert!(
+        diags[0].message.contains("(Car -> Engine -> Car)"),
+        "{}",
+        diags[0].message
+    );
+}
+
+#[test]
+fn keyword_construction_names_public_fields_in_order() {
+    let dir = scratch("keywords");
+    fs::write(
+        dir.join("main.upp"),
+        "class Main:\n  def run(self):\n    let a: Point = Point(x=1, y=2)\n    let b: Point = Point(y=1, x=2)\n    let c: Point = Point(z=1)\n    let d: Point = Point(1, y=2)\n    let e: Named = Named(name=\"a\")\n\nclass Point:\n  x: Int\n  y: Int\n\nclass Named:\n  def __init__(self, name: String):\n    print(name)\n",
+    )
+    .unwrap();
+    let g = imports::resolve(&dir.join("main.upp")).unwrap();
+    let classes: Vec<_> = g.files.iter().flat_map(|f| f.classes.clone()).collect();
+    let diags = check::check(&g, &classes, &Directives::default());
+    let messages: Vec<&str> = diags.iter().map(|d| d.message.as_str()).collect();
+    assert_eq!(messages.len(), 4, "{:?}", messages);
+    assert!(messages[0].contains("'x' está fuera de orden en Point(...)"));
+    assert!(messages[1].contains("Campo público desconocido 'z'"));
+    assert!(messages[2].contains("mezcla argumentos posicionales y con nombre"));
+    assert!(messages[3].contains("Named define __init__"));
+}
@@ -0,0 +1,52 @@
+use ultracpp::cli::{parse_args, BuildFile, Command, EmitMode};
+use ultracpp::codegen::CppStd;
+
+fn args(s: &str) -> Vec<String> {
+    s.split_whitespace().map(|a| a.to_string()).collect()
+}
+
+#[test]
+fn legacy_invocation_is_build() {
+    let cmd = parse_args(&args("game.upp out --emit unity --std=c++20 --emit cmake")).unwrap();
+    let Command::Build(a) = cmd else {
+        panic!("expected build, got {:?}", cmd);
+    };
+    assert_eq!(a.input.as_deref(), Some("game.upp"));
+    assert_eq!(a.out_dir.as_deref(), Some("out"));
+    assert_eq!(a.emit, Some(EmitMode::Unity));
+    assert_eq!(a.std, Some(CppStd::Cpp20));
+    assert_eq!(a.build_files, vec![BuildFile::Cmake]);
+}
+
+#[test]
+fn emit_takes_mode_and_test_takes_compilers() {
+    let Command::Emit(a) = parse_args(&args("emit hybrid src")).unwrap() else {
+        panic!("expected emit");
+    };
+    assert_eq!(a.emit, Some(EmitMode::Hybrid));
+    assert_eq!(a.input.as_deref(), Some("src"));
+    assert!(matches!(
+        parse_args(&args("test src --compilers")).unwrap(),
+        Command::Test {
+            compilers: true,
+            ..
+        }
+    ));
+}
+
+#[test]
+fn rejects_unknown_and_conflicting_flags() {
+    for bad in [
+        "build a.upp --frob",
+        "build a.upp --emit unity --emit hybrid",
+        "build a.upp --compiler g++ --compiler=clang++",
+        "build a.upp --staging --compile",
+        "check a.upp --compile",
+        "build a.upp --std",
+        "build a.upp --compile=yes",
+        "build a.upp out extra",
+        "init a.upp --template nope",
+    ] {
+        assert!(parse_args(&args(bad)).is_err(), "accepted: {}", bad);
+    }
+}
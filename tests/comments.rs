@@ -0,0 +1,85 @@
+use ultracpp::{codegen, parser};
+
+#[test]
+fn hash_comments_are_ignored_everywhere() {
+    let src = r##"
+std  # profile
+entry Main  // the entry class
+# a class
+class Main:  # trailing
+  count: Int  # field comment
+  def run(self):  # method comment
+    # alone in a body
+  # less indented
+    print("#1 // not a comment")  # after code
+    count = 2 # no space
+"##;
+    let d = parser::scan_directives(src);
+    assert_eq!(d.profiles, vec!["std"]);
+    assert_eq!(d.entry.as_deref(), Some("Main"));
+    let classes = parser::parse_all(src);
+    assert_eq!(classes.len(), 1);
+    let c = &classes[0];
+    assert_eq!(c.name, "Main");
+    assert_eq!(c.fields[0].ty, "Int");
+    assert_eq!(c.methods.len(), 1);
+    let s = codegen::source(c);
+    assert!(s.contains("\"#1 // not a comment\""), "{}", s);
+    assert!(!s.contains("alone"), "{}", s);
+    assert!(!s.contains("no space"), "{}", s);
+}
+
+#[test]
+fn doc_comments_reach_the_header() {
+    let src = r#"
+class Helper:
+  x: Int
+
+## A 2D point.
+##
+## Immutable once built.
+class Point:
+  ## Horizontal position.
+  x: Float
+  y: Float
+  ## Distance to the origin.
+  def length(self) -> Float:
+    return 0.0
+  ## Not part of the docs of `length`.
+  # plain comment
+  def scale(self, k: Float):
+    x = x * k
+"#;
+    let classes = parser::parse_all(src);
+    assert_eq!(classes.len(), 2);
+    assert_eq!(classes[0].doc, None);
+    let p = &classes[1];
+    assert_eq!(
+        p.doc.as_deref(),
+        Some("A 2D point.\n\nImmutable once built.")
+    );
+    assert_eq!(p.fields[0].doc.as_deref(), Some("Horizontal position."));
+    assert_eq!(p.fields[1].doc, None);
+    assert_eq!(p.methods[0].doc.as_deref(), Some("Distance to the origin."));
+    assert_eq!(
+        p.methods[1].doc.as_deref(),
+        Some("Not part of the docs of `length`.")
+    );
+
+    let h = codegen::header(p);
+    assert!(
+        h.contains("/// A 2D point.\n///\n/// Immutable once built.\nclass UCPP_API Point {"),
+        "{}",
+        h
+    );
+    assert!(
+        h.contains("  /// Horizontal position.\n  float x;\n"),
+        "{}",
+        h
+    );
+    assert!(
+        h.contains("  /// Distance to the origin.\n  float length("),
+        "{}",
+        h
+    );
+}
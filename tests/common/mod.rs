@@ -0,0 +1,43 @@
+//! Helpers shared by the integration tests.
+
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+
+/// An empty directory under the system temp dir, removed again when it
+/// goes out of scope.
+pub struct Scratch(PathBuf);
+
+impl Deref for Scratch {
+    type Target = Path;
+
+    fn deref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl AsRef<Path> for Scratch {
+    fn as_ref(&self) -> &Path {
+        &self.0
+    }
+}
+
+impl Drop for Scratch {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+/// A fresh [`Scratch`] directory, unique to the test binary, `name` and
+/// process. The path is canonical, as the tools report canonical paths.
+pub fn scratch(name: &str) -> Scratch {
+    let dir = std::env::temp_dir().join(format!(
+        "ultracpp_{}_{}_{}",
+        env!("CARGO_CRATE_NAME"),
+        name,
+        std::process::id()
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    Scratch(fs::canonicalize(dir).unwrap())
+}
@@ -0,0 +1,82 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+use ultracpp::json::Value;
+
+mod common;
+use common::scratch;
+
+#[test]
+fn every_source_has_its_exact_command() {
+    let dir = scratch("compile_commands");
+    fs::write(
+        dir.join("main.upp"),
+        "entry Main\nimport greet.upp\n\nclass Main:\n    def run(self):\n        print(\"hi\")\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("greet.upp"),
+        "class Greet:\n    def hello(self):\n        print(\"hello\")\n",
+    )
+    .unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_ultracpp"))
+        .current_dir(&dir)
+        .args(["build", "main.upp", "out", "--compiler", "g++"])
+        .args(["--std", "c++20", "--sanitize", "ubsan"])
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let gen = dir.join("out/main");
+    let text = fs::read_to_string(gen.join("compile_commands.json")).unwrap();
+    let db = Value::parse(&text).unwrap();
+    let entries = db.as_array().unwrap();
+    let mut files: Vec<&str> = entries
+        .iter()
+        .map(|e| e.get("file").unwrap().as_str().unwrap())
+        .collect();
+    files.sort();
+    assert_eq!(
+        files,
+        [
+            "src/entry.cpp",
+            "src/greet.cpp",
+            "src/main.cpp",
+            "src/pch.cpp"
+        ]
+    );
+
+    let entry = &entries[0];
+    let directory = entry.get("directory").unwrap().as_str().unwrap();
+    assert!(Path::new(directory).is_absolute(), "{}", directory);
+    let args: Vec<&str> = entry
+        .get("arguments")
+        .unwrap()
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|a| a.as_str().unwrap())
+        .collect();
+    assert_eq!(args[0], "g++");
+    assert!(args.contains(&"-std=c++20"), "{:?}", args);
+    assert!(args.contains(&"-fsanitize=undefined"), "{:?}", args);
+
+    // The recorded command compiles the file as-is.
+    if Command::new("g++").arg("--version").output().is_ok() {
+        fs::create_dir_all(gen.join("build/obj/pch")).unwrap();
+        let run = Command::new(args[0])
+            .args(&args[1..])
+            .current_dir(directory)
+            .output()
+            .unwrap();
+        assert!(
+            run.status.success(),
+            "{}",
+            String::from_utf8_lossy(&run.stderr)
+        );
+    }
+}
@@ -0,0 +1,180 @@
+use std::fs;
+use std::process::Command;
+
+use ultracpp::compiler_output::{parse, CompilerDiagnostic, Severity};
+use ultracpp::json::Value;
+
+mod common;
+use common::scratch;
+
+fn diag(
+    file: Option<&str>,
+    line: Option<usize>,
+    column: Option<usize>,
+    severity: Severity,
+    message: &str,
+) -> CompilerDiagnostic {
+    CompilerDiagnostic {
+        file: file.map(String::from),
+        line,
+        column,
+        severity,
+        message: message.to_string(),
+    }
+}
+
+#[test]
+fn parses_gcc_clang_and_msvc_output() {
+    let output = "\
+src/main.cpp: In member function 'void Main::run()':
+src/main.cpp:8:16: error: 'y' was not declared in this scope
+    8 |   std::cout << y << std::endl;
+      |                ^
+include/main.hpp:3: warning: unused parameter
+src/a.cpp:2:10: fatal error: 'b.hpp' file not found
+C:\\dist\\app\\src\\main.cpp(12,5): error C2065: 'y': undeclared identifier
+src\\main.cpp(3): note: see declaration of 'Main'
+LINK : fatal error LNK1120: 1 unresolved externals
+main.cpp:(.text+0x1d): undefined reference to `foo()'
+collect2: error: ld returned 1 exit status
+cc1plus: all warnings being treated as errors
+";
+    assert_eq!(
+        parse(output),
+        vec![
+            diag(
+                Some("src/main.cpp"),
+                Some(8),
+                Some(16),
+                Severity::Error,
+                "'y' was not declared in this scope"
+            ),
+            diag(
+                Some("include/main.hpp"),
+                Some(3),
+                None,
+                Severity::Warning,
+                "unused parameter"
+            ),
+            diag(
+                Some("src/a.cpp"),
+                Some(2),
+                Some(10),
+                Severity::Error,
+                "'b.hpp' file not found"
+            ),
+            diag(
+                Some("C:\\dist\\app\\src\\main.cpp"),
+                Some(12),
+                Some(5),
+                Severity::Error,
+                "C2065: 'y': undeclared identifier"
+            ),
+            diag(
+                Some("src\\main.cpp"),
+                Some(3),
+                None,
+                Severity::Note,
+                "see declaration of 'Main'"
+            ),
+            diag(
+                None,
+                None,
+                None,
+                Severity::Error,
+                "LINK: LNK1120: 1 unresolved externals"
+            ),
+            diag(
+                None,
+                None,
+                None,
+                Severity::Error,
+                "undefined reference to `foo()'"
+            ),
+            diag(
+                None,
+                None,
+                None,
+                Severity::Error,
+                "collect2: ld returned 1 exit status"
+            ),
+        ]
+    );
+}
+
+#[test]
+fn failed_compile_reports_upp_locations() {
+    if Command::new("g++").arg("--version").output().is_err() {
+        return;
+    }
+    let dir = scratch("compiler_output");
+    fs::write(
+        dir.join("main.upp"),
+        "entry Main\n\nclass Main:\n    def run(self):\n        print(missing)\n",
+    )
+    .unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_ultracpp"))
+        .current_dir(&dir)
+        .args(["build", "main.upp", "out", "--compile", "--compiler", "g++"])
+        .args(["--source-map", "--bench"])
+        .output()
+        .unwrap();
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr.contains("main.upp:5: error: 'missing' was not declared"),
+        "{}",
+        stderr
+    );
+    assert!(
+        stderr.contains("compile failed: 1 error(s) from g++"),
+        "{}",
+        stderr
+    );
+
+    let report = fs::read_to_string(dir.join("out/main/report.json")).unwrap();
+    let report = Value::parse(&report).unwrap();
+    assert_eq!(report.get("compiled").and_then(Value::as_bool), Some(false));
+    let diagnostics = report.get("diagnostics").and_then(Value::as_array).unwrap();
+    assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+    let d = &diagnostics[0];
+    assert_eq!(d.get("file").and_then(Value::as_str), Some("main.upp"));
+    assert_eq!(d.get("line").and_then(Value::as_u64), Some(5));
+    assert_eq!(d.get("severity").and_then(Value::as_str), Some("error"));
+}
+
+#[test]
+fn unmapped_locations_are_relative_to_the_working_directory() {
+    if Command::new("g++").arg("--version").output().is_err() {
+        return;
+    }
+    let dir = scratch("compiler_output_paths");
+    fs::write(
+        dir.join("main.upp"),
+        "entry Main\n\nclass Main:\n    def run(self):\n        print(missing)\n",
+    )
+    .unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_ultracpp"))
+        .current_dir(&dir)
+        .args(["build", "main.upp", "out", "--compile", "--compiler", "g++"])
+        .arg("--bench")
+        .output()
+        .unwrap();
+    assert!(!out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(
+        stderr
+            .lines()
+            .any(|l| l.starts_with("out/main/src/main.cpp:")),
+        "{}",
+        stderr
+    );
+
+    let report = fs::read_to_string(dir.join("out/main/report.json")).unwrap();
+    let report = Value::parse(&report).unwrap();
+    let diagnostics = report.get("diagnostics").and_then(Value::as_array).unwrap();
+    assert_eq!(
+        diagnostics[0].get("file").and_then(Value::as_str),
+        Some("out/main/src/main.cpp")
+    );
+}
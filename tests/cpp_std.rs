@@ -0,0 +1,193 @@
+use std::fs;
+use std::process::Command;
+
+use ultracpp::codegen::{self, CppStd};
+use ultracpp::parser;
+
+mod common;
+use common::scratch;
+
+const COUNTER: &str = "\
+entry Main
+
+class Main:
+  def run(self):
+    let c: Counter = Counter()
+    print(\"count:\", c.get_count(), \"zero:\", c.is_zero())
+
+class Counter:
+  count: Int
+
+  def get_count(self) -> Int:
+    return count + 2
+
+  def is_zero(self) -> Bool:
+    return count == 0
+
+  def getaway(self) -> Int:
+    return 1
+
+  def getTotal(self) -> Int:
+    return count
+";
+
+#[test]
+fn newer_standards_pick_newer_idioms() {
+    let classes = parser::parse_all(COUNTER);
+    let src = codegen::source_with(&classes[0], CppStd::Cpp17);
+    assert!(
+        src.contains("  std::cout << std::boolalpha << \"count:\" << \" \" << c.get_count()"),
+        "{}",
+        src
+    );
+    let h = codegen::header_with(&classes[1], CppStd::Cpp17);
+    assert!(h.contains("  [[nodiscard]] int get_count();\n"), "{}", h);
+    assert!(h.contains("  [[nodiscard]] int getTotal();\n"), "{}", h);
+    assert!(h.contains("  int getaway();\n"), "{}", h);
+    assert!(!h.contains("UCPP_PRINT"), "{}", h);
+    assert!(!h.contains("Fields"), "{}", h);
+
+    let src = codegen::source_with(&classes[0], CppStd::Cpp20);
+    assert!(
+        src.contains(
+            "  UCPP_PRINT((\"count: {} zero: {}\\n\", c.get_count(), c.is_zero()), (std::cout << "
+        ),
+        "{}",
+        src
+    );
+    let h = codegen::header_with(&classes[1], CppStd::Cpp20);
+    assert!(
+        h.contains("  [[nodiscard(\"get_count is a query\")]] int get_count();\n"),
+        "{}",
+        h
+    );
+    assert!(
+        h.contains("  struct Fields {\n    int count = 0;\n  };\n  explicit Counter(const Fields& fields);\n"),
+        "{}",
+        h
+    );
+    assert!(
+        h.contains("define UCPP_PRINT(f, s) (std::cout << std::format f)"),
+        "{}",
+        h
+    );
+    assert!(!h.contains("std::print"), "{}", h);
+
+    let h = codegen::header_with(&classes[1], CppStd::Cpp23);
+    assert!(h.contains("#  include <print>\n"), "{}", h);
+    assert!(h.contains("define UCPP_PRINT(f, s) std::print f"), "{}", h);
+}
+
+#[test]
+fn print_falls_back_without_format() {
+    if Command::new("g++").arg("--version").output().is_err() || cfg!(windows) {
+        return;
+    }
+    let dir = scratch("cpp_std");
+    fs::write(dir.join("main.upp"), COUNTER).unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_ultracpp"))
+        .current_dir(&dir)
+        .args(["build", "main.upp", "out", "--compile"])
+        .args(["--compiler", "g++", "--std", "c++20"])
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let run = Command::new(dir.join("out/main/build/bin/main"))
+        .output()
+        .unwrap();
+    // `std::format` and the stream fallback agree on `{}`.
+    assert_eq!(
+        String::from_utf8_lossy(&run.stdout),
+        "count: 2 zero: true\n"
+    );
+}
+
+const SHAPES: &str = "\
+entry Main
+
+class Main:
+  def run(self):
+    let p: Point = Point(x=1, y=2)
+    p = Point(y=3)
+    let m: Scale = Scale()
+    print(p.x, p.y, m.twice(21), m.half(5.0, 2))
+
+class Point:
+  x: Int
+  y: Int
+
+class Scale:
+  def twice(self, n: Integral) -> Int:
+    return n * 2
+
+  def half(self, v: Floating, by: Auto) -> Float:
+    return v / by
+";
+
+#[test]
+fn designated_initialisers_and_concepts_from_cpp20() {
+    let classes = parser::parse_all(SHAPES);
+    let src = codegen::source_with(&classes[0], CppStd::Cpp17);
+    assert!(
+        src.contains("  p = [&] { Point ucpp_obj; ucpp_obj.y = 3; return ucpp_obj; }();\n"),
+        "{}",
+        src
+    );
+    let src = codegen::source_with(&classes[0], CppStd::Cpp20);
+    assert!(
+        src.contains("  Point p = Point(Point::Fields{.x = 1, .y = 2});\n"),
+        "{}",
+        src
+    );
+    assert!(!src.contains("fields.hpp"), "{}", src);
+
+    let h = codegen::header_with(&classes[2], CppStd::Cpp17);
+    assert!(
+        h.contains("  template <typename Auto1, typename Auto2, std::enable_if_t<std::is_floating_point_v<Auto1>, int> = 0>\n  float half(Auto1 v, Auto2 by) {\n"),
+        "{}",
+        h
+    );
+    assert!(h.contains("#include <type_traits>\n"), "{}", h);
+    let h = codegen::header_with(&classes[2], CppStd::Cpp20);
+    assert!(
+        h.contains("  int twice(std::integral auto n) {\n    return n * 2;\n  }\n"),
+        "{}",
+        h
+    );
+    assert!(
+        h.contains("  float half(std::floating_point auto v, auto by) {\n"),
+        "{}",
+        h
+    );
+    assert!(h.contains("#include <concepts>\n"), "{}", h);
+    // Templates are defined in the class.
+    let src = codegen::source_with(&classes[2], CppStd::Cpp20);
+    assert!(!src.contains("Scale::twice"), "{}", src);
+
+    if Command::new("g++").arg("--version").output().is_err() {
+        return;
+    }
+    let dir = scratch("cpp_std_generic");
+    fs::write(dir.join("main.upp"), SHAPES).unwrap();
+    for std in ["c++17", "c++20"] {
+        let out = Command::new(env!("CARGO_BIN_EXE_ultracpp"))
+            .current_dir(&dir)
+            .args(["run", "main.upp", "out", "--compiler", "g++", "--std", std])
+            .output()
+            .unwrap();
+        assert!(
+            out.status.success(),
+            "{}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+        assert!(
+            String::from_utf8_lossy(&out.stdout).ends_with("0 3 42 2.5\n"),
+            "{}",
+            String::from_utf8_lossy(&out.stdout)
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use std::fs;
+use std::process::Command;
+
+use ultracpp::format::format_source;
+
+mod common;
+use common::scratch;
+
+const MESSY: &str = "entry Main
+// geometry helpers
+import geo.upp
+use   std::vector
+std
+
+
+# Entry point
+class Main : Base:
+\tcount:Int   # how many
+\tdef add(self,a:Int,b : Float)->Int:
+\t\tx:Int=a+b*2
+\t\ty := -x
+\t\tif x>1 and not (y<=2):
+\t\t\tprint(\"a+b\",x,sep = \"\") // trace
+\t\telif x==0:
+\t\t\treturn x-1
+
+
+\t\twhile x!=0:
+\t\t\tx=x-1
+\t\treturn f(x,-1)
+\tdef static make()->Main:
+\t\tnative \"\"\"
+\t\tint z = 0;
+\t\t  z++;
+\t\t\"\"\"
+\t\treturn Main()
+";
+
+const CANONICAL: &str = "std
+use std::vector
+// geometry helpers
+import geo.upp
+entry Main
+
+# Entry point
+class Main(Base):
+    count: Int  # how many
+    def add(self, a: Int, b: Float) -> Int:
+        x: Int = a + b * 2
+        y := -x
+        if x > 1 and not (y <= 2):
+            print(\"a+b\", x, sep=\"\")  // trace
+        elif x == 0:
+            return x - 1
+
+        while x != 0:
+            x = x - 1
+        return f(x, -1)
+    def static make() -> Main:
+        native \"\"\"
+            int z = 0;
+              z++;
+            \"\"\"
+        return Main()
+";
+
+#[test]
+fn formats_to_canonical_form() {
+    let out = format_source(MESSY).unwrap();
+    assert_eq!(out, CANONICAL);
+    assert_eq!(format_source(&out).unwrap(), out);
+}
+
+#[test]
+fn fmt_check_reports_without_rewriting() {
+    let dir = scratch("check");
+    let main = dir.join("main.upp");
+    fs::write(&main, "import geo.upp\nclass Main:\n  x:Int\n").unwrap();
+    fs::write(dir.join("geo.upp"), "class Geo:\n    y: Int\n").unwrap();
+    let fmt = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_ultracpp"))
+            .arg("fmt")
+            .arg(&main)
+            .args(args)
+            .output()
+            .unwrap()
+    };
+
+    let out = fmt(&["--check"]);
+    assert!(!out.status.success());
+    let stdout = String::from_utf8_lossy(&out.stdout);
+    assert!(stdout.contains("main.upp"), "{}", stdout);
+    assert!(!stdout.contains("geo.upp"), "{}", stdout);
+    assert_eq!(
+        fs::read_to_string(&main).unwrap(),
+        "import geo.upp\nclass Main:\n  x:Int\n"
+    );
+
+    assert!(fmt(&[]).status.success());
+    assert_eq!(
+        fs::read_to_string(&main).unwrap(),
+        "import geo.upp\n\nclass Main:\n    x: Int\n"
+    );
+    assert!(fmt(&["--check"]).status.success());
+}
+
+#[test]
+fn formatting_a_locked_project_keeps_it_building() {
+    let dir = scratch("locked");
+    let main = dir.join("principal.upp");
+    fs::write(
+        &main,
+        "import util.upp@1.0.0\nentry Principal\nclass Principal:\n  run():\n    Utils.Version.current()\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("util.upp"),
+        "namespace Utils\nclass Version:\n  static current():\n    print(\"1.0.0\")\n",
+    )
+    .unwrap();
+    let run = |args: &[&str]| {
+        let out = Command::new(env!("CARGO_BIN_EXE_ultracpp"))
+            .current_dir(&dir)
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(
+            out.status.success(),
+            "{:?}: {}",
+            args,
+            String::from_utf8_lossy(&out.stderr)
+        );
+    };
+    run(&["build", "principal.upp", "out"]);
+    assert!(dir.join("ultra.lock").exists());
+    let before = fs::read_to_string(dir.join("util.upp")).unwrap();
+    run(&["fmt", "principal.upp"]);
+    assert_ne!(fs::read_to_string(dir.join("util.upp")).unwrap(), before);
+    run(&["build", "principal.upp", "out"]);
+}
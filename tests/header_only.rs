@@ -0,0 +1,163 @@
+use std::fs;
+use std::process::Command;
+
+use ultracpp::codegen::{self, CppStd};
+use ultracpp::parser;
+
+mod common;
+use common::scratch;
+
+const SHAPES: &str = "\
+entry Main
+
+class Main:
+  def run(self):
+    let s: Scene = Scene()
+    print(s.describe())
+
+class Scene:
+  origin: Point
+  boxes: Vector<Box>
+
+  def describe(self) -> String:
+    return \"scene\"
+
+class Box(Shape):
+  def area(self) -> Int:
+    return 4
+
+class Shape:
+  def area(self) -> Int:
+    return 0
+
+class Point:
+  x: Int
+";
+
+#[test]
+fn bases_and_fields_come_first() {
+    let classes = parser::parse_all(SHAPES);
+    let order: Vec<&str> = codegen::definition_order(&classes)
+        .iter()
+        .map(|c| c.name.as_str())
+        .collect();
+    assert_eq!(order, ["Main", "Shape", "Box", "Point", "Scene"]);
+
+    let h = codegen::header_only("My Shapes", &classes, CppStd::Cpp17);
+    assert!(
+        h.starts_with("#ifndef ULTRACPP_MY_SHAPES_HPP\n#define ULTRACPP_MY_SHAPES_HPP\n"),
+        "{}",
+        h
+    );
+    assert!(
+        h.trim_end().ends_with("#endif // ULTRACPP_MY_SHAPES_HPP"),
+        "{}",
+        h
+    );
+    assert!(h.contains("namespace my_shapes {"), "{}", h);
+    assert!(h.contains("inline std::string Scene::describe()"), "{}", h);
+    let pos = |s: &str| {
+        h.find(s)
+            .unwrap_or_else(|| panic!("missing {:?} in\n{}", s, h))
+    };
+    assert!(pos("class UCPP_API Shape {") < pos("class UCPP_API Box"));
+    assert!(pos("class UCPP_API Point {") < pos("class UCPP_API Scene {"));
+}
+
+#[test]
+fn header_links_into_two_translation_units() {
+    if Command::new("g++").arg("--version").output().is_err() || cfg!(windows) {
+        return;
+    }
+    let dir = scratch("header_only");
+    fs::write(dir.join("main.upp"), SHAPES).unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_ultracpp"))
+        .current_dir(&dir)
+        .args([
+            "build",
+            "main.upp",
+            "out",
+            "--emit",
+            "header-only",
+            "--compile",
+        ])
+        .args(["--compiler", "g++"])
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let gen = dir.join("out/main");
+    assert!(gen.join("include/main.hpp").is_file());
+    assert!(!gen.join("src/scene.cpp").exists());
+
+    fs::write(
+        dir.join("other.cpp"),
+        "#include \"main.hpp\"\nint area() { return main_::Box().area(); }\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("use.cpp"),
+        "#include \"main.hpp\"\nint area();\nint main() { return area() + main_::Shape().area() == 4 ? 0 : 1; }\n",
+    )
+    .unwrap();
+    let cc = Command::new("g++")
+        .current_dir(&dir)
+        .args(["-std=c++17", "-Wall", "-Werror", "-Iout/main/include"])
+        .args(["use.cpp", "other.cpp", "-o", "use"])
+        .output()
+        .unwrap();
+    assert!(
+        cc.status.success(),
+        "{}",
+        String::from_utf8_lossy(&cc.stderr)
+    );
+    assert!(Command::new(dir.join("use")).status().unwrap().success());
+}
+
+#[test]
+fn global_base_ships_inside_the_header() {
+    if Command::new("g++").arg("--version").output().is_err() || cfg!(windows) {
+        return;
+    }
+    let dir = scratch("header_only_global");
+    fs::write(dir.join("main.upp"), format!("global\n{}", SHAPES)).unwrap();
+    let out = Command::new(env!("CARGO_BIN_EXE_ultracpp"))
+        .current_dir(&dir)
+        .args(["build", "main.upp", "out", "--emit", "header-only"])
+        .args(["--compile", "--compiler", "g++"])
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    let gen = dir.join("out/main");
+    let h = fs::read_to_string(gen.join("include/main.hpp")).unwrap();
+    assert!(
+        h.contains("class UCPP_API Shape : public Object {"),
+        "{}",
+        h
+    );
+    assert!(!gen.join("include/object.hpp").exists());
+
+    fs::write(
+        dir.join("use.cpp"),
+        "#include \"main.hpp\"\nint main() { Object* o = new main_::Box(); delete o; }\n",
+    )
+    .unwrap();
+    let cc = Command::new("g++")
+        .current_dir(&dir)
+        .args(["-std=c++17", "-Wall", "-Werror", "-Iout/main/include"])
+        .args(["use.cpp", "-o", "use"])
+        .output()
+        .unwrap();
+    assert!(
+        cc.status.success(),
+        "{}",
+        String::from_utf8_lossy(&cc.stderr)
+    );
+}
@@ -0,0 +1,63 @@
+use std::fs;
+
+use ultracpp::imports;
+
+mod common;
+use common::scratch;
+
+#[test]
+fn transitive_imports_resolved_relative_to_importer() {
+    let dir = scratch("transitive");
+    fs::create_dir_all(dir.join("lib")).unwrap();
+    fs::write(dir.join("a.upp"), "import lib/b.upp\nclass A:\n  x: Int\n").unwrap();
+    fs::write(dir.join("lib/b.upp"), "import c.upp\nclass B:\n  y: Int\n").unwrap();
+    fs::write(dir.join("lib/c.upp"), "class C:\n  z: Int\n").unwrap();
+    let g = imports::resolve(&dir.join("a.upp")).expect("resolve failed");
+    let names: Vec<&str> = g
+        .files
+        .iter()
+        .flat_map(|f| f.classes.iter().map(|c| c.name.as_str()))
+        .collect();
+    assert_eq!(names, vec!["A", "B", "C"]);
+}
+
+#[test]
+fn shared_import_parsed_once() {
+    let dir = scratch("diamond");
+    fs::write(
+        dir.join("a.upp"),
+        "import b.upp\nimport c.upp\nclass A:\n  x: Int\n",
+    )
+    .unwrap();
+    fs::write(dir.join("b.upp"), "import d.upp\nclass B:\n  x: Int\n").unwrap();
+    fs::write(dir.join("c.upp"), "import d.upp\nclass C:\n  x: Int\n").unwrap();
+    fs::write(dir.join("d.upp"), "class D:\n  x: Int\n").unwrap();
+    let g = imports::resolve(&dir.join("a.upp")).expect("resolve failed");
+    assert_eq!(g.files.len(), 4);
+}
+
+#[test]
+fn import_cycle_reports_full_chain() {
+    let dir = scratch("cycle");
+    fs::write(dir.join("a.upp"), "import b.upp\nclass A:\n  x: Int\n").unwrap();
+    fs::write(dir.join("b.upp"), "import a.upp\nclass B:\n  x: Int\n").unwrap();
+    let err = imports::resolve(&dir.join("a.upp")).unwrap_err();
+    assert_eq!(err, "import cycle: a.upp -> b.upp -> a.upp");
+}
+
+#[test]
+fn imported_definition_replaces_the_root_one() {
+    let dir = scratch("override");
+    fs::write(
+        dir.join("a.upp"),
+        "import b.upp\nclass Util:\n  x: Int\nclass A:\n  y: Int\n",
+    )
+    .unwrap();
+    fs::write(dir.join("b.upp"), "class Util:\n  z: Float\n").unwrap();
+    let g = imports::resolve(&dir.join("a.upp")).expect("resolve failed");
+    let classes = g.merged_classes();
+    let names: Vec<&str> = classes.iter().map(|c| c.name.as_str()).collect();
+    assert_eq!(names, vec!["Util", "A"]);
+    assert_eq!(classes[0].fields[0].name, "z");
+    assert!(g.defining_file("Util").unwrap().path.ends_with("b.upp"));
+}
@@ -0,0 +1,101 @@
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+use std::time::SystemTime;
+
+mod common;
+use common::{scratch, Scratch};
+
+/// A project with `app/main.upp` importing `app/greet.upp`.
+fn project(name: &str) -> Scratch {
+    let dir = scratch(name);
+    fs::create_dir_all(dir.join("app")).unwrap();
+    fs::write(
+        dir.join("app/main.upp"),
+        "std\nentry Main\nimport greet.upp\n\nclass Main:\n    def run(self):\n        print(\"main\")\n",
+    )
+    .unwrap();
+    fs::write(
+        dir.join("app/greet.upp"),
+        "class Greet:\n    def hello(self):\n        print(\"hello\")\n",
+    )
+    .unwrap();
+    dir
+}
+
+fn build(dir: &Path, extra: &[&str]) {
+    let out = Command::new(env!("CARGO_BIN_EXE_ultracpp"))
+        .current_dir(dir)
+        .args(["build", "app/main.upp", "out"])
+        .args(extra)
+        .output()
+        .unwrap();
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+}
+
+fn mtime(p: &Path) -> SystemTime {
+    fs::metadata(p).unwrap().modified().unwrap()
+}
+
+#[test]
+fn unchanged_classes_keep_their_files() {
+    let dir = project("codegen");
+    let gen = dir.join("out/main");
+    build(&dir, &[]);
+    assert!(gen.join("build/hashes.json").is_file());
+    let files = [
+        "include/main.hpp",
+        "src/main.cpp",
+        "src/entry.cpp",
+        "include/greet.hpp",
+        "src/greet.cpp",
+    ];
+    let before: Vec<SystemTime> = files.iter().map(|f| mtime(&gen.join(f))).collect();
+
+    build(&dir, &[]);
+    let after: Vec<SystemTime> = files.iter().map(|f| mtime(&gen.join(f))).collect();
+    assert_eq!(before, after);
+
+    fs::write(
+        dir.join("app/greet.upp"),
+        "class Greet:\n    def hello(self):\n        print(\"hi\")\n",
+    )
+    .unwrap();
+    build(&dir, &[]);
+    assert!(fs::read_to_string(gen.join("src/greet.cpp"))
+        .unwrap()
+        .contains("\"hi\""));
+    for (f, t) in files.iter().zip(&before).take(3) {
+        assert_eq!(mtime(&gen.join(f)), *t, "{} was rewritten", f);
+    }
+}
+
+#[test]
+fn compile_rebuilds_only_changed_objects() {
+    if Command::new("g++").arg("--version").output().is_err() {
+        return;
+    }
+    let dir = project("compile");
+    let gen = dir.join("out/main");
+    build(&dir, &["--compile", "--compiler", "g++"]);
+    let main_o = mtime(&gen.join("build/obj/main.o"));
+    let exe = mtime(&gen.join("build/bin/main"));
+
+    build(&dir, &["--compile", "--compiler", "g++"]);
+    assert_eq!(mtime(&gen.join("build/obj/main.o")), main_o);
+    assert_eq!(mtime(&gen.join("build/bin/main")), exe);
+
+    fs::write(
+        dir.join("app/greet.upp"),
+        "class Greet:\n    def hello(self):\n        print(\"hi\")\n",
+    )
+    .unwrap();
+    build(&dir, &["--compile", "--compiler", "g++"]);
+    assert_eq!(mtime(&gen.join("build/obj/main.o")), main_o);
+    assert!(mtime(&gen.join("build/obj/greet.o")) >= mtime(&gen.join("src/greet.cpp")));
+    assert!(mtime(&gen.join("build/bin/main")) >= mtime(&gen.join("build/obj/greet.o")));
+}
@@ -10,7 +10,9 @@ class Hijo(Base):
 "#;
     let c = parser::parse(src);
     let h = codegen::header(&c);
-    assert!(h.contains("class Hijo : public Base") || h.contains("class UCPP_API Hijo : public Base"));
+    assert!(
+        h.contains("class Hijo : public Base") || h.contains("class UCPP_API Hijo : public Base")
+    );
 }
 
 #[test]
@@ -10,5 +10,7 @@ class Hijo : Base:
 "#,
     );
     let h = codegen::header(&c);
-    assert!(h.contains("class Hijo : public Base") || h.contains("class UCPP_API Hijo : public Base"));
+    assert!(
+        h.contains("class Hijo : public Base") || h.contains("class UCPP_API Hijo : public Base")
+    );
 }
@@ -0,0 +1,274 @@
+use std::fs;
+use std::io::BufReader;
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+
+use ultracpp::json::{obj, Value};
+use ultracpp::lsp::{path_to_uri, read_message, write_message};
+
+mod common;
+use common::scratch;
+
+/// Drives `ultracpp lsp` over stdio, keeping the notifications it receives.
+struct Client {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+    next_id: u64,
+    notifications: Vec<Value>,
+}
+
+impl Client {
+    fn start() -> Client {
+        let mut child = Command::new(env!("CARGO_BIN_EXE_ultracpp"))
+            .args(["lsp", "--stdio"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        Client {
+            child,
+            stdin,
+            stdout,
+            next_id: 1,
+            notifications: Vec::new(),
+        }
+    }
+
+    fn notify(&mut self, method: &str, params: Value) {
+        let msg = obj(vec![
+            ("jsonrpc", "2.0".into()),
+            ("method", method.into()),
+            ("params", params),
+        ]);
+        write_message(&mut self.stdin, &msg).unwrap();
+    }
+
+    fn request(&mut self, method: &str, params: Value) -> Value {
+        let id = self.next_id;
+        self.next_id += 1;
+        let msg = obj(vec![
+            ("jsonrpc", "2.0".into()),
+            ("id", id.into()),
+            ("method", method.into()),
+            ("params", params),
+        ]);
+        write_message(&mut self.stdin, &msg).unwrap();
+        loop {
+            let reply = read_message(&mut self.stdout)
+                .unwrap()
+                .expect("server closed");
+            if reply.get("id").and_then(Value::as_u64) == Some(id) {
+                return reply.get("result").cloned().unwrap_or(Value::Null);
+            }
+            self.notifications.push(reply);
+        }
+    }
+
+    fn diagnostics_for(&self, uri: &str) -> Vec<Value> {
+        self.notifications
+            .iter()
+            .rev()
+            .find(|n| n.at(&["params", "uri"]).and_then(Value::as_str) == Some(uri))
+            .and_then(|n| n.at(&["params", "diagnostics"]))
+            .and_then(Value::as_array)
+            .map(|d| d.to_vec())
+            .unwrap_or_default()
+    }
+
+    fn finish(mut self) {
+        self.request("shutdown", Value::Null);
+        self.notify("exit", Value::Null);
+        assert!(self.child.wait().unwrap().success());
+    }
+}
+
+fn at(uri: &str, line: usize, character: usize) -> Value {
+    obj(vec![
+        ("textDocument", obj(vec![("uri", uri.into())])),
+        (
+            "position",
+            obj(vec![("line", line.into()), ("character", character.into())]),
+        ),
+    ])
+}
+
+fn labels(v: &Value) -> Vec<String> {
+    v.as_array()
+        .unwrap()
+        .iter()
+        .map(|i| i.get("label").unwrap().as_str().unwrap().to_string())
+        .collect()
+}
+
+const MAIN: &str = "import geo.upp
+entry Main
+
+class Main:
+    pos: Vec2
+    def run(self) -> Void:
+        v: Vec2 = Vec2.origin()
+        self.pos.
+        Shapes.
+";
+
+const GEO: &str = "namespace Shapes
+
+class Vec2:
+    x: Float
+    y: Float
+    def static origin() -> Vec2:
+        return Vec2()
+    def length(self) -> Float:
+        return 0.0
+";
+
+#[test]
+fn diagnostics_follow_unsaved_edits() {
+    let dir = scratch("diag");
+    fs::write(dir.join("geo.upp"), GEO).unwrap();
+    fs::write(dir.join("main.upp"), MAIN).unwrap();
+    let uri = path_to_uri(&dir.join("main.upp"));
+    let mut c = Client::start();
+    let init = c.request("initialize", obj(vec![]));
+    assert_eq!(
+        init.at(&["capabilities", "hoverProvider"]),
+        Some(&Value::Bool(true))
+    );
+    c.notify("initialized", obj(vec![]));
+    let broken = "class Main:\n    def run(self):\n        v: Missing = 1\n";
+    c.notify(
+        "textDocument/didOpen",
+        obj(vec![(
+            "textDocument",
+            obj(vec![
+                ("uri", uri.as_str().into()),
+                ("languageId", "upp".into()),
+                ("version", 1u64.into()),
+                ("text", broken.into()),
+            ]),
+        )]),
+    );
+    c.request(
+        "textDocument/documentSymbol",
+        obj(vec![(
+            "textDocument",
+            obj(vec![("uri", uri.as_str().into())]),
+        )]),
+    );
+    let diags = c.diagnostics_for(&uri);
+    assert_eq!(diags.len(), 1, "{:?}", diags);
+    assert_eq!(
+        diags[0]
+            .at(&["range", "start", "line"])
+            .and_then(Value::as_u64),
+        Some(1)
+    );
+    assert!(diags[0]
+        .get("message")
+        .unwrap()
+        .as_str()
+        .unwrap()
+        .contains("Missing"));
+
+    // MAIN without its two unfinished completion lines.
+    let fixed = MAIN.lines().take(7).collect::<Vec<_>>().join("\n");
+    c.notify(
+        "textDocument/didChange",
+        obj(vec![
+            (
+                "textDocument",
+                obj(vec![("uri", uri.as_str().into()), ("version", 2u64.into())]),
+            ),
+            (
+                "contentChanges",
+                vec![obj(vec![("text", fixed.into())])].into(),
+            ),
+        ]),
+    );
+    c.request(
+        "textDocument/documentSymbol",
+        obj(vec![(
+            "textDocument",
+            obj(vec![("uri", uri.as_str().into())]),
+        )]),
+    );
+    assert!(c.diagnostics_for(&uri).is_empty());
+    c.finish();
+}
+
+#[test]
+fn hover_definition_completion_and_symbols() {
+    let dir = scratch("nav");
+    fs::write(dir.join("geo.upp"), GEO).unwrap();
+    fs::write(dir.join("main.upp"), MAIN).unwrap();
+    let uri = path_to_uri(&dir.join("main.upp"));
+    let mut c = Client::start();
+    c.request("initialize", obj(vec![]));
+    c.notify(
+        "textDocument/didOpen",
+        obj(vec![(
+            "textDocument",
+            obj(vec![
+                ("uri", uri.as_str().into()),
+                ("languageId", "upp".into()),
+                ("version", 1u64.into()),
+                ("text", MAIN.into()),
+            ]),
+        )]),
+    );
+
+    // Hover on `origin` in `Vec2.origin()`.
+    let hover = c.request("textDocument/hover", at(&uri, 6, 25));
+    let text = hover.at(&["contents", "value"]).unwrap().as_str().unwrap();
+    assert!(
+        text.contains("static def Vec2.origin() -> Vec2"),
+        "{}",
+        text
+    );
+
+    // Go to the definition of `Vec2` in the imported file.
+    let def = c.request("textDocument/definition", at(&uri, 4, 10));
+    assert_eq!(
+        def.get("uri").unwrap().as_str().unwrap(),
+        path_to_uri(&dir.join("geo.upp"))
+    );
+    assert_eq!(
+        def.at(&["range", "start", "line"]).and_then(Value::as_u64),
+        Some(2)
+    );
+
+    // `self.pos.` completes the members of the field type.
+    let items = labels(&c.request("textDocument/completion", at(&uri, 7, 17)));
+    assert_eq!(items, vec!["x", "y", "origin", "length"]);
+    // `Shapes.` completes the classes of the namespace.
+    let items = labels(&c.request("textDocument/completion", at(&uri, 8, 15)));
+    assert_eq!(items, vec!["Vec2"]);
+    // Top-level lines complete directive keywords.
+    let items = labels(&c.request("textDocument/completion", at(&uri, 1, 0)));
+    for kw in ["use", "profile", "capability", "entry"] {
+        assert!(items.contains(&kw.to_string()), "{:?}", items);
+    }
+
+    let symbols = c.request(
+        "textDocument/documentSymbol",
+        obj(vec![(
+            "textDocument",
+            obj(vec![("uri", uri.as_str().into())]),
+        )]),
+    );
+    let main = &symbols.as_array().unwrap()[0];
+    assert_eq!(main.get("name").unwrap().as_str(), Some("Main"));
+    assert_eq!(labels_of_children(main), vec!["pos", "run"]);
+    c.finish();
+}
+
+fn labels_of_children(sym: &Value) -> Vec<String> {
+    sym.get("children")
+        .and_then(Value::as_array)
+        .unwrap()
+        .iter()
+        .map(|c| c.get("name").unwrap().as_str().unwrap().to_string())
+        .collect()
+}
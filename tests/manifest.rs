@@ -0,0 +1,57 @@
+use ultracpp::manifest::Manifest;
+use ultracpp::Directives;
+
+#[test]
+fn parse_full_manifest() {
+    let src = r#"
+# team build
+[project]
+name = "juego"
+sources = ["principal.upp", "util.upp"]
+entry = "Principal"
+
+[build]
+emit = "unity"
+std = "c++20"
+compiler = "clang++"
+include_dirs = [
+    "vendor/include", # third party
+    "extra",
+]
+link_libs = ["m"]
+profiles = ["math"]
+release = true
+sanitize = "asan"
+
+[dependencies]
+geom = "^1.2"
+"#;
+    let m = Manifest::parse(src).expect("parse failed");
+    assert_eq!(m.name.as_deref(), Some("juego"));
+    assert_eq!(m.sources, vec!["principal.upp", "util.upp"]);
+    assert_eq!(m.emit.as_deref(), Some("unity"));
+    assert_eq!(m.std.as_deref(), Some("c++20"));
+    assert_eq!(m.include_dirs, vec!["vendor/include", "extra"]);
+    assert_eq!(m.release, Some(true));
+    assert_eq!(m.dependency_imports(), vec!["geom.upp@^1.2"]);
+    assert_eq!(Manifest::parse(&m.render()).unwrap(), m);
+}
+
+#[test]
+fn unknown_key_is_an_error() {
+    let err = Manifest::parse("[build]\noptimise = true\n").unwrap_err();
+    assert!(err.contains("ultra.toml:2"), "{}", err);
+}
+
+#[test]
+fn source_directives_win_over_manifest() {
+    let m =
+        Manifest::parse("[project]\nentry = \"Other\"\n[build]\nprofiles = [\"math\"]\n").unwrap();
+    let mut d = Directives {
+        entry: Some("Principal".to_string()),
+        ..Directives::default()
+    };
+    m.apply_to(&mut d);
+    assert_eq!(d.entry.as_deref(), Some("Principal"));
+    assert_eq!(d.profiles, vec!["math"]);
+}
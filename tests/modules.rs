@@ -0,0 +1,177 @@
+use std::fs;
+use std::process::Command;
+
+use ultracpp::buildplan::{BuildPlan, Target, TargetKind};
+use ultracpp::cli::Compiler;
+use ultracpp::codegen::{self, CppStd};
+use ultracpp::parser;
+
+mod common;
+use common::scratch;
+
+const SHAPES: &str = "\
+entry Main
+
+class Main:
+  def run(self):
+    let s: Scene = Scene()
+    print(s.total())
+
+class Scene:
+  origin: Point
+
+  def total(self) -> Int:
+    let b: Box = Box()
+    return origin.x + b.area()
+
+class Box(Shape):
+  def area(self) -> Int:
+    return 4
+
+  def twin(self) -> Twin:
+    return Twin()
+
+class Twin:
+  def back(self) -> Box:
+    return Box()
+
+class Shape:
+  def area(self) -> Int:
+    return 0
+
+class Point:
+  x: Int
+";
+
+#[test]
+fn modules_are_ordered_by_imports() {
+    let classes = parser::parse_all(SHAPES);
+    let modules = codegen::modules("app", &classes, CppStd::Cpp20);
+    let names: Vec<&str> = modules.iter().map(|m| m.name.as_str()).collect();
+    assert_eq!(
+        names,
+        ["app.shape", "app.box", "app.point", "app.scene", "app.main"]
+    );
+    // Box and Twin refer to each other, so they share a unit.
+    assert_eq!(modules[1].classes, ["Box", "Twin"]);
+
+    let scene = &modules[3].source;
+    assert!(scene.starts_with("module;\n"), "{}", scene);
+    assert!(scene.contains("\nexport module app.scene;\n"), "{}", scene);
+    // Point is part of Scene's interface; Box is only used in a body.
+    assert!(
+        scene.contains("\nimport app.box;\nexport import app.point;\n"),
+        "{}",
+        scene
+    );
+    assert!(
+        scene.contains("export {\nclass UCPP_API Scene {"),
+        "{}",
+        scene
+    );
+    assert!(!scene.contains("<filesystem>"), "{}", scene);
+}
+
+#[test]
+fn module_units_compile_in_order() {
+    let plan = BuildPlan {
+        name: "app".to_string(),
+        sources: ["src/app.a.cppm", "src/app.b.cppm", "src/entry.cpp"]
+            .map(String::from)
+            .to_vec(),
+        include_dirs: vec!["include".to_string()],
+        defines: Vec::new(),
+        flags: Vec::new(),
+        std: CppStd::Cpp20,
+        release: false,
+        sanitize: None,
+        coverage: false,
+        pch: None,
+        link_libs: Vec::new(),
+        targets: vec![Target {
+            kind: TargetKind::Executable,
+            name: "app".to_string(),
+            sources: Vec::new(),
+        }],
+    };
+    let gcc = plan
+        .compile_command(Compiler::Gcc, "src/app.b.cppm")
+        .join(" ");
+    assert!(
+        gcc.contains("-fmodules-ts")
+            && gcc.ends_with("-x c++ -c src/app.b.cppm -o build/obj/app.b.o"),
+        "{}",
+        gcc
+    );
+    let clang = plan
+        .compile_command(Compiler::Clang, "src/entry.cpp")
+        .join(" ");
+    assert!(
+        clang.contains("-fprebuilt-module-path=build/obj") && !clang.contains("-x"),
+        "{}",
+        clang
+    );
+    let ninja = plan.render_ninja(Compiler::Clang);
+    assert!(
+        ninja.contains("build build/obj/app.b.o: cxxmodule src/app.b.cppm | build/obj/app.a.o\n"),
+        "{}",
+        ninja
+    );
+    assert!(
+        ninja.contains("build build/obj/entry.o: cxx src/entry.cpp | build/obj/app.b.o\n"),
+        "{}",
+        ninja
+    );
+    let cmake = plan.render_cmake();
+    assert!(
+        cmake.starts_with("cmake_minimum_required(VERSION 3.28)\n"),
+        "{}",
+        cmake
+    );
+}
+
+#[test]
+fn modules_build_with_gcc() {
+    // GCC supports `-fmodules-ts` well enough from version 12.
+    let major = Command::new("g++")
+        .arg("-dumpversion")
+        .output()
+        .ok()
+        .and_then(|o| {
+            String::from_utf8_lossy(&o.stdout)
+                .split('.')
+                .next()?
+                .trim()
+                .parse::<u32>()
+                .ok()
+        });
+    if major.is_none_or(|m| m < 12) || cfg!(windows) {
+        return;
+    }
+    let dir = scratch("modules");
+    fs::write(dir.join("main.upp"), SHAPES).unwrap();
+    let build = |std: &str| {
+        Command::new(env!("CARGO_BIN_EXE_ultracpp"))
+            .current_dir(&dir)
+            .args(["build", "main.upp", "out", "--emit", "modules", "--compile"])
+            .args(["--compiler", "g++", "--std", std])
+            .output()
+            .unwrap()
+    };
+    let out = build("c++17");
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains("--emit modules needs --std c++20"));
+
+    let out = build("c++20");
+    assert!(
+        out.status.success(),
+        "{}",
+        String::from_utf8_lossy(&out.stderr)
+    );
+    assert!(dir.join("out/main/src/main.scene.cppm").is_file());
+    assert!(!dir.join("out/main/src/pch.cpp").exists());
+    let run = Command::new(dir.join("out/main/build/bin/main"))
+        .output()
+        .unwrap();
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "4\n");
+}
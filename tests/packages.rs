@@ -0,0 +1,147 @@
+use std::fs;
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+use ultracpp::imports;
+use ultracpp::packages::{Lockfile, Version, VersionReq, LOCKFILE_NAME};
+
+mod common;
+use common::scratch;
+
+fn v(s: &str) -> Version {
+    Version::parse(s).unwrap()
+}
+
+#[test]
+fn semver_requirements_match() {
+    let caret = VersionReq::parse("^1.2").unwrap();
+    assert!(caret.matches(&v("1.2.0")));
+    assert!(caret.matches(&v("1.9.3")));
+    assert!(!caret.matches(&v("1.1.9")));
+    assert!(!caret.matches(&v("2.0.0")));
+    let caret0 = VersionReq::parse("^0.2.1").unwrap();
+    assert!(caret0.matches(&v("0.2.5")));
+    assert!(!caret0.matches(&v("0.3.0")));
+    let tilde = VersionReq::parse("~1.2").unwrap();
+    assert!(tilde.matches(&v("1.2.7")));
+    assert!(!tilde.matches(&v("1.3.0")));
+    let exact = VersionReq::parse("1.0.0").unwrap();
+    assert!(exact.matches(&v("1.0.0")));
+    assert!(!exact.matches(&v("1.0.1")));
+}
+
+#[test]
+fn picks_highest_compatible_version_and_locks_it() {
+    let dir = scratch("pick");
+    for ver in ["1.1.0", "1.4.2", "2.0.0"] {
+        let d = dir.join("packages/geom").join(ver);
+        fs::create_dir_all(&d).unwrap();
+        fs::write(
+            d.join("geom.upp"),
+            format!("class Geom:\n  v: Int # {}\n", ver),
+        )
+        .unwrap();
+    }
+    fs::write(
+        dir.join("main.upp"),
+        "import geom.upp@^1.2\nclass Main:\n  x: Int\n",
+    )
+    .unwrap();
+    let g = imports::resolve(&dir.join("main.upp")).expect("resolve failed");
+    assert_eq!(
+        g.imported().next().unwrap().version.as_deref(),
+        Some("1.4.2")
+    );
+    let lock = g.lockfile();
+    assert_eq!(lock.packages.len(), 1);
+    assert_eq!(lock.packages[0].name, "geom");
+    assert_eq!(lock.packages[0].version, "1.4.2");
+    assert_eq!(Lockfile::parse(&lock.render()), lock);
+}
+
+#[test]
+fn changed_content_of_locked_version_fails() {
+    let dir = scratch("hash");
+    let d = dir.join("packages/geom/1.0.0");
+    fs::create_dir_all(&d).unwrap();
+    fs::write(d.join("geom.upp"), "class Geom:\n  v: Int\n").unwrap();
+    fs::write(
+        dir.join("main.upp"),
+        "import geom.upp@^1\nclass Main:\n  x: Int\n",
+    )
+    .unwrap();
+    let g = imports::resolve(&dir.join("main.upp")).expect("resolve failed");
+    fs::write(dir.join(LOCKFILE_NAME), g.lockfile().render()).unwrap();
+    fs::write(d.join("geom.upp"), "class Geom:\n  v: Float\n").unwrap();
+    let err = imports::resolve(&dir.join("main.upp")).unwrap_err();
+    assert!(err.contains("geom@1.0.0"), "{}", err);
+}
+
+#[test]
+fn local_imports_pin_an_exact_version_of_the_formatted_source() {
+    let dir = scratch("local");
+    fs::write(dir.join("util.upp"), "class Util:\n  v: Int\n").unwrap();
+    fs::write(
+        dir.join("main.upp"),
+        "import util.upp@^1.2\nclass Main:\n  x: Int\n",
+    )
+    .unwrap();
+    let err = imports::resolve(&dir.join("main.upp")).unwrap_err();
+    assert!(err.contains("exact version"), "{}", err);
+
+    fs::write(
+        dir.join("main.upp"),
+        "import util.upp@=1.2.0\nclass Main:\n  x: Int\n",
+    )
+    .unwrap();
+    let g = imports::resolve(&dir.join("main.upp")).expect("resolve failed");
+    assert_eq!(
+        g.imported().next().unwrap().version.as_deref(),
+        Some("1.2.0")
+    );
+    assert_eq!(g.lockfile().packages[0].version, "1.2.0");
+    fs::write(dir.join(LOCKFILE_NAME), g.lockfile().render()).unwrap();
+
+    // Layout-only edits keep the pin; code edits break it.
+    fs::write(dir.join("util.upp"), "class Util:\n\n    v:   Int\n").unwrap();
+    imports::resolve(&dir.join("main.upp")).expect("layout change broke the lock");
+    fs::write(dir.join("util.upp"), "class Util:\n  v: Float\n").unwrap();
+    let err = imports::resolve(&dir.join("main.upp")).unwrap_err();
+    assert!(err.contains("util@1.2.0"), "{}", err);
+}
+
+#[test]
+fn rebuilding_leaves_an_unchanged_lockfile_alone() {
+    let dir = scratch("rebuild");
+    fs::write(dir.join("util.upp"), "class Util:\n  v: Int\n").unwrap();
+    fs::write(
+        dir.join("main.upp"),
+        "import util.upp@1.0.0\nentry Main\nclass Main:\n  run():\n    print(1)\n",
+    )
+    .unwrap();
+    let build = || {
+        let out = Command::new(env!("CARGO_BIN_EXE_ultracpp"))
+            .current_dir(&dir)
+            .args(["build", "main.upp", "--out-dir", "out"])
+            .output()
+            .unwrap();
+        assert!(
+            out.status.success(),
+            "{}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+    };
+    build();
+    let lock = dir.join(LOCKFILE_NAME);
+    let contents = fs::read_to_string(&lock).unwrap();
+    let old = SystemTime::now() - Duration::from_secs(3600);
+    fs::File::options()
+        .write(true)
+        .open(&lock)
+        .unwrap()
+        .set_modified(old)
+        .unwrap();
+    build();
+    assert_eq!(fs::read_to_string(&lock).unwrap(), contents);
+    assert_eq!(fs::metadata(&lock).unwrap().modified().unwrap(), old);
+}
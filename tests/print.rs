@@ -0,0 +1,110 @@
+use std::fs;
+use std::process::Command;
+
+use ultracpp::{codegen, parser, Expr, FormatPart};
+
+mod common;
+use common::scratch;
+
+const REPORT: &str = "\
+entry Main
+
+class Main:
+  def run(self):
+    let lives: Int = 3
+    let ratio: Float = 0.5
+    print(\"lives:\", lives, lives > 2)
+    print(\"a\", \"b\", sep=\", \", end=\"!\\n\")
+    let line: String = f\"{ratio:.2f}|{lives:>4}|{lives:<4}|{255:#x}|{7:03}|{{x}}\"
+    print(line)
+    print(f\"done {lives == 3}\", end=\"\")
+";
+
+fn run_body() -> Expr {
+    let classes = parser::parse_all(REPORT);
+    classes[0].methods[0].body.clone()
+}
+
+#[test]
+fn keywords_and_fstrings_parse() {
+    let Expr::Block(stmts) = run_body() else {
+        panic!("expected a block")
+    };
+    match &stmts[3] {
+        Expr::Print { args, sep, end } => {
+            assert_eq!(args.len(), 2);
+            assert_eq!(sep.as_deref(), Some(&Expr::LiteralString(", ".into())));
+            assert_eq!(end.as_deref(), Some(&Expr::LiteralString("!\n".into())));
+        }
+        other => panic!("not a print: {:?}", other),
+    }
+    let Expr::VarDecl { value: Some(v), .. } = &stmts[4] else {
+        panic!("expected a declaration")
+    };
+    let Expr::Format(parts) = &**v else {
+        panic!("not an f-string: {:?}", v)
+    };
+    assert_eq!(
+        parts[0],
+        FormatPart::Value {
+            expr: Expr::Variable("ratio".into()),
+            spec: ".2f".into()
+        }
+    );
+    assert_eq!(parts[1], FormatPart::Text("|".into()));
+    assert_eq!(parts.last(), Some(&FormatPart::Text("|{x}".into())));
+}
+
+#[test]
+fn specs_map_to_stream_manipulators() {
+    let set = |spec: &str| codegen::stream_spec(spec).map(|(set, _)| set);
+    assert_eq!(set(""), Some(String::new()));
+    assert_eq!(
+        set(".2f").as_deref(),
+        Some(" << std::setprecision(2) << std::fixed")
+    );
+    assert_eq!(
+        set("*<6").as_deref(),
+        Some(" << std::left << std::setfill('*') << std::setw(6)")
+    );
+    assert_eq!(
+        set("08X").as_deref(),
+        Some(
+            " << std::hex << std::uppercase << std::internal << std::setfill('0') << std::setw(8)"
+        )
+    );
+    assert_eq!(set("^5"), None);
+    assert_eq!(set("b"), None);
+    assert_eq!(set("#f"), None);
+}
+
+#[test]
+fn every_standard_prints_the_same() {
+    if Command::new("g++").arg("--version").output().is_err() || cfg!(windows) {
+        return;
+    }
+    let dir = scratch("print");
+    fs::write(dir.join("main.upp"), REPORT).unwrap();
+    for std in ["c++17", "c++20"] {
+        let out = Command::new(env!("CARGO_BIN_EXE_ultracpp"))
+            .current_dir(&dir)
+            .args(["build", "main.upp", std, "--compile"])
+            .args(["--compiler", "g++", "--std", std])
+            .output()
+            .unwrap();
+        assert!(
+            out.status.success(),
+            "{}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+        let run = Command::new(dir.join(std).join("main/build/bin/main"))
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&run.stdout),
+            "lives: 3 true\na, b!\n0.50|   3|3   |0xff|007|{x}\ndone true",
+            "{}",
+            std
+        );
+    }
+}
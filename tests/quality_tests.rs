@@ -10,7 +10,11 @@ class Principal:
     let classes = parser::parse_all(src);
     assert_eq!(classes.len(), 1);
     let c = &classes[0];
-    let m = c.methods.iter().find(|m| m.name == "run").expect("run not found");
+    let m = c
+        .methods
+        .iter()
+        .find(|m| m.name == "run")
+        .expect("run not found");
     match &m.body {
         ultracpp::Expr::Block(stmts) => {
             assert!(!stmts.is_empty(), "block empty");
@@ -42,9 +46,16 @@ class Principal:
     for c in classes.iter_mut() {
         c.extra_includes = ultracpp::resolve_includes(&d);
     }
-    let principal = classes.iter().find(|c| c.name == "Principal").expect("Principal not found");
+    let principal = classes
+        .iter()
+        .find(|c| c.name == "Principal")
+        .expect("Principal not found");
     let src_cpp = codegen::source(principal);
-    assert!(src_cpp.contains("#include \"rect.hpp\""), "rect.hpp not included:\n{}", src_cpp);
+    assert!(
+        src_cpp.contains("#include \"rect.hpp\""),
+        "rect.hpp not included:\n{}",
+        src_cpp
+    );
 }
 
 #[test]
@@ -59,8 +70,16 @@ class Principal:
     for c in classes.iter_mut() {
         c.extra_includes = ultracpp::resolve_includes(&d);
     }
-    let principal = classes.iter().find(|c| c.name == "Principal").expect("Principal not found");
+    let principal = classes
+        .iter()
+        .find(|c| c.name == "Principal")
+        .expect("Principal not found");
     let src_cpp = codegen::source(principal);
-    assert!(src_cpp.contains("#include \"version.hpp\"") || src_cpp.contains("#include \"Utils.hpp\"") || src_cpp.contains("#include \"utils.hpp\""),
-        "expected auto-include for dotted static, got:\n{}", src_cpp);
+    assert!(
+        src_cpp.contains("#include \"version.hpp\"")
+            || src_cpp.contains("#include \"Utils.hpp\"")
+            || src_cpp.contains("#include \"utils.hpp\""),
+        "expected auto-include for dotted static, got:\n{}",
+        src_cpp
+    );
 }
@@ -0,0 +1,85 @@
+use std::path::Path;
+
+use ultracpp::json::Value;
+use ultracpp::sourcemap::SourceMap;
+use ultracpp::{codegen, parser};
+
+const SRC: &str = "std
+entry Main
+
+class Main:
+  count: Int
+  def run(self):
+    x: Int = 1
+    if x > 0:
+      print(\"pos\")
+    elif x == 0:
+      print(\"zero\")
+    else:
+      print(\"neg\")
+    count = x
+";
+
+#[test]
+fn line_directives_follow_statements() {
+    let mut classes = parser::parse_all(SRC);
+    assert!(!codegen::source(&classes[0]).contains("#line"));
+
+    classes[0].source_file = Some("/work/main.upp".to_string());
+    let cpp = codegen::finish_line_directives(&codegen::source(&classes[0]), "src/main.cpp");
+    let marks: Vec<&str> = cpp.lines().filter(|l| l.starts_with("#line")).collect();
+    assert_eq!(
+        marks,
+        vec![
+            "#line 6 \"/work/main.upp\"",
+            "#line 7 \"/work/main.upp\"",
+            "#line 8 \"/work/main.upp\"",
+            "#line 9 \"/work/main.upp\"",
+            "#line 10 \"/work/main.upp\"",
+            "#line 11 \"/work/main.upp\"",
+            "#line 13 \"/work/main.upp\"",
+            "#line 14 \"/work/main.upp\"",
+            &format!("#line {} \"src/main.cpp\"", cpp.lines().count() + 1),
+        ],
+        "{}",
+        cpp
+    );
+    let lines: Vec<&str> = cpp.lines().collect();
+    let decl = lines.iter().position(|l| l.contains("int x = 1;")).unwrap();
+    assert_eq!(lines[decl - 1], "#line 7 \"/work/main.upp\"");
+
+    let mut map = SourceMap::default();
+    map.add("src/main.cpp", &cpp);
+    assert_eq!(
+        map.lookup("src/main.cpp", decl + 1),
+        Some(("/work/main.upp", 7))
+    );
+    assert_eq!(
+        map.lookup("/tmp/dist/main/src/main.cpp", decl + 1),
+        Some(("/work/main.upp", 7))
+    );
+    assert_eq!(map.lookup("src/main.cpp", 1), None);
+    assert_eq!(map.lookup("src/main.cpp", lines.len() + 1), None);
+}
+
+#[test]
+fn compiler_diagnostics_point_at_upp() {
+    let mut classes = parser::parse_all(SRC);
+    classes[0].source_file = Some("/work/main.upp".to_string());
+    let cpp = codegen::finish_line_directives(&codegen::source(&classes[0]), "src/main.cpp");
+    let decl = cpp.lines().position(|l| l.contains("int x = 1;")).unwrap() + 1;
+    let mut map = SourceMap::default();
+    map.add("src/main.cpp", &cpp);
+    let map = SourceMap::from_json(&Value::parse(&map.to_json().to_string()).unwrap()).unwrap();
+
+    let output = format!(
+        "src/main.cpp:{}:7: error: boom\nsrc/main.cpp:1:1: note: generated\n\
+         C:\\dist\\main\\src\\main.cpp({}): error C2065: boom\n/work/main.upp:8:3: warning: w\n",
+        decl, decl
+    );
+    assert_eq!(
+        map.rewrite(&output, Path::new("/work")),
+        "main.upp:7:7: error: boom\nsrc/main.cpp:1:1: note: generated\n\
+         main.upp(7): error C2065: boom\nmain.upp:8:3: warning: w\n"
+    );
+}
@@ -0,0 +1,130 @@
+use std::fs;
+use std::process::Command;
+
+use ultracpp::codegen::{self, CppStd};
+use ultracpp::{format, parser, Expr};
+
+mod common;
+use common::scratch;
+
+const STRINGS: &str = "\
+entry Main
+
+class Main:
+  def run(self):
+    let name: String = \"Perú\"
+    print(\"tab\\there\", \"say \\\"hi\\\"\", \"back\\\\slash\", \"\\x41\\101\\u00e9\")
+    print(r\"C:\\new\", f\"{name}\\t!\")
+    let poem: String = \"\"\"one
+  two \"quoted\" # kept
+three\"\"\"
+    print(poem)
+    native \"std::cout << \\\"native\\tend\\n\\\";\"
+    native \"
+std::cout << \\\"block\\tend\\n\\\";
+\"
+";
+
+fn run_body() -> Vec<Expr> {
+    let classes = parser::parse_all(STRINGS);
+    let Expr::Block(stmts) = classes[0].methods[0].body.clone() else {
+        panic!("expected a block")
+    };
+    stmts
+}
+
+#[test]
+fn escapes_raw_and_triple_quotes_decode() {
+    let stmts = run_body();
+    let Expr::Print { args, .. } = &stmts[1] else {
+        panic!("not a print: {:?}", stmts[1])
+    };
+    let text: Vec<Expr> = ["tab\there", "say \"hi\"", "back\\slash", "AAé"]
+        .iter()
+        .map(|s| Expr::LiteralString(s.to_string()))
+        .collect();
+    assert_eq!(args, &text);
+    let Expr::Print { args, .. } = &stmts[2] else {
+        panic!("not a print: {:?}", stmts[2])
+    };
+    assert_eq!(args[0], Expr::LiteralString("C:\\new".into()));
+    let Expr::VarDecl { value: Some(v), .. } = &stmts[3] else {
+        panic!("expected a declaration")
+    };
+    assert_eq!(
+        **v,
+        Expr::LiteralString("one\n  two \"quoted\" # kept\nthree".into())
+    );
+    assert_eq!(stmts.len(), 7);
+}
+
+#[test]
+fn literals_escape_and_pick_u8_by_standard() {
+    let classes = parser::parse_all(STRINGS);
+    let src = codegen::source(&classes[0]);
+    assert!(src.contains("std::string name = u8\"Perú\";"), "{}", src);
+    assert!(
+        src.contains(
+            "<< \"tab\\there\" << \" \" << \"say \\\"hi\\\"\" << \" \" << \"back\\\\slash\""
+        ),
+        "{}",
+        src
+    );
+    assert!(
+        src.contains("std::string poem = \"one\\n  two \\\"quoted\\\" # kept\\nthree\";"),
+        "{}",
+        src
+    );
+    assert!(
+        src.contains("  std::cout << \"native\\tend\\n\";\n"),
+        "{}",
+        src
+    );
+
+    let src = codegen::source_with(&classes[0], CppStd::Cpp20);
+    assert!(src.contains("std::string name = \"Perú\";"), "{}", src);
+    assert!(!src.contains("u8\""), "{}", src);
+}
+
+#[test]
+fn formatting_keeps_string_contents() {
+    let out = format::format_source(STRINGS).unwrap();
+    assert!(
+        out.contains("    let poem: String = \"\"\"one\n  two \"quoted\" # kept\nthree\"\"\"\n"),
+        "{}",
+        out
+    );
+    assert!(out.contains("\"say \\\"hi\\\"\""), "{}", out);
+}
+
+#[test]
+fn compiled_program_prints_the_decoded_text() {
+    if Command::new("g++").arg("--version").output().is_err() || cfg!(windows) {
+        return;
+    }
+    let dir = scratch("string_literals");
+    fs::write(dir.join("main.upp"), STRINGS).unwrap();
+    for std in ["c++17", "c++20"] {
+        let out = Command::new(env!("CARGO_BIN_EXE_ultracpp"))
+            .current_dir(&dir)
+            .args(["build", "main.upp", std, "--compile"])
+            .args(["--compiler", "g++", "--std", std])
+            .output()
+            .unwrap();
+        assert!(
+            out.status.success(),
+            "{}",
+            String::from_utf8_lossy(&out.stderr)
+        );
+        let run = Command::new(dir.join(std).join("main/build/bin/main"))
+            .output()
+            .unwrap();
+        assert_eq!(
+            String::from_utf8_lossy(&run.stdout),
+            "tab\there say \"hi\" back\\slash AAé\nC:\\new Perú\t!\n\
+             one\n  two \"quoted\" # kept\nthree\nnative\tend\nblock\tend\n",
+            "{}",
+            std
+        );
+    }
+}
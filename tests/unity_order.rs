@@ -0,0 +1,47 @@
+use ultracpp::{codegen, parser};
+
+#[test]
+fn definitions_follow_dependencies_across_namespaces() {
+    let mut classes = parser::parse_all(
+        "\
+class Scene:
+  origin: Point
+  items: Vector<Scene>
+
+class Point:
+  x: Int
+
+class Box(Shape):
+  def area(self) -> Int:
+    return 4
+
+class Shape:
+  def area(self) -> Int:
+    return 0
+",
+    );
+    for c in classes.iter_mut() {
+        if c.name == "Point" || c.name == "Shape" {
+            c.namespace = Some("geo".to_string());
+        }
+    }
+    let s = codegen::unity_build(&classes);
+    let defs =
+        &s[s.find("// Class Definitions").unwrap()..s.find("// Class Implementations").unwrap()];
+    let pos = |needle: &str| {
+        defs.find(needle)
+            .unwrap_or_else(|| panic!("missing {:?} in\n{}", needle, defs))
+    };
+    assert!(
+        pos("class UCPP_API Point {") < pos("class UCPP_API Scene {"),
+        "{}",
+        defs
+    );
+    assert!(
+        pos("class UCPP_API Shape {") < pos("class UCPP_API Box : public Shape {"),
+        "{}",
+        defs
+    );
+    // geo is opened for Point, closed for Scene, and reopened for Shape.
+    assert_eq!(defs.matches("namespace geo {\n").count(), 2, "{}", defs);
+}
@@ -1,2 +1,2 @@
 @echo off
-cargo run --quiet -- %* --compile
+cargo run --quiet -- run %*